@@ -0,0 +1 @@
+ALTER TABLE process ADD COLUMN profile TEXT;
@@ -0,0 +1,8 @@
+CREATE TABLE event (
+  id            INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  process_name  TEXT NOT NULL,
+  kind          TEXT NOT NULL,
+  created_at    DATETIME NOT NULL,
+  exit_code     INTEGER
+);
+CREATE INDEX idx_event_created_at ON event (created_at);
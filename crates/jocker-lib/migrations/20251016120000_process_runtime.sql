@@ -0,0 +1,3 @@
+ALTER TABLE process ADD COLUMN started_at DATETIME;
+ALTER TABLE process ADD COLUMN restart_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE process ADD COLUMN exit_code INTEGER;
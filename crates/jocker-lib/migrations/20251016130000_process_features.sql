@@ -0,0 +1,3 @@
+ALTER TABLE process ADD COLUMN features TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE process ADD COLUMN no_default_features BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE process ADD COLUMN all_features BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+ALTER TABLE process ADD COLUMN depends_on TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE process ADD COLUMN healthcheck TEXT;
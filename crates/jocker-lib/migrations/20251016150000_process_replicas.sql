@@ -0,0 +1,4 @@
+ALTER TABLE process ADD COLUMN base_name TEXT NOT NULL DEFAULT '';
+ALTER TABLE process ADD COLUMN instance INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE process ADD COLUMN replicas INTEGER NOT NULL DEFAULT 1;
+UPDATE process SET base_name = name;
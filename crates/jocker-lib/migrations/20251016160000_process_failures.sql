@@ -0,0 +1,2 @@
+ALTER TABLE process ADD COLUMN failure_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE process ADD COLUMN failures_since DATETIME;
@@ -0,0 +1 @@
+ALTER TABLE process ADD COLUMN ports TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1 @@
+ALTER TABLE process ADD COLUMN archived_lines INTEGER NOT NULL DEFAULT 0;
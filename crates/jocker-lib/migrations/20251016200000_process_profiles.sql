@@ -0,0 +1 @@
+ALTER TABLE process ADD COLUMN profiles TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1 @@
+ALTER TABLE metadata ADD COLUMN selected_stack TEXT;
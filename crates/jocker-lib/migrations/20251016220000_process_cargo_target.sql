@@ -0,0 +1,2 @@
+ALTER TABLE process ADD COLUMN package TEXT;
+ALTER TABLE process ADD COLUMN example BOOLEAN NOT NULL DEFAULT FALSE;
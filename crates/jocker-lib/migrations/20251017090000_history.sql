@@ -0,0 +1,8 @@
+CREATE TABLE history (
+  version       INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  created_at    DATETIME NOT NULL,
+  kind          TEXT NOT NULL,
+  process_name  TEXT,
+  change        TEXT NOT NULL
+);
+CREATE INDEX idx_history_process_name ON history (process_name);
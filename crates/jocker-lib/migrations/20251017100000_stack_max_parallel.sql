@@ -0,0 +1 @@
+ALTER TABLE stack ADD COLUMN max_parallel INTEGER;
@@ -0,0 +1 @@
+ALTER TABLE process ADD COLUMN kind TEXT NOT NULL DEFAULT 'service';
@@ -0,0 +1,2 @@
+ALTER TABLE process ADD COLUMN command_line TEXT;
+ALTER TABLE process ADD COLUMN stack TEXT;
@@ -0,0 +1 @@
+ALTER TABLE process ADD COLUMN secrets TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1 @@
+ALTER TABLE process ADD COLUMN launched_env TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1 @@
+ALTER TABLE stack ADD COLUMN user_created BOOLEAN NOT NULL DEFAULT FALSE;
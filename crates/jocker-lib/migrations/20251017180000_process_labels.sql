@@ -0,0 +1 @@
+ALTER TABLE process ADD COLUMN labels TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1 @@
+ALTER TABLE metadata ADD COLUMN cargo_manifests_hash TEXT;
@@ -0,0 +1,9 @@
+CREATE TABLE build (
+  id           INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  started_at   DATETIME NOT NULL,
+  duration_ms  INTEGER NOT NULL,
+  processes    TEXT NOT NULL,
+  profile      TEXT,
+  compiled     INTEGER NOT NULL,
+  success      BOOLEAN NOT NULL
+);
@@ -0,0 +1,4 @@
+CREATE TABLE scaled_process (
+  base_name  TEXT NOT NULL PRIMARY KEY,
+  replicas   INTEGER NOT NULL
+);
@@ -0,0 +1,4 @@
+CREATE TABLE built_artifact (
+  path   TEXT NOT NULL PRIMARY KEY,
+  flags  TEXT NOT NULL
+);
@@ -0,0 +1,26 @@
+-- Lifecycle events of processes are history entries, instead of being recorded twice: in a table
+-- of their own, and as state transitions in the history
+CREATE TABLE history_event (
+  version       INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  created_at    DATETIME NOT NULL,
+  kind          TEXT NOT NULL,
+  process_name  TEXT,
+  change        TEXT NOT NULL,
+  exit_code     INTEGER
+);
+INSERT INTO history_event (created_at, kind, process_name, change, exit_code)
+SELECT created_at, kind, process_name, change, exit_code
+FROM (
+  SELECT created_at, kind, process_name, change, NULL AS exit_code, 0 AS source, version AS seq
+  FROM history
+  WHERE kind <> 'state'
+  UNION ALL
+  SELECT created_at, 'event', process_name, kind, exit_code, 1, id
+  FROM event
+)
+ORDER BY created_at, source, seq;
+DROP TABLE history;
+DROP TABLE event;
+ALTER TABLE history_event RENAME TO history;
+CREATE INDEX idx_history_process_name ON history (process_name);
+CREATE INDEX idx_history_created_at ON history (created_at);
@@ -0,0 +1 @@
+ALTER TABLE process RENAME COLUMN profiles TO groups;
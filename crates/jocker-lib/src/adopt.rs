@@ -0,0 +1,87 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tracing::info;
+
+use crate::{
+    common::{Exec, Process, ProcessState},
+    error::{Error, InnerError, Result},
+    resources::ResourceMonitor,
+    state::State,
+    Pid,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct AdoptArgs {
+    pub processes: Vec<String>,
+}
+
+/// Manage processes started outside of jocker, such as with `cargo run`
+///
+/// Adopted processes are shown as external by `ps`, are followed through their OS pid until they
+/// exit, and are signalled directly by `stop`. Their logs are not available to jocker.
+pub struct Adopt {
+    args: AdoptArgs,
+    state: Arc<State>,
+}
+
+impl Adopt {
+    pub fn new(args: AdoptArgs, state: Arc<State>) -> Self {
+        Adopt { args, state }
+    }
+}
+
+impl Exec<()> for Adopt {
+    async fn exec(&self) -> Result<()> {
+        let processes = self.state.filter_processes(&self.args.processes).await?;
+        let external_pids = external_pids(&self.state, &processes).await?;
+        if !self.args.processes.is_empty() {
+            if let Some(process) = processes.iter().find(|p| {
+                p.state != ProcessState::External && !external_pids.contains_key(p.name())
+            }) {
+                return Err(Error::new(InnerError::ProcessNotRunning(format!(
+                    "No process runs the binary of {} outside of jocker",
+                    process.name()
+                ))));
+            }
+        }
+        let _lock = self.state.lock()?;
+        for process in processes {
+            let Some(pid) = external_pids.get(process.name()) else {
+                continue;
+            };
+            self.state
+                .set_state(process.name(), ProcessState::External)
+                .await?;
+            self.state.set_pid(process.name(), Some(*pid)).await?;
+            info!("Adopted process {} (pid {pid})", process.name());
+        }
+        Ok(())
+    }
+}
+
+/// OS pids of processes running the binary of given stopped processes, without jocker having
+/// launched them
+pub(crate) async fn external_pids(
+    state: &State,
+    processes: &[Process],
+) -> Result<HashMap<String, Pid>> {
+    let candidates: Vec<&Process> = processes
+        .iter()
+        .filter(|p| p.state == ProcessState::Stopped && p.is_cargo() && p.host().is_none())
+        .collect();
+    if candidates.is_empty() {
+        return Ok(HashMap::new());
+    }
+    // Outside of a cargo workspace, there is no binary to look for
+    let Ok(cargo_target_dir) = state.get_cargo_target_dir().await else {
+        return Ok(HashMap::new());
+    };
+    let mut monitor = ResourceMonitor::new();
+    let mut pids = HashMap::new();
+    for process in candidates {
+        if let Some(pid) = monitor.untagged_pid(&process.artifact_path(cargo_target_dir)) {
+            pids.insert(process.name().to_owned(), pid as Pid);
+        }
+    }
+    Ok(pids)
+}
@@ -0,0 +1,206 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::{
+    common::Process, config::ConfigFile, error::Result, logs::secret_values, secrets,
+    state::State, Pid,
+};
+
+/// Size above which an archive file is rotated
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+/// Number of rotated archive files kept per process
+pub const DEFAULT_RETENTION: usize = 5;
+
+const ARCHIVE_DIR: &str = "archive";
+const ARCHIVE_BUFFER: usize = 64;
+
+/// Copy of process logs in the project state directory
+///
+/// Logs held by the scheduler are lost when pueued is reset, archived ones are kept until
+/// `jocker clean`. Each process gets a `<name>.log` file, rotated into `<name>.log.1`,
+/// `<name>.log.2`... once it grows above `max_size`.
+#[derive(Debug)]
+pub struct LogArchive {
+    dir: PathBuf,
+    max_size: u64,
+    retention: usize,
+}
+
+impl LogArchive {
+    pub fn new(state: &State) -> Result<Self> {
+        let logs = ConfigFile::load(state.get_config_file())?
+            .and_then(|config| config.default)
+            .and_then(|default| default.logs)
+            .unwrap_or_default();
+        Ok(Self::with_dir(
+            state.get_project_dir().join(ARCHIVE_DIR),
+            logs.max_size.unwrap_or(DEFAULT_MAX_SIZE),
+            logs.retention.unwrap_or(DEFAULT_RETENTION),
+        ))
+    }
+
+    fn with_dir(dir: PathBuf, max_size: u64, retention: usize) -> Self {
+        Self {
+            dir,
+            max_size,
+            retention,
+        }
+    }
+
+    /// File the latest archived log lines of given process are written to
+    pub fn current_path(&self, process_name: &str) -> PathBuf {
+        self.path(process_name, 0)
+    }
+
+    fn path(&self, process_name: &str, index: usize) -> PathBuf {
+        match index {
+            0 => self.dir.join(format!("{process_name}.log")),
+            i => self.dir.join(format!("{process_name}.log.{i}")),
+        }
+    }
+
+    /// Append log lines of given process not archived yet, whether it is still running or not
+    ///
+    /// Lines already archived are skipped as the scheduler sends them, only new ones are kept.
+    pub async fn sync(&self, state: &State, process: &Process) -> Result<()> {
+        let Some(pid) = *process.pid() else {
+            return Ok(());
+        };
+        let archived = usize::try_from(process.archived_lines)?;
+        let (mut total, mut new_lines) = fetch_lines(state, pid, archived).await?;
+        // Fewer lines than archived means the scheduler lost them, keep everything it has now
+        if total < archived {
+            (total, new_lines) = fetch_lines(state, pid, 0).await?;
+        }
+        if new_lines.is_empty() {
+            return Ok(());
+        }
+        let masked = secret_values(state, process).await?;
+        let new_lines: Vec<String> = new_lines
+            .iter()
+            .map(|line| secrets::mask(line, &masked))
+            .collect();
+        self.append(process.name(), &new_lines)?;
+        state
+            .set_archived_lines(process.name(), total.try_into()?)
+            .await
+    }
+
+    fn append(&self, process_name: &str, lines: &[String]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let content: String = lines.iter().map(|line| format!("{line}\n")).collect();
+        let path = self.path(process_name, 0);
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + content.len() as u64 > self.max_size {
+            self.rotate(process_name)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    fn rotate(&self, process_name: &str) -> Result<()> {
+        if self.retention == 0 {
+            fs::remove_file(self.path(process_name, 0))?;
+            return Ok(());
+        }
+        let oldest = self.path(process_name, self.retention);
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+        for i in (0..self.retention).rev() {
+            let path = self.path(process_name, i);
+            if path.exists() {
+                fs::rename(path, self.path(process_name, i + 1))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Archived log lines of given process, oldest first
+    pub fn read(&self, process_name: &str) -> Result<Vec<String>> {
+        let mut lines = vec![];
+        for i in (0..=self.retention).rev() {
+            let path = self.path(process_name, i);
+            if path.exists() {
+                lines.extend(read_lines(&path)?);
+            }
+        }
+        Ok(lines)
+    }
+}
+
+/// Same as [`LogArchive::sync`], only warning about failures
+///
+/// Archiving logs mustn't prevent processes from being stopped, nor the state from being
+/// refreshed.
+pub(crate) async fn sync_logs(state: &State, process: &Process) {
+    let res = match LogArchive::new(state) {
+        Ok(archive) => archive.sync(state, process).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = res {
+        warn!("Unable to archive logs of process {}: {e}", process.name());
+    }
+}
+
+/// Number of log lines the scheduler holds for given pid, along with the ones after the first
+/// `skip` ones
+async fn fetch_lines(state: &State, pid: Pid, skip: usize) -> Result<(usize, Vec<String>)> {
+    let (tx, mut rx) = mpsc::channel(ARCHIVE_BUFFER);
+    let collect = async {
+        let (mut total, mut lines) = (0, vec![]);
+        while let Some(line) = rx.recv().await {
+            if total >= skip {
+                lines.push(line);
+            }
+            total += 1;
+        }
+        (total, lines)
+    };
+    let (res, fetched) = tokio::join!(state.scheduler().logs(tx, pid, None, false), collect);
+    res?;
+    Ok(fetched)
+}
+
+fn read_lines(path: &Path) -> Result<Vec<String>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(reader.lines().collect::<std::io::Result<_>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| format!("line {i:02}")).collect()
+    }
+
+    #[test]
+    fn rotate_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        // Each line is 8 bytes long, files hold 4 lines
+        let archive = LogArchive::with_dir(dir.path().to_path_buf(), 32, 2);
+
+        archive.append("web", &lines(0..3)).unwrap();
+        archive.append("web", &lines(3..4)).unwrap();
+        assert_eq!(archive.read("web").unwrap(), lines(0..4));
+        assert!(!archive.path("web", 1).exists());
+
+        archive.append("web", &lines(4..8)).unwrap();
+        archive.append("web", &lines(8..12)).unwrap();
+        assert_eq!(archive.read("web").unwrap(), lines(0..12));
+
+        archive.append("web", &lines(12..16)).unwrap();
+        assert!(!archive.path("web", 3).exists());
+        assert_eq!(archive.read("web").unwrap(), lines(4..16));
+
+        assert!(archive.read("api").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+use std::{future::Future, sync::Arc};
+
+use tokio::task::JoinSet;
+use tokio_stream::StreamExt;
+use tracing::{error, info, warn};
+
+use crate::{
+    common::Exec,
+    error::Result,
+    logs::{Logs, LogsArgs},
+    start::{Start, StartArgs},
+    state::State,
+    stop::{self, StopArgs},
+};
+
+/// Start processes, stream their logs and stop them once `shutdown` resolves
+///
+/// Only processes started by this pipeline are stopped, the ones already running are left as is.
+pub struct Attach {
+    args: StartArgs,
+    state: Arc<State>,
+}
+
+impl Attach {
+    pub fn new(args: StartArgs, state: Arc<State>) -> Self {
+        Attach { args, state }
+    }
+
+    pub async fn run(&self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        let mut processes = self.state.filter_processes(&self.args.processes).await?;
+        processes.retain(|p| p.has_labels(&self.args.labels));
+        let max_process_name_len = processes.iter().map(|p| p.name().len()).max().unwrap_or(0);
+        let started: Vec<String> = processes
+            .into_iter()
+            .filter(|p| p.state.is_stopped())
+            .map(|p| p.name().to_string())
+            .collect();
+        Start::new(self.args.clone(), self.state.clone())
+            .exec()
+            .await?;
+
+        let mut entries = Logs::new(
+            LogsArgs {
+                follow: true,
+                tail: false,
+                processes: self.args.processes.clone(),
+                labels: self.args.labels.clone(),
+                ..Default::default()
+            },
+            self.state.clone(),
+        )
+        .run()
+        .await?;
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                entry = entries.next() => match entry {
+                    Some(Ok(entry)) => println!("{}", entry.prefixed(max_process_name_len)),
+                    Some(Err(e)) => warn!("Error while reading logs: {e}"),
+                    None => break,
+                },
+                _ = &mut shutdown => break,
+            }
+        }
+        // Stop reading logs before processes are stopped
+        drop(entries);
+
+        if started.is_empty() {
+            return Ok(());
+        }
+        info!("Stopping processes ...");
+        let mut handles = JoinSet::new();
+        for process in self.state.filter_processes(&started).await? {
+            handles.spawn(stop::run(self.state.clone(), process, StopArgs::default()));
+        }
+        while let Some(res) = handles.join_next().await {
+            match res {
+                Err(e) => error!("Error while stopping process: {e}"),
+                Ok(Err(e)) => error!("Error while stopping process: {e}"),
+                Ok(Ok(())) => (),
+            }
+        }
+        Ok(())
+    }
+}
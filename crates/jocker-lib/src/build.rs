@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use crate::{
+    common::Exec,
+    error::Result,
+    start::{Start, StartArgs},
+    state::State,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct BuildArgs {
+    pub release: bool,
+    pub processes: Vec<String>,
+}
+
+/// Build binaries of processes, without starting them
+pub struct Build {
+    args: BuildArgs,
+    state: Arc<State>,
+}
+
+impl Build {
+    pub fn new(args: BuildArgs, state: Arc<State>) -> Self {
+        Build { args, state }
+    }
+}
+
+impl Exec<()> for Build {
+    async fn exec(&self) -> Result<()> {
+        let mut processes = self.state.filter_processes(&self.args.processes).await?;
+        if self.args.release {
+            for process in processes.iter_mut() {
+                process.profile = Some("release".to_owned());
+            }
+        }
+        Start::new(StartArgs::default(), self.state.clone())
+            .build(&processes)
+            .await
+    }
+}
@@ -1,26 +1,63 @@
 use std::{
-    collections::{HashMap, HashSet},
-    ffi::OsStr,
+    collections::HashMap,
+    env::consts::EXE_SUFFIX,
     fmt::Display,
-    hash::Hash,
-    path::Path,
+    fs,
+    path::{Path, PathBuf},
     process::Stdio,
+    time::SystemTime,
 };
 
 use dotenvy::dotenv_iter;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::process::{Child, Command};
+use tracing::{debug, warn};
 use url::Url;
 
 use crate::error::{Error, InnerError, Result};
 
+const MESSAGE_FORMAT_ARG: &str = "--message-format";
+
 pub struct Cargo;
 
+/// Set of features a crate is built with
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct CargoFeatures {
+    pub features: Vec<String>,
+    pub no_default_features: bool,
+    pub all_features: bool,
+}
+
+/// Crate target built for a process
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum CargoTarget {
+    Bin(String),
+    Example(String),
+}
+
+impl CargoTarget {
+    /// Cargo argument selecting this target
+    pub fn arg(&self) -> String {
+        match self {
+            Self::Bin(name) => format!("--bin={name}"),
+            Self::Example(name) => format!("--example={name}"),
+        }
+    }
+}
+
 impl Cargo {
-    /// Start a `cargo` subprocess that builds given binaries. Returns a handle to it.
-    pub async fn build<S>(target_dir: &Path, binaries: &[S], cargo_args: &[S]) -> Result<Child>
+    /// Start a `cargo` subprocess that builds given targets. Returns a handle to it.
+    pub async fn build<S>(
+        target_dir: &Path,
+        targets: &[CargoTarget],
+        cargo_args: &[S],
+        profile: Option<&str>,
+        package: Option<&str>,
+        features: &CargoFeatures,
+    ) -> Result<Child>
     where
-        S: AsRef<OsStr> + Display + Eq + Hash,
+        S: Display,
     {
         let mut env: HashMap<String, String> = HashMap::new();
         if let Ok(dotenv) = dotenv_iter() {
@@ -32,13 +69,9 @@ impl Cargo {
 
         let mut build = Command::new("cargo");
         build.stdout(Stdio::piped()).stderr(Stdio::piped());
-        build.arg("build");
-        for arg in HashSet::<&S>::from_iter(cargo_args) {
-            build.arg(arg);
-        }
-        for binary in HashSet::<&S>::from_iter(binaries) {
-            build.arg(format!("--bin={binary}"));
-        }
+        let args = Self::build_args(targets, cargo_args, profile, package, features);
+        debug!(dir = %target_dir.display(), "Running cargo {}", args.join(" "));
+        build.args(args);
         for (key, val) in env.iter() {
             build.env(key, val);
         }
@@ -51,15 +84,223 @@ impl Cargo {
         Ok(build)
     }
 
-    pub async fn metadata(target_dir: &Path) -> Result<Vec<SerializedPackage>> {
-        let metadata = Command::new("cargo")
-            .arg("metadata")
-            .arg("--format-version=1")
+    /// Arguments of the `cargo` command building given targets, starting with `build`
+    ///
+    /// A `--message-format` of `cargo_args` is dropped, as built artifacts are read from JSON
+    /// messages.
+    pub fn build_args<S>(
+        targets: &[CargoTarget],
+        cargo_args: &[S],
+        profile: Option<&str>,
+        package: Option<&str>,
+        features: &CargoFeatures,
+    ) -> Vec<String>
+    where
+        S: Display,
+    {
+        // Diagnostics are still rendered on stderr, stdout only gets JSON messages
+        let mut args: Vec<String> = vec![
+            "build".to_owned(),
+            format!("{MESSAGE_FORMAT_ARG}=json-render-diagnostics"),
+        ];
+        let cargo_args = Self::strip_message_format(cargo_args);
+        Self::push_flags(&mut args, targets, &cargo_args, profile, package, features);
+        args
+    }
+
+    /// Given cargo args without `--message-format`, whether its value is attached or not
+    fn strip_message_format<S>(cargo_args: &[S]) -> Vec<String>
+    where
+        S: Display,
+    {
+        let mut args = Vec::new();
+        let mut cargo_args = cargo_args.iter().map(ToString::to_string);
+        while let Some(arg) = cargo_args.next() {
+            let format = if arg == MESSAGE_FORMAT_ARG {
+                cargo_args.next().unwrap_or_default()
+            } else if let Some(format) = arg.strip_prefix(&format!("{MESSAGE_FORMAT_ARG}=")) {
+                format.to_owned()
+            } else {
+                args.push(arg);
+                continue;
+            };
+            warn!(
+                "Ignoring `{MESSAGE_FORMAT_ARG} {format}` of cargo args, builds need JSON messages"
+            );
+        }
+        args
+    }
+
+    /// Arguments of the `cargo` command running given target, built with the same flags as by
+    /// [`Cargo::build_args`], then passed `target_args`
+    pub fn run_args<S>(
+        target: CargoTarget,
+        cargo_args: &[S],
+        profile: Option<&str>,
+        package: Option<&str>,
+        features: &CargoFeatures,
+        target_args: impl IntoIterator<Item = String>,
+    ) -> Vec<String>
+    where
+        S: Display,
+    {
+        let mut args: Vec<String> = vec!["run".to_owned(), "--quiet".to_owned()];
+        Self::push_flags(&mut args, &[target], cargo_args, profile, package, features);
+        args.push("--".to_owned());
+        args.extend(target_args);
+        args
+    }
+
+    fn push_flags<S>(
+        args: &mut Vec<String>,
+        targets: &[CargoTarget],
+        cargo_args: &[S],
+        profile: Option<&str>,
+        package: Option<&str>,
+        features: &CargoFeatures,
+    ) where
+        S: Display,
+    {
+        if let Some(profile) = profile {
+            args.push(format!("--profile={profile}"));
+        }
+        if let Some(package) = package {
+            args.push(format!("--package={package}"));
+        }
+        if !features.features.is_empty() {
+            args.push(format!("--features={}", features.features.join(",")));
+        }
+        if features.no_default_features {
+            args.push("--no-default-features".to_owned());
+        }
+        if features.all_features {
+            args.push("--all-features".to_owned());
+        }
+        // Feature flags may also be given through raw cargo args, cargo refuses duplicates
+        let extra_args = cargo_args
+            .iter()
+            .map(ToString::to_string)
+            .chain(targets.iter().map(CargoTarget::arg));
+        for arg in extra_args {
+            if !args.contains(&arg) {
+                args.push(arg);
+            }
+        }
+    }
+
+    /// Name of the directory, under cargo's target directory, holding artifacts of given profile
+    pub fn profile_dir(profile: Option<&str>) -> &str {
+        match profile {
+            None | Some("dev") | Some("test") => "debug",
+            Some("bench") => "release",
+            Some(profile) => profile,
+        }
+    }
+
+    /// Directory holding build artifacts, honoring `CARGO_TARGET_DIR` and cargo configuration
+    pub async fn target_directory(target_dir: &Path) -> Result<PathBuf> {
+        Ok(Self::export_info(target_dir, true).await?.target_directory)
+    }
+
+    /// Path of given binary once built with given profile
+    pub fn binary_path(cargo_target_dir: &Path, profile: Option<&str>, binary: &str) -> PathBuf {
+        cargo_target_dir
+            .join(Self::profile_dir(profile))
+            .join(format!("{binary}{EXE_SUFFIX}"))
+    }
+
+    /// Path of given example once built with given profile
+    pub fn example_path(cargo_target_dir: &Path, profile: Option<&str>, example: &str) -> PathBuf {
+        cargo_target_dir
+            .join(Self::profile_dir(profile))
+            .join("examples")
+            .join(format!("{example}{EXE_SUFFIX}"))
+    }
+
+    /// Latest modification time of files under `dir`, skipping `excluded` and hidden directories
+    ///
+    /// Entries which can't be read, such as sockets or directories of another user, are skipped.
+    pub async fn sources_modified_at(dir: &Path, excluded: &Path) -> Result<Option<SystemTime>> {
+        let (dir, excluded) = (dir.to_path_buf(), excluded.to_path_buf());
+        tokio::task::spawn_blocking(move || {
+            let entries = fs::read_dir(&dir)?;
+            Ok(Self::entries_modified_at(entries, &excluded))
+        })
+        .await
+        .map_err(|e| Error::new(InnerError::Build(e.to_string())))?
+    }
+
+    fn entries_modified_at(entries: fs::ReadDir, excluded: &Path) -> Option<SystemTime> {
+        let mut latest = None;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let modified_at = if file_type.is_dir() {
+                if path == excluded || entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                match fs::read_dir(&path) {
+                    Ok(entries) => Self::entries_modified_at(entries, excluded),
+                    Err(_) => continue,
+                }
+            } else {
+                entry.metadata().and_then(|m| m.modified()).ok()
+            };
+            latest = latest.max(modified_at);
+        }
+        latest
+    }
+
+    /// Hash of `Cargo.toml` and `Cargo.lock` of given directory, `None` if neither exists
+    ///
+    /// Binaries listed by `cargo metadata` only change along with one of these files.
+    pub fn manifests_hash(target_dir: &Path) -> Result<Option<String>> {
+        let mut hasher = Sha256::new();
+        let mut found = false;
+        for file in ["Cargo.toml", "Cargo.lock"] {
+            let path = target_dir.join(file);
+            if !path.exists() {
+                continue;
+            }
+            found = true;
+            hasher.update(file);
+            hasher.update(fs::read(&path)?);
+        }
+        Ok(found.then(|| hex::encode(hasher.finalize())))
+    }
+
+    /// Whether given binary exists and was built after every source was last modified
+    pub fn is_up_to_date(binary_path: &Path, sources_modified_at: Option<SystemTime>) -> bool {
+        match fs::metadata(binary_path).and_then(|m| m.modified()) {
+            Ok(built_at) => sources_modified_at.is_none_or(|modified_at| built_at > modified_at),
+            Err(_) => false,
+        }
+    }
+
+    async fn export_info(target_dir: &Path, no_deps: bool) -> Result<ExportInfoMinimal> {
+        let mut metadata = Command::new("cargo");
+        metadata.arg("metadata").arg("--format-version=1");
+        if no_deps {
+            metadata.arg("--no-deps");
+        }
+        let metadata = metadata
             .current_dir(target_dir)
             .output()
             .await
             .map_err(Error::with_context(InnerError::Cargo))?;
-        let info: ExportInfoMinimal = serde_json::from_slice(&metadata.stdout).unwrap();
+        if !metadata.status.success() {
+            return Err(Error::new(InnerError::Build(format!(
+                "`cargo metadata` failed: {}",
+                String::from_utf8_lossy(&metadata.stderr).trim()
+            ))));
+        }
+        serde_json::from_slice(&metadata.stdout).map_err(Error::with_context(InnerError::Cargo))
+    }
+
+    pub async fn metadata(target_dir: &Path) -> Result<Vec<SerializedPackage>> {
+        let info = Self::export_info(target_dir, false).await?;
         let ret = info
             .packages
             .into_iter()
@@ -84,9 +325,27 @@ impl Cargo {
     }
 }
 
+/// Message printed on stdout by `cargo build --message-format=json`
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum CargoMessage {
+    /// A crate target was compiled, or was already fresh
+    CompilerArtifact {
+        target: TargetInner,
+        executable: Option<PathBuf>,
+        fresh: bool,
+    },
+    BuildFinished {
+        success: bool,
+    },
+    #[serde(other)]
+    Other,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ExportInfoMinimal {
     pub packages: Vec<SerializedPackage>,
+    pub target_directory: PathBuf,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -103,6 +362,18 @@ pub struct TargetInner {
     pub bin_name: Option<String>,
 }
 
+impl TargetInner {
+    /// Whether this is the crate target built for a process
+    pub fn is(&self, target: &CargoTarget) -> bool {
+        match target {
+            CargoTarget::Bin(name) => self.name == *name && self.kind.contains(&TargetKind::Bin),
+            CargoTarget::Example(name) => {
+                self.name == *name && self.kind.contains(&TargetKind::Example)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum TargetKind {
@@ -110,6 +381,7 @@ pub enum TargetKind {
     Bin,
     Test,
     Bench,
+    Example,
     ExampleLib,
     ExampleBin,
     CustomBuild,
@@ -136,3 +408,165 @@ impl From<SerializedPackage> for BinaryPackage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_path() {
+        let target = Path::new("/tmp/target");
+        assert_eq!(
+            Cargo::binary_path(target, None, "foo"),
+            PathBuf::from("/tmp/target/debug/foo")
+        );
+        assert_eq!(
+            Cargo::binary_path(target, Some("release"), "foo"),
+            PathBuf::from("/tmp/target/release/foo")
+        );
+        assert_eq!(
+            Cargo::binary_path(target, Some("bench"), "foo"),
+            PathBuf::from("/tmp/target/release/foo")
+        );
+        assert_eq!(
+            Cargo::example_path(target, None, "foo"),
+            PathBuf::from("/tmp/target/debug/examples/foo")
+        );
+    }
+
+    #[test]
+    fn build_args() {
+        let features = CargoFeatures {
+            features: vec!["tls".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(
+            Cargo::build_args(
+                &[
+                    CargoTarget::Bin("foo".to_owned()),
+                    CargoTarget::Example("bar".to_owned()),
+                    CargoTarget::Bin("foo".to_owned())
+                ],
+                &["--features=tls", "--message-format=short", "--locked"],
+                Some("release"),
+                Some("server"),
+                &features
+            ),
+            vec![
+                "build",
+                "--message-format=json-render-diagnostics",
+                "--profile=release",
+                "--package=server",
+                "--features=tls",
+                "--locked",
+                "--bin=foo",
+                "--example=bar"
+            ]
+        );
+        assert_eq!(
+            Cargo::build_args(
+                &[CargoTarget::Bin("foo".to_owned())],
+                &["--message-format", "human", "--locked"],
+                None,
+                None,
+                &CargoFeatures::default()
+            ),
+            vec![
+                "build",
+                "--message-format=json-render-diagnostics",
+                "--locked",
+                "--bin=foo"
+            ]
+        );
+        assert_eq!(
+            Cargo::run_args(
+                CargoTarget::Bin("foo".to_owned()),
+                &["--locked"],
+                None,
+                None,
+                &CargoFeatures::default(),
+                ["--port".to_owned(), "80".to_owned()]
+            ),
+            vec!["run", "--quiet", "--locked", "--bin=foo", "--", "--port", "80"]
+        );
+    }
+
+    #[test]
+    fn parse_messages() {
+        let artifact = r#"{"reason":"compiler-artifact","package_id":"ares 0.1.0","target":{"kind":["bin"],"crate_types":["bin"],"name":"ares","src_path":"/tmp/ares/src/main.rs"},"executable":"/tmp/target/debug/ares","fresh":false}"#;
+        assert_eq!(
+            serde_json::from_str::<CargoMessage>(artifact).unwrap(),
+            CargoMessage::CompilerArtifact {
+                target: TargetInner {
+                    kind: vec![TargetKind::Bin],
+                    name: "ares".to_owned(),
+                    bin_name: None,
+                },
+                executable: Some(PathBuf::from("/tmp/target/debug/ares")),
+                fresh: false,
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<CargoMessage>(r#"{"reason":"build-finished","success":false}"#)
+                .unwrap(),
+            CargoMessage::BuildFinished { success: false }
+        );
+        assert_eq!(
+            serde_json::from_str::<CargoMessage>(r#"{"reason":"build-script-executed"}"#).unwrap(),
+            CargoMessage::Other
+        );
+    }
+
+    #[test]
+    fn manifests_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(Cargo::manifests_hash(dir.path()).unwrap(), None);
+        fs::write(dir.path().join("Cargo.toml"), "[workspace]").unwrap();
+        let manifest_hash = Cargo::manifests_hash(dir.path()).unwrap();
+        assert!(manifest_hash.is_some());
+        assert_eq!(Cargo::manifests_hash(dir.path()).unwrap(), manifest_hash);
+        fs::write(dir.path().join("Cargo.lock"), "version = 4").unwrap();
+        assert_ne!(Cargo::manifests_hash(dir.path()).unwrap(), manifest_hash);
+    }
+
+    #[tokio::test]
+    async fn up_to_date_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        fs::create_dir_all(target.join("debug")).unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        let binary = Cargo::binary_path(&target, None, "foo");
+        assert!(!Cargo::is_up_to_date(&binary, None));
+
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        let sources_modified_at = Cargo::sources_modified_at(dir.path(), &target).await.unwrap();
+        assert!(sources_modified_at.is_some());
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        fs::write(&binary, "").unwrap();
+        // Neither the target directory nor hidden directories are sources
+        fs::write(dir.path().join(".git/index"), "").unwrap();
+        assert_eq!(
+            Cargo::sources_modified_at(dir.path(), &target).await.unwrap(),
+            sources_modified_at
+        );
+        assert!(Cargo::is_up_to_date(&binary, sources_modified_at));
+        // Unreadable directories are skipped
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let private = dir.path().join("private");
+            fs::create_dir(&private).unwrap();
+            fs::set_permissions(&private, fs::Permissions::from_mode(0o000)).unwrap();
+            assert_eq!(
+                Cargo::sources_modified_at(dir.path(), &target).await.unwrap(),
+                sources_modified_at
+            );
+        }
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        fs::write(dir.path().join("src/main.rs"), "fn main() { }").unwrap();
+        let sources_modified_at = Cargo::sources_modified_at(dir.path(), &target).await.unwrap();
+        assert!(!Cargo::is_up_to_date(&binary, sources_modified_at));
+    }
+}
@@ -1,3 +1,5 @@
 pub mod cargo;
+pub mod native;
 pub mod pueue;
+pub mod systemd;
 pub mod util;
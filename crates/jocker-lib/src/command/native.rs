@@ -0,0 +1,447 @@
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, File},
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    process::Child,
+    sync::{mpsc::Sender, Mutex},
+    time::{sleep, Instant},
+};
+use tracing::warn;
+
+use crate::{
+    command::util::{self, send_line, shell_command, SHELL},
+    common::ProcessState,
+    error::{filesystem_error, Error, InnerError, Result},
+    scheduler::{timeout_from_env, ScheduledProcess, Scheduler},
+    signal::ProcessSignal,
+    Pid,
+};
+
+const REGISTRY_FILE: &str = "native.json";
+const LOGS_DIR: &str = "logs";
+/// Interval between two checks of whether a stopped process exited
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Creation flag detaching spawned processes from the console signals of `jocker`, the same way
+/// `process_group` does on Unix
+#[cfg(windows)]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+/// Process spawned by the native scheduler, as kept in its registry
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+struct RegisteredProcess {
+    pid: Pid,
+    /// Start time of the process, in seconds since the epoch, as the OS may give its pid to
+    /// another process once it exited
+    started_at: Option<u64>,
+}
+
+/// Scheduler backend spawning processes directly, without relying on an external daemon
+///
+/// Spawned processes are detached in their own process group so they outlive the current
+/// `jocker` invocation. Their PIDs are persisted in the project state directory, next to their
+/// log files, along with their start time: a pid whose process has a different one belongs to
+/// another process, which is never signalled.
+pub(crate) struct Native {
+    group: String,
+    project_dir: PathBuf,
+    /// Processes spawned by this invocation, kept to be able to reap them
+    children: Mutex<HashMap<Pid, Child>>,
+    /// Exit codes of reaped processes spawned by this invocation
+    exit_codes: Mutex<HashMap<Pid, Option<i32>>>,
+    /// Held while the registry is updated, as processes are started concurrently
+    registry_lock: Mutex<()>,
+    /// Longest wait for a process to exit once killed
+    timeout: Duration,
+}
+
+impl Native {
+    pub(crate) fn new(project_id: &str, project_dir: impl Into<PathBuf>) -> Result<Self> {
+        let project_dir = project_dir.into();
+        let logs_dir = project_dir.join(LOGS_DIR);
+        create_dir_all(&logs_dir).map_err(filesystem_error(&logs_dir))?;
+        Ok(Self {
+            group: format!("jocker-{project_id}"),
+            project_dir,
+            children: Mutex::new(HashMap::new()),
+            registry_lock: Mutex::new(()),
+            exit_codes: Mutex::new(HashMap::new()),
+            timeout: timeout_from_env()?,
+        })
+    }
+
+    fn registry_path(&self) -> PathBuf {
+        self.project_dir.join(REGISTRY_FILE)
+    }
+
+    fn log_path(&self, process_name: &str) -> PathBuf {
+        self.project_dir
+            .join(LOGS_DIR)
+            .join(format!("{process_name}.log"))
+    }
+
+    fn registry(&self) -> Result<HashMap<String, RegisteredProcess>> {
+        let path = self.registry_path();
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    fn set_registry(&self, registry: &HashMap<String, RegisteredProcess>) -> Result<()> {
+        serde_json::to_writer(File::create(self.registry_path())?, registry)?;
+        Ok(())
+    }
+
+    async fn is_running(&self, pid: Pid) -> Result<bool> {
+        if self.exit_codes.lock().await.contains_key(&pid) {
+            return Ok(false);
+        }
+        let mut children = self.children.lock().await;
+        if let Some(child) = children.get_mut(&pid) {
+            return Ok(match child.try_wait()? {
+                Some(exit_status) => {
+                    children.remove(&pid);
+                    self.exit_codes.lock().await.insert(pid, exit_status.code());
+                    false
+                }
+                None => true,
+            });
+        }
+        drop(children);
+        // Spawned by another invocation, the pid may have been reused since it exited
+        Ok(match self.started_at(pid)? {
+            Some(started_at) => util::start_time(pid) == Some(started_at),
+            None => util::is_running(pid),
+        })
+    }
+
+    /// Start time of given process, if spawned by this scheduler
+    fn started_at(&self, pid: Pid) -> Result<Option<u64>> {
+        Ok(self
+            .registry()?
+            .into_values()
+            .find(|process| process.pid == pid)
+            .and_then(|process| process.started_at))
+    }
+
+    /// Wait for given process to exit, returning whether it did within `timeout`
+    async fn wait_until_stopped(&self, pid: Pid, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        while self.is_running(pid).await? {
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            sleep(STOP_POLL_INTERVAL).await;
+        }
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl Scheduler for Native {
+    fn group(&self) -> &str {
+        &self.group
+    }
+
+    fn log_file(&self, process_name: &str) -> Option<PathBuf> {
+        Some(self.log_path(process_name))
+    }
+
+    fn describe_start(&self, process_name: &str, command: &str) -> String {
+        let [shell, flag] = SHELL;
+        format!(
+            "{shell} {flag} '{command}' > {}",
+            self.log_path(process_name).display()
+        )
+    }
+
+    async fn start(
+        &self,
+        process_name: String,
+        command: String,
+        path: PathBuf,
+        envs: HashMap<String, String>,
+    ) -> Result<Pid> {
+        let log_file = File::create(self.log_path(&process_name))?;
+        let mut cmd = shell_command(&command);
+        cmd.current_dir(path)
+            .envs(envs)
+            .stdin(Stdio::null())
+            .stdout(log_file.try_clone()?)
+            .stderr(log_file);
+        #[cfg(unix)]
+        cmd.process_group(0);
+        #[cfg(windows)]
+        cmd.creation_flags(CREATE_NEW_PROCESS_GROUP);
+        let child = cmd
+            .spawn()
+            .map_err(Error::with_context(InnerError::Start(format!(
+                "Unable to spawn process {process_name}"
+            ))))?;
+        let pid: Pid = child
+            .id()
+            .ok_or_else(|| {
+                Error::new(InnerError::Start(format!(
+                    "Process {process_name} exited right after being spawned"
+                )))
+            })?
+            .try_into()?;
+        self.children.lock().await.insert(pid, child);
+
+        let _registry = self.registry_lock.lock().await;
+        let mut registry = self.registry()?;
+        registry.insert(
+            process_name,
+            RegisteredProcess {
+                pid,
+                started_at: util::start_time(pid),
+            },
+        );
+        self.set_registry(&registry)?;
+        Ok(pid)
+    }
+
+    /// Without `timeout`, SIGKILL is sent once the scheduler timeout is over
+    async fn stop(&self, pid: Pid, kill: bool, timeout: Option<Duration>) -> Result<()> {
+        if !self.is_running(pid).await? {
+            return Ok(());
+        }
+        if !kill {
+            self.signal(pid, ProcessSignal::Term).await?;
+            if self.wait_until_stopped(pid, timeout.unwrap_or(self.timeout)).await? {
+                return Ok(());
+            }
+            warn!("Process {pid} still runs after SIGTERM, sending it SIGKILL");
+        }
+        match self.signal(pid, ProcessSignal::Kill).await {
+            // Exited in the meantime
+            Err(e) if matches!(e.inner_error, InnerError::ProcessNotRunning(_)) => return Ok(()),
+            res => res?,
+        }
+        if !self.wait_until_stopped(pid, self.timeout).await? {
+            return Err(Error::new(InnerError::Timeout(format!(
+                "Process {pid} still runs {}s after SIGKILL",
+                self.timeout.as_secs()
+            ))));
+        }
+        Ok(())
+    }
+
+    async fn signal(&self, pid: Pid, signal: ProcessSignal) -> Result<()> {
+        if !self.is_running(pid).await? {
+            return Err(Error::new(InnerError::ProcessNotRunning(format!(
+                "Process {pid} is not running anymore, not sending SIG{signal} to it"
+            ))));
+        }
+        // Signal the whole process group, to also reach children of the shell
+        util::signal_group(pid, self.started_at(pid)?, signal).await
+    }
+
+    async fn pause(&self, pid: Pid) -> Result<()> {
+        self.signal(pid, ProcessSignal::Stop).await
+    }
+
+    async fn resume(&self, pid: Pid) -> Result<()> {
+        self.signal(pid, ProcessSignal::Cont).await
+    }
+
+    async fn status(&self) -> Result<HashMap<String, ScheduledProcess>> {
+        let mut status = HashMap::new();
+        for (process_name, RegisteredProcess { pid, .. }) in self.registry()? {
+            let state = if !self.is_running(pid).await? {
+                ProcessState::Stopped
+            } else if util::is_paused(pid) {
+                ProcessState::Paused
+            } else {
+                ProcessState::Running
+            };
+            let exit_code = self.exit_codes.lock().await.get(&pid).copied().flatten();
+            status.insert(
+                process_name,
+                ScheduledProcess {
+                    pid,
+                    state,
+                    exit_code,
+                },
+            );
+        }
+        Ok(status)
+    }
+
+    async fn logs(
+        &self,
+        log_tx: Sender<String>,
+        pid: Pid,
+        lines: Option<usize>,
+        follow: bool,
+    ) -> Result<()> {
+        let process_name = self
+            .registry()?
+            .into_iter()
+            .find(|(_, process)| process.pid == pid)
+            .map(|(name, _)| name)
+            .ok_or_else(|| Error::new(InnerError::ProcessNotFound(vec![pid.to_string()])))?;
+        let mut file = File::open(self.log_path(&process_name))?;
+        let offset = match lines {
+            Some(lines) => util::tail_offset(&mut file, lines)?,
+            None => 0,
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(file);
+
+        let mut line = String::new();
+        while reader.read_line(&mut line)? != 0 {
+            send_line(&log_tx, line.trim_end_matches('\n')).await?;
+            line.clear();
+        }
+        if !follow {
+            return Ok(());
+        }
+
+        let mut pos = reader.stream_position()?;
+        loop {
+            let running = self.is_running(pid).await?;
+            reader.seek(SeekFrom::Start(pos))?;
+            // Only consume complete lines, a partial one will be read on next iteration
+            while reader.read_line(&mut line)? != 0 && line.ends_with('\n') {
+                send_line(&log_tx, line.trim_end_matches('\n')).await?;
+                pos = reader.stream_position()?;
+                line.clear();
+            }
+            line.clear();
+            if !running {
+                break;
+            }
+            sleep(Duration::from_millis(250)).await;
+        }
+        Ok(())
+    }
+
+    async fn clean(&self) -> Result<()> {
+        for process in self.registry()?.into_values() {
+            self.stop(process.pid, true, None).await?;
+        }
+        let registry_path = self.registry_path();
+        if registry_path.exists() {
+            std::fs::remove_file(registry_path)?;
+        }
+        remove_dir_if_exists(&self.project_dir.join(LOGS_DIR))
+    }
+}
+
+fn remove_dir_if_exists(path: &Path) -> Result<()> {
+    if path.exists() {
+        std::fs::remove_dir_all(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn start_logs_stop() {
+        let dir = tempdir().unwrap();
+        let native = Native::new("native-test", dir.path()).unwrap();
+
+        let pid = native
+            .start(
+                "foo".to_owned(),
+                "echo $FOO; sleep 30".to_owned(),
+                dir.path().to_path_buf(),
+                HashMap::from([("FOO".to_owned(), "bar".to_owned())]),
+            )
+            .await
+            .unwrap();
+        let status = native.status().await.unwrap();
+        assert_eq!(status.get("foo").unwrap().pid, pid);
+        assert_eq!(status.get("foo").unwrap().state, ProcessState::Running);
+        // Let the shell print before being stopped
+        while std::fs::metadata(native.log_path("foo")).unwrap().len() == 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        native
+            .stop(pid, false, Some(Duration::from_secs(5)))
+            .await
+            .unwrap();
+        let status = native.status().await.unwrap();
+        assert_eq!(status.get("foo").unwrap().state, ProcessState::Stopped);
+        assert_eq!(status.get("foo").unwrap().exit_code, None);
+
+        let (tx, mut rx) = mpsc::channel(8);
+        native.logs(tx, pid, None, false).await.unwrap();
+        assert_eq!(rx.recv().await, Some("bar".to_owned()));
+        assert_eq!(rx.recv().await, None);
+
+        native.clean().await.unwrap();
+        assert!(native.status().await.unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stop_ignoring_sigterm() {
+        let dir = tempdir().unwrap();
+        let native = Native::new("native-test", dir.path()).unwrap();
+
+        let pid = native
+            .start(
+                "foo".to_owned(),
+                "trap '' TERM; echo ready; sleep 30".to_owned(),
+                dir.path().to_path_buf(),
+                HashMap::new(),
+            )
+            .await
+            .unwrap();
+        // Let the shell ignore SIGTERM before being stopped
+        while std::fs::metadata(native.log_path("foo")).unwrap().len() == 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        let start = Instant::now();
+        native
+            .stop(pid, false, Some(Duration::from_millis(500)))
+            .await
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        let status = native.status().await.unwrap();
+        assert_eq!(status.get("foo").unwrap().state, ProcessState::Stopped);
+
+        native.clean().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reused_pid() {
+        let dir = tempdir().unwrap();
+        let native = Native::new("native-test", dir.path()).unwrap();
+        // The pid of a process which exited now belongs to this test
+        let pid: Pid = std::process::id().try_into().unwrap();
+        native
+            .set_registry(&HashMap::from([(
+                "foo".to_owned(),
+                RegisteredProcess {
+                    pid,
+                    started_at: Some(0),
+                },
+            )]))
+            .unwrap();
+
+        let status = native.status().await.unwrap();
+        assert_eq!(status.get("foo").unwrap().state, ProcessState::Stopped);
+        let err = native.signal(pid, ProcessSignal::Term).await.unwrap_err();
+        assert!(matches!(err.inner_error, InnerError::ProcessNotRunning(_)));
+        native.stop(pid, true, None).await.unwrap();
+    }
+}
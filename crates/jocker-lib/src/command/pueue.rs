@@ -1,133 +1,286 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    env,
+    fs::create_dir_all,
+    future::Future,
+    io::{BufRead, BufReader, ErrorKind},
+    net::TcpListener,
     path::PathBuf,
     process::Stdio,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex as StdMutex,
+    },
     time::Duration,
 };
 
+use async_trait::async_trait;
+use chrono::Local;
 use pueue_lib::{
     network::message::{
-        AddRequest, GroupRequest, KillRequest, LogRequest, ResetRequest, ResetTarget, Signal,
-        StreamRequest, TaskSelection,
+        AddRequest, GroupRequest, KillRequest, LogRequest, ParallelRequest, PauseRequest,
+        ResetRequest, ResetTarget, ShutdownRequest, Signal, StartRequest, StreamRequest,
+        TaskSelection,
     },
-    Client, Group, Request, Response, Settings, Task, TaskStatus,
+    Client, Group, Request, Response, Settings, Task, TaskResult, TaskStatus,
 };
 use snap::read::FrameDecoder;
 use tokio::{
     process::{Child, Command},
     sync::{mpsc::Sender, Mutex},
-    time::sleep,
+    time::{sleep, Instant},
+};
+use tracing::{debug, warn};
+
+use crate::{
+    command::util::{self, send_line},
+    common::{JOCKER, JOCKER_ENV_PUEUE_DAEMON},
+    error::{filesystem_error, lock_error, Error, InnerError, Result},
+    resources::ResourceMonitor,
+    scheduler::{timeout_from_env, ScheduledProcess, Scheduler},
+    signal::ProcessSignal,
+    state::State,
 };
 
-use crate::error::{Error, InnerError, Result};
+const GROUP_PREFIX: &str = "jocker-";
+/// Directory of the embedded `pueued` instance, under the jocker state directory
+pub(crate) const PUEUED_DIR: &str = "pueue";
+/// Longest wait for a freshly started `pueued` to accept connections
+const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_HINT: &str = "Connection to pueued was lost and it couldn't be started again, \
+    make sure `pueued` is installed and runs, or set JOCKER_SCHEDULER=native to do without it";
+/// Interval between two status requests while waiting for `pueued`
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Age under which the last status of `pueued` is reused, instead of being requested again. No
+/// longer than a poll, so that waits see every change.
+const STATUS_TTL: Duration = POLL_INTERVAL;
+
+/// Group of the current stack, named after the project group
+#[derive(Clone)]
+struct StackGroup {
+    name: String,
+    max_parallel: Option<u32>,
+}
 
 pub(crate) struct Pueue {
+    /// Group of the project, holding processes started without a stack
     group: String,
-    client: Mutex<Client>,
+    /// Group new processes are added to when a stack is used
+    stack_group: StdMutex<Option<StackGroup>>,
+    /// Whether this command created the groups, which is only done once it adds a task
+    ///
+    /// Commands merely reading tasks thus leave the state of `pueued` untouched.
+    groups_ensured: AtomicBool,
+    /// Client of `pueued`, connected on first request
+    client: Mutex<Option<Client>>,
+    /// Tasks of the project as of the last status request, along with when they were received
+    ///
+    /// `pueued` serializes the whole state of every group for each status request, while a
+    /// single command requests it many times. Any request changing tasks drops it.
+    snapshot: Mutex<Option<(Instant, HashMap<usize, Task>)>>,
+    /// Longest wait for `pueued` to carry out a request
+    timeout: Duration,
 }
 
 impl Pueue {
-    pub(crate) async fn new(project_id: &str) -> Result<Self> {
-        // Try to start pueued if initial client creation fails
-        let mut client = match Self::client().await {
-            Ok(client) => client,
-            Err(_) => {
-                Pueued::daemonize().await?;
-                Self::client().await?
-            }
-        };
-        let group = Self::init_or_get_group(&mut client, project_id).await?;
+    /// Scheduler of given project, only connecting to `pueued` once a request is sent
+    ///
+    /// Commands which merely read the state thus neither wait for the daemon nor start it.
+    pub(crate) fn new(project_id: &str) -> Result<Self> {
         Ok(Self {
-            group,
-            client: Mutex::new(client),
+            group: format!("{GROUP_PREFIX}{project_id}"),
+            stack_group: StdMutex::new(None),
+            groups_ensured: AtomicBool::new(false),
+            client: Mutex::new(None),
+            snapshot: Mutex::new(None),
+            timeout: timeout_from_env()?,
         })
     }
 
-    pub fn group(&self) -> &str {
-        &self.group
+    /// Client of `pueued`, which is started if it isn't running yet
+    async fn connect() -> Result<Client> {
+        if let Ok(client) = Self::client().await {
+            return Ok(client);
+        }
+        Pueued::daemonize().await?;
+        if PueueDaemon::from_env()? == PueueDaemon::Embedded {
+            warn_global_tasks().await;
+        }
+        // The daemon takes a moment to listen once started
+        let deadline = Instant::now() + DAEMON_START_TIMEOUT;
+        loop {
+            match Self::client().await {
+                Ok(client) => return Ok(client),
+                Err(e) if Instant::now() >= deadline => return Err(e),
+                Err(_) => sleep(POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Connect again to `pueued` after losing it, starting it again if it died
+    ///
+    /// Groups of the project are restored in case the daemon lost its state, if this command
+    /// created them. Tasks it kept are matched back to processes by their label on the next status
+    /// request.
+    async fn reconnect(&self) -> Result<Client> {
+        warn!("Lost connection to pueued, reconnecting ...");
+        let mut client = Self::connect()
+            .await
+            .map_err(|e| e.add_context(RECONNECT_HINT))?;
+        if self.groups_ensured.load(Ordering::Relaxed) {
+            self.ensure_groups(&mut client).await?;
+        }
+        debug!(group = %self.group, "Reconnected to pueued");
+        Ok(client)
+    }
+
+    /// Create the groups of the project and of the current stack, unless they already exist
+    async fn ensure_groups(&self, client: &mut Client) -> Result<()> {
+        ensure_group(client, &self.group, None).await?;
+        if let Some(stack_group) = self.stack_group()? {
+            ensure_group(client, &stack_group.name, stack_group.max_parallel).await?;
+        }
+        Ok(())
+    }
+
+    /// Same as [`Pueue::ensure_groups`], once per command, connecting to `pueued` if needed
+    async fn ensure_groups_once(&self) -> Result<()> {
+        if self.groups_ensured.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mut client = self.client.lock().await;
+        let connected = match client.as_mut() {
+            Some(connected) => connected,
+            None => client.insert(Self::connect().await?),
+        };
+        match self.ensure_groups(connected).await {
+            Err(e) if is_disconnected(&e) => {
+                *connected = self.reconnect().await?;
+                self.ensure_groups(connected).await?;
+            }
+            res => res?,
+        }
+        self.groups_ensured.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Send given request to `pueued` and receive its response, reconnecting once if the
+    /// connection was lost
+    ///
+    /// The daemon is started by the first request if it isn't running. Requests other than
+    /// status ones drop the status snapshot, as they may change tasks.
+    ///
+    /// Only requests reading the state are sent again after reconnecting: `pueued` may have
+    /// carried out the other ones before the connection dropped. Their disconnection error is
+    /// returned, for callers to check the state before sending them again.
+    async fn request(&self, request: impl Into<Request>) -> Result<Response> {
+        let request = request.into();
+        if !matches!(request, Request::Status) {
+            *self.snapshot.lock().await = None;
+        }
+        let mut client = self.client.lock().await;
+        let connected = match client.as_mut() {
+            Some(connected) => connected,
+            None => client.insert(Self::connect().await?),
+        };
+        match send(connected, request.clone()).await {
+            Err(e) if is_disconnected(&e) => {
+                *connected = self.reconnect().await?;
+                if !is_read_only(&request) {
+                    return Err(e);
+                }
+                send(connected, request)
+                    .await
+                    .map_err(|e| e.add_context(RECONNECT_HINT))
+            }
+            response => response,
+        }
+    }
+
+    /// Same as [`Pueue::request`], without starting `pueued`: `None` if it isn't running
+    ///
+    /// Meant for requests reading tasks, as no task runs without the daemon.
+    async fn request_if_running(&self, request: impl Into<Request>) -> Result<Option<Response>> {
+        {
+            let mut client = self.client.lock().await;
+            if client.is_none() {
+                let Ok(connected) = Self::client().await else {
+                    return Ok(None);
+                };
+                *client = Some(connected);
+            }
+        }
+        self.request(request).await.map(Some)
     }
 
     pub(crate) async fn client() -> Result<Client> {
-        let (settings, _) = Settings::read(&None)?;
+        let (settings, _) = Settings::read(&PueueDaemon::from_env()?.config_file()?)?;
         let client = Client::new(settings, true)
             .await
             .map_err(|e| InnerError::Pueue(pueue_lib::Error::Generic(e.to_string())))?;
         Ok(client)
     }
 
-    pub(crate) async fn start(
-        &self,
-        process_name: String,
-        command: String,
-        path: PathBuf,
-        envs: HashMap<String, String>,
-    ) -> Result<usize> {
-        if let Some(process) = self.processes().await?.get(&process_name) {
-            self.remove(process.0).await?;
+    /// Task of each process, indexed by process name
+    ///
+    /// Tasks of previous runs keep the label of their process until they are removed. The task
+    /// which is running or queued wins over them, the latest one otherwise, so that a task left
+    /// running by an interrupted start is found again.
+    async fn processes(&self) -> Result<HashMap<String, (usize, TaskStatus)>> {
+        let mut processes: HashMap<String, (usize, TaskStatus)> = HashMap::new();
+        for task in self.processes_by_pid().await?.into_values() {
+            let label = task.label.unwrap_or("NONE".to_string());
+            let rank = |id: usize, status: &TaskStatus| (is_active(status), id);
+            if processes
+                .get(&label)
+                .is_some_and(|(id, status)| rank(*id, status) > rank(task.id, &task.status))
+            {
+                continue;
+            }
+            processes.insert(label, (task.id, task.status));
         }
-        let mut client = self.client.lock().await;
-        client
-            .send_request(Request::Add(AddRequest {
-                command,
-                path,
-                envs,
-                group: self.group.clone(),
-                label: Some(process_name.clone()),
-                ..Default::default()
-            }))
-            .await?;
-        let rsp = client.receive_response().await?;
-        let task_id = match rsp {
-            Response::AddedTask(task) => task.task_id,
-            e => {
+        Ok(processes)
+    }
+
+    /// Tasks of the project, indexed by ID, reusing the snapshot of a recent status request
+    ///
+    /// The snapshot is held while requesting a new one, concurrent callers share its result.
+    async fn processes_by_pid(&self) -> Result<HashMap<usize, Task>> {
+        let mut snapshot = self.snapshot.lock().await;
+        if let Some((received_at, tasks)) = snapshot.as_ref() {
+            if received_at.elapsed() < STATUS_TTL {
+                return Ok(tasks.clone());
+            }
+        }
+        let tasks: HashMap<usize, Task> = match self.request_if_running(Request::Status).await? {
+            None => HashMap::new(),
+            Some(Response::Status(state)) => state
+                .tasks
+                .into_iter()
+                .filter(|entry| self.owns_group(&entry.1.group))
+                .map(|entry| (entry.1.id, entry.1))
+                .collect(),
+            Some(e) => {
                 return Err(Error::new(InnerError::Pueue(pueue_lib::Error::Generic(
                     format!("{:?}", e),
                 ))))
             }
         };
-        drop(client);
-        while !matches!(
-            self.process_status(&task_id).await?,
-            Some(TaskStatus::Running { .. })
-        ) {
-            sleep(Duration::from_millis(100)).await;
-        }
-        Ok(task_id)
+        *snapshot = Some((Instant::now(), tasks.clone()));
+        Ok(tasks)
     }
 
-    pub(crate) async fn processes(&self) -> Result<HashMap<String, (usize, TaskStatus)>> {
-        Ok(self
-            .processes_by_pid()
-            .await?
-            .into_iter()
-            .map(|entry| {
-                (
-                    entry.1.label.clone().unwrap_or("NONE".to_string()),
-                    (entry.1.id, entry.1.status.clone()),
-                )
-            })
-            .collect())
+    /// Whether given group is the project group or one of its stack groups
+    fn owns_group(&self, group: &str) -> bool {
+        group == self.group
+            || group
+                .strip_prefix(&self.group)
+                .is_some_and(|stack| stack.starts_with('-'))
     }
 
-    async fn processes_by_pid(&self) -> Result<HashMap<usize, Task>> {
-        let mut client = self.client.lock().await;
-        client.send_request(Request::Status).await?;
-        let rsp = client.receive_response().await?;
-        match rsp {
-            Response::Status(state) => {
-                let task_ids = state.task_ids_in_group(&self.group);
-                let tasks = state
-                    .tasks
-                    .into_iter()
-                    .filter(|entry| task_ids.contains(&entry.0))
-                    .map(|entry| (entry.1.id, entry.1))
-                    .collect();
-                Ok(tasks)
-            }
-            e => Err(Error::new(InnerError::Pueue(pueue_lib::Error::Generic(
-                format!("{:?}", e),
-            )))),
-        }
+    fn stack_group(&self) -> Result<Option<StackGroup>> {
+        Ok(self.stack_group.lock().map_err(lock_error)?.clone())
     }
 
     async fn process_status(&self, pid: &usize) -> Result<Option<TaskStatus>> {
@@ -138,50 +291,23 @@ impl Pueue {
             .map(|p| p.status.clone()))
     }
 
-    pub(crate) async fn logs(
-        &self,
-        log_tx: Sender<String>,
-        process_prefix: &str,
-        pid: usize,
-        lines: Option<usize>,
-        follow: bool,
-    ) -> Result<()> {
-        match follow {
-            true => self.follow(log_tx, process_prefix, pid, lines).await,
-            false => self.log(log_tx, process_prefix, pid, lines).await,
-        }
-    }
-
-    async fn log(
-        &self,
-        log_tx: Sender<String>,
-        process_prefix: &str,
-        pid: usize,
-        lines: Option<usize>,
-    ) -> Result<()> {
-        let mut client = self.client.lock().await;
-
-        client
-            .send_request(LogRequest {
+    async fn log(&self, log_tx: Sender<String>, pid: usize, lines: Option<usize>) -> Result<()> {
+        let response = self
+            .request(LogRequest {
                 tasks: TaskSelection::TaskIds(vec![pid]),
                 lines,
                 send_logs: true,
             })
             .await?;
-        let response = client.receive_response().await?;
         match response {
             Response::Log(response) => {
                 for (_, text) in response {
-                    let bytes = text.output.clone().unwrap_or_default();
-                    let mut decompressor = FrameDecoder::new(bytes.as_slice());
-                    let mut buf = vec![];
-                    std::io::copy(&mut decompressor, &mut buf).unwrap();
-                    let content = String::from_utf8(buf)?;
-                    for line in content.lines() {
-                        log_tx
-                            .send(format!("{process_prefix}{}", line))
-                            .await
-                            .unwrap();
+                    let bytes = text.output.unwrap_or_default();
+                    // Outputs are decompressed as lines are sent, they may weigh hundreds of MB
+                    // once decompressed. `pueued` already cut them to the requested lines.
+                    let reader = BufReader::new(FrameDecoder::new(bytes.as_slice()));
+                    for line in reader.lines() {
+                        send_line(&log_tx, &line?).await?;
                     }
                 }
             }
@@ -194,13 +320,7 @@ impl Pueue {
         Ok(())
     }
 
-    async fn follow(
-        &self,
-        log_tx: Sender<String>,
-        process_prefix: &str,
-        pid: usize,
-        lines: Option<usize>,
-    ) -> Result<()> {
+    async fn follow(&self, log_tx: Sender<String>, pid: usize, lines: Option<usize>) -> Result<()> {
         // Create its own client to avoid blocking
         let mut client = Self::client().await?;
         client
@@ -216,10 +336,7 @@ impl Pueue {
                 Response::Stream(response) => {
                     for (_, text) in response.logs {
                         for line in text.lines() {
-                            log_tx
-                                .send(format!("{process_prefix}{}", line))
-                                .await
-                                .unwrap();
+                            send_line(&log_tx, line).await?;
                         }
                     }
                 }
@@ -241,89 +358,499 @@ impl Pueue {
         Ok(())
     }
 
-    pub(crate) async fn stop(&self, pid: usize, kill: bool) -> Result<()> {
+    async fn kill(&self, pid: usize, kill: bool) -> Result<()> {
         let signal = Some(if kill {
             Signal::SigKill
         } else {
             Signal::SigTerm
         });
-        let mut client = self.client.lock().await;
-        client
-            .send_request(Request::Kill(KillRequest {
+        let rsp = self
+            .request(Request::Kill(KillRequest {
                 tasks: TaskSelection::TaskIds(vec![pid]),
                 signal,
             }))
             .await?;
-        let rsp = client.receive_response().await?;
-        if !rsp.success() {
-            return Err(Error::new(InnerError::Pueue(pueue_lib::Error::Generic(
-                format!("{:?}", rsp),
+        check_success(rsp)
+    }
+
+    /// Send given signal to every OS process of given task, children of its shell included
+    ///
+    /// Processes are found through the identifier they inherit, as `pueued` doesn't tell the PID
+    /// of tasks.
+    async fn signal_os_processes(&self, pid: usize, signal: ProcessSignal) -> Result<()> {
+        let label = self
+            .processes_by_pid()
+            .await?
+            .remove(&pid)
+            .and_then(|task| task.label)
+            .ok_or_else(|| Error::new(InnerError::ProcessNotFound(vec![pid.to_string()])))?;
+        let os_pids = ResourceMonitor::new()
+            .pids(&self.group)
+            .remove(&label)
+            .unwrap_or_default();
+        if os_pids.is_empty() {
+            return Err(Error::new(InnerError::ProcessNotRunning(format!(
+                "Pueue task {pid} has no running process"
             ))));
         }
-        drop(client);
-        while !matches!(
-            self.process_status(&pid).await?,
-            Some(TaskStatus::Done { .. })
-        ) {
-            sleep(Duration::from_millis(100)).await;
+        for os_pid in os_pids {
+            util::signal(os_pid as usize, signal).await?;
         }
         Ok(())
     }
 
+    async fn wait_until_done(&self, pid: usize, timeout: Duration) -> Result<()> {
+        self.wait_for_task(pid, "done", timeout, |status| {
+            matches!(status, Some(TaskStatus::Done { .. }))
+        })
+        .await
+    }
+
+    /// Poll the status of given task until `reached` holds, failing after `timeout`
+    ///
+    /// `pueued` doesn't push status updates. Following the log of a task is the only stream it
+    /// offers, which closes once the task is done, but the daemon checks that every second: polling
+    /// is faster.
+    async fn wait_for_task(
+        &self,
+        pid: usize,
+        expected: &str,
+        timeout: Duration,
+        reached: impl Fn(Option<&TaskStatus>) -> bool,
+    ) -> Result<()> {
+        poll_until(
+            timeout,
+            || self.process_status(&pid),
+            |status| reached(status.as_ref()),
+            |status| {
+                let status = status.map_or("removed".to_owned(), |status| format!("{status:?}"));
+                format!(
+                    "Pueue task {pid} is not {expected} after {}s, last status: {status}",
+                    timeout.as_secs()
+                )
+            },
+        )
+        .await
+    }
+
     async fn remove(&self, pid: usize) -> Result<()> {
-        let mut client = self.client.lock().await;
-        client.send_request(Request::Remove(vec![pid])).await?;
-        let rsp = client.receive_response().await?;
-        if !rsp.success() {
-            return Err(Error::new(InnerError::Pueue(pueue_lib::Error::Generic(
-                format!("{:?}", rsp),
-            ))));
+        check_success(self.request(Request::Remove(vec![pid])).await?)?;
+        self.wait_for_task(pid, "removed", self.timeout, |status| status.is_none())
+            .await
+    }
+
+    /// Reset and remove every jocker group left in `pueued`, returning their names
+    ///
+    /// Nothing is done when `pueued` isn't running, as it has no group to clean then.
+    pub(crate) async fn clean_all_groups() -> Result<Vec<String>> {
+        let Ok(mut client) = Self::client().await else {
+            return Ok(vec![]);
+        };
+        let jocker_groups: Vec<String> = groups(&mut client)
+            .await?
+            .into_keys()
+            .filter(|group| group.starts_with(GROUP_PREFIX))
+            .collect();
+        let mut cleaned_groups: HashSet<String> = HashSet::new();
+        // Groups are sorted, a project group thus comes before its stack groups which are cleaned
+        // along with it
+        for group in &jocker_groups {
+            if cleaned_groups.contains(group) {
+                continue;
+            }
+            let pueue = Self {
+                group: group.clone(),
+                stack_group: StdMutex::new(None),
+                groups_ensured: AtomicBool::new(false),
+                client: Mutex::new(Some(Self::client().await?)),
+                snapshot: Mutex::new(None),
+                timeout: timeout_from_env()?,
+            };
+            cleaned_groups.extend(pueue.project_groups().await?);
+            pueue.clean().await?;
         }
-        drop(client);
-        while self.process_status(&pid).await?.is_some() {
-            sleep(Duration::from_millis(100)).await;
+        Ok(jocker_groups)
+    }
+
+    async fn reset_groups(&self, groups: &[String]) -> Result<()> {
+        check_success(
+            self.request(ResetRequest {
+                target: ResetTarget::Groups(groups.to_vec()),
+            })
+            .await?,
+        )?;
+        poll_until(
+            self.timeout,
+            || self.processes(),
+            |remaining| remaining.is_empty(),
+            |remaining| {
+                let mut remaining: Vec<String> = remaining.into_keys().collect();
+                remaining.sort();
+                format!(
+                    "Pueue groups {} are not reset after {}s, tasks left: {}",
+                    groups.join(", "),
+                    self.timeout.as_secs(),
+                    remaining.join(", ")
+                )
+            },
+        )
+        .await
+    }
+
+    /// Groups of the project and of its stacks, none if `pueued` isn't running
+    async fn project_groups(&self) -> Result<Vec<String>> {
+        let groups = match self.request_if_running(GroupRequest::List).await? {
+            None => return Ok(vec![]),
+            Some(Response::Group(rsp)) => rsp.groups,
+            Some(other) => {
+                return Err(Error::new(InnerError::Pueue(pueue_lib::Error::Generic(
+                    format!("{:?}", other),
+                ))))
+            }
+        };
+        Ok(groups
+            .into_keys()
+            .filter(|group| self.owns_group(group))
+            .collect())
+    }
+
+    async fn remove_group(&self, group: &str) -> Result<()> {
+        check_success(self.request(GroupRequest::Remove(group.to_owned())).await?)
+    }
+}
+
+#[async_trait]
+impl Scheduler for Pueue {
+    fn group(&self) -> &str {
+        &self.group
+    }
+
+    fn describe_start(&self, process_name: &str, command: &str) -> String {
+        let group = match self.stack_group() {
+            Ok(Some(stack_group)) => stack_group.name,
+            _ => self.group.clone(),
+        };
+        format!("pueue add --group {group} --label {process_name} -- {command}")
+    }
+
+    /// The group of given stack is created along with the project one by the first started task,
+    /// or updated right away if tasks were already started, in case its limit of parallel tasks
+    /// changed
+    async fn use_stack(&self, stack: &str, max_parallel: Option<u32>) -> Result<()> {
+        let name = format!("{}-{stack}", self.group);
+        *self.stack_group.lock().map_err(lock_error)? = Some(StackGroup {
+            name: name.clone(),
+            max_parallel,
+        });
+        if !self.groups_ensured.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mut client = self.client.lock().await;
+        if let Some(connected) = client.as_mut() {
+            match ensure_group(connected, &name, max_parallel).await {
+                // Reconnecting lists groups again before creating the missing ones, the stack
+                // group included, rather than sending the same request blindly
+                Err(e) if is_disconnected(&e) => *connected = self.reconnect().await?,
+                res => res?,
+            }
         }
         Ok(())
     }
 
-    pub(crate) async fn clean(self) -> Result<()> {
-        self.reset_group(&self.group).await?;
-        self.remove_group(&self.group).await
+    async fn start(
+        &self,
+        process_name: String,
+        command: String,
+        path: PathBuf,
+        envs: HashMap<String, String>,
+    ) -> Result<usize> {
+        self.ensure_groups_once().await?;
+        if let Some(process) = self.processes().await?.get(&process_name) {
+            self.remove(process.0).await?;
+        }
+        let stack_group = self.stack_group()?;
+        // Tasks of a limited stack may wait for a free slot
+        let queueing = stack_group.as_ref().is_some_and(|g| g.max_parallel.is_some());
+        let group = stack_group.map_or_else(|| self.group.clone(), |g| g.name);
+        let add = Request::Add(AddRequest {
+            command,
+            path,
+            envs,
+            group,
+            label: Some(process_name.clone()),
+            ..Default::default()
+        });
+        let rsp = match self.request(add.clone()).await {
+            Err(e) if is_disconnected(&e) => {
+                // The task may have been added before the connection dropped, the previous task
+                // of the process was removed so an active one with its label is the new one
+                let added = self
+                    .processes()
+                    .await?
+                    .remove(&process_name)
+                    .filter(|(_, status)| is_active(status));
+                match added {
+                    Some((task_id, _)) => Ok(task_id),
+                    None => self.request(add).await.map_err(|e| e.add_context(RECONNECT_HINT)),
+                }
+            }
+            rsp => rsp.map(Err),
+        };
+        let task_id = match rsp? {
+            Ok(task_id) => task_id,
+            Err(Response::AddedTask(task)) => task.task_id,
+            Err(e) => {
+                return Err(Error::new(InnerError::Pueue(pueue_lib::Error::Generic(
+                    format!("{:?}", e),
+                ))))
+            }
+        };
+        self.wait_for_task(task_id, "running", self.timeout, |status| match status {
+            // Short lived tasks may be done already, their exit is caught by the next refresh
+            Some(TaskStatus::Running { .. } | TaskStatus::Done { .. }) => true,
+            Some(TaskStatus::Queued { .. }) => queueing,
+            _ => false,
+        })
+        .await?;
+        Ok(task_id)
     }
 
-    async fn init_or_get_group(client: &mut Client, project_id: &str) -> Result<String> {
-        let group = format!("jocker-{project_id}");
-        if !groups(client).await?.contains_key(&group) {
-            add_group(client, &group).await?;
+    async fn stop(&self, pid: usize, kill: bool, timeout: Option<Duration>) -> Result<()> {
+        // A task waiting for a slot of its stack never ran, it only has to be dequeued
+        if let Some(TaskStatus::Queued { .. }) = self.process_status(&pid).await? {
+            return self.remove(pid).await;
         }
-        Ok(group)
+        self.kill(pid, kill).await?;
+        if let Some(timeout) = timeout.filter(|_| !kill) {
+            // Escalating to SIGKILL once `timeout` is over mustn't be preempted by a timeout error
+            let wait = self.wait_until_done(pid, timeout + self.timeout);
+            match tokio::time::timeout(timeout, wait).await {
+                Ok(res) => return res,
+                Err(_) => self.kill(pid, true).await?,
+            }
+        }
+        self.wait_until_done(pid, self.timeout).await
     }
 
-    async fn reset_group(&self, group: &str) -> Result<()> {
-        let mut client = self.client.lock().await;
-        client
-            .send_request(ResetRequest {
-                target: ResetTarget::Groups(vec![group.to_owned()]),
-            })
+    async fn signal(&self, pid: usize, signal: ProcessSignal) -> Result<()> {
+        let signal = match signal {
+            ProcessSignal::Int => Signal::SigInt,
+            ProcessSignal::Kill => Signal::SigKill,
+            ProcessSignal::Term => Signal::SigTerm,
+            ProcessSignal::Cont => Signal::SigCont,
+            ProcessSignal::Stop => Signal::SigStop,
+            // `pueued` only forwards the signals above
+            _ => return self.signal_os_processes(pid, signal).await,
+        };
+        let rsp = self
+            .request(Request::Kill(KillRequest {
+                tasks: TaskSelection::TaskIds(vec![pid]),
+                signal: Some(signal),
+            }))
             .await?;
-        let response = client.receive_response().await?;
-        if !response.success() {
-            return Err(Error::new(InnerError::Pueue(pueue_lib::Error::Generic(
-                format!("{:?}", response),
-            ))));
+        check_success(rsp)
+    }
+
+    async fn pause(&self, pid: usize) -> Result<()> {
+        let rsp = self
+            .request(Request::Pause(PauseRequest {
+                tasks: TaskSelection::TaskIds(vec![pid]),
+                wait: false,
+            }))
+            .await?;
+        check_success(rsp)?;
+        self.wait_for_task(pid, "paused", self.timeout, |status| {
+            matches!(status, Some(TaskStatus::Paused { .. } | TaskStatus::Done { .. }))
+        })
+        .await
+    }
+
+    async fn resume(&self, pid: usize) -> Result<()> {
+        let rsp = self
+            .request(Request::Start(StartRequest {
+                tasks: TaskSelection::TaskIds(vec![pid]),
+            }))
+            .await?;
+        check_success(rsp)?;
+        self.wait_for_task(pid, "running", self.timeout, |status| {
+            matches!(status, Some(TaskStatus::Running { .. } | TaskStatus::Done { .. }))
+        })
+        .await
+    }
+
+    async fn status(&self) -> Result<HashMap<String, ScheduledProcess>> {
+        Ok(self
+            .processes()
+            .await?
+            .into_iter()
+            .map(|(name, (pid, status))| {
+                let exit_code = match &status {
+                    TaskStatus::Done {
+                        result: TaskResult::Success,
+                        ..
+                    } => Some(0),
+                    TaskStatus::Done {
+                        result: TaskResult::Failed(code),
+                        ..
+                    } => Some(*code),
+                    _ => None,
+                };
+                (
+                    name,
+                    ScheduledProcess {
+                        pid,
+                        state: status.into(),
+                        exit_code,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    async fn prune(&self, kept: &HashSet<usize>, older_than: Duration) -> Result<Vec<usize>> {
+        let mut pids: Vec<usize> = self
+            .processes_by_pid()
+            .await?
+            .into_values()
+            .filter(|task| !kept.contains(&task.id))
+            .filter(|task| match &task.status {
+                TaskStatus::Done { end, .. } => (Local::now() - *end)
+                    .to_std()
+                    .is_ok_and(|age| age >= older_than),
+                _ => false,
+            })
+            .map(|task| task.id)
+            .collect();
+        if pids.is_empty() {
+            return Ok(pids);
         }
-        drop(client);
-        while !self.processes().await?.is_empty() {
-            sleep(Duration::from_millis(100)).await;
-            // TODO: Handle timeout
+        pids.sort();
+        check_success(self.request(Request::Remove(pids.clone())).await?)?;
+        Ok(pids)
+    }
+
+    async fn logs(
+        &self,
+        log_tx: Sender<String>,
+        pid: usize,
+        lines: Option<usize>,
+        follow: bool,
+    ) -> Result<()> {
+        match follow {
+            true => self.follow(log_tx, pid, lines).await,
+            false => self.log(log_tx, pid, lines).await,
+        }
+    }
+
+    async fn clean(&self) -> Result<()> {
+        let groups = self.project_groups().await?;
+        if groups.is_empty() {
+            return Ok(());
+        }
+        self.reset_groups(&groups).await?;
+        for group in &groups {
+            self.remove_group(group).await?;
         }
         Ok(())
     }
+}
 
-    async fn remove_group(&self, group: &str) -> Result<()> {
-        let mut client = self.client.lock().await;
+/// `pueued` instance processes are delegated to
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PueueDaemon {
+    /// Instance owned by jocker, keeping its socket and state under the jocker state directory
+    #[default]
+    Embedded,
+    /// Daemon of the user, configured by the global `pueue` settings
+    Global,
+}
+
+impl FromStr for PueueDaemon {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "embedded" => Ok(Self::Embedded),
+            "global" => Ok(Self::Global),
+            _ => Err(Error::new(InnerError::Parse(format!(
+                "{JOCKER_ENV_PUEUE_DAEMON}={s}"
+            )))),
+        }
+    }
+}
+
+impl PueueDaemon {
+    pub(crate) fn from_env() -> Result<Self> {
+        match env::var(JOCKER_ENV_PUEUE_DAEMON) {
+            Ok(daemon) => daemon.parse(),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Settings file of the daemon, `None` standing for the global one found by `pueue` itself
+    ///
+    /// Settings of the embedded instance are written on first use.
+    fn config_file(self) -> Result<Option<PathBuf>> {
+        if self == Self::Global {
+            return Ok(None);
+        }
+        let dir = State::state_home()?.join(JOCKER).join(PUEUED_DIR);
+        let config_file = dir.join("pueue.yml");
+        if !config_file.exists() {
+            create_dir_all(&dir).map_err(filesystem_error(&dir))?;
+            let mut settings = Settings::default();
+            settings.shared.pueue_directory = Some(dir.clone());
+            settings.shared.runtime_directory = Some(dir.clone());
+            #[cfg(unix)]
+            {
+                settings.shared.unix_socket_path = Some(dir.join("pueue.socket"));
+            }
+            settings.shared.pid_path = Some(dir.join("pueue.pid"));
+            // Kept once written, so that every command finds the instance listening on TCP
+            settings.shared.port = free_port()?.to_string();
+            settings.save(&Some(config_file.clone()))?;
+        }
+        Ok(Some(config_file))
+    }
+}
+
+pub(crate) struct Pueued;
+
+impl Pueued {
+    /// Launch `pueued` as a background daemon
+    pub async fn daemonize() -> Result<Child> {
+        let mut build = Command::new("pueued");
+        if let Some(config_file) = PueueDaemon::from_env()?.config_file()? {
+            build.arg("--config").arg(config_file);
+        }
+        #[cfg(unix)]
+        build
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .arg("-d");
+        // `pueued` can't daemonize itself on Windows, it is left running in the background instead
+        #[cfg(windows)]
+        build.stdout(Stdio::null()).stderr(Stdio::null());
+        let build = build.spawn().map_err(|e| {
+            let detail = match e.kind() {
+                ErrorKind::NotFound => "`pueued` is not installed, or not in PATH".to_owned(),
+                _ => format!("Unable to start `pueued` command: {e}"),
+            };
+            Error::new(InnerError::Pueue(pueue_lib::Error::Generic(detail)))
+        })?;
+        Ok(build)
+    }
+
+    /// Stop the embedded `pueued` instance if it is running, leaving the global one alone
+    pub async fn shutdown_embedded() -> Result<bool> {
+        if PueueDaemon::from_env()? != PueueDaemon::Embedded {
+            return Ok(false);
+        }
+        let Ok(mut client) = Pueue::client().await else {
+            return Ok(false);
+        };
         client
-            .send_request(GroupRequest::Remove(group.to_owned()))
+            .send_request(Request::DaemonShutdown(ShutdownRequest::Graceful))
             .await?;
         let response = client.receive_response().await?;
         if !response.success() {
@@ -331,25 +858,102 @@ impl Pueue {
                 format!("{:?}", response),
             ))));
         }
-        Ok(())
+        Ok(true)
     }
 }
 
-pub(crate) struct Pueued;
+/// Free TCP port of the loopback interface, not to clash with the global `pueued` or another
+/// program listening on TCP
+fn free_port() -> Result<u16> {
+    Ok(TcpListener::bind(("127.0.0.1", 0))?.local_addr()?.port())
+}
 
-impl Pueued {
-    /// Launch `pueued` as a background daemon
-    pub async fn daemonize() -> Result<Child> {
-        let mut build = Command::new("pueued");
-        build.stdout(Stdio::piped()).stderr(Stdio::piped());
-        build.arg("-d");
-        let build = build
-            .spawn()
-            .map_err(Error::with_context(InnerError::Pueue(
-                pueue_lib::Error::Generic("Unable to start `pueued -d` command".to_string()),
-            )))?;
-        Ok(build)
+/// Warn about processes left running in the global `pueued` by an earlier version of jocker
+///
+/// They were started before jocker got its embedded instance, which doesn't know about them, and
+/// still run under the group of their project. Nothing is done if the global daemon isn't running.
+async fn warn_global_tasks() {
+    let Ok((settings, _)) = Settings::read(&None) else {
+        return;
+    };
+    let Ok(mut client) = Client::new(settings, true).await else {
+        return;
+    };
+    let Ok(Response::Status(state)) = send(&mut client, Request::Status).await else {
+        return;
+    };
+    let groups: BTreeSet<String> = state
+        .tasks
+        .into_values()
+        .filter(|task| task.group.starts_with(GROUP_PREFIX) && is_active(&task.status))
+        .map(|task| task.group)
+        .collect();
+    if !groups.is_empty() {
+        warn!(
+            "Processes started by an earlier jocker still run in the global pueued, in groups {}. \
+            Stop them from their project with `{JOCKER_ENV_PUEUE_DAEMON}=global jocker stop`",
+            groups.into_iter().collect::<Vec<_>>().join(", ")
+        );
+    }
+}
+
+async fn send(client: &mut Client, request: Request) -> Result<Response> {
+    client.send_request(request).await?;
+    Ok(client.receive_response().await?)
+}
+
+/// Whether given task is running or waiting to
+/// Request `status` every [`POLL_INTERVAL`] until `reached` holds for it, failing with a
+/// [`InnerError::Timeout`] described by `timed_out` from the last status after `timeout`
+async fn poll_until<T, F>(
+    timeout: Duration,
+    mut status: impl FnMut() -> F,
+    reached: impl Fn(&T) -> bool,
+    timed_out: impl FnOnce(T) -> String,
+) -> Result<()>
+where
+    F: Future<Output = Result<T>>,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        let current = status().await?;
+        if reached(&current) {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(Error::new(InnerError::Timeout(timed_out(current))));
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn is_active(status: &TaskStatus) -> bool {
+    matches!(
+        status,
+        TaskStatus::Running { .. } | TaskStatus::Queued { .. } | TaskStatus::Paused { .. }
+    )
+}
+
+/// Whether given request only reads the state of `pueued`, so that sending it twice is harmless
+fn is_read_only(request: &Request) -> bool {
+    matches!(request, Request::Status | Request::Log(_) | Request::Stream(_))
+}
+
+/// Whether given error comes from the connection to `pueued` being lost
+fn is_disconnected(error: &Error) -> bool {
+    matches!(
+        error.inner_error,
+        InnerError::Pueue(pueue_lib::Error::Connection(_) | pueue_lib::Error::IoError(..))
+    )
+}
+
+fn check_success(response: Response) -> Result<()> {
+    if !response.success() {
+        return Err(Error::new(InnerError::Pueue(pueue_lib::Error::Generic(
+            format!("{:?}", response),
+        ))));
     }
+    Ok(())
 }
 
 // Groups
@@ -364,11 +968,47 @@ async fn groups(client: &mut Client) -> Result<BTreeMap<String, Group>> {
     }
 }
 
-async fn add_group(client: &mut Client, group: &str) -> Result<()> {
+/// Add given group if missing, or update its limit of parallel tasks if it changed
+async fn ensure_group(client: &mut Client, group: &str, max_parallel: Option<u32>) -> Result<()> {
+    match groups(client).await?.get(group) {
+        None => add_group(client, group, max_parallel).await,
+        Some(existing) if existing.parallel_tasks != parallel_tasks(max_parallel) => {
+            set_parallel_tasks(client, group, max_parallel).await
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// Limit of parallel tasks of a group as understood by `pueued`, 0 being unlimited
+fn parallel_tasks(max_parallel: Option<u32>) -> usize {
+    max_parallel.map_or(0, |max| max as usize)
+}
+
+async fn add_group(client: &mut Client, group: &str, max_parallel: Option<u32>) -> Result<()> {
     client
         .send_request(Request::Group(GroupRequest::Add {
             name: group.to_string(),
-            parallel_tasks: Some(0), // Unlimited
+            parallel_tasks: Some(parallel_tasks(max_parallel)),
+        }))
+        .await?;
+    let response = client.receive_response().await?;
+    if !response.success() {
+        return Err(Error::new(InnerError::Pueue(pueue_lib::Error::Generic(
+            format!("{:?}", response),
+        ))));
+    }
+    Ok(())
+}
+
+async fn set_parallel_tasks(
+    client: &mut Client,
+    group: &str,
+    max_parallel: Option<u32>,
+) -> Result<()> {
+    client
+        .send_request(Request::Parallel(ParallelRequest {
+            parallel_tasks: parallel_tasks(max_parallel),
+            group: group.to_string(),
         }))
         .await?;
     let response = client.receive_response().await?;
@@ -385,20 +1025,50 @@ mod tests {
     use chrono::Utc;
 
     use super::*;
+    use crate::error::EXIT_TIMEOUT;
+
+    #[tokio::test]
+    async fn poll_timeout() {
+        let statuses = StdMutex::new(vec![2, 1, 0]);
+        let next_status = || {
+            let status = statuses.lock().unwrap().pop();
+            async move { Ok(status.unwrap_or(2)) }
+        };
+        let reached = |n: &i32| *n == 2;
+        let timeout = Duration::from_secs(5);
+        poll_until(timeout, next_status, reached, |n| n.to_string())
+            .await
+            .unwrap();
+
+        let last_status = || async { Ok(1) };
+        let err = poll_until(Duration::ZERO, last_status, reached, |n| n.to_string())
+            .await
+            .unwrap_err();
+        assert!(matches!(&err.inner_error, InnerError::Timeout(message) if message == "1"));
+        assert_eq!(err.exit_code(), EXIT_TIMEOUT);
+    }
 
     #[tokio::test]
     async fn group_init() {
         let project_id = format!("pueue-test-{}", Utc::now().timestamp_millis());
 
-        let p = Pueue::new(&project_id).await.unwrap(); // Group does not exist, create it
-        let group_name = p.group;
+        let p = Pueue::new(&project_id).unwrap(); // Reading tasks doesn't create the group
+        p.request(Request::Status).await.unwrap();
+        let group_name = p.group.clone();
         let mut client = Pueue::client().await.unwrap();
         let grps = groups(&mut client).await.unwrap();
-        assert!(grps.contains_key(&group_name));
+        assert!(!grps.contains_key(&group_name));
         drop(client);
 
-        let p = Pueue::new(&project_id).await.unwrap(); // Group already exists
-        let group_name = p.group.clone();
+        let p = Pueue::new(&project_id).unwrap(); // Starting a process creates it
+        p.start(
+            "sleeper".to_owned(),
+            "sleep 60".to_owned(),
+            env::temp_dir(),
+            HashMap::new(),
+        )
+        .await
+        .unwrap();
         let mut client = Pueue::client().await.unwrap();
         let grps = groups(&mut client).await.unwrap();
         assert!(grps.contains_key(&group_name));
@@ -0,0 +1,444 @@
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, File},
+    io::BufReader,
+    path::PathBuf,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader as AsyncBufReader},
+    process::Command,
+    sync::{mpsc::Sender, Mutex},
+    time::sleep,
+};
+
+use crate::{
+    command::util::{self, send_line, shell_quote, SHELL},
+    common::ProcessState,
+    error::{filesystem_error, Error, InnerError, Result},
+    scheduler::{timeout_from_env, ScheduledProcess, Scheduler},
+    secrets::write_env_file,
+    signal::ProcessSignal,
+    Pid,
+};
+
+const REGISTRY_FILE: &str = "systemd.json";
+/// Interval between two checks of the state of a unit
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Scheduler backend launching processes as transient `systemd --user` units, Linux only
+///
+/// Units outlive the current `jocker` invocation and the terminal it runs in, and get the cgroup
+/// accounting of systemd. Their logs are kept by journald. Main PIDs of units are persisted in
+/// the project state directory, to find units back from the PIDs given by the state, along with
+/// the environment files of units.
+pub(crate) struct Systemd {
+    group: String,
+    project_dir: PathBuf,
+    /// Longest wait for a unit to stop
+    timeout: Duration,
+    /// Held while the registry is updated, as processes are started concurrently
+    registry_lock: Mutex<()>,
+}
+
+impl Systemd {
+    pub(crate) fn new(project_id: &str, project_dir: impl Into<PathBuf>) -> Result<Self> {
+        let project_dir = project_dir.into();
+        create_dir_all(&project_dir).map_err(filesystem_error(&project_dir))?;
+        Ok(Self {
+            group: format!("jocker-{project_id}"),
+            project_dir,
+            timeout: timeout_from_env()?,
+            registry_lock: Mutex::new(()),
+        })
+    }
+
+    fn registry_path(&self) -> PathBuf {
+        self.project_dir.join(REGISTRY_FILE)
+    }
+
+    /// File the environment of given unit is read from, keeping it out of the `systemd-run`
+    /// command line and of the unit properties
+    fn env_file_path(&self, unit: &str) -> PathBuf {
+        self.project_dir.join(format!("{unit}.env"))
+    }
+
+    fn registry(&self) -> Result<HashMap<String, Pid>> {
+        let path = self.registry_path();
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    fn set_registry(&self, registry: &HashMap<String, Pid>) -> Result<()> {
+        serde_json::to_writer(File::create(self.registry_path())?, registry)?;
+        Ok(())
+    }
+
+    /// Name of the unit of given process, characters systemd doesn't allow being replaced
+    fn unit(&self, process_name: &str) -> String {
+        let process_name: String = process_name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("{}-{process_name}.service", self.group)
+    }
+
+    /// Unit of the process with given PID, as registered when it was started
+    fn unit_of(&self, pid: Pid) -> Result<String> {
+        self.registry()?
+            .into_iter()
+            .find(|(_, p)| *p == pid)
+            .map(|(name, _)| self.unit(&name))
+            .ok_or_else(|| Error::new(InnerError::ProcessNotFound(vec![pid.to_string()])))
+    }
+
+    async fn unit_status(&self, unit: &str) -> Result<UnitStatus> {
+        let output = systemctl()
+            .arg("show")
+            .arg(unit)
+            .args(["--property", "LoadState,ActiveState,MainPID,ExecMainStatus"])
+            .stderr(Stdio::null())
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(Error::new(InnerError::Scheduler(format!(
+                "Unable to get the status of unit {unit}"
+            ))));
+        }
+        Ok(UnitStatus::parse(&String::from_utf8(output.stdout)?))
+    }
+
+    /// Poll the status of given unit until it is inactive, failing after `timeout`
+    async fn wait_until_stopped(&self, unit: &str, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        while self.unit_status(unit).await?.is_active() {
+            if Instant::now() >= deadline {
+                return Err(Error::new(InnerError::SchedulerTimeout(format!(
+                    "Unit {unit} is still active after {}s",
+                    timeout.as_secs()
+                ))));
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
+
+    /// Send given signal to every process of given unit
+    async fn signal_unit(&self, unit: &str, signal: ProcessSignal) -> Result<()> {
+        let status = systemctl()
+            .arg("kill")
+            .arg(format!("--signal=SIG{signal}"))
+            .arg(unit)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await?;
+        if !status.success() && self.unit_status(unit).await?.is_active() {
+            return Err(Error::new(InnerError::Scheduler(format!(
+                "Unable to send SIG{signal} to unit {unit}"
+            ))));
+        }
+        Ok(())
+    }
+
+    /// Forget given unit, which systemd keeps around when it failed
+    async fn reset(&self, unit: &str) -> Result<()> {
+        // Fails for units which are already gone, which is fine
+        systemctl()
+            .arg("reset-failed")
+            .arg(unit)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Scheduler for Systemd {
+    fn group(&self) -> &str {
+        &self.group
+    }
+
+    fn describe_start(&self, process_name: &str, command: &str) -> String {
+        let [shell, flag] = SHELL;
+        format!(
+            "systemd-run --user --unit {} -- {shell} {flag} {}",
+            self.unit(process_name),
+            shell_quote(command)
+        )
+    }
+
+    async fn start(
+        &self,
+        process_name: String,
+        command: String,
+        path: PathBuf,
+        envs: HashMap<String, String>,
+    ) -> Result<Pid> {
+        let unit = self.unit(&process_name);
+        if self.unit_status(&unit).await?.is_active() {
+            self.signal_unit(&unit, ProcessSignal::Kill).await?;
+            self.wait_until_stopped(&unit, self.timeout).await?;
+        }
+        self.reset(&unit).await?;
+
+        let env_file = self.env_file_path(&unit);
+        write_env_file(&env_file, &envs)?;
+        let [shell, flag] = SHELL;
+        let mut cmd = Command::new("systemd-run");
+        cmd.arg("--user")
+            .arg("--unit")
+            .arg(&unit)
+            .arg("--working-directory")
+            .arg(path)
+            .arg(format!("--property=EnvironmentFile={}", env_file.display()));
+        let output = cmd
+            .arg("--")
+            .arg(shell)
+            .arg(flag)
+            .arg(&command)
+            .output()
+            .await
+            .map_err(Error::with_context(InnerError::Start(format!(
+                "Unable to run systemd-run for process {process_name}"
+            ))))?;
+        if !output.status.success() {
+            return Err(Error::new(InnerError::Start(format!(
+                "Unable to start unit {unit}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))));
+        }
+        let pid = self
+            .unit_status(&unit)
+            .await?
+            .main_pid
+            .filter(|pid| *pid != 0)
+            .ok_or_else(|| {
+                Error::new(InnerError::Start(format!(
+                    "Process {process_name} exited right after being spawned"
+                )))
+            })?;
+
+        let _registry = self.registry_lock.lock().await;
+        let mut registry = self.registry()?;
+        registry.insert(process_name, pid);
+        self.set_registry(&registry)?;
+        Ok(pid)
+    }
+
+    async fn stop(&self, pid: Pid, kill: bool, timeout: Option<Duration>) -> Result<()> {
+        let unit = self.unit_of(pid)?;
+        let signal = if kill {
+            ProcessSignal::Kill
+        } else {
+            ProcessSignal::Term
+        };
+        self.signal_unit(&unit, signal).await?;
+        if let Some(timeout) = timeout.filter(|_| !kill) {
+            // Escalating to SIGKILL once `timeout` is over mustn't be preempted by a timeout error
+            let wait = self.wait_until_stopped(&unit, timeout + self.timeout);
+            match tokio::time::timeout(timeout, wait).await {
+                Ok(res) => return res,
+                Err(_) => self.signal_unit(&unit, ProcessSignal::Kill).await?,
+            }
+        }
+        self.wait_until_stopped(&unit, self.timeout).await
+    }
+
+    async fn signal(&self, pid: Pid, signal: ProcessSignal) -> Result<()> {
+        self.signal_unit(&self.unit_of(pid)?, signal).await
+    }
+
+    async fn pause(&self, pid: Pid) -> Result<()> {
+        self.signal(pid, ProcessSignal::Stop).await
+    }
+
+    async fn resume(&self, pid: Pid) -> Result<()> {
+        self.signal(pid, ProcessSignal::Cont).await
+    }
+
+    async fn status(&self) -> Result<HashMap<String, ScheduledProcess>> {
+        let mut status = HashMap::new();
+        for (process_name, pid) in self.registry()? {
+            let unit_status = self.unit_status(&self.unit(&process_name)).await?;
+            let (state, exit_code) = if !unit_status.is_active() {
+                (ProcessState::Stopped, unit_status.exit_code)
+            } else if unit_status.main_pid.is_some_and(util::is_paused) {
+                (ProcessState::Paused, None)
+            } else {
+                (ProcessState::Running, None)
+            };
+            status.insert(
+                process_name,
+                ScheduledProcess {
+                    pid,
+                    state,
+                    exit_code,
+                },
+            );
+        }
+        Ok(status)
+    }
+
+    async fn logs(
+        &self,
+        log_tx: Sender<String>,
+        pid: Pid,
+        lines: Option<usize>,
+        follow: bool,
+    ) -> Result<()> {
+        let unit = self.unit_of(pid)?;
+        let mut cmd = Command::new("journalctl");
+        cmd.arg("--user")
+            .arg("--unit")
+            .arg(&unit)
+            .args(["--output", "cat", "--no-pager"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(lines) = lines {
+            cmd.arg("--lines").arg(lines.to_string());
+        }
+        if follow {
+            cmd.arg("--follow");
+        }
+        let mut journalctl = cmd.spawn().map_err(Error::with_context(InnerError::Scheduler(
+            "Unable to run journalctl".to_owned(),
+        )))?;
+        let stdout = journalctl.stdout.take().ok_or_else(|| {
+            Error::new(InnerError::Scheduler(
+                "Unable to read the output of journalctl".to_owned(),
+            ))
+        })?;
+        let mut reader = AsyncBufReader::new(stdout).lines();
+        loop {
+            tokio::select! {
+                line = reader.next_line() => match line? {
+                    Some(line) => send_line(&log_tx, &line).await?,
+                    None => break,
+                },
+                // `journalctl --follow` never ends by itself
+                _ = sleep(Duration::from_millis(250)), if follow => {
+                    if !self.unit_status(&unit).await?.is_active() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn clean(&self) -> Result<()> {
+        for (process_name, _) in self.registry()? {
+            let unit = self.unit(&process_name);
+            if self.unit_status(&unit).await?.is_active() {
+                self.signal_unit(&unit, ProcessSignal::Kill).await?;
+                self.wait_until_stopped(&unit, self.timeout).await?;
+            }
+            self.reset(&unit).await?;
+            let env_file = self.env_file_path(&unit);
+            if env_file.exists() {
+                std::fs::remove_file(env_file)?;
+            }
+        }
+        let registry_path = self.registry_path();
+        if registry_path.exists() {
+            std::fs::remove_file(registry_path)?;
+        }
+        Ok(())
+    }
+}
+
+fn systemctl() -> Command {
+    let mut cmd = Command::new("systemctl");
+    cmd.arg("--user");
+    cmd
+}
+
+/// Properties of a unit, as given by `systemctl show`
+#[derive(Debug, Default, PartialEq)]
+struct UnitStatus {
+    active_state: String,
+    main_pid: Option<Pid>,
+    /// Exit code of the main process of the unit, once it exited
+    exit_code: Option<i32>,
+}
+
+impl UnitStatus {
+    fn parse(output: &str) -> Self {
+        let mut status = Self::default();
+        let mut loaded = true;
+        for (key, value) in output.lines().filter_map(|line| line.split_once('=')) {
+            match key {
+                "LoadState" => loaded = value != "not-found",
+                "ActiveState" => status.active_state = value.to_owned(),
+                "MainPID" => status.main_pid = value.parse().ok(),
+                "ExecMainStatus" => status.exit_code = value.parse().ok(),
+                _ => {}
+            }
+        }
+        // Units which exited successfully are unloaded, along with their exit code
+        if !loaded {
+            status.exit_code = None;
+        }
+        status
+    }
+
+    /// Whether the process of the unit is still running, or about to be
+    fn is_active(&self) -> bool {
+        matches!(
+            self.active_state.as_str(),
+            "active" | "activating" | "deactivating" | "reloading"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_unit_status() {
+        let status = UnitStatus::parse(
+            "LoadState=loaded\nActiveState=failed\nMainPID=0\nExecMainStatus=3\n",
+        );
+        assert!(!status.is_active());
+        assert_eq!(status.main_pid, Some(0));
+        assert_eq!(status.exit_code, Some(3));
+
+        let status = UnitStatus::parse(
+            "LoadState=loaded\nActiveState=active\nMainPID=42\nExecMainStatus=0\n",
+        );
+        assert!(status.is_active());
+        assert_eq!(status.main_pid, Some(42));
+
+        let status = UnitStatus::parse(
+            "LoadState=not-found\nActiveState=inactive\nMainPID=0\nExecMainStatus=0\n",
+        );
+        assert!(!status.is_active());
+        assert_eq!(status.exit_code, None);
+    }
+
+    #[test]
+    fn unit_names() {
+        let systemd = Systemd {
+            group: "jocker-abc".to_owned(),
+            project_dir: PathBuf::new(),
+        };
+        assert_eq!(systemd.unit("api-1"), "jocker-abc-api-1.service");
+        assert_eq!(systemd.unit("foo bar/baz"), "jocker-abc-foo_bar_baz.service");
+    }
+}
@@ -1,9 +1,23 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    process::{ExitStatus, Stdio},
+    time::Duration,
+};
+
+use sysinfo::{ProcessRefreshKind, ProcessStatus, ProcessesToUpdate, System};
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
-    process::Child,
+    process::{Child, Command},
+    sync::mpsc::Sender,
+    time::{sleep, Instant},
 };
+use tracing::info;
 
-use crate::error::Result;
+use crate::{
+    error::{Error, InnerError, Result},
+    signal::ProcessSignal,
+    Pid,
+};
 
 #[expect(async_fn_in_trait)]
 pub trait CommandLogger {
@@ -16,7 +30,7 @@ impl CommandLogger for Child {
             tokio::spawn(async {
                 let mut stdout_reader = BufReader::new(stdout).lines();
                 while let Ok(Some(line)) = stdout_reader.next_line().await {
-                    println!("{}", line);
+                    info!("{line}");
                 }
             });
         }
@@ -24,10 +38,334 @@ impl CommandLogger for Child {
             tokio::spawn(async {
                 let mut stderr_reader = BufReader::new(stderr).lines();
                 while let Ok(Some(line)) = stderr_reader.next_line().await {
-                    println!("{}", line);
+                    info!("{line}");
                 }
             });
         }
         Ok(())
     }
 }
+
+/// Platform shell, with its flag running a command line
+#[cfg(not(windows))]
+pub const SHELL: [&str; 2] = ["sh", "-c"];
+#[cfg(windows)]
+pub const SHELL: [&str; 2] = ["cmd", "/C"];
+
+/// Command running given command line through the platform [`SHELL`]
+pub fn shell_command(command: &str) -> Command {
+    let [shell, flag] = SHELL;
+    let mut cmd = Command::new(shell);
+    cmd.arg(flag).arg(command);
+    cmd
+}
+
+/// Whether an OS process with given pid exists
+pub fn is_running(pid: Pid) -> bool {
+    let pid = sysinfo::Pid::from(pid);
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing(),
+    );
+    system.process(pid).is_some()
+}
+
+/// Start time of the OS process with given pid, in seconds since the epoch, if it exists
+///
+/// Tells a process apart from another one started later with the same pid.
+pub fn start_time(pid: Pid) -> Option<u64> {
+    let pid = sysinfo::Pid::from(pid);
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing(),
+    );
+    system.process(pid).map(|process| process.start_time())
+}
+
+/// Whether the OS process with given pid is stopped by a signal, such as SIGSTOP
+pub fn is_paused(pid: Pid) -> bool {
+    let pid = sysinfo::Pid::from(pid);
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing(),
+    );
+    system
+        .process(pid)
+        .is_some_and(|process| process.status() == ProcessStatus::Stop)
+}
+
+/// Send given signal to the OS process with given pid
+pub async fn signal(pid: Pid, signal: ProcessSignal) -> Result<()> {
+    send_signal(pid, None, false, signal).await
+}
+
+/// Send given signal to every process of the group led by the OS process with given pid, as long
+/// as that process is still the one started at `started_at`, see [`start_time`]
+///
+/// A pid whose process has another start time was given to another process, which isn't
+/// signalled. Windows has no process groups, the processes started by the one with given pid are
+/// terminated along with it anyway.
+pub(crate) async fn signal_group(
+    pid: Pid,
+    started_at: Option<u64>,
+    signal: ProcessSignal,
+) -> Result<()> {
+    send_signal(pid, started_at, true, signal).await
+}
+
+async fn send_signal(
+    pid: Pid,
+    started_at: Option<u64>,
+    group: bool,
+    signal: ProcessSignal,
+) -> Result<()> {
+    let is_signalled_process = || match started_at {
+        Some(started_at) => start_time(pid) == Some(started_at),
+        None => is_running(pid),
+    };
+    if started_at.is_some() && !is_signalled_process() {
+        return Err(Error::new(InnerError::ProcessNotRunning(format!(
+            "Process {pid} is not running anymore, not sending SIG{signal} to it"
+        ))));
+    }
+    #[cfg(unix)]
+    let status = Command::new("kill")
+        .arg("-s")
+        .arg(signal.to_string())
+        .arg("--")
+        .arg(if group {
+            format!("-{pid}")
+        } else {
+            pid.to_string()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await?;
+    // Windows has no signals, terminate the process instead, along with the ones it started as
+    // its shell would be the only one terminated otherwise: whether it leads a group or not
+    #[cfg(not(unix))]
+    let _ = group;
+    #[cfg(windows)]
+    let status = Command::new("taskkill")
+        .arg("/T")
+        .args(match signal {
+            ProcessSignal::Term => vec![],
+            ProcessSignal::Kill => vec!["/F"],
+            _ => {
+                return Err(Error::new(InnerError::Scheduler(format!(
+                    "SIG{signal} is not supported on Windows"
+                ))))
+            }
+        })
+        .arg("/PID")
+        .arg(pid.to_string())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await?;
+    #[cfg(not(any(unix, windows)))]
+    let status: std::process::ExitStatus = {
+        return Err(Error::new(InnerError::Scheduler(format!(
+            "Unable to send SIG{signal} to process {pid}, signals are not supported on this \
+            platform"
+        ))));
+    };
+    if !status.success() && is_signalled_process() {
+        return Err(Error::new(InnerError::Scheduler(format!(
+            "Unable to send SIG{signal} to process {pid}"
+        ))));
+    }
+    Ok(())
+}
+
+/// Stop the OS process with given pid, escalating to SIGKILL if it still runs after `timeout`
+pub async fn terminate(pid: Pid, kill: bool, timeout: Duration) -> Result<()> {
+    let first_signal = if kill {
+        ProcessSignal::Kill
+    } else {
+        ProcessSignal::Term
+    };
+    signal(pid, first_signal).await?;
+    let mut killed = kill;
+    let mut deadline = Instant::now() + timeout;
+    while is_running(pid) {
+        if Instant::now() >= deadline {
+            if killed {
+                return Err(Error::new(InnerError::Timeout(format!(
+                    "Process {pid} still runs after SIGKILL"
+                ))));
+            }
+            signal(pid, ProcessSignal::Kill).await?;
+            killed = true;
+            deadline = Instant::now() + timeout;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    Ok(())
+}
+
+/// Send a log line read by a scheduler to the receiving end of logs
+pub(crate) async fn send_line(log_tx: &Sender<String>, line: &str) -> Result<()> {
+    log_tx
+        .send(line.to_owned())
+        .await
+        .map_err(|e| Error::new(InnerError::Scheduler(e.to_string())))
+}
+
+/// Size of the chunks log files are read backward by
+const TAIL_CHUNK_SIZE: u64 = 8 * 1024;
+
+/// Offset of the start of the last `lines` lines of given log file, read backward from its end
+///
+/// Only the tail is read, however large the file grew. A line being written, without its line
+/// feed yet, counts as the last one.
+pub(crate) fn tail_offset(file: &mut (impl Read + Seek), lines: usize) -> io::Result<u64> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if lines == 0 {
+        return Ok(len);
+    }
+    let mut line_feeds = 0;
+    let mut chunk_end = len;
+    let mut chunk = vec![0; TAIL_CHUNK_SIZE as usize];
+    while chunk_end > 0 {
+        let chunk_start = chunk_end.saturating_sub(TAIL_CHUNK_SIZE);
+        let chunk = &mut chunk[..(chunk_end - chunk_start) as usize];
+        file.seek(SeekFrom::Start(chunk_start))?;
+        file.read_exact(chunk)?;
+        for (idx, byte) in chunk.iter().enumerate().rev() {
+            let offset = chunk_start + idx as u64;
+            // The line feed ending the file terminates the last line, it doesn't start one
+            if *byte != b'\n' || offset + 1 == len {
+                continue;
+            }
+            line_feeds += 1;
+            if line_feeds == lines {
+                return Ok(offset + 1);
+            }
+        }
+        chunk_end = chunk_start;
+    }
+    Ok(0)
+}
+
+/// Quote given value so it is passed as a single word to `sh`
+pub fn shell_quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c))
+    {
+        return value.to_owned();
+    }
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quote given value so it is passed as a single argument to a program run by `cmd`
+///
+/// The value is quoted the way programs split their command line, then every character `cmd`
+/// interprets, quotes included, is escaped with `^`, so neither `&` nor `%VAR%` is expanded.
+pub fn cmd_quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@+\\".contains(c))
+    {
+        return value.to_owned();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in value.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        // Backslashes are only special before a quote, which is escaped along with them
+        let escaped = if c == '"' { backslashes * 2 + 1 } else { backslashes };
+        quoted.push_str(&"\\".repeat(escaped));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    // The closing quote mustn't be escaped by trailing backslashes
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+        .chars()
+        .flat_map(|c| {
+            if "()%!^\"<>&|".contains(c) {
+                vec!['^', c]
+            } else {
+                vec![c]
+            }
+        })
+        .collect()
+}
+
+/// Quote given value so it is passed as a single argument through the platform [`SHELL`]
+pub fn shell_arg(value: &str) -> String {
+    if cfg!(windows) {
+        cmd_quote(value)
+    } else {
+        shell_quote(value)
+    }
+}
+
+/// Exit code of a process, following shells convention of `128 + signal` for killed ones
+pub fn exit_code(exit_status: ExitStatus) -> i32 {
+    if let Some(code) = exit_status.code() {
+        return code;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt as _;
+        if let Some(signal) = exit_status.signal() {
+            return 128 + signal;
+        }
+    }
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn tail_offsets() {
+        let mut log = Cursor::new(b"foo\nbar\nbaz\n".to_vec());
+        assert_eq!(tail_offset(&mut log, 0).unwrap(), 12);
+        assert_eq!(tail_offset(&mut log, 1).unwrap(), 8);
+        assert_eq!(tail_offset(&mut log, 2).unwrap(), 4);
+        assert_eq!(tail_offset(&mut log, 5).unwrap(), 0);
+        let mut partial = Cursor::new(b"foo\nbar".to_vec());
+        assert_eq!(tail_offset(&mut partial, 1).unwrap(), 4);
+        let mut long = Cursor::new([vec![b'a'; 20_000], b"\nfoo\n".to_vec()].concat());
+        assert_eq!(tail_offset(&mut long, 1).unwrap(), 20_001);
+        assert_eq!(tail_offset(&mut Cursor::new(vec![]), 3).unwrap(), 0);
+    }
+
+    #[test]
+    fn quote_shell_words() {
+        assert_eq!(shell_quote("--foo=bar"), "--foo=bar");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("foo bar"), "'foo bar'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn quote_cmd_arguments() {
+        assert_eq!(cmd_quote(r"C:\app\api.exe"), r"C:\app\api.exe");
+        assert_eq!(cmd_quote(""), r#"^"^""#);
+        assert_eq!(cmd_quote("foo bar"), r#"^"foo bar^""#);
+        assert_eq!(cmd_quote(r#"say "hi""#), r#"^"say \^"hi\^"^""#);
+        assert_eq!(cmd_quote(r"C:\my app\"), r#"^"C:\my app\\^""#);
+        assert_eq!(cmd_quote("a&b %PATH%"), r#"^"a^&b ^%PATH^%^""#);
+    }
+}
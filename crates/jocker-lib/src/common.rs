@@ -1,20 +1,45 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
+use chrono::{DateTime, Utc};
 use pueue_lib::TaskStatus;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    config::ConfigProcess,
+    command::cargo::{Cargo, CargoFeatures, CargoTarget},
+    config::{
+        ConfigCrashLoop, ConfigDependencyCondition, ConfigHealthcheck, ConfigLimits, ConfigProcess,
+        ConfigProcessKind, ConfigSecret,
+    },
     error::{Error, InnerError, Result},
     Pid,
 };
 
 pub const JOCKER: &str = "jocker";
 pub(crate) const MAX_RECURSION_LEVEL: u8 = 10;
+const DEFAULT_HEALTHCHECK_TIMEOUT: Duration = Duration::from_secs(60);
+/// Number of failed exits a process may have within [`DEFAULT_CRASH_LOOP_WINDOW`] before crash
+/// looping, unless configured otherwise
+pub const DEFAULT_CRASH_LOOP_MAX_FAILURES: u32 = 3;
+/// Seconds failed exits are counted over, unless configured otherwise
+pub const DEFAULT_CRASH_LOOP_WINDOW: u64 = 60;
 pub const JOCKER_ENV_STACK: &str = "JOCKER_STACK";
+/// Comma separated groups of optional processes to enable, when none is given on CLI
+pub const JOCKER_ENV_ENABLE: &str = "JOCKER_ENABLE";
+pub const JOCKER_ENV_SCHEDULER: &str = "JOCKER_SCHEDULER";
+/// Seconds to wait for the scheduler to carry out a request before giving up on it
+pub const JOCKER_ENV_SCHEDULER_TIMEOUT: &str = "JOCKER_SCHEDULER_TIMEOUT";
+/// `pueued` instance to use: `embedded`, owned by jocker, by default, or `global` for the user's one
+pub const JOCKER_ENV_PUEUE_DAEMON: &str = "JOCKER_PUEUE_DAEMON";
+/// Set on every launched process to `<scheduler group>/<process name>`, to be able to find it back
+pub const JOCKER_ENV_PROCESS_ID: &str = "JOCKER_PROCESS_ID";
+/// Set on every launched process to its instance index, starting at 0
+pub const JOCKER_ENV_INSTANCE: &str = "JOCKER_INSTANCE";
 
 #[expect(async_fn_in_trait)]
 pub trait Exec<T> {
@@ -23,36 +48,224 @@ pub trait Exec<T> {
 
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Process {
+    /// Unique name of this instance, which is the configured name for the first one
     pub name: String,
+    /// Configured name of the process this is an instance of
+    pub base_name: String,
+    /// Index of this instance, starting at 0
+    pub instance: u32,
+    /// Number of instances of the configured process
+    pub replicas: u32,
     pub binary: String,
+    pub command: Option<String>,
+    pub profile: Option<String>,
     pub state: ProcessState,
     pub pid: Option<Pid>,
     pub args: Vec<String>,
     pub cargo_args: Vec<String>,
+    pub features: Vec<String>,
+    pub no_default_features: bool,
+    pub all_features: bool,
     pub env: HashMap<String, String>,
+    pub ports: Vec<u16>,
+    /// Groups enabling the process, which is always enabled without any
+    pub groups: Vec<String>,
+    /// Free-form labels, selecting processes across stacks
+    pub labels: BTreeMap<String, String>,
+    pub depends_on: BTreeMap<String, DependencyCondition>,
+    pub healthcheck: Option<Healthcheck>,
+    pub limits: Option<Limits>,
+    /// Thresholds of crash loop detection, the default ones if `None`
+    pub crash_loop: Option<CrashLoop>,
+    pub started_at: Option<DateTime<Utc>>,
+    /// End of the last run, once the process stopped or exited
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub restart_count: u32,
+    pub exit_code: Option<i32>,
+    /// Consecutive failed exits, counted since [`Self::failures_since`]
+    pub failure_count: u32,
+    pub failures_since: Option<DateTime<Utc>>,
+    /// Log lines of the current run already copied to the log archive
+    pub archived_lines: u32,
+    /// Package [`Self::binary`] belongs to, when it must be given to cargo
+    pub package: Option<String>,
+    /// Whether [`Self::binary`] is the name of an example rather than of a binary target
+    pub example: bool,
+    /// SSH destination the process runs on, the local machine if `None`
+    pub host: Option<String>,
+    pub kind: ProcessKind,
+    /// Command line of the current or last run, as launched
+    pub command_line: Option<String>,
+    /// Stack in use when the process was last started
+    pub stack: Option<String>,
+    /// Sources of the secrets given to the process as environment variables, by variable name
+    pub secrets: BTreeMap<String, Secret>,
+    /// Environment of the current or last run, as launched with secrets masked
+    pub launched_env: BTreeMap<String, EnvVar>,
 }
 
 impl Process {
     pub fn new(name: &str, binary: &str) -> Process {
         Self {
             name: name.to_string(),
+            base_name: name.to_string(),
+            instance: 0,
+            replicas: 1,
             binary: binary.to_string(),
+            command: None,
+            profile: None,
             state: ProcessState::Stopped,
             pid: None,
             args: Vec::new(),
             cargo_args: Vec::new(),
+            features: Vec::new(),
+            no_default_features: false,
+            all_features: false,
             env: HashMap::new(),
+            ports: Vec::new(),
+            groups: Vec::new(),
+            labels: BTreeMap::new(),
+            depends_on: BTreeMap::new(),
+            healthcheck: None,
+            limits: None,
+            crash_loop: None,
+            started_at: None,
+            stopped_at: None,
+            restart_count: 0,
+            exit_code: None,
+            failure_count: 0,
+            failures_since: None,
+            archived_lines: 0,
+            package: None,
+            example: false,
+            host: None,
+            kind: ProcessKind::Service,
+            command_line: None,
+            stack: None,
+            secrets: BTreeMap::new(),
+            launched_env: BTreeMap::new(),
         }
     }
 
+    /// Whether the process is enabled by one of given enabled groups, or doesn't need any
+    pub fn is_enabled(&self, enabled_groups: &[String]) -> bool {
+        self.groups.is_empty() || self.groups.iter().any(|g| enabled_groups.contains(g))
+    }
+
+    /// Whether the process has all of given labels, as `(key, value)`
+    pub fn has_labels(&self, labels: &[(String, String)]) -> bool {
+        labels.iter().all(|(key, value)| self.labels.get(key) == Some(value))
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    pub fn base_name(&self) -> &str {
+        &self.base_name
+    }
+
+    /// Whether this process is given name, or an instance of the process configured with it
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name || self.base_name == name
+    }
+
+    /// Instance of this process with given index
+    pub fn nth_instance(&self, instance: u32) -> Process {
+        let name = if instance == 0 {
+            self.base_name.clone()
+        } else {
+            format!("{}.{instance}", self.base_name)
+        };
+        Self {
+            name,
+            instance,
+            state: ProcessState::Stopped,
+            pid: None,
+            started_at: None,
+            stopped_at: None,
+            restart_count: 0,
+            exit_code: None,
+            failure_count: 0,
+            failures_since: None,
+            archived_lines: 0,
+            command_line: None,
+            stack: None,
+            launched_env: BTreeMap::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Every instance of this process, according to its number of replicas
+    pub fn instances(&self) -> Vec<Process> {
+        (0..self.replicas.max(1))
+            .map(|i| self.nth_instance(i))
+            .collect()
+    }
+
+    /// Count a failed exit happening at given date, returning whether the process is crash looping
+    ///
+    /// Failures older than the crash loop window of the process are forgotten.
+    pub fn record_failure(&mut self, at: DateTime<Utc>) -> bool {
+        let crash_loop = self.crash_loop.clone().unwrap_or_default();
+        match self.failures_since {
+            Some(since) if at - since <= crash_loop.window() => self.failure_count += 1,
+            _ => {
+                self.failure_count = 1;
+                self.failures_since = Some(at);
+            }
+        }
+        self.failure_count > crash_loop.max_failures
+    }
+
     pub fn binary(&self) -> &str {
         &self.binary
     }
 
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_deref()
+    }
+
+    /// Whether this process is built with cargo, or is an arbitrary shell command
+    pub fn is_cargo(&self) -> bool {
+        self.command.is_none()
+    }
+
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    pub fn package(&self) -> Option<&str> {
+        self.package.as_deref()
+    }
+
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// Whether the process runs to completion rather than until it is stopped
+    pub fn is_task(&self) -> bool {
+        self.kind == ProcessKind::Task
+    }
+
+    /// Crate target built by cargo for this process
+    pub fn cargo_target(&self) -> CargoTarget {
+        if self.example {
+            CargoTarget::Example(self.binary.clone())
+        } else {
+            CargoTarget::Bin(self.binary.clone())
+        }
+    }
+
+    /// Path of the binary, or example, built by cargo for this process
+    pub fn artifact_path(&self, cargo_target_dir: &Path) -> PathBuf {
+        if self.example {
+            Cargo::example_path(cargo_target_dir, self.profile(), &self.binary)
+        } else {
+            Cargo::binary_path(cargo_target_dir, self.profile(), &self.binary)
+        }
+    }
+
     pub fn pid(&self) -> &Option<Pid> {
         &self.pid
     }
@@ -64,16 +277,56 @@ impl Process {
     pub fn cargo_args(&self) -> &[String] {
         self.cargo_args.as_slice()
     }
+
+    /// Cargo features to build binary with
+    pub fn cargo_features(&self) -> CargoFeatures {
+        let mut features = self.features.clone();
+        features.sort();
+        features.dedup();
+        CargoFeatures {
+            features,
+            no_default_features: self.no_default_features,
+            all_features: self.all_features,
+        }
+    }
 }
 
 impl From<(String, ConfigProcess)> for Process {
     fn from(value: (String, ConfigProcess)) -> Self {
         Self {
-            binary: value.1.binary.unwrap_or(value.0.clone()),
+            binary: value
+                .1
+                .example
+                .clone()
+                .or(value.1.binary)
+                .unwrap_or(value.0.clone()),
+            example: value.1.example.is_some(),
+            package: value.1.package,
+            host: value.1.host,
+            kind: value.1.kind.into(),
+            base_name: value.0.clone(),
+            replicas: value.1.replicas.unwrap_or(1),
             name: value.0,
+            command: value.1.command,
+            profile: value.1.profile,
             args: value.1.args,
             cargo_args: value.1.cargo_args,
+            features: value.1.features,
+            no_default_features: value.1.no_default_features,
+            all_features: value.1.all_features,
             env: value.1.env,
+            ports: value.1.ports,
+            groups: value.1.groups,
+            labels: value.1.labels.into_iter().collect(),
+            depends_on: value
+                .1
+                .depends_on
+                .into_iter()
+                .map(|(name, dependency)| (name, dependency.condition.into()))
+                .collect(),
+            healthcheck: value.1.healthcheck.map(Into::into),
+            limits: value.1.limits.map(Into::into),
+            crash_loop: value.1.crash_loop.map(Into::into),
             ..Default::default()
         }
     }
@@ -89,6 +342,10 @@ impl Ord for Process {
             core::cmp::Ordering::Equal => {}
             ord => return ord,
         }
+        match self.command.cmp(&other.command) {
+            core::cmp::Ordering::Equal => {}
+            ord => return ord,
+        }
         match self.state.cmp(&other.state) {
             core::cmp::Ordering::Equal => {}
             ord => return ord,
@@ -107,11 +364,122 @@ impl PartialOrd for Process {
     }
 }
 
+/// Processes designated by a process filter given on CLI
+///
+/// A filter is either a process name, a glob pattern such as `api-*`, or a prefix of a single
+/// configured process name. No process is returned if nothing matches.
+pub fn match_filter<'a>(filter: &str, processes: &'a [Process]) -> Result<Vec<&'a Process>> {
+    if filter.contains(['*', '?', '[']) {
+        let pattern = glob_regex(filter)?;
+        return Ok(processes
+            .iter()
+            .filter(|p| pattern.is_match(&p.name) || pattern.is_match(&p.base_name))
+            .collect());
+    }
+    let matched: Vec<&Process> = processes.iter().filter(|p| p.matches(filter)).collect();
+    if !matched.is_empty() {
+        return Ok(matched);
+    }
+    let prefixed: Vec<&Process> = processes
+        .iter()
+        .filter(|p| p.base_name.starts_with(filter))
+        .collect();
+    let base_names: HashSet<&str> = prefixed.iter().map(|p| p.base_name()).collect();
+    if base_names.len() == 1 {
+        return Ok(prefixed);
+    }
+    Ok(vec![])
+}
+
+/// Process names the user may have meant with a filter matching nothing
+pub fn suggest_filter(filter: &str, processes: &[Process]) -> Vec<String> {
+    let base_names: BTreeSet<&str> = processes.iter().map(|p| p.base_name()).collect();
+    // Ambiguous prefix
+    let prefixed: Vec<String> = base_names
+        .iter()
+        .filter(|name| name.starts_with(filter))
+        .map(|name| name.to_string())
+        .collect();
+    if !prefixed.is_empty() {
+        return prefixed;
+    }
+    // Typo
+    let max_distance = (filter.chars().count() / 3).max(1);
+    base_names
+        .into_iter()
+        .map(|name| (edit_distance(filter, name), name))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, name)| vec![name.to_owned()])
+        .unwrap_or_default()
+}
+
+/// Label filter given on CLI as `key=value`
+pub fn parse_label(label: &str) -> Result<(String, String)> {
+    match label.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(Error::new(InnerError::Parse(format!(
+            "Expected a label as key=value, got {label}"
+        )))),
+    }
+}
+
+/// Regex matching the whole of given glob pattern, supporting `*`, `?` and `[...]`
+fn glob_regex(pattern: &str) -> Result<Regex> {
+    let mut regex = String::from("^");
+    let mut in_class = false;
+    for c in pattern.chars() {
+        match c {
+            '*' if !in_class => regex.push_str(".*"),
+            '?' if !in_class => regex.push('.'),
+            '[' if !in_class => {
+                in_class = true;
+                regex.push('[');
+            }
+            ']' if in_class => {
+                in_class = false;
+                regex.push(']');
+            }
+            '!' if in_class && regex.ends_with('[') => regex.push('^'),
+            c if in_class => regex.push(c),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).map_err(|e| Error::new(InnerError::Parse(format!("{pattern}: {e}"))))
+}
+
+/// Levenshtein distance between given strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Ord, PartialOrd, Serialize)]
 pub enum ProcessState {
     Stopped,
     Building,
     Running,
+    /// Waiting for a free slot of its stack before running
+    Queued,
+    /// Exited with failures too many times in a row, and won't be restarted automatically
+    CrashLooping,
+    /// Task which ran to completion, successfully or not
+    Done,
+    /// Running outside of jocker, from the binary jocker would launch, its OS pid as pid once
+    /// adopted
+    External,
+    /// Frozen by SIGSTOP, keeping its memory until it is resumed
+    Paused,
     Unknown,
 }
 
@@ -121,12 +489,30 @@ impl Default for ProcessState {
     }
 }
 
+impl ProcessState {
+    /// Whether the process was started, even if it still waits for a slot of its stack or is
+    /// paused
+    pub fn is_started(&self) -> bool {
+        matches!(self, Self::Running | Self::Queued | Self::Paused)
+    }
+
+    /// Whether the process isn't running and can be started again
+    pub fn is_stopped(&self) -> bool {
+        matches!(self, Self::Stopped | Self::Done)
+    }
+}
+
 impl Display for ProcessState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let str = match self {
             ProcessState::Stopped => "stopped",
             ProcessState::Building => "building",
             ProcessState::Running => "running",
+            ProcessState::Queued => "queued",
+            ProcessState::CrashLooping => "crashlooping",
+            ProcessState::Done => "done",
+            ProcessState::External => "external",
+            ProcessState::Paused => "paused",
             ProcessState::Unknown => "unknown",
         };
         write!(f, "{str}")
@@ -137,7 +523,9 @@ impl From<TaskStatus> for ProcessState {
     fn from(value: TaskStatus) -> Self {
         match value {
             TaskStatus::Running { .. } => Self::Running,
-            TaskStatus::Paused { .. } | TaskStatus::Done { .. } => Self::Stopped,
+            TaskStatus::Queued { .. } => Self::Queued,
+            TaskStatus::Paused { .. } => Self::Paused,
+            TaskStatus::Done { .. } => Self::Stopped,
             _ => Self::Unknown,
         }
     }
@@ -151,17 +539,389 @@ impl TryFrom<String> for ProcessState {
             "stopped" => Self::Stopped,
             "building" => Self::Building,
             "running" => Self::Running,
+            "queued" => Self::Queued,
+            "crashlooping" => Self::CrashLooping,
+            "done" => Self::Done,
+            "external" => Self::External,
+            "paused" => Self::Paused,
             "unknown" => Self::Unknown,
             _ => Err(Error::new(InnerError::Parse(value)))?,
         })
     }
 }
 
+/// State a dependency must reach before its dependents are launched
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyCondition {
+    #[default]
+    Started,
+    Healthy,
+    CompletedSuccessfully,
+}
+
+impl From<ConfigDependencyCondition> for DependencyCondition {
+    fn from(value: ConfigDependencyCondition) -> Self {
+        match value {
+            ConfigDependencyCondition::Started => Self::Started,
+            ConfigDependencyCondition::Healthy => Self::Healthy,
+            ConfigDependencyCondition::CompletedSuccessfully => Self::CompletedSuccessfully,
+        }
+    }
+}
+
+/// What is expected of a process once launched
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ProcessKind {
+    /// Keeps running until stopped
+    #[default]
+    Service,
+    /// Runs to completion, such as migrations or code generation
+    Task,
+}
+
+impl Display for ProcessKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ProcessKind::Service => "service",
+            ProcessKind::Task => "task",
+        };
+        write!(f, "{str}")
+    }
+}
+
+impl TryFrom<String> for ProcessKind {
+    type Error = Error;
+
+    fn try_from(value: String) -> std::prelude::v1::Result<Self, Self::Error> {
+        Ok(match value.as_str() {
+            "service" => Self::Service,
+            "task" => Self::Task,
+            _ => Err(Error::new(InnerError::Parse(value)))?,
+        })
+    }
+}
+
+impl From<ConfigProcessKind> for ProcessKind {
+    fn from(value: ConfigProcessKind) -> Self {
+        match value {
+            ConfigProcessKind::Service => Self::Service,
+            ConfigProcessKind::Task => Self::Task,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Healthcheck {
+    pub command: Option<String>,
+    pub tcp: Option<String>,
+    pub http: Option<String>,
+    pub timeout: Option<u64>,
+}
+
+impl Healthcheck {
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_HEALTHCHECK_TIMEOUT)
+    }
+}
+
+impl From<ConfigHealthcheck> for Healthcheck {
+    fn from(value: ConfigHealthcheck) -> Self {
+        Self {
+            command: value.command,
+            tcp: value.tcp,
+            http: value.http,
+            timeout: value.timeout,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Limits {
+    /// Maximum memory, as given in the configuration
+    pub memory: Option<String>,
+    /// Maximum CPU time, in percent of a single CPU
+    pub cpu_percent: Option<u32>,
+}
+
+impl From<ConfigLimits> for Limits {
+    fn from(value: ConfigLimits) -> Self {
+        Self {
+            memory: value.memory,
+            cpu_percent: value.cpus.map(|cpus| (cpus * 100.0).round() as u32),
+        }
+    }
+}
+
+/// Number of failed exits within a window after which a process is crash looping
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CrashLoop {
+    pub max_failures: u32,
+    /// Seconds failed exits are counted over
+    pub window: u64,
+}
+
+impl CrashLoop {
+    pub fn window(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.window.try_into().unwrap_or(i64::MAX))
+    }
+}
+
+impl Default for CrashLoop {
+    fn default() -> Self {
+        Self {
+            max_failures: DEFAULT_CRASH_LOOP_MAX_FAILURES,
+            window: DEFAULT_CRASH_LOOP_WINDOW,
+        }
+    }
+}
+
+impl From<ConfigCrashLoop> for CrashLoop {
+    fn from(value: ConfigCrashLoop) -> Self {
+        Self {
+            max_failures: value.max_failures.unwrap_or(DEFAULT_CRASH_LOOP_MAX_FAILURES),
+            window: value.window.unwrap_or(DEFAULT_CRASH_LOOP_WINDOW),
+        }
+    }
+}
+
+/// Value of an environment variable given to a process, with where it comes from
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EnvVar {
+    pub value: String,
+    pub source: EnvSource,
+}
+
+impl EnvVar {
+    pub fn new(value: impl Into<String>, source: EnvSource) -> Self {
+        Self {
+            value: value.into(),
+            source,
+        }
+    }
+}
+
+/// Origin of an environment variable given to a process, by increasing precedence
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvSource {
+    /// Set by jocker, such as `JOCKER_INSTANCE`
+    Jocker,
+    /// `.env` file of the directory jocker runs from
+    Dotenv,
+    /// `env` of the process configuration, or of the one it extends
+    Config,
+    /// Secret listed by the process configuration
+    Secret,
+    /// Given on the command line with `--env` or `--env-file`, for a single invocation
+    Cli,
+}
+
+impl Display for EnvSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            EnvSource::Jocker => "jocker",
+            EnvSource::Dotenv => "dotenv",
+            EnvSource::Config => "config",
+            EnvSource::Secret => "secret",
+            EnvSource::Cli => "cli",
+        };
+        write!(f, "{str}")
+    }
+}
+
+/// Where the value of a secret is read from, only when a process using it is started
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Secret {
+    /// File holding the value, relative to the project directory
+    File(PathBuf),
+    /// Entry of the `pass` password store
+    Pass(String),
+    /// Shell command printing the value
+    Command(String),
+}
+
+impl TryFrom<ConfigSecret> for Secret {
+    type Error = Error;
+
+    fn try_from(value: ConfigSecret) -> std::result::Result<Self, Self::Error> {
+        match (value.file, value.pass, value.command) {
+            (Some(file), None, None) => Ok(Self::File(file)),
+            (None, Some(pass), None) => Ok(Self::Pass(pass)),
+            (None, None, Some(command)) => Ok(Self::Command(command)),
+            _ => Err(Error::new(InnerError::Secret(
+                "A secret needs exactly one of file, pass or command".to_owned(),
+            ))),
+        }
+    }
+}
+
+impl From<Secret> for ConfigSecret {
+    fn from(value: Secret) -> Self {
+        match value {
+            Secret::File(file) => Self {
+                file: Some(file),
+                ..Default::default()
+            },
+            Secret::Pass(pass) => Self {
+                pass: Some(pass),
+                ..Default::default()
+            },
+            Secret::Command(command) => Self {
+                command: Some(command),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum EventKind {
+    Started,
+    Stopped,
+    Exited,
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            EventKind::Started => "started",
+            EventKind::Stopped => "stopped",
+            EventKind::Exited => "exited",
+        };
+        write!(f, "{str}")
+    }
+}
+
+impl TryFrom<String> for EventKind {
+    type Error = Error;
+
+    fn try_from(value: String) -> std::prelude::v1::Result<Self, Self::Error> {
+        Ok(match value.as_str() {
+            "started" => Self::Started,
+            "stopped" => Self::Stopped,
+            "exited" => Self::Exited,
+            _ => Err(Error::new(InnerError::Parse(value)))?,
+        })
+    }
+}
+
+/// Lifecycle event of a process
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Event {
+    pub process_name: String,
+    pub kind: EventKind,
+    pub created_at: DateTime<Utc>,
+    pub exit_code: Option<i32>,
+}
+
+impl Event {
+    pub fn new(process_name: &str, kind: EventKind) -> Self {
+        Self {
+            process_name: process_name.to_string(),
+            kind,
+            created_at: Utc::now(),
+            exit_code: None,
+        }
+    }
+}
+
+/// Change of the project, broadcast to subscribers of [`State::subscribe`](crate::state::State::subscribe)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum JockerEvent {
+    /// Process launched by its scheduler
+    ProcessStarted { process_name: String, pid: usize },
+    /// Process stopped on request
+    ProcessStopped { process_name: String },
+    /// Binary of a process built, or failed to
+    BuildFinished { process_name: String, success: bool },
+    /// Process moved to another state, including exits noticed on refresh
+    StateChanged {
+        process_name: String,
+        state: ProcessState,
+    },
+}
+
+/// Part of the project a [`HistoryEntry`] changed
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum HistoryKind {
+    /// Configuration reloaded, with processes added or removed
+    Config,
+    /// Stack selected with `jocker stack use`
+    Stack,
+    /// Lifecycle [`Event`] of a process, with its kind as change
+    Event,
+}
+
+impl Display for HistoryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            HistoryKind::Config => "config",
+            HistoryKind::Stack => "stack",
+            HistoryKind::Event => "event",
+        };
+        write!(f, "{str}")
+    }
+}
+
+impl TryFrom<String> for HistoryKind {
+    type Error = Error;
+
+    fn try_from(value: String) -> std::prelude::v1::Result<Self, Self::Error> {
+        Ok(match value.as_str() {
+            "config" => Self::Config,
+            "stack" => Self::Stack,
+            "event" => Self::Event,
+            _ => Err(Error::new(InnerError::Parse(value)))?,
+        })
+    }
+}
+
+/// Append-only record of a change of the project state
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    /// Version of the project state this change led to, increasing with each change
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub kind: HistoryKind,
+    /// Process the change is about, if any
+    pub process_name: Option<String>,
+    /// Description of the change, such as `started` or `exited (exit code 1)`
+    pub change: String,
+}
+
+/// `cargo build` run by jocker, covering every process sharing the same build flags
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BuildRecord {
+    pub id: i64,
+    pub started_at: DateTime<Utc>,
+    /// Duration of the `cargo build` command, including the wait for another cargo command
+    /// holding the lock of the build directory
+    pub duration_ms: u64,
+    /// Names of the processes whose binary was built
+    pub processes: Vec<String>,
+    /// Cargo profile, the default one if `None`
+    pub profile: Option<String>,
+    /// Crates compiled by the build, rather than found fresh
+    pub compiled: u32,
+    pub success: bool,
+}
+
 #[derive(Clone, Debug)]
 pub struct Stack {
     pub name: String,
     pub processes: HashSet<String>,
     pub inherited_processes: HashSet<String>,
+    /// Maximum number of processes of the stack running at once, unlimited if `None`
+    pub max_parallel: Option<u32>,
+    /// Created with `jocker stack create` rather than by the configuration, so kept when it is
+    /// reloaded
+    pub user_created: bool,
 }
 
 impl Stack {
@@ -172,3 +932,128 @@ impl Stack {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_enable_process() {
+        let mut process = Process::new("foo", "foo");
+        assert!(process.is_enabled(&[]));
+        process.groups = vec!["debug".to_owned(), "admin".to_owned()];
+        assert!(!process.is_enabled(&[]));
+        assert!(!process.is_enabled(&["tracing".to_owned()]));
+        assert!(process.is_enabled(&["tracing".to_owned(), "admin".to_owned()]));
+    }
+
+    #[test]
+    fn labels_select_process() {
+        let mut process = Process::new("foo", "foo");
+        process.labels = BTreeMap::from([
+            ("team".to_owned(), "payments".to_owned()),
+            ("tier".to_owned(), "backend".to_owned()),
+        ]);
+        let team = parse_label("team=payments").unwrap();
+        let tier = parse_label("tier=frontend").unwrap();
+        assert!(process.has_labels(&[]));
+        assert!(process.has_labels(std::slice::from_ref(&team)));
+        assert!(!process.has_labels(&[team, tier]));
+        assert!(parse_label("team").is_err());
+        assert!(parse_label("=payments").is_err());
+    }
+
+    #[test]
+    fn example_process() {
+        let config = ConfigProcess {
+            example: Some("demo".to_owned()),
+            package: Some("foo".to_owned()),
+            ..Default::default()
+        };
+        let process = Process::from(("foo-demo".to_owned(), config));
+        assert_eq!(
+            process.cargo_target(),
+            CargoTarget::Example("demo".to_owned())
+        );
+        assert_eq!(process.package(), Some("foo"));
+        assert_eq!(
+            process.artifact_path(Path::new("/tmp/target")),
+            PathBuf::from("/tmp/target/debug/examples/demo")
+        );
+        let process = Process::from(("bar".to_owned(), ConfigProcess::default()));
+        assert_eq!(process.cargo_target(), CargoTarget::Bin("bar".to_owned()));
+    }
+
+    #[test]
+    fn process_filters() {
+        let processes = vec![
+            Process::new("api-gateway", "api"),
+            Process::new("api-worker", "api"),
+            Process::new("athena", "ares"),
+            Process::new("eris", "eris"),
+            Process::new("eris", "eris").nth_instance(1),
+        ];
+        let names = |filter| -> Vec<String> {
+            match_filter(filter, &processes)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.name().to_owned())
+                .collect()
+        };
+
+        assert_eq!(names("eris"), vec!["eris", "eris.1"]);
+        assert_eq!(names("eris.1"), vec!["eris.1"]);
+        assert_eq!(names("api-*"), vec!["api-gateway", "api-worker"]);
+        assert_eq!(names("eris.?"), vec!["eris.1"]);
+        assert_eq!(names("[ae]*s"), vec!["eris", "eris.1"]);
+        assert_eq!(names("ath"), vec!["athena"]);
+        // Ambiguous prefix
+        assert!(names("a").is_empty());
+        assert!(names("apollo").is_empty());
+
+        assert_eq!(
+            suggest_filter("a", &processes),
+            vec!["api-gateway", "api-worker", "athena"]
+        );
+        assert_eq!(suggest_filter("athna", &processes), vec!["athena"]);
+        assert!(suggest_filter("apollo", &processes).is_empty());
+    }
+
+    #[test]
+    fn crash_loop_detection() {
+        let mut process = Process::new("foo", "foo");
+        let start = Utc::now();
+        for i in 0..DEFAULT_CRASH_LOOP_MAX_FAILURES {
+            assert!(!process.record_failure(start + chrono::Duration::seconds(i.into())));
+        }
+        assert!(process.record_failure(start + chrono::Duration::seconds(10)));
+
+        // Failures outside of the window start a new count
+        let later = start + CrashLoop::default().window() + chrono::Duration::seconds(1);
+        assert!(!process.record_failure(later));
+        assert_eq!(process.failure_count, 1);
+        assert_eq!(process.failures_since, Some(later));
+    }
+
+    #[test]
+    fn configured_crash_loop() {
+        let mut process = Process::new("foo", "foo");
+        process.crash_loop = Some(
+            ConfigCrashLoop {
+                max_failures: Some(1),
+                window: Some(600),
+            }
+            .into(),
+        );
+        let start = Utc::now();
+        assert!(!process.record_failure(start));
+        assert!(process.record_failure(start + chrono::Duration::seconds(300)));
+
+        // Only overridden thresholds change
+        let crash_loop = CrashLoop::from(ConfigCrashLoop {
+            max_failures: Some(5),
+            window: None,
+        });
+        assert_eq!(crash_loop.window, DEFAULT_CRASH_LOOP_WINDOW);
+    }
+}
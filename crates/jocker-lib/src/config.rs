@@ -1,65 +1,478 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs::File,
-    io::BufReader,
-    path::Path,
+    fs,
+    path::{Path, PathBuf},
 };
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::error::Result;
+use crate::{
+    common::MAX_RECURSION_LEVEL,
+    error::{Error, InnerError, Result},
+    interpolate::{config_env, envsubst, envsubst_known},
+};
+
+pub const CONFIG_FILE: &str = "jocker.yml";
+pub const CONFIG_OVERRIDE_FILE: &str = "jocker.override.yml";
+pub const CONFIG_FILES: [&str; 2] = [CONFIG_FILE, CONFIG_OVERRIDE_FILE];
+/// Configuration files looked for in the target directory, by order of preference
+pub const CONFIG_FILE_CANDIDATES: [&str; 3] = [CONFIG_FILE, "jocker.toml", "jocker.json"];
+
+/// Format of a configuration file, given by its extension
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ConfigFormat {
+    #[default]
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Format of given file, YAML unless it has a `toml` or `json` extension
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::Toml,
+            Some("json") => Self::Json,
+            _ => Self::Yaml,
+        }
+    }
+}
 
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+/// First existing configuration file of given directory, `jocker.yml` if there is none
+pub fn find_config_file(target_dir: &Path) -> PathBuf {
+    CONFIG_FILE_CANDIDATES
+        .iter()
+        .map(|file| target_dir.join(file))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| target_dir.join(CONFIG_FILE))
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigFile {
+    /// Configuration files merged into this one, relative to it. Stacks, processes and defaults of
+    /// this file take precedence over included ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<PathBuf>,
     pub default: Option<ConfigDefault>,
     #[serde(default)]
     pub stacks: HashMap<String, ConfigStack>,
+    #[serde(default)]
     pub processes: HashMap<String, ConfigProcess>,
+    /// Secrets processes may be given as environment variables, by name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub secrets: HashMap<String, ConfigSecret>,
 }
 
 impl ConfigFile {
-    pub fn load(target_dir: &Path) -> Result<Option<Self>> {
-        let filepath = target_dir.join("jocker.yml");
+    pub fn load(filepath: &Path) -> Result<Option<Self>> {
         if !filepath.exists() {
             return Ok(None);
         }
-        let file = File::open(filepath)?;
-        let reader = BufReader::new(file);
-        let res = serde_yml::from_reader(reader)?;
-        Ok(res)
+        let config = Self::load_with_includes(filepath, 0, &mut HashSet::new(), &mut Vec::new())?;
+        Ok(Some(config.resolve_extends()?.interpolate(&config_env())))
+    }
+
+    /// Given configuration file and every file it includes, recursively
+    pub fn files(filepath: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        if filepath.exists() {
+            Self::load_with_includes(filepath, 0, &mut HashSet::new(), &mut files)?;
+        }
+        Ok(files)
+    }
+
+    /// Load given file, merging files it includes into it
+    ///
+    /// `browsed_files` holds the chain of files including this one, to detect loops, while `files`
+    /// collects every loaded file.
+    fn load_with_includes(
+        filepath: &Path,
+        recursion_level: u8,
+        browsed_files: &mut HashSet<PathBuf>,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<Self> {
+        if recursion_level > MAX_RECURSION_LEVEL {
+            return Err(Error::new(InnerError::RecursionDeepnessTooHigh));
+        }
+        let filepath = fs::canonicalize(filepath).map_err(|e| {
+            Error::from(e).add_context(format!(
+                "Unable to read configuration file {}",
+                filepath.display()
+            ))
+        })?;
+        if !browsed_files.insert(filepath.clone()) {
+            return Err(Error::new(InnerError::RecursionLoop)
+                .add_context(format!("{} includes itself", filepath.display())));
+        }
+        files.push(filepath.clone());
+        let content = fs::read_to_string(&filepath)?;
+        let mut config = Self::parse(ConfigFormat::from_path(&filepath), &content)
+            .map_err(|e| e.add_context(format!("In {}", filepath.display())))?
+            .unwrap_or_default();
+        config.merge_includes(&filepath, recursion_level, browsed_files, files)?;
+        browsed_files.remove(&filepath);
+        Ok(config)
+    }
+
+    fn merge_includes(
+        &mut self,
+        filepath: &Path,
+        recursion_level: u8,
+        browsed_files: &mut HashSet<PathBuf>,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        let dir = filepath.parent().unwrap_or(Path::new("."));
+        for include in self.include.clone() {
+            let included = Self::load_with_includes(
+                &dir.join(include),
+                recursion_level + 1,
+                browsed_files,
+                files,
+            )?;
+            self.merge(included);
+        }
+        Ok(())
+    }
+
+    /// Merge files included by this configuration, read from given file
+    pub(crate) fn merge_included_files(&mut self, filepath: &Path) -> Result<()> {
+        let filepath = fs::canonicalize(filepath).unwrap_or(filepath.to_path_buf());
+        let mut browsed_files = HashSet::from([filepath.clone()]);
+        self.merge_includes(&filepath, 0, &mut browsed_files, &mut Vec::new())
+    }
+
+    /// Add stacks, processes, secrets and defaults of given configuration missing from this one
+    pub fn merge(&mut self, other: ConfigFile) {
+        for (name, stack) in other.stacks {
+            self.stacks.entry(name).or_insert(stack);
+        }
+        for (name, process) in other.processes {
+            self.processes.entry(name).or_insert(process);
+        }
+        for (name, secret) in other.secrets {
+            self.secrets.entry(name).or_insert(secret);
+        }
+        match (self.default.as_mut(), other.default) {
+            (None, default) => self.default = default,
+            (Some(default), Some(other)) => {
+                default.stack = default.stack.take().or(other.stack);
+                default.process = default.process.take().or(other.process);
+                default.logs = default.logs.take().or(other.logs);
+                default.build = default.build.take().or(other.build);
+                default.history = default.history.take().or(other.history);
+                default.tasks = default.tasks.take().or(other.tasks);
+            }
+            (Some(_), None) => {}
+        }
+    }
+
+    /// Deserialize given content, without resolving nor interpolating anything
+    pub fn parse(format: ConfigFormat, content: &str) -> Result<Option<Self>> {
+        Ok(match format {
+            ConfigFormat::Yaml => serde_yml::from_str(content)?,
+            ConfigFormat::Toml => Some(toml::from_str(content)?),
+            ConfigFormat::Json => Some(serde_json::from_str(content)?),
+        })
+    }
+
+    /// Merge processes with the ones they extend
+    fn resolve_extends(mut self) -> Result<Self> {
+        let mut processes = HashMap::with_capacity(self.processes.len());
+        for process_name in self.processes.keys() {
+            let process =
+                Self::extended_process(&self.processes, process_name, 0, &mut HashSet::new())?;
+            processes.insert(process_name.to_owned(), process);
+        }
+        self.processes = processes;
+        Ok(self)
+    }
+
+    fn extended_process(
+        processes: &HashMap<String, ConfigProcess>,
+        process_name: &str,
+        recursion_level: u8,
+        browsed_processes: &mut HashSet<String>,
+    ) -> Result<ConfigProcess> {
+        if recursion_level > MAX_RECURSION_LEVEL {
+            return Err(Error::new(InnerError::RecursionDeepnessTooHigh));
+        }
+        if !browsed_processes.insert(process_name.to_owned()) {
+            return Err(Error::new(InnerError::RecursionLoop));
+        }
+        let mut process = processes.get(process_name).cloned().ok_or_else(|| {
+            Error::new(InnerError::ProcessNotFound(vec![process_name.to_owned()]))
+        })?;
+        let Some(parent_name) = process.extends.clone() else {
+            return Ok(process);
+        };
+        let parent = Self::extended_process(
+            processes,
+            &parent_name,
+            recursion_level + 1,
+            browsed_processes,
+        )?;
+        if process.binary.is_none() && process.example.is_none() {
+            process.binary = parent.binary;
+            process.example = parent.example;
+            if process.binary.is_none() && process.example.is_none() {
+                process.binary = Some(parent_name);
+            }
+        }
+        process.package = process.package.or(parent.package);
+        process.host = process.host.or(parent.host);
+        if process.args.is_empty() {
+            process.args = parent.args;
+        }
+        if process.cargo_args.is_empty() {
+            process.cargo_args = parent.cargo_args;
+        }
+        let mut env = parent.env;
+        env.extend(process.env);
+        process.env = env;
+        let mut secrets = parent.secrets;
+        secrets.retain(|secret| !process.secrets.contains(secret));
+        secrets.extend(process.secrets);
+        process.secrets = secrets;
+        let mut labels = parent.labels;
+        labels.extend(process.labels);
+        process.labels = labels;
+        Ok(process)
+    }
+
+    /// Substitute variables of given environment in processes `args`, `env`, `command` and `host`
+    ///
+    /// Placeholders of `args` and `command` referring to variables unknown from given environment,
+    /// or defined by the process `env`, are kept to be substituted when the process is launched.
+    fn interpolate(mut self, env: &HashMap<String, String>) -> Self {
+        for process in self.processes.values_mut() {
+            let shell_env: HashMap<String, String> = env
+                .iter()
+                .filter(|(key, _)| !process.env.contains_key(*key))
+                .map(|(key, val)| (key.to_owned(), val.to_owned()))
+                .collect();
+            for val in process.env.values_mut() {
+                *val = envsubst(val, env);
+            }
+            for arg in process.args.iter_mut() {
+                *arg = envsubst_known(arg, &shell_env);
+            }
+            if let Some(command) = process.command.as_mut() {
+                *command = envsubst_known(command, &shell_env);
+            }
+            if let Some(host) = process.host.as_mut() {
+                *host = envsubst(host, env);
+            }
+        }
+        self
     }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigDefault {
     pub stack: Option<String>,
     pub process: Option<ConfigProcessDefault>,
+    pub logs: Option<ConfigLogs>,
+    pub build: Option<ConfigBuild>,
+    pub history: Option<ConfigHistory>,
+    pub tasks: Option<ConfigTasks>,
+}
+
+/// Building of process binaries
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigBuild {
+    /// Maximum number of `cargo build` commands run at once, 2 by default
+    pub parallelism: Option<usize>,
+}
+
+/// Record of the changes of the project state, read with `jocker history`
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigHistory {
+    /// Number of latest changes kept when the configuration is reloaded, 10000 by default
+    pub max_entries: Option<u32>,
 }
 
+/// Tasks left by processes once they exit, kept by schedulers such as pueue
 #[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigTasks {
+    /// Seconds after which a finished task is removed when processes are started, 86400 by
+    /// default
+    pub max_age: Option<u64>,
+}
+
+/// Archiving of process logs into the state directory
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigLogs {
+    /// Size in bytes above which an archive file is rotated, 10 MiB by default
+    pub max_size: Option<u64>,
+    /// Number of rotated archive files kept per process, 5 by default
+    pub retention: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigProcessDefault {
     #[serde(default)]
     pub cargo_args: Vec<String>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigStack {
     #[serde(default)]
     pub inherits: HashSet<String>,
     #[serde(default)]
     pub processes: HashSet<String>,
+    /// Processes left out of the stack, such as heavy ones of an inherited stack
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub exclude: HashSet<String>,
+    /// Maximum number of processes of the stack running at once, others waiting for a free slot.
+    /// Unlimited by default.
+    pub max_parallel: Option<u32>,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigProcess {
+    /// Process to inherit binary, host, args, cargo_args, env, secrets and labels from, own ones
+    /// taking precedence
+    pub extends: Option<String>,
     pub binary: Option<String>,
+    /// Example to run instead of a binary
+    pub example: Option<String>,
+    /// Package the binary or example belongs to, given to cargo with `-p`
+    pub package: Option<String>,
+    /// SSH destination to run the process on, such as `user@server`, instead of the local machine
+    pub host: Option<String>,
+    /// Whether the process keeps running or runs to completion
+    #[serde(default)]
+    pub kind: ConfigProcessKind,
+    pub command: Option<String>,
+    pub profile: Option<String>,
     #[serde(default)]
     pub args: Vec<String>,
     #[serde(default)]
     pub cargo_args: Vec<String>,
     #[serde(default)]
+    pub features: Vec<String>,
+    #[serde(default)]
+    pub no_default_features: bool,
+    #[serde(default)]
+    pub all_features: bool,
+    #[serde(default)]
     pub env: HashMap<String, String>,
+    /// Secrets, defined in the top-level `secrets` section, given to the process as environment
+    /// variables named after them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secrets: Vec<String>,
+    #[serde(default)]
+    pub depends_on: HashMap<String, ConfigDependency>,
+    pub healthcheck: Option<ConfigHealthcheck>,
+    /// Resources the process may use, unlimited by default
+    pub limits: Option<ConfigLimits>,
+    /// Failed exits after which the process is no longer restarted, 3 within 60 seconds by
+    /// default
+    pub crash_loop: Option<ConfigCrashLoop>,
+    /// Number of instances to run, 1 by default
+    pub replicas: Option<u32>,
+    /// TCP ports the process listens on, checked to be free before starting it
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    /// Groups enabling the process with `--enable`, which is always enabled when empty
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Free-form labels, such as `team: payments`, selecting processes with `--label`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigDependency {
+    #[serde(default)]
+    pub condition: ConfigDependencyCondition,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigDependencyCondition {
+    /// Dependency has been launched
+    #[default]
+    Started,
+    /// Dependency passes its healthcheck
+    Healthy,
+    /// Dependency is a task which exited successfully
+    CompletedSuccessfully,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigProcessKind {
+    /// Runs until stopped
+    #[default]
+    Service,
+    /// Runs to completion, such as migrations, seeders or code generation. `start` waits for it
+    /// to exit before launching its dependents.
+    Task,
+}
+
+/// Probe telling whether a process is ready to serve its dependents
+///
+/// When several probes are given, all of them must succeed.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigHealthcheck {
+    /// Shell command that must exit successfully
+    pub command: Option<String>,
+    /// Address, or local port, that must accept TCP connections
+    pub tcp: Option<String>,
+    /// URL that must answer with a 2xx status
+    pub http: Option<String>,
+    /// Seconds to wait for the process to be healthy
+    pub timeout: Option<u64>,
+}
+
+/// Resources a process may use
+///
+/// Enforced through cgroups v2 when a systemd user instance is available. Otherwise, memory caps
+/// the address space of the process and CPU only lowers its priority.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigLimits {
+    /// Maximum memory, in bytes or with a `K`, `M`, `G` or `T` suffix such as `512M`
+    pub memory: Option<String>,
+    /// Maximum number of CPUs, which may be fractional such as `1.5`
+    pub cpus: Option<f64>,
+}
+
+/// Number of failed exits within a window after which a process is crash looping
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigCrashLoop {
+    /// Failed exits the process may have within the window, 3 by default
+    pub max_failures: Option<u32>,
+    /// Seconds failed exits are counted over, 60 by default
+    pub window: Option<u64>,
+}
+
+/// Source a secret is read from when a process using it is started, exactly one being given
+///
+/// Values are never written to the state database nor printed.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigSecret {
+    /// File holding the value, relative to the project directory. A trailing newline is dropped.
+    pub file: Option<PathBuf>,
+    /// Entry of the `pass` password store, whose first line is the value
+    pub pass: Option<String>,
+    /// Shell command printing the value
+    pub command: Option<String>,
 }
 
 #[cfg(test)]
@@ -70,11 +483,160 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn parse_formats() {
+        let yaml = "processes:\n  foo:\n    binary: bar\n    args:\n      - --baz\n";
+        let toml = "[processes.foo]\nbinary = \"bar\"\nargs = [\"--baz\"]\n";
+        let json = r#"{"processes": {"foo": {"binary": "bar", "args": ["--baz"]}}}"#;
+        for (format, content) in [
+            (ConfigFormat::Yaml, yaml),
+            (ConfigFormat::Toml, toml),
+            (ConfigFormat::Json, json),
+        ] {
+            let config = ConfigFile::parse(format, content).unwrap().unwrap();
+            assert_eq!(config.processes["foo"].binary.as_deref(), Some("bar"));
+            assert_eq!(config.processes["foo"].args, vec!["--baz"]);
+        }
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("deploy/jocker.toml")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("jocker.yaml")),
+            ConfigFormat::Yaml
+        );
+    }
+
+    #[test]
+    fn include_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("services")).unwrap();
+        fs::write(
+            dir.path().join("jocker.yml"),
+            "include:\n  - services/api.yml\nprocesses:\n  api:\n    binary: api-v2\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("services/api.yml"),
+            "include:\n  - workers.toml\nprocesses:\n  api:\n    binary: api\n  db: {}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("services/workers.toml"),
+            "[default]\nstack = \"all\"\n[stacks.all]\nprocesses = [\"api\", \"worker\"]\n[processes.worker]\n",
+        )
+        .unwrap();
+
+        let config = ConfigFile::load(&dir.path().join("jocker.yml"))
+            .unwrap()
+            .unwrap();
+        let mut process_names: Vec<&String> = config.processes.keys().collect();
+        process_names.sort();
+        assert_eq!(process_names, vec!["api", "db", "worker"]);
+        assert_eq!(config.processes["api"].binary.as_deref(), Some("api-v2"));
+        assert!(config.stacks.contains_key("all"));
+        assert_eq!(config.default.and_then(|d| d.stack).as_deref(), Some("all"));
+        assert_eq!(
+            ConfigFile::files(&dir.path().join("jocker.yml"))
+                .unwrap()
+                .len(),
+            3
+        );
+
+        fs::write(
+            dir.path().join("services/workers.toml"),
+            "include = [\"../jocker.yml\"]\n",
+        )
+        .unwrap();
+        assert!(ConfigFile::load(&dir.path().join("jocker.yml")).is_err());
+    }
+
+    #[test]
+    fn extend_processes() {
+        let config: ConfigFile = serde_yml::from_str(
+            r#"
+processes:
+  worker:
+    args:
+      - --queue=default
+    cargo_args:
+      - --locked
+    env:
+      LEVEL: info
+      REGION: eu
+  mailer:
+    extends: worker
+    args:
+      - --queue=mails
+    env:
+      LEVEL: debug
+  urgent-mailer:
+    extends: mailer
+"#,
+        )
+        .unwrap();
+        let config = config.resolve_extends().unwrap();
+        let mailer = &config.processes["mailer"];
+        assert_eq!(mailer.binary.as_deref(), Some("worker"));
+        assert_eq!(mailer.args, vec!["--queue=mails"]);
+        assert_eq!(mailer.cargo_args, vec!["--locked"]);
+        assert_eq!(mailer.env["LEVEL"], "debug");
+        assert_eq!(mailer.env["REGION"], "eu");
+        let urgent_mailer = &config.processes["urgent-mailer"];
+        assert_eq!(urgent_mailer.binary.as_deref(), Some("worker"));
+        assert_eq!(urgent_mailer.args, vec!["--queue=mails"]);
+        assert_eq!(config.processes["worker"].binary, None);
+
+        let config: ConfigFile = serde_yml::from_str(
+            r#"
+processes:
+  foo:
+    extends: bar
+  bar:
+    extends: foo
+"#,
+        )
+        .unwrap();
+        assert!(config.resolve_extends().is_err());
+    }
+
+    #[test]
+    fn interpolate_processes() {
+        let config: ConfigFile = serde_yml::from_str(
+            r#"
+processes:
+  web:
+    command: ./serve --port=${PORT:-8080} --root=${ROOT}
+    args:
+      - --user=${USER_NAME}
+      - --level=${LEVEL}
+    env:
+      LEVEL: ${LOG_LEVEL:-info}
+      NAME: ${USER_NAME}
+"#,
+        )
+        .unwrap();
+        let env = HashMap::from([
+            ("USER_NAME".to_owned(), "alice".to_owned()),
+            ("LEVEL".to_owned(), "trace".to_owned()),
+        ]);
+        let config = config.interpolate(&env);
+        let web = &config.processes["web"];
+        assert_eq!(
+            web.command.as_deref(),
+            Some("./serve --port=${PORT:-8080} --root=${ROOT}")
+        );
+        // LEVEL of the process environment takes precedence over the shell one
+        assert_eq!(web.args, vec!["--user=alice", "--level=${LEVEL}"]);
+        assert_eq!(web.env["LEVEL"], "info");
+        assert_eq!(web.env["NAME"], "alice");
+    }
+
     #[test]
     #[ignore = "Temporary thing to generate JsonSchema"]
     fn generate_json_schema() {
         let schema = schema_for!(ConfigFile);
-        File::create(
+        fs::File::create(
             Path::new(env!("CARGO_MANIFEST_DIR"))
                 .join("..")
                 .join("..")
@@ -0,0 +1,256 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+
+use crate::{
+    common::Exec,
+    error::{Error, InnerError, Result},
+    events::{parse_since, Events, EventsArgs},
+    logs::{Logs, LogsArgs},
+    ps::{Ps, PsArgs},
+    start::{Start, StartArgs},
+    state::State,
+    stop::{Stop, StopArgs},
+};
+
+#[cfg(unix)]
+const SOCKET_FILE: &str = "jocker.sock";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DaemonArgs {
+    pub port: Option<u16>,
+}
+
+/// Long-running server exposing a small HTTP/JSON API over [`State`]
+///
+/// Routes:
+/// - `GET /processes`
+/// - `POST /processes/{name}/start`
+/// - `POST /processes/{name}/stop`
+/// - `GET /processes/{name}/logs`
+/// - `GET /events?since={date or duration}`
+pub struct Daemon {
+    args: DaemonArgs,
+    state: Arc<State>,
+}
+
+impl Daemon {
+    pub fn new(args: DaemonArgs, state: Arc<State>) -> Self {
+        Daemon { args, state }
+    }
+
+    /// Serve requests on the unix socket of the project state directory
+    #[cfg(unix)]
+    async fn listen_socket(&self) -> Result<()> {
+        let socket_path = self.state.get_project_dir().join(SOCKET_FILE);
+        if socket_path.exists() {
+            std::fs::remove_file(&socket_path)?;
+        }
+        let listener = tokio::net::UnixListener::bind(&socket_path)?;
+        info!("Listening on {}", socket_path.display());
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tokio::spawn(handle(self.state.clone(), stream));
+        }
+    }
+
+    #[cfg(not(unix))]
+    async fn listen_socket(&self) -> Result<()> {
+        Err(Error::new(InnerError::Parse(
+            "Unix sockets are not available on this platform, use --port".to_owned(),
+        )))
+    }
+}
+
+impl Exec<()> for Daemon {
+    async fn exec(&self) -> Result<()> {
+        if let Some(port) = self.args.port {
+            let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+            info!("Listening on http://127.0.0.1:{port}");
+            loop {
+                let (stream, _) = listener.accept().await?;
+                tokio::spawn(handle(self.state.clone(), stream));
+            }
+        }
+
+        self.listen_socket().await
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    ListProcesses,
+    StartProcess(String),
+    StopProcess(String),
+    ProcessLogs(String),
+    ListEvents(Option<String>),
+}
+
+impl Route {
+    fn parse(method: &str, path: &str) -> Option<Self> {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        match (method, segments.as_slice()) {
+            ("GET", ["processes"]) => Some(Self::ListProcesses),
+            ("POST", ["processes", name, "start"]) => Some(Self::StartProcess(name.to_string())),
+            ("POST", ["processes", name, "stop"]) => Some(Self::StopProcess(name.to_string())),
+            ("GET", ["processes", name, "logs"]) => Some(Self::ProcessLogs(name.to_string())),
+            ("GET", ["events"]) => Some(Self::ListEvents(
+                query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("since="))
+                    .map(ToString::to_string),
+            )),
+            _ => None,
+        }
+    }
+}
+
+async fn handle<S: AsyncRead + AsyncWrite + Unpin>(state: Arc<State>, stream: S) {
+    let mut stream = BufReader::new(stream);
+    let (status, body) = match read_request(&mut stream).await {
+        Ok(Some(route)) => match dispatch(state, route).await {
+            Ok(body) => (200, body),
+            Err(e) => (500, json!({ "error": e.to_string() })),
+        },
+        Ok(None) => (404, json!({ "error": "Not found" })),
+        Err(e) => (400, json!({ "error": e.to_string() })),
+    };
+    let body = body.to_string();
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    };
+    let response = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let stream = stream.get_mut();
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        warn!("Error while answering request: {e}");
+    }
+    let _ = stream.shutdown().await;
+}
+
+/// Read request head, discarding headers and body, and resolve its route
+async fn read_request<S: AsyncRead + Unpin>(stream: &mut BufReader<S>) -> Result<Option<Route>> {
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(Error::new(InnerError::Parse(request_line)));
+    };
+    let mut header = String::new();
+    while stream.read_line(&mut header).await? != 0 && header != "\r\n" {
+        header.clear();
+    }
+    Ok(Route::parse(method, path))
+}
+
+async fn dispatch(state: Arc<State>, route: Route) -> Result<serde_json::Value> {
+    Ok(match route {
+        Route::ListProcesses => to_json(Ps::new(PsArgs::default(), state).run().await?)?,
+        Route::StartProcess(name) => {
+            Start::new(
+                StartArgs {
+                    processes: vec![name],
+                    ..Default::default()
+                },
+                state,
+            )
+            .exec()
+            .await?;
+            json!({ "status": "started" })
+        }
+        Route::StopProcess(name) => {
+            Stop::new(
+                StopArgs {
+                    processes: vec![name],
+                    ..Default::default()
+                },
+                state,
+            )
+            .exec()
+            .await?;
+            json!({ "status": "stopped" })
+        }
+        Route::ProcessLogs(name) => {
+            let entries = Logs::new(
+                LogsArgs {
+                    processes: vec![name],
+                    ..Default::default()
+                },
+                state,
+            )
+            .run()
+            .await?;
+            let lines: Vec<String> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.line))
+                .collect()
+                .await;
+            to_json(lines)?
+        }
+        Route::ListEvents(since) => {
+            let since = since.as_deref().map(parse_since).transpose()?;
+            to_json(
+                Events::new(
+                    EventsArgs {
+                        since,
+                        ..Default::default()
+                    },
+                    state,
+                )
+                .run()
+                .await?,
+            )?
+        }
+    })
+}
+
+fn to_json<T: Serialize>(value: T) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_route() {
+        assert_eq!(
+            Route::parse("GET", "/processes"),
+            Some(Route::ListProcesses)
+        );
+        assert_eq!(
+            Route::parse("POST", "/processes/foo/start"),
+            Some(Route::StartProcess("foo".to_owned()))
+        );
+        assert_eq!(
+            Route::parse("POST", "/processes/foo/stop?kill=true"),
+            Some(Route::StopProcess("foo".to_owned()))
+        );
+        assert_eq!(
+            Route::parse("GET", "/processes/foo/logs"),
+            Some(Route::ProcessLogs("foo".to_owned()))
+        );
+        assert_eq!(
+            Route::parse("GET", "/events"),
+            Some(Route::ListEvents(None))
+        );
+        assert_eq!(
+            Route::parse("GET", "/events?since=10m"),
+            Some(Route::ListEvents(Some("10m".to_owned())))
+        );
+        assert_eq!(Route::parse("GET", "/processes/foo/start"), None);
+        assert_eq!(Route::parse("DELETE", "/processes"), None);
+    }
+}
@@ -1,17 +1,49 @@
-use std::{collections::HashSet, path::Path, str::FromStr as _};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr as _,
+    time::Duration,
+};
 
-use chrono::{DateTime, TimeZone, Utc};
-use sqlx::{Pool, Sqlite, SqlitePool};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+    Pool, Sqlite, SqliteConnection, SqlitePool,
+};
 use tokio::fs::File;
 use url::Url;
 
 use crate::{
     command::cargo::BinaryPackage,
-    common::{Process, ProcessState, Stack},
+    common::{
+        BuildRecord, EnvVar, Event, HistoryEntry, HistoryKind, Process, ProcessState, Stack,
+    },
     error::{Error, InnerError, Result},
 };
 
 const DB_FILE: &str = "db.sqlite3";
+/// Maximum wait for another connection to release the database by default
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Settings of the connections to the database of a project, set through
+/// [`StateBuilder`](crate::state::StateBuilder)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DatabaseOptions {
+    /// Maximum wait for another connection to release the database, before failing with
+    /// `database is locked`
+    pub busy_timeout: Duration,
+    /// Write-ahead logging, letting commands read while another one writes
+    pub wal: bool,
+}
+
+impl Default for DatabaseOptions {
+    fn default() -> Self {
+        Self {
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            wal: true,
+        }
+    }
+}
 
 pub struct BinaryPackageSql {
     pub name: String,
@@ -31,12 +63,42 @@ impl TryFrom<BinaryPackageSql> for BinaryPackage {
 
 pub struct ProcessSql {
     pub name: String,
+    pub base_name: String,
+    pub instance: i64,
+    pub replicas: i64,
     pub binary: String,
+    pub command: Option<String>,
+    pub profile: Option<String>,
     pub state: String,
     pub pid: Option<i64>,
     pub args: String,
     pub cargo_args: String,
+    pub features: String,
+    pub no_default_features: bool,
+    pub all_features: bool,
     pub env: String,
+    pub ports: String,
+    pub groups: String,
+    pub depends_on: String,
+    pub healthcheck: Option<String>,
+    pub limits: Option<String>,
+    pub crash_loop: Option<String>,
+    pub started_at: Option<NaiveDateTime>,
+    pub stopped_at: Option<NaiveDateTime>,
+    pub restart_count: i64,
+    pub exit_code: Option<i64>,
+    pub failure_count: i64,
+    pub failures_since: Option<NaiveDateTime>,
+    pub archived_lines: i64,
+    pub package: Option<String>,
+    pub example: bool,
+    pub host: Option<String>,
+    pub kind: String,
+    pub command_line: Option<String>,
+    pub stack: Option<String>,
+    pub secrets: String,
+    pub launched_env: String,
+    pub labels: String,
 }
 
 impl TryFrom<ProcessSql> for Process {
@@ -45,12 +107,123 @@ impl TryFrom<ProcessSql> for Process {
     fn try_from(value: ProcessSql) -> std::result::Result<Self, Self::Error> {
         Ok(Self {
             name: value.name,
+            base_name: value.base_name,
+            instance: value.instance.try_into()?,
+            replicas: value.replicas.try_into()?,
             binary: value.binary,
+            command: value.command,
+            profile: value.profile,
             state: value.state.try_into()?,
             pid: value.pid.map(TryFrom::try_from).transpose()?,
             args: serde_json::from_str(&value.args)?,
             cargo_args: serde_json::from_str(&value.cargo_args)?,
+            features: serde_json::from_str(&value.features)?,
+            no_default_features: value.no_default_features,
+            all_features: value.all_features,
             env: serde_json::from_str(&value.env)?,
+            ports: serde_json::from_str(&value.ports)?,
+            groups: serde_json::from_str(&value.groups)?,
+            labels: serde_json::from_str(&value.labels)?,
+            depends_on: serde_json::from_str(&value.depends_on)?,
+            healthcheck: value
+                .healthcheck
+                .map(|v| serde_json::from_str(&v))
+                .transpose()?,
+            limits: value
+                .limits
+                .map(|v| serde_json::from_str(&v))
+                .transpose()?,
+            crash_loop: value
+                .crash_loop
+                .map(|v| serde_json::from_str(&v))
+                .transpose()?,
+            started_at: value.started_at.map(|v| Utc.from_utc_datetime(&v)),
+            stopped_at: value.stopped_at.map(|v| Utc.from_utc_datetime(&v)),
+            restart_count: value.restart_count.try_into()?,
+            exit_code: value.exit_code.map(TryFrom::try_from).transpose()?,
+            failure_count: value.failure_count.try_into()?,
+            failures_since: value.failures_since.map(|v| Utc.from_utc_datetime(&v)),
+            archived_lines: value.archived_lines.try_into()?,
+            package: value.package,
+            example: value.example,
+            host: value.host,
+            kind: value.kind.try_into()?,
+            command_line: value.command_line,
+            stack: value.stack,
+            secrets: serde_json::from_str(&value.secrets)?,
+            launched_env: serde_json::from_str(&value.launched_env)?,
+        })
+    }
+}
+
+pub struct EventSql {
+    pub process_name: String,
+    pub kind: String,
+    pub created_at: NaiveDateTime,
+    pub exit_code: Option<i64>,
+}
+
+impl TryFrom<EventSql> for Event {
+    type Error = Error;
+
+    fn try_from(value: EventSql) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            process_name: value.process_name,
+            kind: value.kind.try_into()?,
+            created_at: Utc.from_utc_datetime(&value.created_at),
+            exit_code: value.exit_code.map(TryFrom::try_from).transpose()?,
+        })
+    }
+}
+
+pub struct HistoryEntrySql {
+    pub version: i64,
+    pub created_at: NaiveDateTime,
+    pub kind: String,
+    pub process_name: Option<String>,
+    pub change: String,
+    pub exit_code: Option<i64>,
+}
+
+impl TryFrom<HistoryEntrySql> for HistoryEntry {
+    type Error = Error;
+
+    fn try_from(value: HistoryEntrySql) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            version: value.version,
+            created_at: Utc.from_utc_datetime(&value.created_at),
+            kind: value.kind.try_into()?,
+            process_name: value.process_name,
+            change: match value.exit_code {
+                Some(exit_code) => format!("{} (exit code {exit_code})", value.change),
+                None => value.change,
+            },
+        })
+    }
+}
+
+pub struct BuildRecordSql {
+    pub id: i64,
+    pub started_at: NaiveDateTime,
+    pub duration_ms: i64,
+    pub processes: String,
+    pub profile: Option<String>,
+    pub compiled: i64,
+    pub success: bool,
+}
+
+impl TryFrom<BuildRecordSql> for BuildRecord {
+    type Error = Error;
+
+    fn try_from(value: BuildRecordSql) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.id,
+            started_at: Utc.from_utc_datetime(&value.started_at),
+            duration_ms: value.duration_ms.try_into()?,
+            processes: serde_json::from_str(&value.processes)?,
+            profile: value.profile,
+            compiled: value.compiled.try_into()?,
+            success: value.success,
         })
     }
 }
@@ -60,8 +233,11 @@ pub(crate) struct Database {
 }
 
 impl Database {
-    pub(crate) async fn new(database_directory_path: impl AsRef<Path>) -> Result<Self> {
-        let pool = Self::init_pool(&database_directory_path).await?;
+    pub(crate) async fn new(
+        database_directory_path: impl AsRef<Path>,
+        options: DatabaseOptions,
+    ) -> Result<Self> {
+        let pool = Self::init_pool(&database_directory_path, options).await?;
         Ok(Self { pool })
     }
 
@@ -99,6 +275,21 @@ impl Database {
         Ok(binaries_updated_at)
     }
 
+    pub(crate) async fn get_cargo_manifests_hash(&self) -> Result<Option<String>> {
+        let mut conn = self.pool.acquire().await?;
+        let cargo_manifests_hash = sqlx::query_scalar!(
+            r#"
+                SELECT cargo_manifests_hash
+                FROM metadata
+                LIMIT 1
+            "#,
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten();
+        Ok(cargo_manifests_hash)
+    }
+
     pub(crate) async fn get_config_updated_at(&self) -> Result<Option<DateTime<Utc>>> {
         let mut conn = self.pool.acquire().await?;
         let config_updated_at = sqlx::query_scalar!(
@@ -115,6 +306,22 @@ impl Database {
         Ok(config_updated_at)
     }
 
+    pub(crate) async fn get_config_file(&self) -> Result<Option<PathBuf>> {
+        let mut conn = self.pool.acquire().await?;
+        let config_file = sqlx::query_scalar!(
+            r#"
+                SELECT config_file
+                FROM metadata
+                LIMIT 1
+            "#,
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten()
+        .map(PathBuf::from);
+        Ok(config_file)
+    }
+
     pub(crate) async fn get_default_stack(&self) -> Result<Option<String>> {
         let mut conn = self.pool.acquire().await?;
         let default_stack = sqlx::query_scalar!(
@@ -130,12 +337,243 @@ impl Database {
         Ok(default_stack)
     }
 
+    pub(crate) async fn get_selected_stack(&self) -> Result<Option<String>> {
+        let mut conn = self.pool.acquire().await?;
+        let selected_stack = sqlx::query_scalar!(
+            r#"
+                SELECT selected_stack
+                FROM metadata
+                LIMIT 1
+            "#,
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten();
+        Ok(selected_stack)
+    }
+
+    /// Record given event in the history, as an entry of kind [`HistoryKind::Event`]
+    pub(crate) async fn add_event(&self, event: &Event) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let kind = HistoryKind::Event.to_string();
+        let change = event.kind.to_string();
+        sqlx::query!(
+            r#"
+                INSERT INTO history (created_at, kind, process_name, change, exit_code)
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            event.created_at,
+            kind,
+            event.process_name,
+            change,
+            event.exit_code,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn get_events(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Event>> {
+        let mut conn = self.pool.acquire().await?;
+        let since = since.unwrap_or(DateTime::UNIX_EPOCH);
+        let kind = HistoryKind::Event.to_string();
+        let events = sqlx::query_as!(
+            EventSql,
+            r#"
+                SELECT process_name AS "process_name!", change AS kind, created_at, exit_code
+                FROM history
+                WHERE kind = $1 AND created_at >= $2
+                ORDER BY created_at ASC, version ASC
+            "#,
+            kind,
+            since,
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<Vec<_>>>()?;
+        Ok(events)
+    }
+
+    pub(crate) async fn add_history(
+        &self,
+        kind: HistoryKind,
+        process_name: Option<&str>,
+        change: &str,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        Self::insert_history(&mut conn, kind, process_name, change).await
+    }
+
+    /// History of the project, or of given process only, oldest change first
+    pub(crate) async fn get_history(
+        &self,
+        process_name: Option<&str>,
+    ) -> Result<Vec<HistoryEntry>> {
+        let mut conn = self.pool.acquire().await?;
+        let history = sqlx::query_as!(
+            HistoryEntrySql,
+            r#"
+                SELECT version, created_at, kind, process_name, change, exit_code
+                FROM history
+                WHERE ?1 IS NULL OR process_name = ?1
+                ORDER BY version ASC
+            "#,
+            process_name,
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<Vec<_>>>()?;
+        Ok(history)
+    }
+
+    /// Remove the oldest history entries, keeping the last `max_entries` ones
+    pub(crate) async fn prune_history(&self, max_entries: u32) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"
+                DELETE FROM history
+                WHERE version <= (SELECT MAX(version) FROM history) - ?1
+            "#,
+            max_entries,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a build, whose ID is assigned by the database, removing the oldest ones past the
+    /// last `max_builds`
+    pub(crate) async fn add_build(&self, build: &BuildRecord, max_builds: u32) -> Result<()> {
+        let duration_ms = i64::try_from(build.duration_ms)?;
+        let processes = serde_json::to_value(&build.processes)?;
+        let mut conn = self.pool.begin().await?;
+        sqlx::query!(
+            r#"
+                INSERT INTO build (started_at, duration_ms, processes, profile, compiled, success)
+                VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            build.started_at,
+            duration_ms,
+            processes,
+            build.profile,
+            build.compiled,
+            build.success,
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            r#"
+                DELETE FROM build
+                WHERE id <= (SELECT MAX(id) FROM build) - ?1
+            "#,
+            max_builds,
+        )
+        .execute(&mut *conn)
+        .await?;
+        conn.commit().await?;
+        Ok(())
+    }
+
+    /// Last `limit` builds, or the ones covering given process only, most recent first
+    pub(crate) async fn get_builds(
+        &self,
+        process_name: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<BuildRecord>> {
+        let mut conn = self.pool.acquire().await?;
+        let builds = sqlx::query_as!(
+            BuildRecordSql,
+            r#"
+                SELECT id, started_at, duration_ms, processes, profile, compiled, success
+                FROM build
+                WHERE ?1 IS NULL OR EXISTS (SELECT 1 FROM json_each(processes) WHERE value = ?1)
+                ORDER BY id DESC
+                LIMIT ?2
+            "#,
+            process_name,
+            limit,
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<Vec<_>>>()?;
+        Ok(builds)
+    }
+
+    /// Record given artifacts as built with given flags
+    pub(crate) async fn set_built_artifacts(&self, paths: &[String], flags: &str) -> Result<()> {
+        let mut conn = self.pool.begin().await?;
+        for path in paths {
+            sqlx::query!(
+                r#"
+                    INSERT INTO built_artifact (path, flags)
+                    VALUES (?1, ?2)
+                    ON CONFLICT(path) DO UPDATE SET
+                        flags = excluded.flags
+                "#,
+                path,
+                flags,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+        conn.commit().await?;
+        Ok(())
+    }
+
+    /// Flags artifacts were last built with, by path
+    pub(crate) async fn get_built_artifacts(&self) -> Result<HashMap<String, String>> {
+        let mut conn = self.pool.acquire().await?;
+        let rows = sqlx::query!(
+            r#"
+                SELECT path, flags
+                FROM built_artifact
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(rows.into_iter().map(|row| (row.path, row.flags)).collect())
+    }
+
+    async fn insert_history(
+        conn: &mut SqliteConnection,
+        kind: HistoryKind,
+        process_name: Option<&str>,
+        change: &str,
+    ) -> Result<()> {
+        let kind = kind.to_string();
+        let created_at = Utc::now();
+        sqlx::query!(
+            r#"
+                INSERT INTO history (created_at, kind, process_name, change)
+                VALUES ($1, $2, $3, $4)
+            "#,
+            created_at,
+            kind,
+            process_name,
+            change,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
     pub(crate) async fn get_processes(&self) -> Result<Vec<Process>> {
         let mut conn = self.pool.acquire().await?;
         let processes = sqlx::query_as!(
             ProcessSql,
             r#"
-                SELECT name, binary, state, pid, args, cargo_args, env
+                SELECT
+                    name, base_name, instance, replicas, binary, command, profile, state, pid,
+                    args, cargo_args, features, no_default_features, all_features, env, ports,
+                    depends_on, healthcheck, started_at, restart_count, exit_code, failure_count,
+                    failures_since, archived_lines, groups, package, example, host, limits, kind,
+                    command_line, stack, secrets, launched_env, labels, stopped_at, crash_loop
                 FROM process
                 ORDER BY name ASC
             "#,
@@ -150,9 +588,9 @@ impl Database {
 
     pub(crate) async fn get_stack(&self, stack: &str) -> Result<Stack> {
         let mut conn = self.pool.begin().await?;
-        let name = sqlx::query_scalar!(
+        let row = sqlx::query!(
             r#"
-                SELECT name
+                SELECT name, max_parallel, user_created
                 FROM stack
                 WHERE name = $1
             "#,
@@ -187,12 +625,33 @@ impl Database {
         .collect();
         conn.commit().await?;
         Ok(Stack {
-            name,
+            name: row.name,
             processes,
             inherited_processes,
+            max_parallel: row.max_parallel.map(TryInto::try_into).transpose()?,
+            user_created: row.user_created,
         })
     }
 
+    pub(crate) async fn get_stacks(&self) -> Result<Vec<Stack>> {
+        let mut conn = self.pool.acquire().await?;
+        let names = sqlx::query_scalar!(
+            r#"
+                SELECT name
+                FROM stack
+                ORDER BY name ASC
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        drop(conn);
+        let mut stacks = Vec::with_capacity(names.len());
+        for name in names {
+            stacks.push(self.get_stack(&name).await?);
+        }
+        Ok(stacks)
+    }
+
     pub(crate) async fn set_binaries(&self, binaries: &[BinaryPackage]) -> Result<()> {
         let mut conn = self.pool.begin().await?;
         sqlx::query!(
@@ -237,6 +696,24 @@ impl Database {
         Ok(())
     }
 
+    pub(crate) async fn set_cargo_manifests_hash(&self, hash: &Option<String>) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"
+                INSERT INTO metadata (id, cargo_manifests_hash)
+                VALUES ($1, $2)
+                ON CONFLICT(id)
+                DO UPDATE SET
+                    cargo_manifests_hash = excluded.cargo_manifests_hash
+            "#,
+            0,
+            hash,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
     pub(crate) async fn set_config_updated_at(&self, date: DateTime<Utc>) -> Result<()> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query!(
@@ -255,6 +732,25 @@ impl Database {
         Ok(())
     }
 
+    pub(crate) async fn set_config_file(&self, config_file: &Path) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let config_file = config_file.to_string_lossy();
+        sqlx::query!(
+            r#"
+                INSERT INTO metadata (id, config_file)
+                VALUES ($1, $2)
+                ON CONFLICT(id)
+                DO UPDATE SET
+                    config_file = excluded.config_file
+            "#,
+            0,
+            config_file,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
     pub(crate) async fn set_default_stack(&self, stack: &Option<String>) -> Result<()> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query!(
@@ -273,6 +769,24 @@ impl Database {
         Ok(())
     }
 
+    pub(crate) async fn set_selected_stack(&self, stack: &Option<String>) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"
+                INSERT INTO metadata (id, selected_stack)
+                VALUES ($1, $2)
+                ON CONFLICT(id)
+                DO UPDATE SET
+                    selected_stack = excluded.selected_stack
+            "#,
+            0,
+            stack,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
     pub(crate) async fn set_process_pid(&self, process_name: &str, pid: Option<i32>) -> Result<()> {
         let mut conn = self.pool.acquire().await?;
         sqlx::query!(
@@ -289,63 +803,258 @@ impl Database {
         Ok(())
     }
 
-    pub(crate) async fn set_process_state(
+    pub(crate) async fn set_process_exit_code(
         &self,
         process_name: &str,
-        state: ProcessState,
+        exit_code: Option<i32>,
     ) -> Result<()> {
         let mut conn = self.pool.acquire().await?;
-        let state = state.to_string();
         sqlx::query!(
             r#"
                 UPDATE process
-                SET state = ?2
+                SET exit_code = ?2
                 WHERE name = ?1
             "#,
             process_name,
-            state,
+            exit_code,
         )
         .execute(&mut *conn)
         .await?;
         Ok(())
     }
 
-    pub(crate) async fn set_processes(&self, processes: &[Process]) -> Result<()> {
-        let mut conn = self.pool.begin().await?;
-
+    pub(crate) async fn set_process_failures(
+        &self,
+        process_name: &str,
+        failure_count: u32,
+        failures_since: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
         sqlx::query!(
             r#"
-                DELETE FROM process
+                UPDATE process
+                SET failure_count = ?2, failures_since = ?3
+                WHERE name = ?1
             "#,
+            process_name,
+            failure_count,
+            failures_since,
         )
         .execute(&mut *conn)
         .await?;
-        for proc in processes {
-            let state = proc.state.to_string();
-            let pid: Option<i64> = proc.pid.map(TryInto::try_into).transpose()?;
-            let args = serde_json::to_value(&proc.args)?;
-            let cargo_args = serde_json::to_value(&proc.cargo_args)?;
-            let env = serde_json::to_value(&proc.env)?;
-            sqlx::query!(
-                r#"
-                    INSERT INTO process (name, binary, state, pid, args, cargo_args, env)
-                    VALUES ($1, $2, $3, $4, $5, $6, $7)
-                "#,
-                proc.name,
-                proc.binary,
-                state,
-                pid,
-                args,
-                cargo_args,
-                env,
-            )
-            .execute(&mut *conn)
+        Ok(())
+    }
+
+    pub(crate) async fn set_process_archived_lines(
+        &self,
+        process_name: &str,
+        archived_lines: u32,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"
+                UPDATE process
+                SET archived_lines = ?2
+                WHERE name = ?1
+            "#,
+            process_name,
+            archived_lines,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a new start of given process, counting it as a restart if it already ran before
+    pub(crate) async fn set_process_started(
+        &self,
+        process_name: &str,
+        started_at: DateTime<Utc>,
+        command_line: &str,
+        stack: Option<&str>,
+        env: &BTreeMap<String, EnvVar>,
+    ) -> Result<()> {
+        let env = serde_json::to_value(env)?;
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query!(
+            r#"
+                UPDATE process
+                SET
+                    restart_count = CASE
+                        WHEN started_at IS NULL THEN 0
+                        ELSE restart_count + 1
+                    END,
+                    started_at = ?2,
+                    exit_code = NULL,
+                    archived_lines = 0,
+                    command_line = ?3,
+                    stack = ?4,
+                    launched_env = ?5
+                WHERE name = ?1
+            "#,
+            process_name,
+            started_at,
+            command_line,
+            stack,
+            env,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns whether the state of the process changed
+    ///
+    /// Leaving started states records the end of the current run, if it wasn't already.
+    pub(crate) async fn set_process_state(
+        &self,
+        process_name: &str,
+        state: ProcessState,
+    ) -> Result<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let stopping = !state.is_started();
+        let state = state.to_string();
+        let stopped_at = Utc::now();
+        // The state is set again on every refresh, checking it first spares a write, which would
+        // wait for concurrent commands holding the database
+        let current = sqlx::query_scalar!(
+            r#"
+                SELECT state
+                FROM process
+                WHERE name = ?1
+            "#,
+            process_name,
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        if current.is_none_or(|current| current == state) {
+            return Ok(false);
+        }
+        let changed = sqlx::query!(
+            r#"
+                UPDATE process
+                SET
+                    state = ?2,
+                    stopped_at = CASE
+                        WHEN ?3 AND started_at IS NOT NULL
+                            AND (stopped_at IS NULL OR stopped_at < started_at)
+                        THEN ?4
+                        ELSE stopped_at
+                    END
+                WHERE name = ?1 AND state <> ?2
+            "#,
+            process_name,
+            state,
+            stopping,
+            stopped_at,
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected()
+            > 0;
+        Ok(changed)
+    }
+
+    /// Sync stored processes with given ones
+    ///
+    /// Existing processes only get their configuration updated, keeping their pid, state and
+    /// other runtime columns. Processes missing from the given ones are removed.
+    pub(crate) async fn set_processes(&self, processes: &[Process]) -> Result<()> {
+        let names = serde_json::to_value(processes.iter().map(Process::name).collect::<Vec<_>>())?;
+        let mut conn = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM process
+                WHERE name NOT IN (SELECT value FROM json_each(?1))
+            "#,
+            names,
+        )
+        .execute(&mut *conn)
+        .await?;
+        for proc in processes {
+            Self::upsert_process(&mut conn, proc).await?;
+        }
+        conn.commit().await?;
+        Ok(())
+    }
+
+    /// Add given processes, without touching existing ones
+    pub(crate) async fn add_processes(&self, processes: &[Process]) -> Result<()> {
+        let mut conn = self.pool.begin().await?;
+        for proc in processes {
+            Self::upsert_process(&mut conn, proc).await?;
+        }
+        conn.commit().await?;
+        Ok(())
+    }
+
+    pub(crate) async fn remove_processes(&self, process_names: &[String]) -> Result<()> {
+        let mut conn = self.pool.begin().await?;
+        for process_name in process_names {
+            sqlx::query!(
+                r#"
+                    DELETE FROM process
+                    WHERE name = ?1
+                "#,
+                process_name,
+            )
+            .execute(&mut *conn)
             .await?;
         }
         conn.commit().await?;
         Ok(())
     }
 
+    /// Set the number of replicas of every instance of given configured process
+    ///
+    /// The number is also kept apart, to override the configured one when the configuration is
+    /// loaded again.
+    pub(crate) async fn set_process_replicas(&self, base_name: &str, replicas: u32) -> Result<()> {
+        let mut conn = self.pool.begin().await?;
+        sqlx::query!(
+            r#"
+                UPDATE process
+                SET replicas = ?2
+                WHERE base_name = ?1
+            "#,
+            base_name,
+            replicas,
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            r#"
+                INSERT INTO scaled_process (base_name, replicas)
+                VALUES (?1, ?2)
+                ON CONFLICT(base_name) DO UPDATE SET
+                    replicas = excluded.replicas
+            "#,
+            base_name,
+            replicas,
+        )
+        .execute(&mut *conn)
+        .await?;
+        conn.commit().await?;
+        Ok(())
+    }
+
+    /// Number of replicas processes were scaled to, by configured process name
+    pub(crate) async fn get_scaled_replicas(&self) -> Result<HashMap<String, u32>> {
+        let mut conn = self.pool.acquire().await?;
+        let rows = sqlx::query!(
+            r#"
+                SELECT base_name, replicas
+                FROM scaled_process
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        rows.into_iter()
+            .map(|row| Ok((row.base_name, u32::try_from(row.replicas)?)))
+            .collect()
+    }
+
     pub(crate) async fn set_stacks(&self, stacks: &[Stack]) -> Result<()> {
         let processes: HashSet<String> = self
             .get_processes()
@@ -357,76 +1066,242 @@ impl Database {
         // Lock after getting processes to avoid deadlock
         let mut conn = self.pool.begin().await?;
 
+        // Stacks created from the CLI are kept, unless the configuration now defines them
         sqlx::query!(
             r#"
                 DELETE FROM stack
+                WHERE NOT user_created
             "#,
         )
         .execute(&mut *conn)
         .await?;
         for stack in stacks {
-            let stack_processes = stack.processes.iter();
-            let inherited_processes = stack.inherited_processes.iter();
-            let missing_processes: Vec<String> = stack_processes
-                .clone()
-                .chain(inherited_processes.clone())
+            let missing_processes: Vec<String> = stack
+                .processes
+                .iter()
+                .chain(stack.inherited_processes.iter())
                 .filter(|&stack_process| !processes.contains(stack_process))
                 .cloned()
                 .collect();
             if !missing_processes.is_empty() {
-                return Err(Error::new(InnerError::ProcessNotFound(missing_processes)));
+                let hint = "Declare them under `processes`, or remove them from the stack";
+                return Err(Error::new(InnerError::ProcessNotFound(missing_processes))
+                    .add_context(format!("Referenced by stack {}", stack.name))
+                    .with_hint(hint));
             }
             sqlx::query!(
                 r#"
-                    INSERT INTO stack (name)
-                    VALUES ($1)
+                    DELETE FROM stack
+                    WHERE name = $1
                 "#,
                 stack.name,
             )
             .execute(&mut *conn)
             .await?;
-            for process in stack_processes {
-                sqlx::query!(
-                    r#"
-                        INSERT INTO rel_stack_process (stack_name, process_name)
-                        VALUES ($1, $2)
-                    "#,
-                    stack.name,
-                    process,
-                )
-                .execute(&mut *conn)
-                .await?;
-            }
-            for process in inherited_processes {
-                sqlx::query!(
-                    r#"
-                        INSERT INTO rel_stack_inherited_process (stack_name, process_name)
-                        VALUES ($1, $2)
-                    "#,
-                    stack.name,
-                    process,
-                )
-                .execute(&mut *conn)
-                .await?;
-            }
+            Self::insert_stack(&mut conn, stack).await?;
         }
 
         conn.commit().await?;
         Ok(())
     }
 
-    async fn init_pool(database_directory_path: impl AsRef<Path>) -> Result<Pool<Sqlite>> {
+    pub(crate) async fn add_stack(&self, stack: &Stack) -> Result<()> {
+        let mut conn = self.pool.begin().await?;
+        Self::insert_stack(&mut conn, stack).await?;
+        conn.commit().await?;
+        Ok(())
+    }
+
+    /// Returns whether a user-created stack with given name was deleted
+    pub(crate) async fn delete_stack(&self, stack: &str) -> Result<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let result = sqlx::query!(
+            r#"
+                DELETE FROM stack
+                WHERE name = $1 AND user_created
+            "#,
+            stack,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn insert_stack(conn: &mut SqliteConnection, stack: &Stack) -> Result<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO stack (name, max_parallel, user_created)
+                VALUES ($1, $2, $3)
+            "#,
+            stack.name,
+            stack.max_parallel,
+            stack.user_created,
+        )
+        .execute(&mut *conn)
+        .await?;
+        for process in &stack.processes {
+            sqlx::query!(
+                r#"
+                    INSERT INTO rel_stack_process (stack_name, process_name)
+                    VALUES ($1, $2)
+                "#,
+                stack.name,
+                process,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+        for process in &stack.inherited_processes {
+            sqlx::query!(
+                r#"
+                    INSERT INTO rel_stack_inherited_process (stack_name, process_name)
+                    VALUES ($1, $2)
+                "#,
+                stack.name,
+                process,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Insert given process, or only update the configuration of an existing one
+    async fn upsert_process(conn: &mut SqliteConnection, proc: &Process) -> Result<()> {
+        let state = proc.state.to_string();
+        let pid: Option<i64> = proc.pid.map(TryInto::try_into).transpose()?;
+        let args = serde_json::to_value(&proc.args)?;
+        let cargo_args = serde_json::to_value(&proc.cargo_args)?;
+        let features = serde_json::to_value(&proc.features)?;
+        let env = serde_json::to_value(&proc.env)?;
+        let ports = serde_json::to_value(&proc.ports)?;
+        let groups = serde_json::to_value(&proc.groups)?;
+        let labels = serde_json::to_value(&proc.labels)?;
+        let depends_on = serde_json::to_value(&proc.depends_on)?;
+        let secrets = serde_json::to_value(&proc.secrets)?;
+        let launched_env = serde_json::to_value(&proc.launched_env)?;
+        let healthcheck = proc
+            .healthcheck
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let kind = proc.kind.to_string();
+        let limits = proc
+            .limits
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let crash_loop = proc
+            .crash_loop
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        sqlx::query!(
+            r#"
+                INSERT INTO process (
+                    name, base_name, instance, replicas, binary, command, profile, state, pid,
+                    args, cargo_args, features, no_default_features, all_features, env, ports,
+                    depends_on, healthcheck, started_at, restart_count, exit_code, failure_count,
+                    failures_since, archived_lines, groups, package, example, host, limits, kind,
+                    command_line, stack, secrets, launched_env, labels, stopped_at, crash_loop
+                )
+                VALUES (
+                    $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17,
+                    $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32, $33,
+                    $34, $35, $36, $37
+                )
+                ON CONFLICT(name) DO UPDATE SET
+                    base_name = excluded.base_name,
+                    instance = excluded.instance,
+                    replicas = excluded.replicas,
+                    binary = excluded.binary,
+                    command = excluded.command,
+                    profile = excluded.profile,
+                    args = excluded.args,
+                    cargo_args = excluded.cargo_args,
+                    features = excluded.features,
+                    no_default_features = excluded.no_default_features,
+                    all_features = excluded.all_features,
+                    env = excluded.env,
+                    ports = excluded.ports,
+                    depends_on = excluded.depends_on,
+                    healthcheck = excluded.healthcheck,
+                    groups = excluded.groups,
+                    package = excluded.package,
+                    example = excluded.example,
+                    host = excluded.host,
+                    limits = excluded.limits,
+                    crash_loop = excluded.crash_loop,
+                    kind = excluded.kind,
+                    secrets = excluded.secrets,
+                    labels = excluded.labels
+            "#,
+            proc.name,
+            proc.base_name,
+            proc.instance,
+            proc.replicas,
+            proc.binary,
+            proc.command,
+            proc.profile,
+            state,
+            pid,
+            args,
+            cargo_args,
+            features,
+            proc.no_default_features,
+            proc.all_features,
+            env,
+            ports,
+            depends_on,
+            healthcheck,
+            proc.started_at,
+            proc.restart_count,
+            proc.exit_code,
+            proc.failure_count,
+            proc.failures_since,
+            proc.archived_lines,
+            groups,
+            proc.package,
+            proc.example,
+            proc.host,
+            limits,
+            kind,
+            proc.command_line,
+            proc.stack,
+            secrets,
+            launched_env,
+            labels,
+            proc.stopped_at,
+            crash_loop,
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn init_pool(
+        database_directory_path: impl AsRef<Path>,
+        options: DatabaseOptions,
+    ) -> Result<Pool<Sqlite>> {
         let database_path = database_directory_path.as_ref().join(DB_FILE);
         if !database_path.exists() {
             File::create(&database_path).await?;
         }
 
-        let pool = SqlitePool::connect(
-            database_path
-                .to_str()
-                .ok_or_else(|| Error::new(InnerError::Filesystem))?,
-        )
-        .await?;
+        // Concurrent commands wait for each other's writes instead of failing right away, such as
+        // `jocker start` while `jocker logs -f` records events
+        let journal_mode = match options.wal {
+            true => SqliteJournalMode::Wal,
+            false => SqliteJournalMode::Delete,
+        };
+        let options = SqliteConnectOptions::new()
+            .filename(&database_path)
+            .journal_mode(journal_mode)
+            .busy_timeout(options.busy_timeout)
+            // Already the default of sqlx: stack links cascade along with removed processes and
+            // stacks, and removing the default stack unsets it
+            .foreign_keys(true);
+        let pool = SqlitePool::connect_with(options).await?;
         sqlx::migrate!().run(&pool).await?;
         Ok(pool)
     }
@@ -434,11 +1309,20 @@ impl Database {
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashMap, thread::sleep, time::Duration};
+    use std::{
+        collections::{BTreeMap, HashMap},
+        thread::sleep,
+        time::Duration,
+    };
 
     use tempfile::{tempdir, TempDir};
     use url::Url;
 
+    use crate::common::{
+        CrashLoop, DependencyCondition, EnvSource, EnvVar, EventKind, Healthcheck, Limits,
+        ProcessKind, Secret,
+    };
+
     use super::*;
 
     #[tokio::test]
@@ -487,6 +1371,112 @@ mod tests {
         assert_eq!(bins[2].id, source_bins[0].id);
     }
 
+    #[tokio::test]
+    async fn add_get_events() {
+        let (dir, db) = setup().await.unwrap();
+
+        let events = db.get_events(None).await.unwrap();
+        assert!(events.is_empty());
+
+        let started = Event::new("foo", EventKind::Started);
+        db.add_event(&started).await.unwrap();
+        sleep(Duration::from_millis(10));
+        let since = Utc::now();
+        let exited = Event {
+            exit_code: Some(1),
+            ..Event::new("foo", EventKind::Exited)
+        };
+        db.add_event(&exited).await.unwrap();
+
+        let events = db.get_events(None).await.unwrap();
+        assert_eq!(events, vec![started, exited.clone()]);
+        let events = db.get_events(Some(since)).await.unwrap();
+        assert_eq!(events, vec![exited]);
+
+        drop(dir);
+    }
+
+    #[tokio::test]
+    async fn builds() {
+        let (dir, db) = setup().await.unwrap();
+        let build = |processes: &[&str], success| BuildRecord {
+            id: 0,
+            started_at: Utc::now(),
+            duration_ms: 1500,
+            processes: processes.iter().map(|p| p.to_string()).collect(),
+            profile: None,
+            compiled: 3,
+            success,
+        };
+        db.add_build(&build(&["foo", "bar"], true), 2).await.unwrap();
+        db.add_build(&build(&["bar"], false), 2).await.unwrap();
+        db.add_build(&build(&["foo"], true), 2).await.unwrap();
+
+        let builds = db.get_builds(None, 10).await.unwrap();
+        assert_eq!(builds.len(), 2);
+        assert_eq!(builds[0].processes, vec!["foo"]);
+        assert_eq!(builds[0].duration_ms, 1500);
+        assert_eq!(builds[1].processes, vec!["bar"]);
+        assert!(!builds[1].success);
+        let builds = db.get_builds(Some("foo"), 10).await.unwrap();
+        assert_eq!(builds.len(), 1);
+        assert_eq!(db.get_builds(None, 1).await.unwrap().len(), 1);
+
+        drop(dir);
+    }
+
+    #[tokio::test]
+    async fn history() {
+        let (dir, db) = setup().await.unwrap();
+        db.set_processes(&test_processes()).await.unwrap();
+        db.add_history(HistoryKind::Config, None, "reloaded")
+            .await
+            .unwrap();
+        db.add_event(&Event::new("foo", EventKind::Started))
+            .await
+            .unwrap();
+        db.add_event(&Event {
+            exit_code: Some(1),
+            ..Event::new("bar", EventKind::Exited)
+        })
+        .await
+        .unwrap();
+        // States aren't history, only actual changes of them are written
+        assert!(db
+            .set_process_state("foo", ProcessState::Running)
+            .await
+            .unwrap());
+        assert!(!db
+            .set_process_state("foo", ProcessState::Running)
+            .await
+            .unwrap());
+
+        let history = db.get_history(None).await.unwrap();
+        let changes: Vec<(Option<&str>, &str)> = history
+            .iter()
+            .map(|entry| (entry.process_name.as_deref(), entry.change.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (None, "reloaded"),
+                (Some("foo"), "started"),
+                (Some("bar"), "exited (exit code 1)"),
+            ]
+        );
+        assert!(history.windows(2).all(|w| w[0].version < w[1].version));
+        let history = db.get_history(Some("foo")).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].kind, HistoryKind::Event);
+
+        db.prune_history(1).await.unwrap();
+        let history = db.get_history(None).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].process_name.as_deref(), Some("bar"));
+
+        drop(dir);
+    }
+
     #[tokio::test]
     async fn get_set_binaries_updated_at() {
         let (dir, db) = setup().await.unwrap();
@@ -518,6 +1508,38 @@ mod tests {
         drop(dir);
     }
 
+    #[tokio::test]
+    async fn get_set_config_file() {
+        let (dir, db) = setup().await.unwrap();
+
+        assert!(db.get_config_file().await.unwrap().is_none());
+
+        let config_file = Path::new("/tmp/project/jocker.yml");
+        db.set_config_file(config_file).await.unwrap();
+        assert_eq!(
+            db.get_config_file().await.unwrap().as_deref(),
+            Some(config_file)
+        );
+
+        drop(dir);
+    }
+
+    #[tokio::test]
+    async fn get_set_selected_stack() {
+        let (dir, db) = setup().await.unwrap();
+
+        assert!(db.get_selected_stack().await.unwrap().is_none());
+
+        let selected_stack = Some("full".to_owned());
+        db.set_selected_stack(&selected_stack).await.unwrap();
+        assert_eq!(db.get_selected_stack().await.unwrap(), selected_stack);
+
+        db.set_selected_stack(&None).await.unwrap();
+        assert!(db.get_selected_stack().await.unwrap().is_none());
+
+        drop(dir);
+    }
+
     #[tokio::test]
     async fn get_set_default_stack() {
         let (dir, db) = setup().await.unwrap();
@@ -573,6 +1595,88 @@ mod tests {
         assert_eq!(processes[1].pid(), &Some(42));
         assert_eq!(processes[1].state, ProcessState::Building);
 
+        let first_start = Utc::now();
+        let env = BTreeMap::from([("FOO".to_owned(), EnvVar::new("bar", EnvSource::Config))]);
+        db.set_process_started(
+            &expected_processes[0].name,
+            first_start,
+            "./foo --bar",
+            Some("full"),
+            &env,
+        )
+        .await
+        .unwrap();
+        db.set_process_exit_code(&expected_processes[0].name, Some(3))
+            .await
+            .unwrap();
+        let processes = db.get_processes().await.unwrap();
+        assert_eq!(processes[1].started_at, Some(first_start));
+        assert_eq!(processes[1].stopped_at, None);
+        assert_eq!(processes[1].restart_count, 0);
+        assert_eq!(processes[1].exit_code, Some(3));
+        assert_eq!(processes[1].command_line.as_deref(), Some("./foo --bar"));
+        assert_eq!(processes[1].stack.as_deref(), Some("full"));
+        assert_eq!(processes[1].launched_env, env);
+
+        let second_start = Utc::now();
+        db.set_process_started(
+            &expected_processes[0].name,
+            second_start,
+            "./foo",
+            None,
+            &BTreeMap::new(),
+        )
+        .await
+        .unwrap();
+        let processes = db.get_processes().await.unwrap();
+        assert_eq!(processes[1].started_at, Some(second_start));
+        assert_eq!(processes[1].restart_count, 1);
+        assert_eq!(processes[1].exit_code, None);
+        assert_eq!(processes[1].stack, None);
+
+        db.set_process_state(&expected_processes[0].name, ProcessState::Running)
+            .await
+            .unwrap();
+        db.set_process_state(&expected_processes[0].name, ProcessState::Stopped)
+            .await
+            .unwrap();
+        let stopped_at = db.get_processes().await.unwrap()[1].stopped_at;
+        assert!(stopped_at.is_some_and(|stopped_at| stopped_at >= second_start));
+        // Only the first state out of a run is its end
+        db.set_process_state(&expected_processes[0].name, ProcessState::Building)
+            .await
+            .unwrap();
+        assert_eq!(db.get_processes().await.unwrap()[1].stopped_at, stopped_at);
+
+        drop(dir);
+    }
+
+    #[tokio::test]
+    async fn add_remove_process_instances() {
+        let (dir, db) = setup().await.unwrap();
+
+        let processes = test_processes();
+        db.set_processes(&processes).await.unwrap();
+        let mut foo = processes[0].clone();
+        foo.replicas = 3;
+        db.add_processes(&foo.instances()[1..]).await.unwrap();
+        db.set_process_replicas("foo", 3).await.unwrap();
+        let processes = db.get_processes().await.unwrap();
+        let names: Vec<&str> = processes.iter().map(|p| p.name()).collect();
+        assert_eq!(names, vec!["bar", "foo", "foo.1", "foo.2"]);
+        assert_eq!(processes[3].base_name, "foo");
+        assert_eq!(processes[3].instance, 2);
+        assert!(processes[1..].iter().all(|p| p.replicas == 3));
+        assert_eq!(processes[0].replicas, 1);
+        let scaled = db.get_scaled_replicas().await.unwrap();
+        assert_eq!(scaled, HashMap::from([("foo".to_owned(), 3)]));
+
+        db.remove_processes(&["foo.2".to_owned()]).await.unwrap();
+        let processes = db.get_processes().await.unwrap();
+        assert_eq!(processes.len(), 3);
+        db.set_process_replicas("foo", 2).await.unwrap();
+        assert_eq!(db.get_scaled_replicas().await.unwrap()["foo"], 2);
+
         drop(dir);
     }
 
@@ -598,6 +1702,29 @@ mod tests {
         drop(dir);
     }
 
+    #[tokio::test]
+    async fn set_processes_keeps_runtime_state() {
+        let (dir, db) = setup().await.unwrap();
+        let expected_processes = test_processes();
+        db.set_processes(&expected_processes).await.unwrap();
+        let name = expected_processes[0].name();
+        db.set_process_pid(name, Some(42)).await.unwrap();
+        db.set_process_state(name, ProcessState::Running)
+            .await
+            .unwrap();
+
+        let mut updated = expected_processes[0].clone();
+        updated.args = vec!["--verbose".to_owned()];
+        db.set_processes(&[updated]).await.unwrap();
+        let processes = db.get_processes().await.unwrap();
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].args, vec!["--verbose".to_owned()]);
+        assert_eq!(processes[0].pid, Some(42));
+        assert_eq!(processes[0].state, ProcessState::Running);
+
+        drop(dir);
+    }
+
     #[tokio::test]
     async fn get_set_stacks() {
         let (dir, db) = setup().await.unwrap();
@@ -613,10 +1740,17 @@ mod tests {
         assert_eq!(&stack.name, "foo");
         assert_eq!(stack.processes, HashSet::from(["bar".to_owned()]));
         assert_eq!(stack.inherited_processes, HashSet::new());
+        assert_eq!(stack.max_parallel, None);
+        let stacks = db.get_stacks().await.unwrap();
+        assert_eq!(stacks.len(), 2);
+        assert_eq!(&stacks[0].name, "baz");
+        assert_eq!(&stacks[1].name, "foo");
+
         let stack = db.get_stack("baz").await.unwrap();
         assert_eq!(&stack.name, "baz");
         assert_eq!(stack.processes, HashSet::from(["foo".to_owned()]));
         assert_eq!(stack.inherited_processes, HashSet::from(["bar".to_owned()]));
+        assert_eq!(stack.max_parallel, Some(2));
 
         db.set_processes(&expected_processes[1..=1]).await.unwrap();
         let processes = db.get_processes().await.unwrap();
@@ -626,9 +1760,131 @@ mod tests {
         drop(dir);
     }
 
+    #[tokio::test]
+    async fn user_created_stacks() {
+        let (dir, db) = setup().await.unwrap();
+
+        db.set_processes(&test_processes()).await.unwrap();
+        let user_stack = Stack {
+            name: "qux".to_owned(),
+            processes: HashSet::from(["foo".to_owned()]),
+            inherited_processes: HashSet::new(),
+            max_parallel: None,
+            user_created: true,
+        };
+        db.add_stack(&user_stack).await.unwrap();
+        db.set_stacks(&test_stacks()).await.unwrap();
+        let stack = db.get_stack("qux").await.unwrap();
+        assert!(stack.user_created);
+        assert_eq!(stack.processes, HashSet::from(["foo".to_owned()]));
+        assert_eq!(db.get_stacks().await.unwrap().len(), 3);
+
+        // A stack of the configuration is never deleted, and replaces a user one of the same name
+        assert!(!db.delete_stack("foo").await.unwrap());
+        let config_stack = Stack {
+            user_created: false,
+            ..user_stack
+        };
+        db.set_stacks(&[config_stack]).await.unwrap();
+        assert!(!db.get_stack("qux").await.unwrap().user_created);
+        assert!(!db.delete_stack("qux").await.unwrap());
+
+        db.add_stack(&Stack {
+            name: "quux".to_owned(),
+            processes: HashSet::from(["bar".to_owned()]),
+            inherited_processes: HashSet::new(),
+            max_parallel: None,
+            user_created: true,
+        })
+        .await
+        .unwrap();
+        assert!(db.delete_stack("quux").await.unwrap());
+        assert!(db.get_stack("quux").await.is_err());
+
+        drop(dir);
+    }
+
+    #[tokio::test]
+    async fn cascading_deletions() {
+        async fn links(db: &Database, stack: &str) -> i64 {
+            sqlx::query_scalar(
+                "SELECT (SELECT COUNT(*) FROM rel_stack_process WHERE stack_name = $1)
+                    + (SELECT COUNT(*) FROM rel_stack_inherited_process WHERE stack_name = $1)",
+            )
+            .bind(stack)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap()
+        }
+        let (dir, db) = setup().await.unwrap();
+
+        let processes = test_processes();
+        db.set_processes(&processes).await.unwrap();
+        let stacks = test_stacks();
+        db.set_stacks(&stacks).await.unwrap();
+        db.set_default_stack(&Some("foo".to_owned())).await.unwrap();
+        assert_eq!(links(&db, "foo").await, 1);
+
+        // Removing a stack from the configuration unsets it as default
+        db.set_stacks(&stacks[1..]).await.unwrap();
+        assert_eq!(db.get_default_stack().await.unwrap(), None);
+        assert_eq!(links(&db, "foo").await, 0);
+        assert_eq!(links(&db, "baz").await, 2);
+
+        // Removed processes are removed from every stack, user-created ones included
+        db.add_stack(&Stack {
+            name: "qux".to_owned(),
+            processes: HashSet::from(["foo".to_owned(), "bar".to_owned()]),
+            inherited_processes: HashSet::new(),
+            max_parallel: None,
+            user_created: true,
+        })
+        .await
+        .unwrap();
+        db.set_processes(&processes[..1]).await.unwrap();
+        let stack = db.get_stack("qux").await.unwrap();
+        assert_eq!(stack.processes, HashSet::from(["foo".to_owned()]));
+        let stack = db.get_stack("baz").await.unwrap();
+        assert_eq!(stack.processes, HashSet::from(["foo".to_owned()]));
+        assert!(stack.inherited_processes.is_empty());
+
+        assert!(db.delete_stack("qux").await.unwrap());
+        assert_eq!(links(&db, "qux").await, 0);
+        assert_eq!(db.get_processes().await.unwrap().len(), 1);
+
+        drop(dir);
+    }
+
+    #[tokio::test]
+    async fn database_options() {
+        async fn pragmas(db: &Database) -> (String, i64, bool) {
+            let journal_mode = sqlx::query_scalar("PRAGMA journal_mode").fetch_one(&db.pool);
+            let busy_timeout = sqlx::query_scalar("PRAGMA busy_timeout").fetch_one(&db.pool);
+            let foreign_keys = sqlx::query_scalar("PRAGMA foreign_keys").fetch_one(&db.pool);
+            (
+                journal_mode.await.unwrap(),
+                busy_timeout.await.unwrap(),
+                foreign_keys.await.unwrap(),
+            )
+        }
+        let (dir, db) = setup().await.unwrap();
+        assert_eq!(pragmas(&db).await, ("wal".to_owned(), 10_000, true));
+        drop(dir);
+
+        let dir = tempdir().unwrap();
+        let options = DatabaseOptions {
+            busy_timeout: Duration::from_millis(500),
+            wal: false,
+        };
+        let db = Database::new(&dir, options).await.unwrap();
+        assert_eq!(pragmas(&db).await, ("delete".to_owned(), 500, true));
+
+        drop(dir);
+    }
+
     async fn setup() -> Result<(TempDir, Database)> {
         let dir = tempdir()?;
-        let db = Database::new(&dir).await?;
+        let db = Database::new(&dir, DatabaseOptions::default()).await?;
         Ok((dir, db))
     }
 
@@ -636,21 +1892,94 @@ mod tests {
         vec![
             Process {
                 name: "foo".to_owned(),
+                base_name: "foo".to_owned(),
+                instance: 0,
+                replicas: 1,
                 binary: "foo".to_owned(),
+                command: None,
+                profile: Some("release".to_owned()),
                 state: ProcessState::Stopped,
                 pid: None,
                 args: Vec::new(),
                 cargo_args: Vec::new(),
+                features: vec!["foo".to_owned(), "bar".to_owned()],
+                no_default_features: true,
+                all_features: false,
                 env: HashMap::new(),
+                ports: vec![8080],
+                groups: vec!["debug".to_owned()],
+                labels: BTreeMap::from([("team".to_owned(), "payments".to_owned())]),
+                depends_on: BTreeMap::new(),
+                healthcheck: Some(Healthcheck {
+                    tcp: Some("8080".to_owned()),
+                    timeout: Some(5),
+                    ..Default::default()
+                }),
+                limits: Some(Limits {
+                    memory: Some("512M".to_owned()),
+                    cpu_percent: Some(150),
+                }),
+                crash_loop: Some(CrashLoop {
+                    max_failures: 5,
+                    window: 300,
+                }),
+                started_at: None,
+                stopped_at: None,
+                restart_count: 0,
+                exit_code: None,
+                failure_count: 0,
+                failures_since: None,
+                archived_lines: 0,
+                package: Some("foo-server".to_owned()),
+                example: true,
+                host: Some("dev@server".to_owned()),
+                kind: ProcessKind::Task,
+                command_line: None,
+                stack: None,
+                secrets: BTreeMap::from([(
+                    "API_TOKEN".to_owned(),
+                    Secret::Pass("dev/api".to_owned()),
+                )]),
+                launched_env: BTreeMap::new(),
             },
             Process {
                 name: "bar".to_owned(),
+                base_name: "bar".to_owned(),
+                instance: 0,
+                replicas: 1,
                 binary: "bar".to_owned(),
+                command: Some("bar --baz".to_owned()),
+                profile: None,
                 state: ProcessState::Stopped,
                 pid: None,
                 args: Vec::new(),
                 cargo_args: Vec::new(),
+                features: Vec::new(),
+                no_default_features: false,
+                all_features: true,
                 env: HashMap::new(),
+                ports: Vec::new(),
+                groups: Vec::new(),
+                labels: BTreeMap::new(),
+                depends_on: BTreeMap::from([("foo".to_owned(), DependencyCondition::Healthy)]),
+                healthcheck: None,
+                limits: None,
+                crash_loop: None,
+                started_at: None,
+                stopped_at: None,
+                restart_count: 0,
+                exit_code: None,
+                failure_count: 0,
+                failures_since: None,
+                archived_lines: 0,
+                package: None,
+                example: false,
+                host: None,
+                kind: ProcessKind::Service,
+                command_line: None,
+                stack: None,
+                secrets: BTreeMap::new(),
+                launched_env: BTreeMap::new(),
             },
         ]
     }
@@ -661,11 +1990,15 @@ mod tests {
                 name: "foo".to_owned(),
                 processes: HashSet::from(["bar".to_owned()]),
                 inherited_processes: Default::default(),
+                max_parallel: None,
+                user_created: false,
             },
             Stack {
                 name: "baz".to_owned(),
                 processes: HashSet::from(["foo".to_owned()]),
                 inherited_processes: HashSet::from(["bar".to_owned()]),
+                max_parallel: Some(2),
+                user_created: false,
             },
         ]
     }
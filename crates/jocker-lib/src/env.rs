@@ -0,0 +1,68 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    command::util::shell_quote,
+    common::{EnvSource, EnvVar, Exec},
+    error::Result,
+    start::{is_secret, launch_env_sources, SECRET_MASK},
+    state::State,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct EnvArgs {
+    pub process: String,
+}
+
+/// Resolve the environment of a process, telling where each variable comes from
+///
+/// A started process gets the environment it was launched with, a stopped one the environment it
+/// would be launched with now. Secrets are masked.
+pub struct Env {
+    args: EnvArgs,
+    state: Arc<State>,
+}
+
+impl Env {
+    pub fn new(args: EnvArgs, state: Arc<State>) -> Self {
+        Env { args, state }
+    }
+
+    pub async fn run(&self) -> Result<BTreeMap<String, EnvVar>> {
+        let process = self
+            .state
+            .filter_processes(&[self.args.process.clone()])
+            .await?
+            .remove(0);
+        let mut env = if process.state.is_stopped() || process.launched_env.is_empty() {
+            let mut env = launch_env_sources(&self.state, &process);
+            for name in process.secrets.keys() {
+                env.insert(name.to_owned(), EnvVar::new(SECRET_MASK, EnvSource::Secret));
+            }
+            env
+        } else {
+            process.launched_env
+        };
+        for (key, var) in env.iter_mut() {
+            if is_secret(key) {
+                var.value = SECRET_MASK.to_owned();
+            }
+        }
+        Ok(env)
+    }
+}
+
+impl Exec<String> for Env {
+    async fn exec(&self) -> Result<String> {
+        let lines: Vec<(String, EnvSource)> = self
+            .run()
+            .await?
+            .into_iter()
+            .map(|(key, var)| (format!("{key}={}", shell_quote(&var.value)), var.source))
+            .collect();
+        let width = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+        Ok(lines
+            .into_iter()
+            .map(|(line, source)| format!("{line:<width$}  # {source}\n"))
+            .collect())
+    }
+}
@@ -1,11 +1,97 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use serde::Serialize;
 
 /// Alias for a `Result` with the error type [`jocker::Error`].
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Exit status of unexpected failures
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit status of invalid command line arguments
+pub const EXIT_USAGE: i32 = 2;
+/// Exit status of an invalid configuration
+pub const EXIT_CONFIG: i32 = 3;
+/// Exit status when a filtered process or stack doesn't exist
+pub const EXIT_NOT_FOUND: i32 = 4;
+/// Exit status when cargo fails to build processes
+pub const EXIT_BUILD: i32 = 5;
+/// Exit status when processes fail to start or to become healthy
+pub const EXIT_START: i32 = 6;
+/// Exit status when waiting for processes times out
+pub const EXIT_TIMEOUT: i32 = 7;
+/// Exit status when acting upon a process which isn't running
+pub const EXIT_NOT_RUNNING: i32 = 8;
+/// Exit status when the scheduler can't be reached or fails
+pub const EXIT_SCHEDULER: i32 = 9;
+/// Exit status when another jocker command is changing the project
+pub const EXIT_LOCKED: i32 = 10;
+
+/// Formats errors can be reported in
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(Error::new(InnerError::Parse(s.to_owned()))),
+        }
+    }
+}
+
+/// Kind of an error, meant for tools to branch on
+///
+/// Codes are serialized in snake case, and are kept stable across releases: a new kind of error
+/// gets a new code rather than changing an existing one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    Build,
+    Config,
+    Database,
+    Dependency,
+    Env,
+    Filesystem,
+    Healthcheck,
+    Internal,
+    Io,
+    Lock,
+    Parse,
+    Port,
+    ProcessNotFound,
+    ProcessNotRunning,
+    ProjectLocked,
+    Scheduler,
+    SchedulerTimeout,
+    Secret,
+    StackNotFound,
+    Start,
+    Timeout,
+    Tmux,
+}
+
+/// Serializable form of an [`Error`]
+#[derive(Debug, Serialize)]
+pub struct ErrorOutput {
+    pub code: ErrorCode,
+    pub message: String,
+    pub context: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
 pub struct Error {
     pub inner_error: InnerError,
     pub debug_context: Vec<String>,
+    /// How to fix the error, instead of the default one of its kind
+    pub hint: Option<String>,
 }
 
 impl Error {
@@ -13,6 +99,7 @@ impl Error {
         Self {
             inner_error,
             debug_context: vec![],
+            hint: None,
         }
     }
 
@@ -27,16 +114,154 @@ impl Error {
         self.debug_context.push(context.into());
         self
     }
+
+    pub fn with_hint<T: Into<String>>(mut self, hint: T) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Exit status the CLI should return for this error
+    pub fn exit_code(&self) -> i32 {
+        match self.inner_error {
+            InnerError::Parse(_) => EXIT_USAGE,
+            InnerError::Dependency(_)
+            | InnerError::Env(_)
+            | InnerError::RecursionDeepnessTooHigh
+            | InnerError::RecursionLoop
+            | InnerError::SerdeYaml(_)
+            | InnerError::Toml(_) => EXIT_CONFIG,
+            InnerError::ProcessNotFound(_) | InnerError::StackNotFound(_) => EXIT_NOT_FOUND,
+            InnerError::Build(_) | InnerError::Cargo => EXIT_BUILD,
+            InnerError::Healthcheck(_)
+            | InnerError::Port(_)
+            | InnerError::Secret(_)
+            | InnerError::Start(_) => EXIT_START,
+            InnerError::Timeout(_) => EXIT_TIMEOUT,
+            InnerError::ProcessNotRunning(_) => EXIT_NOT_RUNNING,
+            InnerError::Pueue(_) | InnerError::Scheduler(_) | InnerError::SchedulerTimeout(_) => {
+                EXIT_SCHEDULER
+            }
+            InnerError::ProjectLocked(_) => EXIT_LOCKED,
+            _ => EXIT_FAILURE,
+        }
+    }
+
+    /// Stable code of the kind of this error
+    pub fn code(&self) -> ErrorCode {
+        match self.inner_error {
+            InnerError::Build(_) | InnerError::Cargo => ErrorCode::Build,
+            InnerError::RecursionDeepnessTooHigh
+            | InnerError::RecursionLoop
+            | InnerError::SerdeYaml(_)
+            | InnerError::Toml(_) => ErrorCode::Config,
+            InnerError::Sqlite(_) | InnerError::Sqlx(_) | InnerError::SqlxMigration(_) => {
+                ErrorCode::Database
+            }
+            InnerError::Dependency(_) => ErrorCode::Dependency,
+            InnerError::Env(_) | InnerError::Var(_) => ErrorCode::Env,
+            InnerError::Filesystem(_) => ErrorCode::Filesystem,
+            InnerError::Healthcheck(_) => ErrorCode::Healthcheck,
+            InnerError::Io(_) => ErrorCode::Io,
+            InnerError::Lock(_) => ErrorCode::Lock,
+            InnerError::Parse(_) | InnerError::ParseIntError(_) | InnerError::Url(_) => {
+                ErrorCode::Parse
+            }
+            InnerError::Port(_) => ErrorCode::Port,
+            InnerError::ProcessNotFound(_) => ErrorCode::ProcessNotFound,
+            InnerError::ProcessNotRunning(_) => ErrorCode::ProcessNotRunning,
+            InnerError::ProjectLocked(_) => ErrorCode::ProjectLocked,
+            InnerError::Pueue(_) | InnerError::Scheduler(_) => ErrorCode::Scheduler,
+            InnerError::SchedulerTimeout(_) => ErrorCode::SchedulerTimeout,
+            InnerError::Secret(_) => ErrorCode::Secret,
+            InnerError::StackNotFound(_) => ErrorCode::StackNotFound,
+            InnerError::Start(_) => ErrorCode::Start,
+            InnerError::Timeout(_) => ErrorCode::Timeout,
+            InnerError::Tmux(_) => ErrorCode::Tmux,
+            InnerError::FromUtf8Error(_)
+            | InnerError::Notify(_)
+            | InnerError::Ps(_)
+            | InnerError::SerdeJson(_)
+            | InnerError::SystemTime(_)
+            | InnerError::TryFromInt(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// Human readable message, with what the error is about
+    pub fn message(&self) -> String {
+        let detail = match &self.inner_error {
+            InnerError::Build(detail)
+            | InnerError::Dependency(detail)
+            | InnerError::Env(detail)
+            | InnerError::Filesystem(detail)
+            | InnerError::Healthcheck(detail)
+            | InnerError::Lock(detail)
+            | InnerError::Parse(detail)
+            | InnerError::Port(detail)
+            | InnerError::ProcessNotRunning(detail)
+            | InnerError::ProjectLocked(detail)
+            | InnerError::Ps(detail)
+            | InnerError::Scheduler(detail)
+            | InnerError::SchedulerTimeout(detail)
+            | InnerError::Secret(detail)
+            | InnerError::StackNotFound(detail)
+            | InnerError::Start(detail)
+            | InnerError::Timeout(detail)
+            | InnerError::Tmux(detail) => Some(detail.to_owned()),
+            InnerError::ProcessNotFound(processes) => Some(processes.join(", ")),
+            inner_error => std::error::Error::source(inner_error).map(ToString::to_string),
+        };
+        match detail {
+            Some(detail) => format!("{}: {detail}", self.inner_error),
+            None => self.inner_error.to_string(),
+        }
+    }
+
+    /// Suggested fix, for failures users commonly run into
+    pub fn hint(&self) -> Option<String> {
+        if let Some(hint) = &self.hint {
+            return Some(hint.to_owned());
+        }
+        let hint = match &self.inner_error {
+            InnerError::ProcessNotFound(_) => {
+                "List processes with `jocker ps`, they are declared under `processes` in jocker.yml"
+            }
+            InnerError::ProjectLocked(_) => "Wait for the other command to finish, then try again",
+            InnerError::Pueue(_) => {
+                "Make sure `pueued` is installed, with `cargo install pueue`, and runs, or set \
+                JOCKER_SCHEDULER=native to do without it"
+            }
+            InnerError::Sqlx(e) if is_database_locked(e) => {
+                "Another jocker command is writing to the project database, wait for it to finish \
+                then try again"
+            }
+            InnerError::StackNotFound(_) => {
+                "Declare it under `stacks` in jocker.yml, or create it with `jocker stack create`"
+            }
+            _ => return None,
+        };
+        Some(hint.to_owned())
+    }
+
+    /// Serializable form of this error, for tools wrapping jocker
+    pub fn output(&self) -> ErrorOutput {
+        ErrorOutput {
+            code: self.code(),
+            message: self.message(),
+            context: self.debug_context.clone(),
+            hint: self.hint(),
+        }
+    }
 }
 
+/// What went wrong, followed by one line per context, then by how to fix it if known
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self.inner_error)?;
-        if !self.debug_context.is_empty() {
-            write!(f, " With context:")?;
-            for (idx, context) in self.debug_context.iter().enumerate() {
-                write!(f, "[{}] {}", idx + 1, context)?
-            }
+        write!(f, "{}", self.message())?;
+        for context in &self.debug_context {
+            write!(f, "\n  {context}")?;
+        }
+        if let Some(hint) = self.hint() {
+            write!(f, "\n  Hint: {hint}")?;
         }
         Ok(())
     }
@@ -55,34 +280,57 @@ impl<T: Into<InnerError>> From<T> for Error {
         Error {
             inner_error: src.into(),
             debug_context: vec![],
+            hint: None,
         }
     }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum InnerError {
+    #[error("Build error")]
+    Build(String),
     #[error("cargo error")]
     Cargo,
+    #[error("Dependency error")]
+    Dependency(String),
     #[error("Env error")]
     Env(String),
     #[error("Filesystem error")]
-    Filesystem,
+    Filesystem(String),
+    #[error("Healthcheck error")]
+    Healthcheck(String),
     #[error("Lock error")]
     Lock(String),
     #[error("Parse error")]
     Parse(String),
+    #[error("Port error")]
+    Port(String),
     #[error("Process not found error")]
     ProcessNotFound(Vec<String>),
+    #[error("Process not running error")]
+    ProcessNotRunning(String),
+    #[error("Project locked error")]
+    ProjectLocked(String),
     #[error("ps error")]
     Ps(String),
     #[error("Recursion deepness too high")]
     RecursionDeepnessTooHigh,
     #[error("Recursion loop")]
     RecursionLoop,
+    #[error("Scheduler error")]
+    Scheduler(String),
+    #[error("Scheduler timeout error")]
+    SchedulerTimeout(String),
+    #[error("Secret error")]
+    Secret(String),
     #[error("Stack not found error")]
     StackNotFound(String),
     #[error("Start stage error")]
     Start(String),
+    #[error("Timeout error")]
+    Timeout(String),
+    #[error("tmux error")]
+    Tmux(String),
 
     #[error("UTF-8 error")]
     FromUtf8Error(#[from] std::string::FromUtf8Error),
@@ -106,6 +354,8 @@ pub enum InnerError {
     SqlxMigration(#[from] sqlx::migrate::MigrateError),
     #[error("SystemTime error")]
     SystemTime(#[from] std::time::SystemTimeError),
+    #[error("TOML error")]
+    Toml(#[from] toml::de::Error),
     #[error("TryFromInt error")]
     TryFromInt(#[from] std::num::TryFromIntError),
     #[error("URL error")]
@@ -117,3 +367,91 @@ pub enum InnerError {
 pub fn lock_error(e: impl Display) -> Error {
     Error::new(InnerError::Lock(e.to_string()))
 }
+
+/// Error of a filesystem operation on given path, naming it
+pub fn filesystem_error(path: &Path) -> impl FnOnce(std::io::Error) -> Error + '_ {
+    move |e| Error::new(InnerError::Filesystem(format!("{}: {e}", path.display())))
+}
+
+/// Whether SQLite gave up waiting for another connection to release the database
+fn is_database_locked(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => {
+            matches!(e.code().as_deref(), Some("5" | "6")) || e.message().contains("locked")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let exit_code = |inner_error| Error::new(inner_error).exit_code();
+        assert_eq!(exit_code(InnerError::Parse("x".to_owned())), EXIT_USAGE);
+        assert_eq!(exit_code(InnerError::RecursionLoop), EXIT_CONFIG);
+        assert_eq!(
+            exit_code(InnerError::ProcessNotFound(vec!["x".to_owned()])),
+            EXIT_NOT_FOUND
+        );
+        assert_eq!(exit_code(InnerError::Build("x".to_owned())), EXIT_BUILD);
+        assert_eq!(exit_code(InnerError::Timeout("x".to_owned())), EXIT_TIMEOUT);
+        assert_eq!(
+            exit_code(InnerError::SchedulerTimeout("x".to_owned())),
+            EXIT_SCHEDULER
+        );
+        assert_eq!(
+            exit_code(InnerError::ProjectLocked("x".to_owned())),
+            EXIT_LOCKED
+        );
+        assert_eq!(exit_code(InnerError::Filesystem("x".to_owned())), EXIT_FAILURE);
+        // Context doesn't change the exit status
+        assert_eq!(
+            Error::new(InnerError::Start("x".to_owned()))
+                .add_context("y")
+                .exit_code(),
+            EXIT_START
+        );
+    }
+
+    #[test]
+    fn error_output() {
+        let processes = vec!["api".to_owned(), "web".to_owned()];
+        let error = Error::new(InnerError::ProcessNotFound(processes))
+            .add_context("Unable to start processes");
+        assert_eq!(
+            serde_json::to_value(error.output()).unwrap(),
+            serde_json::json!({
+                "code": "process_not_found",
+                "message": "Process not found error: api, web",
+                "context": ["Unable to start processes"],
+                "hint": "List processes with `jocker ps`, they are declared under `processes` in \
+                    jocker.yml",
+            })
+        );
+        let error = Error::new(InnerError::Timeout("x".to_owned()));
+        assert_eq!(error.code(), ErrorCode::Timeout);
+        assert!(serde_json::to_value(error.output()).unwrap().get("hint").is_none());
+        assert_eq!("json".parse::<ErrorFormat>().unwrap(), ErrorFormat::Json);
+    }
+
+    #[test]
+    fn display_hints() {
+        let error = Error::new(InnerError::Filesystem("jocker.yml: No such file".to_owned()))
+            .add_context("Unable to read configuration");
+        assert_eq!(
+            error.to_string(),
+            "Filesystem error: jocker.yml: No such file\n  Unable to read configuration"
+        );
+        let error = Error::new(InnerError::StackNotFound("full".to_owned()));
+        assert_eq!(
+            error.to_string(),
+            "Stack not found error: full\n  Hint: Declare it under `stacks` in jocker.yml, or \
+            create it with `jocker stack create`"
+        );
+        let error = error.with_hint("Run `jocker stack use` without a stack");
+        assert_eq!(error.hint().as_deref(), Some("Run `jocker stack use` without a stack"));
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    common::{Event, Exec},
+    error::{Error, InnerError, Result},
+    state::State,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct EventsArgs {
+    pub since: Option<DateTime<Utc>>,
+    pub processes: Vec<String>,
+}
+
+pub struct Events {
+    args: EventsArgs,
+    state: Arc<State>,
+}
+
+impl Events {
+    pub fn new(args: EventsArgs, state: Arc<State>) -> Self {
+        Events { args, state }
+    }
+
+    pub async fn run(&self) -> Result<Vec<Event>> {
+        let events = self.state.get_events(self.args.since).await?;
+        if self.args.processes.is_empty() {
+            return Ok(events);
+        }
+        Ok(events
+            .into_iter()
+            .filter(|event| self.args.processes.contains(&event.process_name))
+            .collect())
+    }
+}
+
+impl Exec<Vec<Event>> for Events {
+    async fn exec(&self) -> Result<Vec<Event>> {
+        self.run().await
+    }
+}
+
+/// Parse either an RFC 3339 date, or a duration relative to now such as `30s`, `10m`, `2h`, `1d`
+pub fn parse_since(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date.to_utc());
+    }
+    let parse_error = || Error::new(InnerError::Parse(value.to_owned()));
+    let (unit_index, _) = value.char_indices().last().ok_or_else(parse_error)?;
+    let (amount, unit) = value.split_at(unit_index);
+    let amount: i64 = amount.parse()?;
+    let duration = match unit {
+        "s" => Duration::seconds(amount),
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        _ => return Err(parse_error()),
+    };
+    Ok(Utc::now() - duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(
+            parse_since("2025-06-10T16:54:09Z").unwrap(),
+            DateTime::parse_from_rfc3339("2025-06-10T16:54:09Z")
+                .unwrap()
+                .to_utc()
+        );
+        let since = parse_since("10m").unwrap();
+        let expected = Utc::now() - Duration::minutes(10);
+        assert!((expected - since).num_seconds().abs() <= 1);
+        assert!(parse_since("").is_err());
+        assert!(parse_since("10y").is_err());
+        assert!(parse_since("tenm").is_err());
+        assert!(parse_since("10é").is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use std::{process::Stdio, sync::Arc};
+
+use tokio::process::Command;
+
+use crate::{
+    command::util::exit_code,
+    common::Exec,
+    error::{Error, InnerError, Result},
+    secrets,
+    start::process_env,
+    state::State,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ExecArgs {
+    pub process: String,
+    pub command: Vec<String>,
+}
+
+/// Run an arbitrary command with the environment and working directory of a process
+///
+/// Standard streams are inherited from the current terminal. Returns the exit code of the command.
+pub struct ExecCommand {
+    args: ExecArgs,
+    state: Arc<State>,
+}
+
+impl ExecCommand {
+    pub fn new(args: ExecArgs, state: Arc<State>) -> Self {
+        ExecCommand { args, state }
+    }
+}
+
+impl Exec<i32> for ExecCommand {
+    async fn exec(&self) -> Result<i32> {
+        let process = self
+            .state
+            .filter_processes(&[self.args.process.clone()])
+            .await?
+            .remove(0);
+        let Some((program, args)) = self.args.command.split_first() else {
+            return Err(Error::new(InnerError::Start(
+                "No command given to execute".to_owned(),
+            )));
+        };
+        let mut env = process_env(&process);
+        env.extend(secrets::resolve(&process.secrets, self.state.get_target_dir()).await?);
+        let exit_status = Command::new(program)
+            .args(args)
+            .current_dir(self.state.get_target_dir())
+            .envs(env)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .await
+            .map_err(Error::with_context(InnerError::Start(format!(
+                "Unable to execute {program}"
+            ))))?;
+        Ok(exit_code(exit_status))
+    }
+}
@@ -0,0 +1,433 @@
+use std::{path::PathBuf, sync::Arc};
+
+use serde_json::{json, Map, Value};
+use tracing::{info, warn};
+
+use crate::{
+    command::{cargo::Cargo, util::shell_quote},
+    common::{DependencyCondition, Exec, Healthcheck, Process, ProcessState},
+    error::{filesystem_error, Result},
+    interpolate::envsubst_known,
+    resources::ResourceMonitor,
+    start::{process_command, process_env},
+    state::State,
+    JOCKER,
+};
+
+/// Formats a stack can be exported to
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ExportFormat {
+    /// One systemd user `.service` unit per process
+    #[default]
+    Systemd,
+    /// VS Code `tasks.json` driving processes and stacks, and optionally `launch.json`
+    Vscode,
+    /// A `docker-compose.yml` running each process with cargo in a Rust container
+    Compose,
+}
+
+/// Image of the containers of an exported compose file, which build and run processes
+const COMPOSE_IMAGE: &str = "rust:latest";
+/// Directory the project is mounted to in containers of an exported compose file
+const COMPOSE_WORKDIR: &str = "/app";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExportArgs {
+    pub format: ExportFormat,
+    /// Stack to export, instead of the current one
+    pub stack: Option<String>,
+    /// Directory to write exported files to, instead of the current one, or its `.vscode`
+    /// directory for [`ExportFormat::Vscode`]
+    pub output: Option<PathBuf>,
+    /// Also generate debugger attach configurations, for [`ExportFormat::Vscode`]
+    pub launch: bool,
+}
+
+/// File generated by an [`Export`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExportedFile {
+    pub name: String,
+    pub content: String,
+}
+
+pub struct Export {
+    args: ExportArgs,
+    state: Arc<State>,
+}
+
+impl Export {
+    pub fn new(args: ExportArgs, state: Arc<State>) -> Self {
+        Export { args, state }
+    }
+
+    pub async fn run(&self) -> Result<Vec<ExportedFile>> {
+        let process_names: Vec<String> = match &self.args.stack {
+            Some(stack) => self
+                .state
+                .get_stack(stack)
+                .await?
+                .get_all_processes()
+                .into_iter()
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        let mut processes = self.state.filter_processes(&process_names).await?;
+        processes.sort();
+        match self.args.format {
+            ExportFormat::Systemd => {
+                let mut files = Vec::with_capacity(processes.len());
+                for process in &processes {
+                    files.push(self.systemd_unit(process).await?);
+                }
+                Ok(files)
+            }
+            ExportFormat::Vscode => {
+                let mut files = vec![self.vscode_tasks(&processes).await?];
+                if self.args.launch {
+                    files.push(self.vscode_launch(&processes).await?);
+                }
+                Ok(files)
+            }
+            ExportFormat::Compose => Ok(vec![compose_file(&processes)?]),
+        }
+    }
+
+    async fn systemd_unit(&self, process: &Process) -> Result<ExportedFile> {
+        let env = process_env(process);
+        let command = process_command(&self.state, process, &env).await?;
+        let mut environment: Vec<String> = env
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    "Environment=\"{}\"\n",
+                    systemd_escape(&format!("{key}={value}"))
+                )
+            })
+            .collect();
+        environment.sort();
+
+        let dependencies = if process.depends_on.is_empty() {
+            String::new()
+        } else {
+            let units: Vec<String> = process.depends_on.keys().map(|d| unit_name(d)).collect();
+            let units = units.join(" ");
+            format!("After={units}\nRequires={units}\n")
+        };
+
+        let content = format!(
+            "[Unit]\n\
+             Description=jocker process {name}\n\
+             {dependencies}\
+             \n\
+             [Service]\n\
+             Type=simple\n\
+             WorkingDirectory={working_directory}\n\
+             {environment}\
+             ExecStart=/bin/sh -c \"{command}\"\n\
+             Restart=on-failure\n\
+             \n\
+             [Install]\n\
+             WantedBy=default.target\n",
+            name = process.name(),
+            working_directory = self.state.get_target_dir().display(),
+            environment = environment.concat(),
+            command = systemd_escape(&command).replace('$', "$$"),
+        );
+
+        Ok(ExportedFile {
+            name: unit_name(process.name()),
+            content,
+        })
+    }
+
+    /// Tasks starting, stopping and following logs of each process, then of each stack
+    async fn vscode_tasks(&self, processes: &[Process]) -> Result<ExportedFile> {
+        let stacks: Vec<String> = match &self.args.stack {
+            Some(stack) => vec![stack.to_owned()],
+            None => {
+                let mut stacks: Vec<String> = self
+                    .state
+                    .get_stacks()
+                    .await?
+                    .into_iter()
+                    .map(|stack| stack.name)
+                    .collect();
+                stacks.sort();
+                stacks
+            }
+        };
+        let cwd = self.state.get_target_dir().display().to_string();
+        let mut tasks = Vec::new();
+        for process in processes {
+            let name = process.name();
+            tasks.push(vscode_task(&cwd, format!("start {name}"), &["start", name]));
+            tasks.push(vscode_task(&cwd, format!("stop {name}"), &["stop", name]));
+            tasks.push(vscode_logs_task(&cwd, format!("logs {name}"), &["logs", "-f", name]));
+        }
+        for stack in &stacks {
+            let start = ["--stack", stack, "start"];
+            let stop = ["--stack", stack, "stop"];
+            let logs = ["--stack", stack, "logs", "-f", "-p"];
+            tasks.push(vscode_task(&cwd, format!("start stack {stack}"), &start));
+            tasks.push(vscode_task(&cwd, format!("stop stack {stack}"), &stop));
+            tasks.push(vscode_logs_task(&cwd, format!("logs stack {stack}"), &logs));
+        }
+        let tasks = json!({ "version": "2.0.0", "tasks": tasks });
+        Ok(ExportedFile {
+            name: "tasks.json".to_owned(),
+            content: serde_json::to_string_pretty(&tasks)? + "\n",
+        })
+    }
+
+    /// CodeLLDB configurations attaching to each local binary process
+    ///
+    /// Running processes are attached to by the PID of their binary, which changes once they are
+    /// restarted. Others are waited for, to be attached to once launched.
+    async fn vscode_launch(&self, processes: &[Process]) -> Result<ExportedFile> {
+        let cargo_target_dir = self.state.get_cargo_target_dir().await?;
+        let mut monitor = ResourceMonitor::new();
+        let mut configurations = Vec::new();
+        for process in processes {
+            if process.command.is_some() || process.host.is_some() {
+                continue;
+            }
+            let program = process.artifact_path(cargo_target_dir);
+            let mut configuration = json!({
+                "name": format!("Attach to {}", process.name()),
+                "type": "lldb",
+                "request": "attach",
+                "program": program.display().to_string(),
+            });
+            let pid = (process.state == ProcessState::Running)
+                .then(|| {
+                    monitor.executable_pid(self.state.scheduler_group(), process.name(), &program)
+                })
+                .flatten();
+            match pid {
+                Some(pid) => configuration["pid"] = json!(pid),
+                None => configuration["waitFor"] = json!(true),
+            }
+            configurations.push(configuration);
+        }
+        let launch = json!({ "version": "0.2.0", "configurations": configurations });
+        Ok(ExportedFile {
+            name: "launch.json".to_owned(),
+            content: serde_json::to_string_pretty(&launch)? + "\n",
+        })
+    }
+}
+
+impl Exec<()> for Export {
+    async fn exec(&self) -> Result<()> {
+        let output = match (&self.args.output, self.args.format) {
+            (Some(output), _) => output.clone(),
+            (None, ExportFormat::Systemd | ExportFormat::Compose) => std::env::current_dir()?,
+            (None, ExportFormat::Vscode) => std::env::current_dir()?.join(".vscode"),
+        };
+        std::fs::create_dir_all(&output).map_err(filesystem_error(&output))?;
+        for file in self.run().await? {
+            let path = output.join(&file.name);
+            std::fs::write(&path, file.content).map_err(filesystem_error(&path))?;
+            info!("Exported {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Compose file with a service per process, mounting the project to build and run it with cargo
+///
+/// Secrets and processes running on another host are left out.
+fn compose_file(processes: &[Process]) -> Result<ExportedFile> {
+    let mut services = Map::new();
+    for process in processes {
+        if process.host.is_some() {
+            warn!("Process {} runs on another host, it isn't exported", process.name());
+            continue;
+        }
+        services.insert(process.name().to_owned(), compose_service(process));
+    }
+    let compose = json!({
+        "services": services,
+        "volumes": { "cargo-registry": {}, "cargo-target": {} },
+    });
+    Ok(ExportedFile {
+        name: "docker-compose.yml".to_owned(),
+        content: serde_yml::to_string(&compose)?,
+    })
+}
+
+fn compose_service(process: &Process) -> Value {
+    let env = process_env(process);
+    let command: Vec<String> = match &process.command {
+        Some(command) => vec!["sh".to_owned(), "-c".to_owned(), command.to_owned()],
+        None => {
+            let build_args = Cargo::build_args(
+                &[process.cargo_target()],
+                process.cargo_args(),
+                process.profile(),
+                process.package(),
+                &process.cargo_features(),
+            );
+            // Same flags as builds, without the subcommand and the JSON message format
+            let mut command = vec!["cargo".to_owned(), "run".to_owned()];
+            command.extend(build_args.into_iter().skip(2));
+            if !process.args.is_empty() {
+                command.push("--".to_owned());
+                command.extend(process.args.iter().map(|arg| envsubst_known(arg, &env)));
+            }
+            command
+        }
+    };
+    let restart = if process.is_task() { "no" } else { "unless-stopped" };
+    let mut service = json!({
+        "image": COMPOSE_IMAGE,
+        "working_dir": COMPOSE_WORKDIR,
+        "volumes": [
+            format!(".:{COMPOSE_WORKDIR}"),
+            format!("cargo-target:{COMPOSE_WORKDIR}/target"),
+            "cargo-registry:/usr/local/cargo/registry",
+        ],
+        "command": command,
+        "environment": env,
+        "restart": restart,
+    });
+    if !process.ports.is_empty() {
+        let ports: Vec<String> = process.ports.iter().map(|p| format!("{p}:{p}")).collect();
+        service["ports"] = json!(ports);
+    }
+    if !process.depends_on.is_empty() {
+        let depends_on: Map<String, Value> = process
+            .depends_on
+            .iter()
+            .map(|(name, condition)| {
+                let condition = match condition {
+                    DependencyCondition::Started => "service_started",
+                    DependencyCondition::Healthy => "service_healthy",
+                    DependencyCondition::CompletedSuccessfully => {
+                        "service_completed_successfully"
+                    }
+                };
+                (name.to_owned(), json!({ "condition": condition }))
+            })
+            .collect();
+        service["depends_on"] = Value::Object(depends_on);
+    }
+    if let Some(healthcheck) = &process.healthcheck {
+        service["healthcheck"] = compose_healthcheck(healthcheck);
+    }
+    if process.replicas > 1 {
+        service["deploy"] = json!({ "replicas": process.replicas });
+    }
+    if !process.groups.is_empty() {
+        service["profiles"] = json!(process.groups);
+    }
+    if !process.labels.is_empty() {
+        service["labels"] = json!(process.labels);
+    }
+    service
+}
+
+/// Compose healthcheck running every probe of given one in a shell
+fn compose_healthcheck(healthcheck: &Healthcheck) -> Value {
+    let mut probes = Vec::new();
+    if let Some(command) = &healthcheck.command {
+        probes.push(format!("({command})"));
+    }
+    if let Some(tcp) = &healthcheck.tcp {
+        let (host, port) = tcp.rsplit_once(':').unwrap_or(("localhost", tcp));
+        probes.push(format!("bash -c '</dev/tcp/{host}/{port}'"));
+    }
+    if let Some(http) = &healthcheck.http {
+        probes.push(format!("curl -fsS -o /dev/null {}", shell_quote(http)));
+    }
+    json!({
+        "test": ["CMD-SHELL", probes.join(" && ")],
+        "interval": "2s",
+        "start_period": format!("{}s", healthcheck.timeout().as_secs()),
+    })
+}
+
+/// VS Code task running jocker with given arguments, from the project directory
+fn vscode_task(cwd: &str, label: String, args: &[&str]) -> Value {
+    json!({
+        "label": format!("{JOCKER}: {label}"),
+        "type": "process",
+        "command": JOCKER,
+        "args": args,
+        "options": { "cwd": cwd },
+        "problemMatcher": [],
+    })
+}
+
+/// Same as [`vscode_task`], for a command which keeps running in its own terminal
+fn vscode_logs_task(cwd: &str, label: String, args: &[&str]) -> Value {
+    let mut task = vscode_task(cwd, label, args);
+    task["isBackground"] = json!(true);
+    task["presentation"] = json!({ "panel": "dedicated" });
+    task
+}
+
+fn unit_name(process_name: &str) -> String {
+    format!("jocker-{process_name}.service")
+}
+
+/// Escape a value to be used inside a double-quoted systemd directive
+fn systemd_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::*;
+
+    #[test]
+    fn escape_systemd_values() {
+        assert_eq!(systemd_escape("foo bar"), "foo bar");
+        assert_eq!(
+            systemd_escape(r#"echo "100%" \o/"#),
+            r#"echo \"100%%\" \\o/"#
+        );
+    }
+
+    #[test]
+    fn compose_services() {
+        let mut api = Process::new("api", "api");
+        api.package = Some("backend".to_owned());
+        api.args = vec!["--port=${PORT}".to_owned()];
+        api.env = HashMap::from([("PORT".to_owned(), "8080".to_owned())]);
+        api.ports = vec![8080];
+        api.depends_on = BTreeMap::from([("db".to_owned(), DependencyCondition::Healthy)]);
+        api.healthcheck = Some(Healthcheck {
+            tcp: Some("8080".to_owned()),
+            ..Default::default()
+        });
+        let service = compose_service(&api);
+        assert_eq!(
+            service["command"],
+            json!(["cargo", "run", "--package=backend", "--bin=api", "--", "--port=8080"])
+        );
+        assert_eq!(service["environment"]["PORT"], "8080");
+        assert_eq!(service["ports"], json!(["8080:8080"]));
+        assert_eq!(service["depends_on"]["db"]["condition"], "service_healthy");
+        assert_eq!(
+            service["healthcheck"]["test"],
+            json!(["CMD-SHELL", "bash -c '</dev/tcp/localhost/8080'"])
+        );
+    }
+
+    #[test]
+    fn vscode_tasks() {
+        let task = vscode_logs_task("/project", "logs api".to_owned(), &["logs", "-f", "api"]);
+        assert_eq!(task["label"], "jocker: logs api");
+        assert_eq!(task["command"], "jocker");
+        assert_eq!(task["args"], json!(["logs", "-f", "api"]));
+        assert_eq!(task["options"]["cwd"], "/project");
+        assert_eq!(task["isBackground"], true);
+    }
+}
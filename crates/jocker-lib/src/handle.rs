@@ -0,0 +1,119 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::time::{sleep, Instant};
+use tokio_stream::Stream;
+
+use crate::{
+    common::{Exec, Process, ProcessState},
+    error::{Error, InnerError, Result},
+    logs::{LogEntry, Logs, LogsArgs},
+    start::{Start, StartArgs},
+    state::State,
+    stop::{Stop, StopArgs},
+};
+
+/// Interval between two checks of the state of a process waited for
+const WAIT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Control over a single process of the project, obtained with [`State::process`]
+///
+/// Like on CLI, starting or stopping the base name of a scaled process applies to all of its
+/// instances, and starting a process starts its dependencies first.
+pub struct ProcessHandle {
+    name: String,
+    state: Arc<State>,
+}
+
+impl ProcessHandle {
+    pub(crate) fn new(name: String, state: Arc<State>) -> Self {
+        Self { name, state }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub async fn start(&self) -> Result<()> {
+        self.process().await?;
+        Start::new(
+            StartArgs {
+                processes: vec![self.name.clone()],
+                ..Default::default()
+            },
+            self.state.clone(),
+        )
+        .exec()
+        .await?;
+        Ok(())
+    }
+
+    pub async fn stop(&self) -> Result<()> {
+        self.process().await?;
+        Stop::new(
+            StopArgs {
+                processes: vec![self.name.clone()],
+                ..Default::default()
+            },
+            self.state.clone(),
+        )
+        .exec()
+        .await?;
+        Ok(())
+    }
+
+    pub async fn restart(&self) -> Result<()> {
+        // Held across both, so that no other command changes the process in between
+        let _lock = self.state.lock()?;
+        self.stop().await?;
+        self.start().await
+    }
+
+    /// Log entries of the process, also the ones written from now on with `follow`
+    pub async fn logs(&self, follow: bool) -> Result<impl Stream<Item = Result<LogEntry>>> {
+        self.process().await?;
+        Logs::new(
+            LogsArgs {
+                follow,
+                processes: vec![self.name.clone()],
+                ..Default::default()
+            },
+            self.state.clone(),
+        )
+        .run()
+        .await
+    }
+
+    /// Current state of the process, as last seen by its scheduler
+    pub async fn status(&self) -> Result<ProcessState> {
+        self.state.refresh(false).await?;
+        Ok(self.process().await?.state)
+    }
+
+    /// Wait for the process to reach given state, failing once `timeout` is exceeded
+    pub async fn wait_until(&self, state: ProcessState, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let current = self.status().await?;
+            if current == state {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::new(InnerError::Timeout(format!(
+                    "Process {} is still {current} after {}s, instead of {state}",
+                    self.name,
+                    timeout.as_secs()
+                ))));
+            }
+            sleep(WAIT_INTERVAL).await;
+        }
+    }
+
+    async fn process(&self) -> Result<Process> {
+        self.state
+            .get_processes()
+            .await?
+            .into_iter()
+            .find(|process| process.name() == self.name)
+            .ok_or_else(|| Error::new(InnerError::ProcessNotFound(vec![self.name.clone()])))
+    }
+}
@@ -0,0 +1,149 @@
+use std::{process::Stdio, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    time::{sleep, timeout},
+};
+use url::Url;
+
+use crate::{
+    command::util::shell_command,
+    common::{Healthcheck, Process},
+    error::{Error, InnerError, Result},
+    start::process_env,
+    state::State,
+};
+
+pub(crate) const HEALTHCHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// Maximum duration of a single probe, so a hanging one doesn't exceed the healthcheck timeout
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether given process currently passes every probe of its healthcheck
+///
+/// A process without healthcheck is always considered healthy.
+pub async fn is_healthy(state: &State, process: &Process) -> bool {
+    let Some(healthcheck) = process.healthcheck.as_ref() else {
+        return true;
+    };
+    timeout(PROBE_TIMEOUT, probe(state, process, healthcheck))
+        .await
+        .unwrap_or(false)
+}
+
+/// Wait until given process is healthy, failing once its healthcheck timeout is exceeded
+pub async fn wait_healthy(state: &State, process: &Process) -> Result<()> {
+    let Some(healthcheck) = process.healthcheck.as_ref() else {
+        return Ok(());
+    };
+    let wait = async {
+        while !is_healthy(state, process).await {
+            sleep(HEALTHCHECK_INTERVAL).await;
+        }
+    };
+    timeout(healthcheck.timeout(), wait).await.map_err(|_| {
+        Error::new(InnerError::Timeout(format!(
+            "Process {} is not healthy after {}s",
+            process.name(),
+            healthcheck.timeout().as_secs()
+        )))
+    })
+}
+
+async fn probe(state: &State, process: &Process, healthcheck: &Healthcheck) -> bool {
+    if let Some(command) = healthcheck.command.as_ref() {
+        let status = shell_command(command)
+            .current_dir(state.get_target_dir())
+            .envs(process_env(process))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+        if !status.is_ok_and(|status| status.success()) {
+            return false;
+        }
+    }
+    if let Some(address) = healthcheck.tcp.as_ref() {
+        if TcpStream::connect(tcp_address(address)).await.is_err() {
+            return false;
+        }
+    }
+    if let Some(url) = healthcheck.http.as_ref() {
+        if !http_ok(url).await.unwrap_or(false) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Accept a bare port as a shorthand for a local address
+fn tcp_address(address: &str) -> String {
+    if address.parse::<u16>().is_ok() {
+        format!("127.0.0.1:{address}")
+    } else {
+        address.to_owned()
+    }
+}
+
+/// Whether a GET on given plain HTTP URL answers with a 2xx status
+async fn http_ok(url: &str) -> Result<bool> {
+    let url = Url::parse(url)?;
+    if url.scheme() != "http" {
+        return Err(Error::new(InnerError::Healthcheck(format!(
+            "Unsupported healthcheck URL scheme: {}",
+            url.scheme()
+        ))));
+    }
+    let host = url.host_str().unwrap_or("127.0.0.1");
+    let port = url.port_or_known_default().unwrap_or(80);
+    let mut path = url.path().to_owned();
+    if let Some(query) = url.query() {
+        path = format!("{path}?{query}");
+    }
+    let mut stream = BufReader::new(TcpStream::connect((host, port)).await?);
+    stream
+        .get_mut()
+        .write_all(format!("GET {path} HTTP/1.0\r\nHost: {host}\r\n\r\n").as_bytes())
+        .await?;
+    let mut status_line = String::new();
+    stream.read_line(&mut status_line).await?;
+    Ok(status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .is_some_and(|code| (200..300).contains(&code)))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::AsyncReadExt as _, net::TcpListener};
+
+    use super::*;
+
+    #[test]
+    fn parse_tcp_address() {
+        assert_eq!(tcp_address("8080"), "127.0.0.1:8080");
+        assert_eq!(tcp_address("localhost:8080"), "localhost:8080");
+    }
+
+    #[tokio::test]
+    async fn http_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            for status in ["200 OK", "503 Service Unavailable"] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await.unwrap();
+                stream
+                    .write_all(format!("HTTP/1.0 {status}\r\n\r\n").as_bytes())
+                    .await
+                    .unwrap();
+            }
+        });
+        let url = format!("http://127.0.0.1:{port}/health");
+        assert!(http_ok(&url).await.unwrap());
+        assert!(!http_ok(&url).await.unwrap());
+    }
+}
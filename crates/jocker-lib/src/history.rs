@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use crate::{
+    common::{Exec, HistoryEntry},
+    error::Result,
+    state::State,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct HistoryArgs {
+    /// Only list changes of given process
+    pub process: Option<String>,
+}
+
+/// Changes of the project state, oldest first
+///
+/// Configuration reloads, stack selections and process state transitions are recorded, the
+/// oldest ones being pruned past `default.history.max_entries` when the configuration is reloaded.
+pub struct History {
+    args: HistoryArgs,
+    state: Arc<State>,
+}
+
+impl History {
+    pub fn new(args: HistoryArgs, state: Arc<State>) -> Self {
+        History { args, state }
+    }
+
+    pub async fn run(&self) -> Result<Vec<HistoryEntry>> {
+        self.state.get_history(self.args.process.as_deref()).await
+    }
+}
+
+impl Exec<Vec<HistoryEntry>> for History {
+    async fn exec(&self) -> Result<Vec<HistoryEntry>> {
+        self.run().await
+    }
+}
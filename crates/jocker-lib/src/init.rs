@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use tracing::info;
+
+use crate::{
+    command::cargo::{Cargo, TargetKind},
+    common::Exec,
+    error::{filesystem_error, Error, InnerError, Result},
+    state::State,
+};
+
+const DEFAULT_STACK: &str = "all";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct InitArgs {
+    pub force: bool,
+}
+
+/// Write a starter configuration file, with one process per binary of the cargo workspace
+pub struct Init {
+    args: InitArgs,
+    state: Arc<State>,
+}
+
+impl Init {
+    pub fn new(args: InitArgs, state: Arc<State>) -> Self {
+        Init { args, state }
+    }
+
+    /// Content of the configuration file to write
+    pub async fn run(&self) -> Result<String> {
+        let mut binaries: Vec<String> = Cargo::metadata(self.state.get_target_dir())
+            .await?
+            .into_iter()
+            .flat_map(|package| package.targets)
+            .filter(|target| target.kind.contains(&TargetKind::Bin))
+            .map(|target| target.name)
+            .collect();
+        binaries.sort();
+        binaries.dedup();
+        Ok(render_config(&binaries))
+    }
+}
+
+impl Exec<()> for Init {
+    async fn exec(&self) -> Result<()> {
+        let path = self.state.get_config_file();
+        if path.exists() && !self.args.force {
+            let error = InnerError::Filesystem(format!("{} already exists", path.display()));
+            return Err(Error::new(error).with_hint("Use --force to overwrite it"));
+        }
+        std::fs::write(path, self.run().await?).map_err(filesystem_error(path))?;
+        info!("Created {}", path.display());
+        Ok(())
+    }
+}
+
+fn render_config(binaries: &[String]) -> String {
+    let stack_processes: String = binaries
+        .iter()
+        .map(|binary| format!("      - {binary}\n"))
+        .collect();
+    let processes: String = binaries
+        .iter()
+        .map(|binary| format!("  {binary}:\n    binary: {binary}\n"))
+        .collect();
+    format!(
+        "default:\n  \
+           stack: {DEFAULT_STACK}\n\
+         \n\
+         stacks:\n  \
+           {DEFAULT_STACK}:\n    \
+             processes:\n\
+         {stack_processes}\
+         \n\
+         # Available process options are documented in schema.json\n\
+         processes:\n\
+         {processes}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::ConfigFile;
+
+    use super::*;
+
+    #[test]
+    fn render_valid_config() {
+        let config = render_config(&["bar".to_owned(), "foo".to_owned()]);
+        let config: ConfigFile = serde_yml::from_str(&config).unwrap();
+        assert_eq!(
+            config.default.unwrap().stack,
+            Some(DEFAULT_STACK.to_owned())
+        );
+        assert_eq!(config.stacks[DEFAULT_STACK].processes.len(), 2);
+        assert_eq!(config.processes["foo"].binary, Some("foo".to_owned()));
+    }
+}
@@ -0,0 +1,104 @@
+use std::{collections::HashMap, env, path::PathBuf};
+
+use dotenvy::dotenv_iter;
+use once_cell::sync::OnceCell;
+use regex::Regex;
+
+const DOTENV_FILE: &str = ".env";
+
+static ENVSUBST_REGEX: OnceCell<Regex> = OnceCell::new();
+
+pub fn envsubst(value: &str, env: &HashMap<String, String>) -> String {
+    substitute(value, |var_name, default| {
+        Some(
+            env.get(var_name)
+                .cloned()
+                .or(default.map(str::to_owned))
+                .unwrap_or_default(),
+        )
+    })
+}
+
+/// Same as [`envsubst`], leaving placeholders of variables missing from `env` untouched, so they
+/// can be substituted later on
+pub(crate) fn envsubst_known(value: &str, env: &HashMap<String, String>) -> String {
+    substitute(value, |var_name, _| env.get(var_name).cloned())
+}
+
+/// Replace `${VAR}` and `${VAR:-default}` placeholders by the value `resolve` gives for the
+/// variable name and its default, keeping the placeholder when it gives nothing
+fn substitute(value: &str, resolve: impl Fn(&str, Option<&str>) -> Option<String>) -> String {
+    let re = ENVSUBST_REGEX.get_or_init(|| Regex::new(r"\$\{([a-zA-Z0-9-_:/.\[\]]*)}").unwrap());
+
+    let mut last_range_end = 0;
+    let mut ret = "".to_string();
+    // We take all captures, replace them by their associated env value, then build a new string
+    // keeping the characters outside of placeholders, using captures' ranges.
+    for capture in re.captures_iter(value) {
+        let (_, [name]) = capture.extract();
+        let range = capture
+            .get(0)
+            .expect("Cannot happen as i == 0 is guaranteed to return Some")
+            .range();
+        if range.start != 0 {
+            ret.push_str(&value[last_range_end..range.start]);
+        }
+        last_range_end = range.end;
+        let split: Vec<&str> = name.split(":-").collect();
+        let var_name = split.first().copied().unwrap_or_default();
+        let default = split.get(1).copied();
+        match resolve(var_name, default) {
+            Some(var_value) => ret.push_str(&var_value),
+            None => ret.push_str(&value[range]),
+        }
+    }
+    if last_range_end != value.len() {
+        ret.push_str(&value[last_range_end..value.len()]);
+    }
+    ret
+}
+
+/// `.env` file variables are read from, the first one found from the current directory up
+pub(crate) fn dotenv_file() -> Option<PathBuf> {
+    env::current_dir()
+        .ok()?
+        .ancestors()
+        .map(|dir| dir.join(DOTENV_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Variables available to the configuration: the ones of the `.env` file, overridden by the shell
+/// ones
+pub(crate) fn config_env() -> HashMap<String, String> {
+    let mut config_env: HashMap<String, String> = HashMap::new();
+    if let Ok(dotenv) = dotenv_iter() {
+        config_env.extend(dotenv.flatten());
+    }
+    config_env.extend(env::vars());
+    config_env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envsubst() {
+        let mut env = HashMap::new();
+        assert_eq!(&envsubst("${FOO:-baz}", &env), "baz");
+        env.insert("FOO".to_string(), "BAR".to_string());
+        assert_eq!(&envsubst("FOO", &env), "FOO");
+        assert_eq!(&envsubst("${FOO}", &env), "BAR");
+        assert_eq!(&envsubst("${FOO:-baz}", &env), "BAR");
+    }
+
+    #[test]
+    fn test_envsubst_known() {
+        let mut env = HashMap::new();
+        env.insert("FOO".to_string(), "BAR".to_string());
+        assert_eq!(
+            &envsubst_known("--foo=${FOO} --qux=${QUX:-baz}", &env),
+            "--foo=BAR --qux=${QUX:-baz}"
+        );
+    }
+}
@@ -1,13 +1,49 @@
+pub mod adopt;
+pub mod archive;
+pub mod attach;
+pub mod build;
 pub mod command;
 pub mod common;
 pub mod config;
+pub mod daemon;
 pub mod database;
+pub mod env;
 pub mod error;
+pub mod events;
+pub mod exec;
+pub mod export;
+pub mod handle;
+pub mod health;
+pub mod history;
+pub mod init;
+pub mod interpolate;
+pub mod limits;
+pub mod lock;
 pub mod logs;
+pub mod pause;
+pub mod ports;
+pub mod projects;
 pub mod ps;
+pub mod remote;
+pub mod repair;
+pub mod resources;
+pub mod run;
+pub mod scale;
+pub mod scheduler;
+pub mod secrets;
+pub mod show;
+pub mod signal;
+pub mod stack;
 pub mod start;
 pub mod state;
+pub mod stats;
+pub mod status;
 pub mod stop;
+pub mod testing;
+pub mod tmux;
+pub mod validate;
+pub mod wait;
+pub mod watch;
 
 pub const JOCKER: &str = "jocker";
 
@@ -0,0 +1,131 @@
+use std::{env, path::Path, process::Stdio};
+
+use tokio::{process::Command, sync::OnceCell};
+use tracing::warn;
+
+use crate::{
+    command::util::{shell_quote, SHELL},
+    common::Limits,
+    error::{Error, InnerError, Result},
+};
+
+/// Niceness given to processes with a CPU limit which can't be enforced through cgroups
+const FALLBACK_NICENESS: u8 = 10;
+
+/// Number of bytes of given memory size, such as `512M`, suffixes being powers of 1024
+pub(crate) fn parse_memory(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((idx, _)) => value.split_at(idx),
+        None => (value, ""),
+    };
+    let exponent = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(1024u64.pow(exponent))
+}
+
+/// Command line running given one within `limits`
+///
+/// Limits are enforced by a transient systemd scope when the systemd user instance manages
+/// cgroups v2. Otherwise the memory limit caps the address space with `prlimit`, and the CPU one
+/// only lowers the priority of the process with `nice`.
+pub(crate) async fn limit_command(
+    process_name: &str,
+    limits: &Limits,
+    command: &str,
+) -> Result<String> {
+    if !cfg!(target_os = "linux") {
+        warn!("Resource limits of process {process_name} are only enforced on Linux");
+        return Ok(command.to_owned());
+    }
+    let memory = limits
+        .memory
+        .as_deref()
+        .map(|memory| {
+            parse_memory(memory).ok_or_else(|| {
+                Error::new(InnerError::Start(format!(
+                    "Process {process_name} has invalid memory limit {memory}"
+                )))
+            })
+        })
+        .transpose()?;
+    let [shell, flag] = SHELL;
+    let mut wrapper = Vec::new();
+    if has_systemd_cgroups().await {
+        wrapper.push("systemd-run --user --scope --quiet".to_owned());
+        if let Some(memory) = memory {
+            wrapper.push(format!("-p MemoryMax={memory}"));
+        }
+        if let Some(cpu_percent) = limits.cpu_percent {
+            wrapper.push(format!("-p CPUQuota={cpu_percent}%"));
+        }
+        wrapper.push("--".to_owned());
+    } else {
+        if limits.cpu_percent.is_some() {
+            warn!(
+                "cgroups v2 are unavailable, process {process_name} only gets a lower CPU priority"
+            );
+            require_program(process_name, "nice", "CPU", "coreutils")?;
+            wrapper.push(format!("nice -n {FALLBACK_NICENESS}"));
+        }
+        if let Some(memory) = memory {
+            require_program(process_name, "prlimit", "memory", "util-linux")?;
+            wrapper.push(format!("prlimit --as={memory} --"));
+        }
+    }
+    wrapper.push(format!("{shell} {flag} {}", shell_quote(command)));
+    Ok(wrapper.join(" "))
+}
+
+/// Whether processes can be put in transient systemd scopes backed by cgroups v2
+async fn has_systemd_cgroups() -> bool {
+    static HAS_SYSTEMD_CGROUPS: OnceCell<bool> = OnceCell::const_new();
+    *HAS_SYSTEMD_CGROUPS
+        .get_or_init(|| async {
+            Path::new("/sys/fs/cgroup/cgroup.controllers").exists()
+                && Command::new("systemctl")
+                    .args(["--user", "show-environment"])
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .await
+                    .is_ok_and(|status| status.success())
+        })
+        .await
+}
+
+/// Fail unless `program`, enforcing the `kind` limit of given process, is found in `PATH`
+fn require_program(process_name: &str, program: &str, kind: &str, package: &str) -> Result<()> {
+    let found = env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()));
+    if !found {
+        return Err(Error::new(InnerError::Start(format!(
+            "`{program}` isn't installed, process {process_name} can't get its {kind} limit \
+            without cgroups v2"
+        )))
+        .with_hint(format!("Install {package}, or remove the {kind} limit of the process")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_sizes() {
+        assert_eq!(parse_memory("1024"), Some(1024));
+        assert_eq!(parse_memory("512M"), Some(512 * 1024 * 1024));
+        assert_eq!(parse_memory("2g"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_memory("64KB"), Some(64 * 1024));
+        assert_eq!(parse_memory("1.5G"), None);
+        assert_eq!(parse_memory("M"), None);
+        assert_eq!(parse_memory("12Q"), None);
+    }
+}
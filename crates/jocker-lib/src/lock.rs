@@ -0,0 +1,86 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    process,
+};
+
+use fs2::FileExt;
+
+use crate::{
+    error::{Error, InnerError, Result},
+    Pid,
+};
+
+const LOCK_FILE: &str = "jocker.lock";
+
+/// Advisory lock held by the jocker command changing a project, released when dropped
+///
+/// The lock is an OS file lock on a file of the project state directory, which is never removed:
+/// the lock of a command that died is released along with its file descriptor. The file holds the
+/// pid of its owner, only to tell who holds it.
+#[derive(Debug)]
+pub struct ProjectLock {
+    file: File,
+}
+
+impl ProjectLock {
+    /// Lock given project state directory, failing if another running command holds it
+    pub fn acquire(project_dir: &Path) -> Result<Self> {
+        let path = project_dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if let Err(e) = file.try_lock_exclusive() {
+            if e.kind() != fs2::lock_contended_error().kind() {
+                return Err(e.into());
+            }
+            let owner = fs::read_to_string(&path)
+                .ok()
+                .and_then(|pid| pid.trim().parse::<Pid>().ok());
+            let message = match owner {
+                Some(owner) => {
+                    format!("Another jocker command is running on this project (pid {owner})")
+                }
+                None => "Another jocker command is running on this project".to_owned(),
+            };
+            return Err(Error::new(InnerError::ProjectLocked(message)));
+        }
+        file.set_len(0)?;
+        file.write_all(process::id().to_string().as_bytes())?;
+        Ok(Self { file })
+    }
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclusive_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = ProjectLock::acquire(dir.path()).unwrap();
+        let err = ProjectLock::acquire(dir.path()).unwrap_err();
+        assert!(matches!(err.inner_error, InnerError::ProjectLocked(_)));
+        drop(lock);
+        assert!(dir.path().join(LOCK_FILE).exists());
+        ProjectLock::acquire(dir.path()).unwrap();
+    }
+
+    #[test]
+    fn stale_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        // Left by a command which died, its pid doesn't matter as it holds no lock anymore
+        fs::write(dir.path().join(LOCK_FILE), process::id().to_string()).unwrap();
+        ProjectLock::acquire(dir.path()).unwrap();
+    }
+}
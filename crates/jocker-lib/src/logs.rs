@@ -1,13 +1,27 @@
-use std::sync::Arc;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
 
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::Serialize;
 use tokio::{
-    sync::mpsc::{self, Receiver, Sender},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{self, Sender},
+    },
     task::JoinSet,
 };
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tracing::warn;
 
 use crate::{
-    common::{Exec, Process, ProcessState},
+    archive::{sync_logs, LogArchive},
+    common::{Exec, Process},
     error::{Error, InnerError, Result},
+    secrets, Pid,
 };
 
 use crate::state::State;
@@ -15,9 +29,127 @@ use crate::state::State;
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct LogsArgs {
     pub follow: bool,
+    /// Print lines prefixed with the name of their process, only used by [`Logs::exec`]
     pub process_prefix: bool,
     pub tail: bool,
+    /// Only keep lines matching this regular expression
+    pub grep: Option<String>,
+    /// Keep lines not matching [`Self::grep`] instead
+    pub invert: bool,
+    /// Read logs archived in the state directory instead of the scheduler ones
+    pub from_file: bool,
     pub processes: Vec<String>,
+    /// Only keep processes having all of these labels, as `(key, value)`
+    pub labels: Vec<(String, String)>,
+    /// Number of entries kept for a consumer lagging behind, [`DEFAULT_LOGS_BUFFER`] by default
+    pub buffer: Option<usize>,
+}
+
+/// Number of log entries kept for a consumer lagging behind by default
+pub const DEFAULT_LOGS_BUFFER: usize = 1024;
+
+/// Buffer between a scheduler and the filtering of its log lines
+const LOG_FILTER_BUFFER: usize = 64;
+
+/// Output of a process a log line was written to
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+    /// Both outputs interleaved, as schedulers keep them
+    Combined,
+}
+
+/// Log line of a process
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct LogEntry {
+    pub process_name: String,
+    /// When the line was read, schedulers don't record when it was written
+    pub timestamp: DateTime<Utc>,
+    pub stream: LogStream,
+    pub line: String,
+}
+
+impl LogEntry {
+    fn new(process_name: &str, line: String) -> Self {
+        Self {
+            process_name: process_name.to_owned(),
+            timestamp: Utc::now(),
+            stream: LogStream::Combined,
+            line,
+        }
+    }
+
+    /// Line prefixed with the name of its process, padded to `width`
+    pub fn prefixed(&self, width: usize) -> String {
+        format!("{:width$} > {}", self.process_name, self.line)
+    }
+}
+
+/// Log entries of several processes, interleaved as they are read
+///
+/// Reading logs stops once dropped.
+struct LogEntries {
+    entries: ReceiverStream<Result<LogEntry>>,
+    _readers: JoinSet<()>,
+}
+
+impl Stream for LogEntries {
+    type Item = Result<LogEntry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.entries).poll_next(cx)
+    }
+}
+
+/// Sending end of the log entries of every read process
+#[derive(Clone)]
+enum EntrySender {
+    /// Waits for the consumer once its buffer is full
+    Bounded(Sender<Result<LogEntry>>),
+    /// Drops the oldest entries once the buffer is full, so that a slow consumer doesn't stall
+    /// schedulers streaming logs. Errors are never dropped.
+    DropOldest {
+        entries: broadcast::Sender<LogEntry>,
+        errors: Sender<Result<LogEntry>>,
+    },
+}
+
+impl EntrySender {
+    /// Send given entry, returning `false` once the consumer is gone
+    async fn send(&self, entry: Result<LogEntry>) -> bool {
+        match (self, entry) {
+            (Self::Bounded(tx), entry) => tx.send(entry).await.is_ok(),
+            (Self::DropOldest { entries, .. }, Ok(entry)) => entries.send(entry).is_ok(),
+            (Self::DropOldest { errors, .. }, Err(e)) => errors.send(Err(e)).await.is_ok(),
+        }
+    }
+}
+
+/// Selection of log lines, applied before they are sent to the consumer
+#[derive(Clone, Debug)]
+struct LineFilter {
+    regex: Regex,
+    invert: bool,
+}
+
+impl LineFilter {
+    fn new(args: &LogsArgs) -> Result<Option<Self>> {
+        let Some(pattern) = args.grep.as_ref() else {
+            return Ok(None);
+        };
+        let regex = Regex::new(pattern)
+            .map_err(|e| Error::new(InnerError::Parse(format!("{pattern}: {e}"))))?;
+        Ok(Some(Self {
+            regex,
+            invert: args.invert,
+        }))
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        self.regex.is_match(line) != self.invert
+    }
 }
 
 pub struct Logs {
@@ -30,52 +162,127 @@ impl Logs {
         Logs { args, state }
     }
 
-    pub async fn run(&self) -> Result<(JoinSet<Result<()>>, Receiver<String>)> {
-        let processes = self.state.filter_processes(&self.args.processes).await?;
-        let mut handles = JoinSet::new();
-        let max_process_name_len = processes.iter().fold(0, |acc, e| {
-            if acc < e.name().len() {
-                e.name().len()
-            } else {
-                acc
-            }
-        });
-        let (tx, rx) = mpsc::channel(processes.len() * 2);
+    /// Log entries of selected processes
+    ///
+    /// Failing to read logs of a process yields an error, while entries of other processes keep
+    /// coming.
+    pub async fn run(&self) -> Result<impl Stream<Item = Result<LogEntry>>> {
+        let processes = self.processes().await?;
+        self.entries(processes)
+    }
+
+    async fn processes(&self) -> Result<Vec<Process>> {
+        let mut processes = self.state.filter_processes(&self.args.processes).await?;
+        processes.retain(|p| p.has_labels(&self.args.labels));
+        Ok(processes)
+    }
+
+    fn entries(&self, processes: Vec<Process>) -> Result<LogEntries> {
+        let filter = LineFilter::new(&self.args)?;
+        let archive = if self.args.from_file {
+            Some(Arc::new(LogArchive::new(&self.state)?))
+        } else {
+            None
+        };
+        let mut readers = JoinSet::new();
+        let buffer = self.args.buffer.unwrap_or(DEFAULT_LOGS_BUFFER).max(1);
+        let (tx, rx) = if self.args.follow {
+            // Entries wait in the broadcast buffer, where the oldest ones are dropped
+            let (consumer_tx, rx) = mpsc::channel(1);
+            let (entries, mut entries_rx) = broadcast::channel(buffer);
+            let forward_tx = consumer_tx.clone();
+            readers.spawn(async move {
+                loop {
+                    match entries_rx.recv().await {
+                        Ok(entry) => {
+                            if forward_tx.send(Ok(entry)).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(RecvError::Lagged(count)) => {
+                            warn!("Logs are read too slowly, dropped {count} lines");
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
+            let errors = consumer_tx;
+            (EntrySender::DropOldest { entries, errors }, rx)
+        } else {
+            let (tx, rx) = mpsc::channel(buffer);
+            (EntrySender::Bounded(tx), rx)
+        };
         for process in processes {
-            let state = self.state.clone();
-            handles.spawn(run(
-                state,
-                process,
-                self.args.clone(),
-                max_process_name_len,
-                tx.clone(),
-            ));
+            let read = {
+                let (archive, state, args) =
+                    (archive.clone(), self.state.clone(), self.args.clone());
+                let (filter, tx) = (filter.clone(), tx.clone());
+                async move {
+                    let masked = secret_values(&state, &process).await?;
+                    match archive {
+                        Some(archive) => {
+                            // Lines of running processes are archived as they are read
+                            if process.state.is_started() {
+                                sync_logs(&state, &process).await;
+                            }
+                            run_from_file(archive, process, &masked, filter, tx).await
+                        }
+                        None => run(state, process, args, &masked, filter, tx).await,
+                    }
+                }
+            };
+            let tx = tx.clone();
+            readers.spawn(async move {
+                if let Err(e) = read.await {
+                    tx.send(Err(e)).await;
+                }
+            });
         }
-
-        Ok((handles, rx))
+        Ok(LogEntries {
+            entries: ReceiverStream::new(rx),
+            _readers: readers,
+        })
     }
 }
 
 impl Exec<()> for Logs {
     async fn exec(&self) -> Result<()> {
-        let (mut handles, mut rx) = self.run().await.unwrap();
+        let processes = self.processes().await?;
+        let max_process_name_len = processes.iter().map(|p| p.name().len()).max().unwrap_or(0);
+        let mut entries = self.entries(processes)?;
 
-        while let Some(message) = rx.recv().await {
-            println!("{message}");
+        // Logs of other processes are still printed, the first error is reported once done
+        let mut result = Ok(());
+        while let Some(entry) = entries.next().await {
+            match entry {
+                Ok(entry) if self.args.process_prefix => {
+                    println!("{}", entry.prefixed(max_process_name_len))
+                }
+                Ok(entry) => println!("{}", entry.line),
+                Err(e) if result.is_ok() => result = Err(e),
+                Err(_) => {}
+            }
         }
 
-        while (handles.join_next().await).is_some() {}
-
-        Ok(())
+        result
     }
 }
 
+/// Values of the secrets of given process, masked in its log lines
+pub(crate) async fn secret_values(state: &State, process: &Process) -> Result<Vec<String>> {
+    Ok(secrets::resolve(&process.secrets, state.get_target_dir())
+        .await?
+        .into_values()
+        .collect())
+}
+
 async fn run(
     state: Arc<State>,
     process: Process,
     args: LogsArgs,
-    max_process_name_len: usize,
-    log_tx: Sender<String>,
+    masked: &[String],
+    filter: Option<LineFilter>,
+    log_tx: EntrySender,
 ) -> Result<()> {
     let process_name = process.name();
     // get file
@@ -83,28 +290,35 @@ async fn run(
 
     // get pos to end of file
     // let f = File::open(&path).await?;
-    let process_prefix = if args.process_prefix {
-        format!("{process_name:max_process_name_len$} > ")
-    } else {
-        "".to_string()
-    };
     if !args.tail {
         // let reader = BufReader::new(f);
         // let mut lines = reader.lines();
-        state
-            .scheduler()
-            .logs(
-                log_tx,
-                &process_prefix,
-                process.pid().ok_or_else(|| {
-                    Error::new(InnerError::Pueue(pueue_lib::Error::Generic(
-                        "PID missing for log".to_owned(),
-                    )))
-                })?,
-                None,
-                args.follow,
-            )
-            .await?;
+        let Some(pid) = last_pid(&state, &process).await? else {
+            // Scheduler lost track of the process, fall back to its archived logs
+            let archive = Arc::new(LogArchive::new(&state)?);
+            if archive.read(process_name)?.is_empty() {
+                return Err(Error::new(InnerError::ProcessNotRunning(
+                    process_name.to_owned(),
+                )));
+            }
+            return run_from_file(archive, process, masked, filter, log_tx).await;
+        };
+        // Logs of a stopped process won't grow anymore
+        let follow = args.follow && !process.state.is_stopped();
+        let (line_tx, mut line_rx) = mpsc::channel::<String>(LOG_FILTER_BUFFER);
+        let forward = async {
+            while let Some(line) = line_rx.recv().await {
+                let line = secrets::mask(&line, masked);
+                if filter.as_ref().is_some_and(|f| !f.is_match(&line)) {
+                    continue;
+                }
+                if !log_tx.send(Ok(LogEntry::new(process_name, line))).await {
+                    break;
+                }
+            }
+        };
+        let (res, ()) = tokio::join!(state.scheduler().logs(line_tx, pid, None, follow), forward);
+        res?;
         // while let Ok(Some(line)) = lines.next_line().await {
         //     log_tx
         //         .send(format!("{process_prefix}{}", line))
@@ -113,7 +327,7 @@ async fn run(
         // }
     }
 
-    if !args.follow || process.state == ProcessState::Stopped {
+    if !args.follow || process.state.is_stopped() {
         return Ok(());
     }
 
@@ -156,3 +370,70 @@ async fn run(
 
     Ok(())
 }
+
+/// Scheduler PID of the last run of given process, which scheduler logs are still available for
+/// once it stopped
+async fn last_pid(state: &State, process: &Process) -> Result<Option<Pid>> {
+    if let Some(pid) = *process.pid() {
+        return Ok(Some(pid));
+    }
+    Ok(state
+        .scheduler()
+        .status()
+        .await?
+        .get(process.name())
+        .map(|scheduled| scheduled.pid))
+}
+
+async fn run_from_file(
+    archive: Arc<LogArchive>,
+    process: Process,
+    masked: &[String],
+    filter: Option<LineFilter>,
+    log_tx: EntrySender,
+) -> Result<()> {
+    let process_name = process.name();
+    for line in archive.read(process_name)? {
+        // Lines archived before secrets were masked there
+        let line = secrets::mask(&line, masked);
+        if filter.as_ref().is_some_and(|f| !f.is_match(&line)) {
+            continue;
+        }
+        if !log_tx.send(Ok(LogEntry::new(process_name, line))).await {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_lines() {
+        let args = LogsArgs {
+            grep: Some("ERROR|WARN".to_owned()),
+            ..Default::default()
+        };
+        let filter = LineFilter::new(&args).unwrap().unwrap();
+        assert!(filter.is_match("2025-06-10 ERROR boom"));
+        assert!(!filter.is_match("2025-06-10 INFO fine"));
+
+        let filter = LineFilter::new(&LogsArgs {
+            invert: true,
+            ..args
+        })
+        .unwrap()
+        .unwrap();
+        assert!(!filter.is_match("2025-06-10 ERROR boom"));
+        assert!(filter.is_match("2025-06-10 INFO fine"));
+
+        assert!(LineFilter::new(&LogsArgs::default()).unwrap().is_none());
+        assert!(LineFilter::new(&LogsArgs {
+            grep: Some("(".to_owned()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use tracing::{info, warn};
+
+use crate::{
+    common::{Exec, ProcessState},
+    error::Result,
+    state::State,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PauseArgs {
+    pub processes: Vec<String>,
+}
+
+/// Freeze running processes with SIGSTOP, without stopping them
+///
+/// Paused processes keep their memory and their ports, and are shown as paused by `ps` until they
+/// are resumed. Stopping a paused process resumes it first, so that it handles SIGTERM.
+pub struct Pause {
+    args: PauseArgs,
+    state: Arc<State>,
+}
+
+impl Pause {
+    pub fn new(args: PauseArgs, state: Arc<State>) -> Self {
+        Pause { args, state }
+    }
+}
+
+impl Exec<()> for Pause {
+    async fn exec(&self) -> Result<()> {
+        let _lock = self.state.lock()?;
+        for process in self.state.filter_processes(&self.args.processes).await? {
+            match process.pid {
+                Some(pid) if process.state == ProcessState::Running => {
+                    self.state.scheduler().pause(pid).await?;
+                    self.state
+                        .set_state(process.name(), ProcessState::Paused)
+                        .await?;
+                    info!("Process {} paused", process.name());
+                }
+                _ if process.state == ProcessState::Paused => {
+                    info!("Process is already paused: {}", process.name());
+                }
+                _ => warn!("Process {} is {}, not pausing it", process.name(), process.state),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ResumeArgs {
+    pub processes: Vec<String>,
+}
+
+/// Let paused processes run again, with SIGCONT
+pub struct Resume {
+    args: ResumeArgs,
+    state: Arc<State>,
+}
+
+impl Resume {
+    pub fn new(args: ResumeArgs, state: Arc<State>) -> Self {
+        Resume { args, state }
+    }
+}
+
+impl Exec<()> for Resume {
+    async fn exec(&self) -> Result<()> {
+        let _lock = self.state.lock()?;
+        for process in self.state.filter_processes(&self.args.processes).await? {
+            match process.pid {
+                Some(pid) if process.state == ProcessState::Paused => {
+                    self.state.scheduler().resume(pid).await?;
+                    self.state
+                        .set_state(process.name(), ProcessState::Running)
+                        .await?;
+                    info!("Process {} resumed", process.name());
+                }
+                // Resuming every process of the stack mustn't complain about those running
+                _ if self.args.processes.is_empty() => {}
+                _ => warn!("Process {} is {}, not resuming it", process.name(), process.state),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use std::{collections::HashMap, net::TcpListener};
+
+/// OS process listening on a TCP port
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PortOwner {
+    pub pid: u32,
+    /// Command name of the process, as reported by the OS
+    pub name: String,
+}
+
+/// Whether given TCP port can be bound on every interface
+pub fn is_port_available(port: u16) -> bool {
+    TcpListener::bind(("0.0.0.0", port)).is_ok()
+}
+
+/// OS processes listening on TCP ports, indexed by port
+///
+/// Only processes visible to the current user are found. Always empty on platforms other than
+/// Linux.
+pub fn listening_ports() -> HashMap<u16, PortOwner> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::listening_ports()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        HashMap::new()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{collections::HashMap, fs};
+
+    use super::PortOwner;
+
+    /// State of a listening socket in `/proc/net/tcp`
+    const TCP_LISTEN: &str = "0A";
+
+    pub(super) fn listening_ports() -> HashMap<u16, PortOwner> {
+        let mut ports_by_inode: HashMap<u64, u16> = HashMap::new();
+        for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+            if let Ok(content) = fs::read_to_string(table) {
+                ports_by_inode.extend(
+                    parse_listening_sockets(&content)
+                        .into_iter()
+                        .map(|(port, inode)| (inode, port)),
+                );
+            }
+        }
+        let mut owners = HashMap::new();
+        if ports_by_inode.is_empty() {
+            return owners;
+        }
+        let Ok(proc_entries) = fs::read_dir("/proc") else {
+            return owners;
+        };
+        for entry in proc_entries.flatten() {
+            let Some(pid) = entry
+                .file_name()
+                .to_str()
+                .and_then(|p| p.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+                continue;
+            };
+            for fd in fds.flatten() {
+                let Some(inode) = fs::read_link(fd.path()).ok().and_then(|target| {
+                    target
+                        .to_str()?
+                        .strip_prefix("socket:[")?
+                        .strip_suffix(']')?
+                        .parse::<u64>()
+                        .ok()
+                }) else {
+                    continue;
+                };
+                if let Some(port) = ports_by_inode.get(&inode) {
+                    let name = fs::read_to_string(entry.path().join("comm"))
+                        .map(|comm| comm.trim().to_owned())
+                        .unwrap_or_default();
+                    owners.insert(*port, PortOwner { pid, name });
+                }
+            }
+        }
+        owners
+    }
+
+    /// Port and inode of every listening socket of a `/proc/net/tcp` table
+    pub(super) fn parse_listening_sockets(content: &str) -> Vec<(u16, u64)> {
+        content
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.get(3) != Some(&TCP_LISTEN) {
+                    return None;
+                }
+                let (_, port) = fields.get(1)?.rsplit_once(':')?;
+                let port = u16::from_str_radix(port, 16).ok()?;
+                let inode = fields.get(9)?.parse().ok()?;
+                Some((port, inode))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_bound_port() {
+        let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(!is_port_available(port));
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            listening_ports().get(&port).map(|owner| owner.pid),
+            Some(std::process::id())
+        );
+        drop(listener);
+        assert!(is_port_available(port));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_proc_net_tcp() {
+        let content = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 12345 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:A2C4 0100007F:1F90 01 00000000:00000000 00:00000000 00000000  1000        0 23456 1 0000000000000000 20 4 30 10 -1
+";
+        assert_eq!(linux::parse_listening_sockets(content), vec![(8080, 12345)]);
+    }
+}
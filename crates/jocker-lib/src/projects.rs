@@ -0,0 +1,413 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, read_dir, OpenOptions},
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use tracing::{error, info, warn};
+
+use crate::{
+    command::pueue::{Pueue, Pueued, PUEUED_DIR},
+    common::Exec,
+    error::{Error, InnerError, Result},
+    lock::ProjectLock,
+    scheduler::Scheduler,
+    state::State,
+    JOCKER,
+};
+
+const PROJECTS_FILE: &str = "projects.json";
+const PROJECTS_LOCK_FILE: &str = "projects.lock";
+
+/// Project whose state is kept by jocker
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Project {
+    /// Name of the state directory of the project, hashed from its path
+    pub id: String,
+    /// Target directory of the project, `None` for state directories predating the registry
+    pub path: Option<PathBuf>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl Project {
+    /// Whether the directory of the project is gone, so its state is of no use anymore
+    pub fn is_stale(&self) -> bool {
+        self.path.as_ref().is_some_and(|path| !path.exists())
+    }
+
+    /// Path of the project, or its id for state directories predating the registry
+    fn name(&self) -> String {
+        match &self.path {
+            Some(path) => path.display().to_string(),
+            None => self.id.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct RegisteredProject {
+    path: PathBuf,
+    last_used_at: DateTime<Utc>,
+}
+
+/// Global registry mapping project paths to their state directory
+///
+/// State directories are named after a hash of the project path, the registry is stored next to
+/// them so they can be told apart.
+pub struct ProjectRegistry {
+    dir: PathBuf,
+}
+
+impl ProjectRegistry {
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_dir(State::state_home()?.join(JOCKER)))
+    }
+
+    pub(crate) fn with_dir(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// State directory of given project
+    pub fn project_dir(&self, project_id: &str) -> PathBuf {
+        self.dir.join(project_id)
+    }
+
+    /// Every project with a state directory or an entry in the registry, sorted by path
+    pub fn projects(&self) -> Result<Vec<Project>> {
+        let mut registered = self.read()?;
+        let mut projects = vec![];
+        if self.dir.exists() {
+            for entry in read_dir(&self.dir)? {
+                let entry = entry?;
+                // The embedded `pueued` instance keeps its state next to projects
+                if !entry.file_type()?.is_dir() || entry.file_name() == PUEUED_DIR {
+                    continue;
+                }
+                let id = entry.file_name().to_string_lossy().into_owned();
+                let registered = registered.remove(&id);
+                projects.push(Project {
+                    id,
+                    path: registered.as_ref().map(|p| p.path.clone()),
+                    last_used_at: registered.map(|p| p.last_used_at),
+                });
+            }
+        }
+        // Entries whose state directory was removed by hand
+        projects.extend(registered.into_iter().map(|(id, registered)| Project {
+            id,
+            path: Some(registered.path),
+            last_used_at: Some(registered.last_used_at),
+        }));
+        projects.sort_by(|a, b| a.path.cmp(&b.path).then_with(|| a.id.cmp(&b.id)));
+        Ok(projects)
+    }
+
+    /// Record that the project at given path was just used
+    pub fn touch(&self, project_id: &str, path: &Path) -> Result<()> {
+        self.update(|registered| {
+            registered.insert(
+                project_id.to_owned(),
+                RegisteredProject {
+                    path: path.to_path_buf(),
+                    last_used_at: Utc::now(),
+                },
+            );
+            true
+        })
+    }
+
+    pub fn remove(&self, project_id: &str) -> Result<()> {
+        self.update(|registered| registered.remove(project_id).is_some())
+    }
+
+    /// Change the registry while holding its lock, so concurrent commands don't lose each other's
+    /// changes. It is only written if `change` returns `true`.
+    fn update(
+        &self,
+        change: impl FnOnce(&mut BTreeMap<String, RegisteredProject>) -> bool,
+    ) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.dir.join(PROJECTS_LOCK_FILE))?;
+        // Released along with the file once updated
+        lock.lock_exclusive()?;
+        let mut registered = self.read()?;
+        if change(&mut registered) {
+            self.write(&registered)?;
+        }
+        Ok(())
+    }
+
+    /// Registered projects, none if the registry can't be parsed as it is only informative
+    fn read(&self) -> Result<BTreeMap<String, RegisteredProject>> {
+        let path = self.dir.join(PROJECTS_FILE);
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        match serde_json::from_str(&fs::read_to_string(&path)?) {
+            Ok(registered) => Ok(registered),
+            Err(e) => {
+                warn!("Ignoring invalid project registry {}: {e}", path.display());
+                Ok(BTreeMap::new())
+            }
+        }
+    }
+
+    fn write(&self, registered: &BTreeMap<String, RegisteredProject>) -> Result<()> {
+        // Replace the file at once, so concurrent commands never read half of it
+        let mut pending = NamedTempFile::new_in(&self.dir)?;
+        pending.write_all(serde_json::to_string_pretty(registered)?.as_bytes())?;
+        pending.persist(self.dir.join(PROJECTS_FILE)).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
+/// Stop processes of given project and remove its state directory, while holding its lock
+pub(crate) async fn clean_project(
+    project_id: &str,
+    project_dir: &Path,
+    scheduler: &dyn Scheduler,
+) -> Result<()> {
+    let _lock = ProjectLock::acquire(project_dir)?;
+    scheduler.clean().await?;
+    fs::remove_dir_all(project_dir)?;
+    ProjectRegistry::new()?.remove(project_id)
+}
+
+/// Remove the state of the project with given id, whatever its configuration
+async fn clean_project_id(registry: &ProjectRegistry, project_id: &str) -> Result<()> {
+    let project_dir = registry.project_dir(project_id);
+    if !project_dir.exists() {
+        return registry.remove(project_id);
+    }
+    let scheduler = State::new_scheduler(project_id, &project_dir).await?;
+    clean_project(project_id, &project_dir, scheduler.as_ref()).await
+}
+
+/// Clean given projects one after the other, returning names of the ones which couldn't be cleaned
+///
+/// A failure is logged, and the next projects are cleaned anyway.
+async fn clean_projects<F>(projects: Vec<Project>, clean: impl Fn(&Project) -> F) -> Vec<String>
+where
+    F: Future<Output = Result<()>>,
+{
+    let mut failed = Vec::new();
+    for project in projects {
+        match clean(&project).await {
+            Ok(()) => info!("Removed state of project {}", project.name()),
+            Err(e) => {
+                error!("Unable to clean project {}: {e}", project.name());
+                failed.push(project.name());
+            }
+        }
+    }
+    failed
+}
+
+/// List projects whose state is kept by jocker
+#[derive(Default)]
+pub struct ListProjects;
+
+impl ListProjects {
+    pub fn new() -> Self {
+        ListProjects
+    }
+
+    pub async fn run(&self) -> Result<Vec<Project>> {
+        ProjectRegistry::new()?.projects()
+    }
+}
+
+impl Exec<Vec<Project>> for ListProjects {
+    async fn exec(&self) -> Result<Vec<Project>> {
+        self.run().await
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CleanProjectArgs {
+    /// Target directory of the project, which may not exist anymore
+    pub path: PathBuf,
+}
+
+/// Stop processes of the project at given path and remove its state
+pub struct CleanProject {
+    args: CleanProjectArgs,
+}
+
+impl CleanProject {
+    pub fn new(args: CleanProjectArgs) -> Self {
+        CleanProject { args }
+    }
+}
+
+impl Exec<()> for CleanProject {
+    async fn exec(&self) -> Result<()> {
+        let path = fs::canonicalize(&self.args.path).unwrap_or_else(|_| self.args.path.clone());
+        let registry = ProjectRegistry::new()?;
+        let project_id = State::get_project_id(&path);
+        let known = registry
+            .projects()?
+            .iter()
+            .any(|project| project.id == project_id);
+        if !known {
+            let error = InnerError::Filesystem(format!(
+                "No state is kept for project {}",
+                path.display()
+            ));
+            return Err(Error::new(error).with_hint("List projects with `jocker projects list`"));
+        }
+        clean_project_id(&registry, &project_id).await?;
+        info!("Removed state of project {}", path.display());
+        Ok(())
+    }
+}
+
+/// Remove the state of projects whose directory doesn't exist anymore
+#[derive(Default)]
+pub struct GcProjects;
+
+impl GcProjects {
+    pub fn new() -> Self {
+        GcProjects
+    }
+}
+
+impl Exec<()> for GcProjects {
+    async fn exec(&self) -> Result<()> {
+        let registry = ProjectRegistry::new()?;
+        let mut removed = 0;
+        for project in registry.projects()?.into_iter().filter(Project::is_stale) {
+            clean_project_id(&registry, &project.id).await?;
+            if let Some(path) = project.path {
+                info!("Removed state of project {}", path.display());
+            }
+            removed += 1;
+        }
+        if removed == 0 {
+            info!("No stale project");
+        }
+        Ok(())
+    }
+}
+
+/// Stop processes and remove the state of every project, along with leftover `pueue` groups
+#[derive(Default)]
+pub struct CleanAllProjects;
+
+impl CleanAllProjects {
+    pub fn new() -> Self {
+        CleanAllProjects
+    }
+}
+
+impl Exec<()> for CleanAllProjects {
+    async fn exec(&self) -> Result<()> {
+        let registry = ProjectRegistry::new()?;
+        let failed = clean_projects(registry.projects()?, |project| {
+            clean_project_id(&registry, &project.id)
+        })
+        .await;
+        // Groups of projects whose state directory was removed by hand, or cleaned while using
+        // the native scheduler
+        for group in Pueue::clean_all_groups().await? {
+            info!("Removed pueue group {group}");
+        }
+        if Pueued::shutdown_embedded().await? {
+            info!("Stopped pueued instance of jocker");
+        }
+        if !failed.is_empty() {
+            return Err(Error::new(InnerError::Filesystem(format!(
+                "Unable to clean projects: {}",
+                failed.join(", ")
+            ))));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = ProjectRegistry::with_dir(dir.path());
+        assert!(registry.projects().unwrap().is_empty());
+
+        let project_dir = tempfile::tempdir().unwrap();
+        registry.touch("aaa", project_dir.path()).unwrap();
+        registry
+            .touch("bbb", Path::new("/nonexistent/project"))
+            .unwrap();
+        // State directory without any registry entry
+        fs::create_dir(registry.project_dir("ccc")).unwrap();
+
+        let projects = registry.projects().unwrap();
+        let ids: Vec<&str> = projects.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["ccc", "bbb", "aaa"]);
+        let stale: Vec<&str> = projects
+            .iter()
+            .filter(|p| p.is_stale())
+            .map(|p| p.id.as_str())
+            .collect();
+        assert_eq!(stale, vec!["bbb"]);
+
+        registry.remove("bbb").unwrap();
+        let projects = registry.projects().unwrap();
+        assert_eq!(projects.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn clean_projects_despite_failures() {
+        let project = |id: &str, path: Option<&str>| Project {
+            id: id.to_owned(),
+            path: path.map(PathBuf::from),
+            last_used_at: None,
+        };
+        let projects = vec![
+            project("aaa", Some("/projects/api")),
+            project("bbb", Some("/projects/locked")),
+            project("ccc", None),
+            project("ddd", None),
+        ];
+        let attempted = std::sync::Mutex::new(Vec::new());
+        let failed = clean_projects(projects, |project| {
+            attempted.lock().unwrap().push(project.id.clone());
+            let locked = project.id == "bbb" || project.id == "ddd";
+            async move {
+                if locked {
+                    return Err(Error::new(InnerError::ProjectLocked("locked".to_owned())));
+                }
+                Ok(())
+            }
+        })
+        .await;
+        assert_eq!(failed, vec!["/projects/locked", "ddd"]);
+        assert_eq!(*attempted.lock().unwrap(), vec!["aaa", "bbb", "ccc", "ddd"]);
+    }
+
+    #[test]
+    fn invalid_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = ProjectRegistry::with_dir(dir.path());
+        fs::write(dir.path().join(PROJECTS_FILE), "{").unwrap();
+        assert!(registry.projects().unwrap().is_empty());
+
+        // Overwritten by the next change
+        registry.touch("aaa", dir.path()).unwrap();
+        let projects = registry.projects().unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].path.as_deref(), Some(dir.path()));
+    }
+}
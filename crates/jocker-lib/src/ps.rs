@@ -1,8 +1,15 @@
-use std::sync::Arc;
+use std::{cmp::Ordering, collections::BTreeMap, str::FromStr, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Mutex;
 
 use crate::{
+    adopt::external_pids,
     common::{Exec, Process, ProcessState},
-    error::Result,
+    error::{Error, InnerError, Result},
+    health::is_healthy,
+    resources::ResourceMonitor,
     state::State,
     Pid,
 };
@@ -10,12 +17,130 @@ use crate::{
 #[derive(Debug, Default, PartialEq)]
 pub struct PsArgs {
     pub processes: Vec<String>,
+    pub sort: PsSort,
+    /// Only list processes matching one of these states, or every process if empty
+    pub states: Vec<PsStateFilter>,
+    /// Only keep processes having all of these labels, as `(key, value)`
+    pub labels: Vec<(String, String)>,
+}
+
+/// State processes listed by [`Ps`] can be filtered on
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PsStateFilter {
+    State(ProcessState),
+    /// Running, but failing its healthcheck
+    Unhealthy,
+}
+
+impl FromStr for PsStateFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "unhealthy" => Ok(Self::Unhealthy),
+            _ => Ok(Self::State(s.to_owned().try_into()?)),
+        }
+    }
 }
 
+impl PsStateFilter {
+    async fn matches(&self, state: &State, process: &Process) -> bool {
+        match self {
+            Self::State(process_state) => process.state == *process_state,
+            Self::Unhealthy => {
+                process.state == ProcessState::Running && !is_healthy(state, process).await
+            }
+        }
+    }
+}
+
+/// Column processes listed by [`Ps`] are sorted by
+///
+/// Names, states and PIDs are sorted in ascending order, quantities with the largest first.
+/// Processes are sorted by name when equal.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PsSort {
+    #[default]
+    Name,
+    State,
+    Pid,
+    Uptime,
+    Restarts,
+    ExitCode,
+    Cpu,
+    Memory,
+}
+
+impl FromStr for PsSort {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "state" => Ok(Self::State),
+            "pid" => Ok(Self::Pid),
+            "uptime" => Ok(Self::Uptime),
+            "restarts" => Ok(Self::Restarts),
+            "exit_code" => Ok(Self::ExitCode),
+            "cpu" => Ok(Self::Cpu),
+            "mem" | "memory" => Ok(Self::Memory),
+            _ => Err(Error::new(InnerError::Parse(s.to_owned()))),
+        }
+    }
+}
+
+impl PsSort {
+    fn compare(&self, a: &PsOutput, b: &PsOutput) -> Ordering {
+        let ordering = match self {
+            Self::Name => Ordering::Equal,
+            Self::State => a.state.cmp(&b.state),
+            Self::Pid => a.pid.cmp(&b.pid),
+            // Earliest start first, processes which aren't running last
+            Self::Uptime => match (a.uptime_start(), b.uptime_start()) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            Self::Restarts => b.restart_count.cmp(&a.restart_count),
+            Self::ExitCode => a.exit_code.cmp(&b.exit_code),
+            Self::Cpu => b.cpu.partial_cmp(&a.cpu).unwrap_or(Ordering::Equal),
+            Self::Memory => b.memory.cmp(&a.memory),
+        };
+        ordering.then_with(|| a.name.cmp(&b.name))
+    }
+}
+
+#[derive(Serialize)]
 pub struct PsOutput {
     pub name: String,
     pub state: ProcessState,
     pub pid: Option<Pid>,
+    pub started_at: Option<DateTime<Utc>>,
+    /// End of the last run, once the process stopped or exited
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub restart_count: u32,
+    pub exit_code: Option<i32>,
+    /// TCP ports declared in the configuration
+    pub ports: Vec<u16>,
+    /// TCP ports the process is actually listening on, including the ones of its children
+    pub listening_ports: Vec<u16>,
+    /// CPU usage, in percent of a single core
+    pub cpu: Option<f32>,
+    /// Resident memory, in bytes
+    pub memory: Option<u64>,
+    /// Command line of the current or last run, binary path and args resolved
+    pub command: Option<String>,
+    /// Stack in use when the process was last started
+    pub stack: Option<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl PsOutput {
+    /// Start of the current run of the process, if it is running
+    fn uptime_start(&self) -> Option<DateTime<Utc>> {
+        self.started_at.filter(|_| self.state == ProcessState::Running)
+    }
 }
 
 impl From<Process> for PsOutput {
@@ -24,6 +149,17 @@ impl From<Process> for PsOutput {
             name: value.name,
             state: value.state,
             pid: value.pid,
+            started_at: value.started_at,
+            stopped_at: value.stopped_at,
+            restart_count: value.restart_count,
+            exit_code: value.exit_code,
+            ports: value.ports,
+            listening_ports: Vec::new(),
+            cpu: None,
+            memory: None,
+            command: value.command_line,
+            stack: value.stack,
+            labels: value.labels,
         }
     }
 }
@@ -31,17 +167,85 @@ impl From<Process> for PsOutput {
 pub struct Ps {
     args: PsArgs,
     state: Arc<State>,
+    /// Kept across runs so CPU usage is computed since the previous one
+    monitor: Mutex<ResourceMonitor>,
 }
 
 impl Ps {
     pub fn new(args: PsArgs, state: Arc<State>) -> Self {
-        Ps { args, state }
+        Ps {
+            args,
+            state,
+            monitor: Mutex::new(ResourceMonitor::new()),
+        }
     }
 
     pub async fn run(&self) -> Result<Vec<PsOutput>> {
-        let mut processes = self.state.filter_processes(&self.args.processes).await?;
+        let mut output = self.processes().await?;
+        output.sort_by(|a, b| self.args.sort.compare(a, b));
+        Ok(output)
+    }
+
+    async fn processes(&self) -> Result<Vec<PsOutput>> {
+        let mut processes = self.filter_states().await?;
         processes.sort();
-        Ok(processes.into_iter().map(PsOutput::from).collect())
+        if !processes.iter().any(|p| p.state == ProcessState::Running) {
+            return Ok(processes.into_iter().map(PsOutput::from).collect());
+        }
+        let (usages, mut listening_ports) = {
+            let mut monitor = self.monitor.lock().await;
+            let usages = monitor.sample(self.state.scheduler_group()).await;
+            (
+                usages,
+                monitor.listening_ports(self.state.scheduler_group()),
+            )
+        };
+        Ok(processes
+            .into_iter()
+            .map(|process| {
+                let usage = usages
+                    .get(process.name())
+                    .filter(|_| process.state == ProcessState::Running)
+                    .copied();
+                let listening_ports = listening_ports
+                    .remove(process.name())
+                    .filter(|_| process.state == ProcessState::Running)
+                    .unwrap_or_default();
+                PsOutput {
+                    listening_ports,
+                    cpu: usage.map(|u| u.cpu),
+                    memory: usage.map(|u| u.memory),
+                    ..PsOutput::from(process)
+                }
+            })
+            .collect())
+    }
+
+    /// Processes selected by arguments, with one of the requested states
+    async fn filter_states(&self) -> Result<Vec<Process>> {
+        let mut processes = self.state.filter_processes(&self.args.processes).await?;
+        processes.retain(|p| p.has_labels(&self.args.labels));
+        // Binaries run outside of jocker are shown as such, even if they weren't adopted
+        let external_pids = external_pids(&self.state, &processes).await?;
+        for process in processes.iter_mut() {
+            if let Some(pid) = external_pids.get(process.name()) {
+                process.state = ProcessState::External;
+                process.pid = Some(*pid);
+            }
+        }
+        if self.args.states.is_empty() {
+            return Ok(processes);
+        }
+        let mut matching = Vec::new();
+        'processes: for process in processes {
+            for filter in &self.args.states {
+                if filter.matches(&self.state, &process).await {
+                    matching.push(process);
+                    continue 'processes;
+                }
+            }
+        }
+        Ok(matching)
     }
 }
 
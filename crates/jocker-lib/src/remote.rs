@@ -0,0 +1,177 @@
+use std::{path::Path, process::Stdio};
+
+use sha2::{Digest, Sha256};
+use tokio::{fs, process::Command};
+
+use crate::{
+    command::util::shell_quote,
+    error::{filesystem_error, Error, InnerError, Result},
+};
+
+/// Directory binaries are copied to on remote hosts, relative to the home directory there
+const REMOTE_BIN_DIR: &str = ".cache/jocker/bin";
+/// Directory environment files are copied to on remote hosts, only readable by their user
+const REMOTE_ENV_DIR: &str = ".cache/jocker/env";
+
+/// Path of given locally built binary once copied to a remote host, relative to the home
+/// directory there
+///
+/// Binaries are stored under the hash of their content, so that binaries of different projects
+/// sharing a name don't overwrite each other, and an unchanged binary isn't copied again.
+pub(crate) async fn binary_path(artifact: &Path) -> Result<String> {
+    let binary = artifact
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| {
+            Error::new(InnerError::Start(format!(
+                "Invalid binary path {}",
+                artifact.display()
+            )))
+        })?;
+    let content = fs::read(artifact)
+        .await
+        .map_err(filesystem_error(artifact))?;
+    let hash = hex::encode(Sha256::digest(content));
+    Ok(format!("{REMOTE_BIN_DIR}/{hash}/{binary}"))
+}
+
+/// Copy given locally built binary to `host` unless it is already there, returning its path there
+pub(crate) async fn upload(host: &str, artifact: &Path) -> Result<String> {
+    let remote_path = binary_path(artifact).await?;
+    if exists(host, &remote_path).await? {
+        return Ok(remote_path);
+    }
+    // Copied aside first, so that an interrupted copy isn't taken for the binary
+    let partial_path = format!("{remote_path}.part");
+    let dir = remote_path.rsplit_once('/').map_or(REMOTE_BIN_DIR, |(dir, _)| dir);
+    run(
+        Command::new("ssh")
+            .arg(host)
+            .arg(format!("mkdir -p {}", shell_quote(dir))),
+        host,
+    )
+    .await?;
+    run(
+        Command::new("scp")
+            .arg("-q")
+            .arg(artifact)
+            .arg(format!("{host}:{partial_path}")),
+        host,
+    )
+    .await?;
+    run(
+        Command::new("ssh").arg(host).arg(format!(
+            "mv {} {}",
+            shell_quote(&partial_path),
+            shell_quote(&remote_path)
+        )),
+        host,
+    )
+    .await?;
+    Ok(remote_path)
+}
+
+/// Path of the environment file of given process once copied to a remote host, relative to the
+/// home directory there
+pub(crate) fn env_file_path(project_id: &str, process_name: &str) -> String {
+    format!("{REMOTE_ENV_DIR}/{project_id}-{process_name}.env")
+}
+
+/// Copy given local environment file to `remote_path` on `host`, only readable by its user there
+pub(crate) async fn upload_env_file(host: &str, env_file: &Path, remote_path: &str) -> Result<()> {
+    run(
+        Command::new("ssh")
+            .arg(host)
+            .arg(format!("mkdir -p -m 700 {REMOTE_ENV_DIR}")),
+        host,
+    )
+    .await?;
+    // Permissions of the local file are kept
+    run(
+        Command::new("scp")
+            .arg("-q")
+            .arg("-p")
+            .arg(env_file)
+            .arg(format!("{host}:{remote_path}")),
+        host,
+    )
+    .await
+}
+
+/// Local command running given command line on `host` over SSH, with the environment read from
+/// `env_file` there
+///
+/// The environment is kept out of the command line, which other users of both hosts can see. The
+/// command runs from the directory at the same path as `dir` on `host` if there is one, from the
+/// home directory otherwise. A terminal is allocated, so the remote command is hung up along with
+/// the SSH session when the local process is stopped.
+pub(crate) fn ssh_command(host: &str, dir: &Path, env_file: &str, command: &str) -> String {
+    // The environment file is relative to the home directory SSH sessions start from
+    let remote = format!(
+        "set -a; . {} || exit 1; set +a; cd {} 2>/dev/null; exec {command}",
+        shell_quote(env_file),
+        shell_quote(&dir.display().to_string())
+    );
+    format!("ssh -tt {} {}", shell_quote(host), shell_quote(&remote))
+}
+
+/// Whether given path exists on `host`
+async fn exists(host: &str, path: &str) -> Result<bool> {
+    let status = Command::new("ssh")
+        .arg(host)
+        .arg(format!("test -e {}", shell_quote(path)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map_err(Error::with_context(InnerError::Start(format!(
+            "Unable to reach {host} over SSH"
+        ))))?;
+    Ok(status.success())
+}
+
+async fn run(command: &mut Command, host: &str) -> Result<()> {
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(Error::with_context(InnerError::Start(format!(
+            "Unable to reach {host} over SSH"
+        ))))?;
+    if !output.status.success() {
+        return Err(Error::new(InnerError::Start(format!(
+            "Unable to copy files to {host}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_command() {
+        let env_file = env_file_path("f00", "api");
+        assert_eq!(
+            ssh_command("dev@server", Path::new("/src/my app"), &env_file, "./api --port 80"),
+            r"ssh -tt dev@server 'set -a; . .cache/jocker/env/f00-api.env || exit 1; set +a; cd '\''/src/my app'\'' 2>/dev/null; exec ./api --port 80'"
+        );
+    }
+
+    #[tokio::test]
+    async fn binary_path_by_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let (api, other) = (dir.path().join("api"), dir.path().join("other/api"));
+        std::fs::create_dir(dir.path().join("other")).unwrap();
+        std::fs::write(&api, "v1").unwrap();
+        std::fs::write(&other, "v2").unwrap();
+        let path = binary_path(&api).await.unwrap();
+        assert!(path.starts_with(REMOTE_BIN_DIR) && path.ends_with("/api"));
+        assert_ne!(path, binary_path(&other).await.unwrap());
+        std::fs::write(&other, "v1").unwrap();
+        assert_eq!(path, binary_path(&other).await.unwrap());
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    sync::Arc,
+};
+
+use tracing::{info, warn};
+
+use crate::{
+    common::{Exec, Process, Stack},
+    error::Result,
+    scheduler::ScheduledProcess,
+    state::State,
+    Pid,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RepairArgs {
+    /// Only report issues, without repairing them
+    pub dry_run: bool,
+}
+
+/// Inconsistency between the database, the scheduler and the configuration
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StateIssue {
+    /// Process pointing at a task the scheduler doesn't run for it anymore
+    DeadTask { process: String, task: Pid },
+    /// Started task of a process, which the database doesn't point at
+    UntrackedTask { process: String, task: Pid },
+    /// Started task whose label matches no process of the project
+    OrphanTask { label: String, task: Pid },
+    /// Stack listing processes which don't exist, as left by an interrupted reload
+    StackMissingProcesses { stack: String, processes: Vec<String> },
+    /// Selected stack which doesn't exist anymore
+    MissingSelectedStack(String),
+}
+
+impl Display for StateIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DeadTask { process, task } => {
+                write!(f, "process {process} points at task {task}, which no longer runs it")
+            }
+            Self::UntrackedTask { process, task } => {
+                write!(f, "process {process} runs as task {task}, unknown to the database")
+            }
+            Self::OrphanTask { label, task } => {
+                write!(f, "task {task} runs {label}, which is not a process of the project")
+            }
+            Self::StackMissingProcesses { stack, processes } => write!(
+                f,
+                "stack {stack} lists unknown processes {}",
+                processes.join(", ")
+            ),
+            Self::MissingSelectedStack(stack) => write!(f, "selected stack {stack} doesn't exist"),
+        }
+    }
+}
+
+/// Detect and repair a state left out of sync with the scheduler, by a crash or an interrupted
+/// refresh
+///
+/// Orphan tasks are stopped, then the state is refreshed from scratch: processes point again at
+/// the tasks running them, and stacks are reloaded from the configuration. Issues left after that
+/// are reported. Meant to be run without refreshing the state first, as that may be what fails.
+pub struct Repair {
+    args: RepairArgs,
+    state: Arc<State>,
+}
+
+impl Repair {
+    pub fn new(args: RepairArgs, state: Arc<State>) -> Self {
+        Repair { args, state }
+    }
+
+    async fn issues(&self) -> Result<Vec<StateIssue>> {
+        Ok(find_issues(
+            &self.state.get_processes().await?,
+            &self.state.scheduler().status().await?,
+            &self.state.get_stacks().await?,
+            self.state.get_selected_stack().await?,
+        ))
+    }
+}
+
+impl Exec<Vec<StateIssue>> for Repair {
+    /// Issues found, repaired unless running dry
+    async fn exec(&self) -> Result<Vec<StateIssue>> {
+        let issues = self.issues().await?;
+        if self.args.dry_run || issues.is_empty() {
+            return Ok(issues);
+        }
+        let _lock = self.state.lock()?;
+        for issue in &issues {
+            match issue {
+                StateIssue::OrphanTask { label, task } => {
+                    info!("Stopping orphan task {task} of {label} ...");
+                    self.state.scheduler().stop(*task, false, None).await?;
+                }
+                StateIssue::MissingSelectedStack(_) => self.state.set_selected_stack(&None).await?,
+                _ => {}
+            }
+        }
+        self.state.refresh(true).await?;
+        for issue in self.issues().await? {
+            warn!("Unable to repair: {issue}");
+        }
+        Ok(issues)
+    }
+}
+
+/// Inconsistencies between processes and stacks of the database, and tasks of the scheduler
+fn find_issues(
+    processes: &[Process],
+    scheduled: &HashMap<String, ScheduledProcess>,
+    stacks: &[Stack],
+    selected_stack: Option<String>,
+) -> Vec<StateIssue> {
+    let mut issues = vec![];
+    for process in processes {
+        let sp = scheduled.get(process.name());
+        match (process.pid, sp) {
+            (pid, Some(sp)) if sp.state.is_started() && pid != Some(sp.pid) => {
+                issues.push(StateIssue::UntrackedTask {
+                    process: process.name().to_owned(),
+                    task: sp.pid,
+                });
+            }
+            (Some(pid), sp) if process.state.is_started() && sp.map(|sp| sp.pid) != Some(pid) => {
+                issues.push(StateIssue::DeadTask {
+                    process: process.name().to_owned(),
+                    task: pid,
+                });
+            }
+            _ => {}
+        }
+    }
+    let names: HashSet<&str> = processes.iter().map(|p| p.name()).collect();
+    let mut orphans: Vec<StateIssue> = scheduled
+        .iter()
+        .filter(|(label, sp)| sp.state.is_started() && !names.contains(label.as_str()))
+        .map(|(label, sp)| StateIssue::OrphanTask {
+            label: label.to_owned(),
+            task: sp.pid,
+        })
+        .collect();
+    orphans.sort_by_key(|issue| issue.to_string());
+    issues.extend(orphans);
+    for stack in stacks {
+        let mut missing_processes: Vec<String> = stack
+            .get_all_processes()
+            .into_iter()
+            .filter(|process| !names.contains(process.as_str()))
+            .cloned()
+            .collect();
+        if !missing_processes.is_empty() {
+            missing_processes.sort();
+            issues.push(StateIssue::StackMissingProcesses {
+                stack: stack.name.clone(),
+                processes: missing_processes,
+            });
+        }
+    }
+    if let Some(selected_stack) = selected_stack {
+        if !stacks.iter().any(|stack| stack.name == selected_stack) {
+            issues.push(StateIssue::MissingSelectedStack(selected_stack));
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::ProcessState;
+
+    use super::*;
+
+    #[test]
+    fn find_state_issues() {
+        let mut api = Process::new("api", "api");
+        api.state = ProcessState::Running;
+        api.pid = Some(3);
+        let mut web = Process::new("web", "web");
+        web.state = ProcessState::Stopped;
+        let mut db = Process::new("db", "db");
+        db.state = ProcessState::Running;
+        db.pid = Some(1);
+        let running = |pid| ScheduledProcess {
+            pid,
+            state: ProcessState::Running,
+            exit_code: None,
+        };
+        let scheduled = HashMap::from([
+            ("web".to_owned(), running(4)),
+            ("db".to_owned(), running(1)),
+            ("worker".to_owned(), running(2)),
+        ]);
+        let stack = Stack {
+            name: "full".to_owned(),
+            processes: HashSet::from(["api".to_owned(), "cache".to_owned()]),
+            inherited_processes: HashSet::new(),
+            max_parallel: None,
+            user_created: false,
+        };
+        assert_eq!(
+            find_issues(&[api, web, db], &scheduled, &[stack], Some("old".to_owned())),
+            vec![
+                StateIssue::DeadTask {
+                    process: "api".to_owned(),
+                    task: 3
+                },
+                StateIssue::UntrackedTask {
+                    process: "web".to_owned(),
+                    task: 4
+                },
+                StateIssue::OrphanTask {
+                    label: "worker".to_owned(),
+                    task: 2
+                },
+                StateIssue::StackMissingProcesses {
+                    stack: "full".to_owned(),
+                    processes: vec!["cache".to_owned()]
+                },
+                StateIssue::MissingSelectedStack("old".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Serialize;
+use sysinfo::{
+    ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind, MINIMUM_CPU_UPDATE_INTERVAL,
+};
+use tokio::time::sleep;
+
+use crate::{common::JOCKER_ENV_PROCESS_ID, ports};
+
+/// Resources consumed by a managed process, including every child it spawned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    /// CPU usage, in percent of a single core
+    pub cpu: f32,
+    /// Resident memory, in bytes
+    pub memory: u64,
+}
+
+/// Sample resources of OS processes launched by jocker
+///
+/// Processes are matched through the [`JOCKER_ENV_PROCESS_ID`] variable they inherit, which does
+/// not depend on the scheduler backend and also covers their children.
+#[derive(Default)]
+pub struct ResourceMonitor {
+    system: System,
+    primed: bool,
+}
+
+impl ResourceMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resources of processes of given scheduler group, indexed by process name
+    ///
+    /// CPU usage is computed since the previous sample, so the first one waits for
+    /// [`MINIMUM_CPU_UPDATE_INTERVAL`] to get meaningful values.
+    pub async fn sample(&mut self, group: &str) -> HashMap<String, ResourceUsage> {
+        if !self.primed {
+            self.refresh();
+            sleep(MINIMUM_CPU_UPDATE_INTERVAL).await;
+            self.primed = true;
+        }
+        self.refresh();
+
+        let mut usages: HashMap<String, ResourceUsage> = HashMap::new();
+        for (process_name, process) in self.tagged_processes(group) {
+            let usage = usages.entry(process_name.to_owned()).or_default();
+            usage.cpu += process.cpu_usage();
+            usage.memory += process.memory();
+        }
+        usages
+    }
+
+    /// TCP ports listened on by processes of given scheduler group, indexed by process name
+    ///
+    /// Relies on the OS processes found by the last [`Self::sample`].
+    pub fn listening_ports(&self, group: &str) -> HashMap<String, Vec<u16>> {
+        let owners = ports::listening_ports();
+        let mut ports_by_pid: HashMap<u32, Vec<u16>> = HashMap::new();
+        for (port, owner) in owners {
+            ports_by_pid.entry(owner.pid).or_default().push(port);
+        }
+        let mut listening_ports: HashMap<String, Vec<u16>> = HashMap::new();
+        for (process_name, process) in self.tagged_processes(group) {
+            if let Some(ports) = ports_by_pid.get(&process.pid().as_u32()) {
+                listening_ports
+                    .entry(process_name.to_owned())
+                    .or_default()
+                    .extend(ports);
+            }
+        }
+        for ports in listening_ports.values_mut() {
+            ports.sort();
+            ports.dedup();
+        }
+        listening_ports
+    }
+
+    /// PIDs of OS processes of given scheduler group, including children, indexed by process name
+    pub fn pids(&mut self, group: &str) -> HashMap<String, Vec<u32>> {
+        self.refresh();
+        let mut pids: HashMap<String, Vec<u32>> = HashMap::new();
+        for (process_name, process) in self.tagged_processes(group) {
+            pids.entry(process_name.to_owned())
+                .or_default()
+                .push(process.pid().as_u32());
+        }
+        for pids in pids.values_mut() {
+            pids.sort();
+        }
+        pids
+    }
+
+    /// PID of the OS process of given managed process running `executable`, rather than the shell
+    /// or the children around it
+    pub fn executable_pid(
+        &mut self,
+        group: &str,
+        process_name: &str,
+        executable: &Path,
+    ) -> Option<u32> {
+        self.refresh();
+        self.tagged_processes(group)
+            .find(|(name, process)| *name == process_name && process.exe() == Some(executable))
+            .map(|(_, process)| process.pid().as_u32())
+    }
+
+    /// PID of an OS process running `executable` which jocker didn't launch, such as a
+    /// `cargo run` from a terminal
+    pub fn untagged_pid(&mut self, executable: &Path) -> Option<u32> {
+        self.refresh();
+        let prefix = format!("{JOCKER_ENV_PROCESS_ID}=");
+        self.system
+            .processes()
+            .values()
+            .filter(|process| process.thread_kind().is_none())
+            .find(|process| {
+                process.exe() == Some(executable)
+                    && !process
+                        .environ()
+                        .iter()
+                        .any(|var| var.to_string_lossy().starts_with(&prefix))
+            })
+            .map(|process| process.pid().as_u32())
+    }
+
+    /// OS processes launched for given scheduler group, with the name of their managed process
+    fn tagged_processes<'a>(
+        &'a self,
+        group: &str,
+    ) -> impl Iterator<Item = (&'a str, &'a sysinfo::Process)> {
+        let prefix = format!("{JOCKER_ENV_PROCESS_ID}={group}/");
+        self.system.processes().values().filter_map(move |process| {
+            // Threads are listed alongside processes on Linux, don't count them twice
+            if process.thread_kind().is_some() {
+                return None;
+            }
+            let process_name = process
+                .environ()
+                .iter()
+                .find_map(|var| var.to_str()?.strip_prefix(&prefix))?;
+            Some((process_name, process))
+        })
+    }
+
+    fn refresh(&mut self) {
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::nothing()
+                .with_cpu()
+                .with_memory()
+                .with_environ(UpdateKind::OnlyIfNotSet)
+                .with_exe(UpdateKind::OnlyIfNotSet),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Stdio;
+
+    use tokio::process::Command;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn sample_tagged_process() {
+        let mut child = Command::new("sleep")
+            .arg("30")
+            .env(JOCKER_ENV_PROCESS_ID, "resources-test/foo")
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let mut monitor = ResourceMonitor::new();
+        let usages = monitor.sample("resources-test").await;
+        child.kill().await.unwrap();
+
+        assert_eq!(usages.len(), 1);
+        assert!(usages.get("foo").unwrap().memory > 0);
+        assert!(monitor.sample("other-group").await.is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+use std::{path::PathBuf, process::Stdio, sync::Arc};
+
+use crate::{
+    command::util::{exit_code, shell_command, shell_quote},
+    common::Exec,
+    error::{Error, InnerError, Result},
+    secrets,
+    start::{cli_env, process_command, process_env, Start, StartArgs},
+    state::State,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RunArgs {
+    pub process: String,
+    pub args: Vec<String>,
+    /// Variables overriding the environment of the process, as `(key, value)`
+    pub env: Vec<(String, String)>,
+    /// Files of variables overriding the environment of the process, `env` taking precedence
+    pub env_files: Vec<PathBuf>,
+}
+
+/// Build and run a single process in the foreground, without managing it
+///
+/// Standard streams are inherited from the current terminal. Returns the exit code of the process.
+pub struct Run {
+    args: RunArgs,
+    state: Arc<State>,
+}
+
+impl Run {
+    pub fn new(args: RunArgs, state: Arc<State>) -> Self {
+        Run { args, state }
+    }
+}
+
+impl Exec<i32> for Run {
+    async fn exec(&self) -> Result<i32> {
+        let process = self
+            .state
+            .filter_processes(&[self.args.process.clone()])
+            .await?
+            .remove(0);
+        Start::new(StartArgs::default(), self.state.clone())
+            .build(&[process.clone()])
+            .await?;
+
+        let mut env = process_env(&process);
+        env.extend(secrets::resolve(&process.secrets, self.state.get_target_dir()).await?);
+        env.extend(cli_env(&self.args.env, &self.args.env_files)?);
+        let mut command = process_command(&self.state, &process, &env).await?;
+        for arg in &self.args.args {
+            command.push(' ');
+            command.push_str(&shell_quote(arg));
+        }
+        let exit_status = shell_command(&command)
+            .current_dir(self.state.get_target_dir())
+            .envs(env)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .await
+            .map_err(Error::with_context(InnerError::Start(format!(
+                "Unable to run process {}",
+                process.name()
+            ))))?;
+        Ok(exit_code(exit_status))
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use tracing::{error, info};
+
+use crate::{
+    common::{Exec, Process, ProcessState},
+    error::{Error, InnerError, Result},
+    start::{Start, StartArgs},
+    state::State,
+    stop::{self, StopArgs},
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ScaleArgs {
+    /// Wanted number of instances, by configured process name
+    pub replicas: Vec<(String, u32)>,
+}
+
+/// Change the number of instances of processes
+///
+/// Extra instances are stopped and removed, the ones failing to stop being kept. New instances
+/// are started if the process is running. The number of instances is kept when the configuration
+/// is loaded again.
+pub struct Scale {
+    args: ScaleArgs,
+    state: Arc<State>,
+}
+
+impl Scale {
+    pub fn new(args: ScaleArgs, state: Arc<State>) -> Self {
+        Scale { args, state }
+    }
+
+    async fn scale(&self, process_name: &str, replicas: u32) -> Result<()> {
+        let instances: Vec<Process> = self
+            .state
+            .get_processes()
+            .await?
+            .into_iter()
+            .filter(|p| p.base_name() == process_name)
+            .collect();
+        let Some(first) = instances.iter().min_by_key(|p| p.instance) else {
+            return Err(Error::new(InnerError::ProcessNotFound(vec![
+                process_name.to_owned()
+            ])));
+        };
+
+        // Instances are only removed once stopped, so that none keeps running untracked
+        let mut removed = Vec::new();
+        let mut stop_failed = Vec::new();
+        for process in instances.iter().filter(|p| p.instance >= replicas) {
+            match stop::run(self.state.clone(), process.clone(), StopArgs::default()).await {
+                Ok(()) => removed.push(process.name().to_string()),
+                Err(e) => {
+                    error!("Unable to stop process {}: {e}", process.name());
+                    stop_failed.push(process.name().to_string());
+                }
+            }
+        }
+        self.state.remove_processes(&removed).await?;
+        if !stop_failed.is_empty() {
+            return Err(Error::new(InnerError::Scheduler(format!(
+                "Unable to scale process {process_name} down, instances still running: {}",
+                stop_failed.join(", ")
+            ))));
+        }
+
+        let mut template = first.clone();
+        template.replicas = replicas;
+        let added: Vec<Process> = (0..replicas)
+            .filter(|i| !instances.iter().any(|p| p.instance == *i))
+            .map(|i| template.nth_instance(i))
+            .collect();
+        self.state.add_processes(&added).await?;
+        self.state.set_replicas(process_name, replicas).await?;
+        info!("Process {process_name} scaled to {replicas} instances");
+
+        if !added.is_empty() && instances.iter().any(|p| p.state == ProcessState::Running) {
+            Start::new(
+                StartArgs {
+                    processes: added.iter().map(|p| p.name().to_string()).collect(),
+                    ..Default::default()
+                },
+                self.state.clone(),
+            )
+            .exec()
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+impl Exec<()> for Scale {
+    async fn exec(&self) -> Result<()> {
+        let _lock = self.state.lock()?;
+        // Other processes are still scaled, the ones which failed are reported once done
+        let mut failed = Vec::new();
+        for (process_name, replicas) in &self.args.replicas {
+            if let Err(e) = self.scale(process_name, *replicas).await {
+                error!("Unable to scale process {process_name}: {e}");
+                failed.push(process_name.as_str());
+            }
+        }
+        if !failed.is_empty() {
+            return Err(Error::new(InnerError::Start(format!(
+                "Processes failed to scale: {}",
+                failed.join(", ")
+            ))));
+        }
+        Ok(())
+    }
+}
+
+/// Parse a `<process>=<replicas>` pair
+pub fn parse_replicas(value: &str) -> Result<(String, u32)> {
+    let parse_error = || Error::new(InnerError::Parse(value.to_owned()));
+    let (process_name, replicas) = value.split_once('=').ok_or_else(parse_error)?;
+    let replicas: u32 = replicas.parse().map_err(|_| parse_error())?;
+    if process_name.is_empty() || replicas == 0 {
+        return Err(parse_error());
+    }
+    Ok((process_name.to_owned(), replicas))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_replicas() {
+        assert_eq!(parse_replicas("web=3").unwrap(), ("web".to_owned(), 3));
+        assert!(parse_replicas("web").is_err());
+        assert!(parse_replicas("web=0").is_err());
+        assert!(parse_replicas("=2").is_err());
+        assert!(parse_replicas("web=many").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::Sender;
+
+use crate::{
+    common::{ProcessState, JOCKER_ENV_SCHEDULER_TIMEOUT},
+    error::{Error, InnerError, Result},
+    signal::ProcessSignal,
+    Pid,
+};
+
+/// Longest wait for a scheduler to carry out a request, unless set in the environment
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Scheduler timeout set in the environment, if any
+pub(crate) fn timeout_from_env() -> Result<Duration> {
+    let Ok(timeout) = env::var(JOCKER_ENV_SCHEDULER_TIMEOUT) else {
+        return Ok(DEFAULT_TIMEOUT);
+    };
+    let seconds = timeout.parse::<u64>().map_err(|e| {
+        Error::new(InnerError::Parse(format!(
+            "{JOCKER_ENV_SCHEDULER_TIMEOUT}={timeout}: {e}"
+        )))
+    })?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Available [`Scheduler`] backends
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SchedulerKind {
+    /// Delegate processes to a `pueued` daemon, the default one except on Windows
+    #[cfg_attr(not(windows), default)]
+    Pueue,
+    /// Spawn processes directly, without any external daemon
+    #[cfg_attr(windows, default)]
+    Native,
+    /// Launch processes as transient `systemd --user` units, Linux only
+    Systemd,
+}
+
+impl FromStr for SchedulerKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "pueue" => Ok(Self::Pueue),
+            "native" => Ok(Self::Native),
+            "systemd" => Ok(Self::Systemd),
+            _ => Err(Error::new(InnerError::Parse(s.to_owned()))),
+        }
+    }
+}
+
+/// Process as seen by a [`Scheduler`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScheduledProcess {
+    pub pid: Pid,
+    pub state: ProcessState,
+    /// Exit code of the process, if it exited by itself
+    pub exit_code: Option<i32>,
+}
+
+/// Backend in charge of running processes in the background on behalf of jocker
+#[async_trait]
+pub trait Scheduler: Send + Sync {
+    /// Identifier of the group of processes managed for the current project
+    fn group(&self) -> &str;
+
+    /// How [`Scheduler::start`] would launch given command, shown by dry runs
+    fn describe_start(&self, process_name: &str, command: &str) -> String;
+
+    /// Start processes of given stack in their own group, at most `max_parallel` of them running
+    /// at once while the others wait for a free slot
+    ///
+    /// Backends without groups keep every process in the group of the project.
+    async fn use_stack(&self, _stack: &str, _max_parallel: Option<u32>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Launch given command, labelled with its process name. Returns its scheduler ID.
+    async fn start(
+        &self,
+        process_name: String,
+        command: String,
+        path: PathBuf,
+        envs: HashMap<String, String>,
+    ) -> Result<Pid>;
+
+    /// Stop given process, escalating to SIGKILL if it is still running after `timeout`
+    async fn stop(&self, pid: Pid, kill: bool, timeout: Option<Duration>) -> Result<()>;
+
+    /// Send given signal to given process, without waiting for it to be handled
+    async fn signal(&self, pid: Pid, signal: ProcessSignal) -> Result<()>;
+
+    /// Freeze given process with SIGSTOP, until it is resumed
+    async fn pause(&self, pid: Pid) -> Result<()>;
+
+    /// Let given paused process run again, with SIGCONT
+    async fn resume(&self, pid: Pid) -> Result<()>;
+
+    /// State of every scheduled process of the group, indexed by process name
+    async fn status(&self) -> Result<HashMap<String, ScheduledProcess>>;
+
+    /// Send log lines of given process to `log_tx`
+    async fn logs(
+        &self,
+        log_tx: Sender<String>,
+        pid: Pid,
+        lines: Option<usize>,
+        follow: bool,
+    ) -> Result<()>;
+
+    /// Remove tasks which finished more than `older_than` ago, except the `kept` ones, returning
+    /// their IDs
+    ///
+    /// Backends which don't keep finished tasks around have nothing to prune.
+    async fn prune(&self, _kept: &HashSet<Pid>, _older_than: Duration) -> Result<Vec<Pid>> {
+        Ok(vec![])
+    }
+
+    /// File the scheduler writes logs of given process to, if it keeps them in a plain file
+    fn log_file(&self, _process_name: &str) -> Option<PathBuf> {
+        None
+    }
+
+    /// Remove every resource held by the scheduler for the current project
+    async fn clean(&self) -> Result<()>;
+}
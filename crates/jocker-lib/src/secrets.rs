@@ -0,0 +1,181 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use tokio::{fs, process::Command};
+
+use crate::{
+    command::util::{shell_command, shell_quote},
+    common::Secret,
+    error::{filesystem_error, Error, InnerError, Result},
+    start::SECRET_MASK,
+};
+
+/// Directory of the files secrets are handed to processes through, in the project state directory
+const SECRETS_DIR: &str = "secrets";
+
+/// Values of given secrets, by environment variable name, read from their sources
+///
+/// Relative secret files are read from `project_dir`.
+pub(crate) async fn resolve(
+    secrets: &BTreeMap<String, Secret>,
+    project_dir: &Path,
+) -> Result<HashMap<String, String>> {
+    let mut values = HashMap::with_capacity(secrets.len());
+    for (name, secret) in secrets {
+        let value = read(secret, project_dir).await.map_err(|reason| {
+            Error::new(InnerError::Secret(format!(
+                "Unable to read secret {name}: {reason}"
+            )))
+        })?;
+        values.insert(name.to_owned(), value);
+    }
+    Ok(values)
+}
+
+/// Given text, with every occurrence of given secret values replaced by [`SECRET_MASK`]
+pub(crate) fn mask<'a>(text: &str, values: impl IntoIterator<Item = &'a String>) -> String {
+    let mut values: Vec<&String> = values.into_iter().filter(|v| !v.is_empty()).collect();
+    // Longest values first, so one containing another is masked whole
+    values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    values
+        .into_iter()
+        .fold(text.to_owned(), |text, value| text.replace(value, SECRET_MASK))
+}
+
+/// Command line loading given secrets from a file only the current user can read, then running
+/// `command`
+///
+/// Secrets are kept out of the command line and out of the environment given to the scheduler,
+/// which pueue saves in its state file. The file is written to the project state directory, and
+/// rewritten at each start.
+pub(crate) fn env_file_command(
+    project_dir: &Path,
+    process_name: &str,
+    values: &HashMap<String, String>,
+    command: &str,
+) -> Result<String> {
+    let path = env_file_path(project_dir, process_name);
+    write_env_file(&path, values)?;
+    Ok(format!(
+        "set -a; . {} || exit 1; set +a; {command}",
+        shell_quote(&path.display().to_string())
+    ))
+}
+
+/// File the environment of given process is written to before it is launched
+pub(crate) fn env_file_path(project_dir: &Path, process_name: &str) -> PathBuf {
+    project_dir
+        .join(SECRETS_DIR)
+        .join(format!("{process_name}.env"))
+}
+
+/// Write given variables to `path` as `KEY="value"` lines, only readable by the current user
+///
+/// Values are escaped the way both `sh` and the `EnvironmentFile=` of systemd read them back.
+pub(crate) fn write_env_file(path: &Path, env: &HashMap<String, String>) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(filesystem_error(dir))?;
+    }
+    // Permissions only apply to created files, the previous one is replaced
+    if path.exists() {
+        std::fs::remove_file(path).map_err(filesystem_error(path))?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(filesystem_error(path))?;
+    for (key, value) in BTreeMap::from_iter(env) {
+        writeln!(file, "{key}=\"{}\"", env_file_escape(value))?;
+    }
+    Ok(())
+}
+
+fn env_file_escape(value: &str) -> String {
+    value.chars().fold(String::with_capacity(value.len()), |mut escaped, c| {
+        if matches!(c, '\\' | '"' | '$' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+async fn read(secret: &Secret, project_dir: &Path) -> std::result::Result<String, String> {
+    let value = match secret {
+        Secret::File(path) => fs::read_to_string(project_dir.join(path))
+            .await
+            .map_err(|e| format!("{}: {e}", path.display()))?,
+        Secret::Pass(entry) => {
+            let output = run(Command::new("pass").arg("show").arg(entry)).await?;
+            output.lines().next().unwrap_or_default().to_owned()
+        }
+        Secret::Command(command) => run(&mut shell_command(command)).await?,
+    };
+    Ok(value.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+async fn run(command: &mut Command) -> std::result::Result<String, String> {
+    let output = command.output().await.map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    String::from_utf8(output.stdout).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn resolve_secrets() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("db.pass"), "hunter2\n").unwrap();
+        let secrets = BTreeMap::from([
+            ("DB_PASSWORD".to_owned(), Secret::File(PathBuf::from("db.pass"))),
+            ("API_TOKEN".to_owned(), Secret::Command("echo s3cr3t".to_owned())),
+        ]);
+        let values = resolve(&secrets, dir.path()).await.unwrap();
+        assert_eq!(values["DB_PASSWORD"], "hunter2");
+        assert_eq!(values["API_TOKEN"], "s3cr3t");
+
+        let secrets = BTreeMap::from([("MISSING".to_owned(), Secret::Command("false".to_owned()))]);
+        assert!(resolve(&secrets, dir.path()).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn env_file() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let dir = tempdir().unwrap();
+        let values = HashMap::from([("TOKEN".to_owned(), r#"a"b$c`d\e f"#.to_owned())]);
+        let command =
+            env_file_command(dir.path(), "api", &values, r#"printf %s "$TOKEN""#).unwrap();
+        assert!(!command.contains("a\"b"));
+        let output = shell_command(&command).output().await.unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), values["TOKEN"]);
+        let metadata = std::fs::metadata(env_file_path(dir.path(), "api")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn mask_secrets() {
+        let values = ["s3cr3t".to_owned(), "s3cr3t-admin".to_owned(), String::new()];
+        assert_eq!(
+            mask("./api --token s3cr3t --admin s3cr3t-admin", &values),
+            "./api --token ******** --admin ********"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use crate::{
+    common::{DependencyCondition, Exec, Process, ProcessKind},
+    config::{
+        ConfigCrashLoop, ConfigDefault, ConfigDependency, ConfigDependencyCondition, ConfigFile,
+        ConfigHealthcheck, ConfigLimits, ConfigProcess, ConfigProcessKind, ConfigSecret,
+        ConfigStack,
+    },
+    error::{Error, InnerError, Result},
+    state::State,
+};
+
+/// Formats the effective configuration can be printed in
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ShowFormat {
+    #[default]
+    Yaml,
+    Json,
+}
+
+impl FromStr for ShowFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "yaml" => Ok(Self::Yaml),
+            "json" => Ok(Self::Json),
+            _ => Err(Error::new(InnerError::Parse(s.to_owned()))),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ShowArgs {
+    pub format: ShowFormat,
+}
+
+/// Render the configuration as resolved by jocker
+///
+/// Process defaults are merged into each process, stacks list their inherited processes, and
+/// replicas reflect the current scale.
+pub struct Show {
+    args: ShowArgs,
+    state: Arc<State>,
+}
+
+impl Show {
+    pub fn new(args: ShowArgs, state: Arc<State>) -> Self {
+        Show { args, state }
+    }
+
+    pub async fn run(&self) -> Result<ConfigFile> {
+        let processes: Vec<Process> = self
+            .state
+            .get_processes()
+            .await?
+            .into_iter()
+            .filter(|process| process.instance == 0)
+            .collect();
+        let secrets: HashMap<String, ConfigSecret> = processes
+            .iter()
+            .flat_map(|process| process.secrets.clone())
+            .map(|(name, secret)| (name, secret.into()))
+            .collect();
+        let processes: HashMap<String, ConfigProcess> = processes
+            .into_iter()
+            .map(|process| (process.name.clone(), process.into()))
+            .collect();
+        let stacks: HashMap<String, ConfigStack> = self
+            .state
+            .get_stacks()
+            .await?
+            .into_iter()
+            .map(|stack| {
+                let processes = stack.get_all_processes().into_iter().cloned().collect();
+                (
+                    stack.name,
+                    ConfigStack {
+                        processes,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        Ok(ConfigFile {
+            include: Vec::new(),
+            default: Some(ConfigDefault {
+                stack: self.state.get_default_stack().await?,
+                process: None,
+                logs: None,
+                build: None,
+                history: None,
+                tasks: None,
+            }),
+            stacks,
+            processes,
+            secrets,
+        })
+    }
+}
+
+impl Exec<String> for Show {
+    async fn exec(&self) -> Result<String> {
+        // Go through a JSON value to get keys sorted, whatever the map implementation
+        let config = serde_json::to_value(self.run().await?)?;
+        Ok(match self.args.format {
+            ShowFormat::Yaml => serde_yml::to_string(&config)?,
+            ShowFormat::Json => serde_json::to_string_pretty(&config)?,
+        })
+    }
+}
+
+impl From<Process> for ConfigProcess {
+    fn from(value: Process) -> Self {
+        Self {
+            extends: None,
+            binary: (!value.example).then(|| value.binary.clone()),
+            example: value.example.then_some(value.binary),
+            package: value.package,
+            host: value.host,
+            kind: match value.kind {
+                ProcessKind::Service => ConfigProcessKind::Service,
+                ProcessKind::Task => ConfigProcessKind::Task,
+            },
+            command: value.command,
+            profile: value.profile,
+            args: value.args,
+            cargo_args: value.cargo_args,
+            features: value.features,
+            no_default_features: value.no_default_features,
+            all_features: value.all_features,
+            env: value.env,
+            secrets: value.secrets.into_keys().collect(),
+            ports: value.ports,
+            groups: value.groups,
+            labels: value.labels.into_iter().collect(),
+            depends_on: value
+                .depends_on
+                .into_iter()
+                .map(|(name, condition)| {
+                    let condition = match condition {
+                        DependencyCondition::Started => ConfigDependencyCondition::Started,
+                        DependencyCondition::Healthy => ConfigDependencyCondition::Healthy,
+                        DependencyCondition::CompletedSuccessfully => {
+                            ConfigDependencyCondition::CompletedSuccessfully
+                        }
+                    };
+                    (name, ConfigDependency { condition })
+                })
+                .collect(),
+            healthcheck: value.healthcheck.map(|healthcheck| ConfigHealthcheck {
+                command: healthcheck.command,
+                tcp: healthcheck.tcp,
+                http: healthcheck.http,
+                timeout: healthcheck.timeout,
+            }),
+            limits: value.limits.map(|limits| ConfigLimits {
+                memory: limits.memory,
+                cpus: limits
+                    .cpu_percent
+                    .map(|cpu_percent| f64::from(cpu_percent) / 100.0),
+            }),
+            crash_loop: value.crash_loop.map(|crash_loop| ConfigCrashLoop {
+                max_failures: Some(crash_loop.max_failures),
+                window: Some(crash_loop.window),
+            }),
+            replicas: Some(value.replicas),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::{fmt::Display, str::FromStr, sync::Arc};
+
+use tracing::{info, warn};
+
+use crate::{
+    command::util,
+    common::{Exec, ProcessState},
+    error::{Error, InnerError, Result},
+    state::State,
+};
+
+/// Signal [`Signal`] can send, named after its POSIX name
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProcessSignal {
+    Hup,
+    Int,
+    Quit,
+    Kill,
+    Usr1,
+    Usr2,
+    Term,
+    Cont,
+    Stop,
+    Winch,
+}
+
+impl FromStr for ProcessSignal {
+    type Err = Error;
+
+    /// Parse a signal name, with or without its `SIG` prefix, whatever its case
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let name = s.to_ascii_uppercase();
+        match name.strip_prefix("SIG").unwrap_or(&name) {
+            "HUP" => Ok(Self::Hup),
+            "INT" => Ok(Self::Int),
+            "QUIT" => Ok(Self::Quit),
+            "KILL" => Ok(Self::Kill),
+            "USR1" => Ok(Self::Usr1),
+            "USR2" => Ok(Self::Usr2),
+            "TERM" => Ok(Self::Term),
+            "CONT" => Ok(Self::Cont),
+            "STOP" => Ok(Self::Stop),
+            "WINCH" => Ok(Self::Winch),
+            _ => Err(Error::new(InnerError::Parse(s.to_owned()))),
+        }
+    }
+}
+
+impl Display for ProcessSignal {
+    /// Name of the signal without its `SIG` prefix, as expected by `kill -s`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Self::Hup => "HUP",
+            Self::Int => "INT",
+            Self::Quit => "QUIT",
+            Self::Kill => "KILL",
+            Self::Usr1 => "USR1",
+            Self::Usr2 => "USR2",
+            Self::Term => "TERM",
+            Self::Cont => "CONT",
+            Self::Stop => "STOP",
+            Self::Winch => "WINCH",
+        };
+        write!(f, "{str}")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SignalArgs {
+    pub process: String,
+    pub signal: ProcessSignal,
+}
+
+/// Send a signal to a running process, such as SIGHUP to reload its configuration
+///
+/// Every instance of a scaled process is signalled. The state of processes is left as is, use
+/// `pause` and `stop` rather than sending SIGSTOP or SIGTERM.
+pub struct Signal {
+    args: SignalArgs,
+    state: Arc<State>,
+}
+
+impl Signal {
+    pub fn new(args: SignalArgs, state: Arc<State>) -> Self {
+        Signal { args, state }
+    }
+}
+
+impl Exec<()> for Signal {
+    async fn exec(&self) -> Result<()> {
+        let signal = self.args.signal;
+        let mut signalled = false;
+        for process in self
+            .state
+            .filter_processes(&[self.args.process.clone()])
+            .await?
+        {
+            match process.pid {
+                // Adopted processes aren't scheduled, they are signalled directly
+                Some(pid) if process.state == ProcessState::External => {
+                    util::signal(pid, signal).await?
+                }
+                Some(pid)
+                    if matches!(process.state, ProcessState::Running | ProcessState::Paused) =>
+                {
+                    self.state.scheduler().signal(pid, signal).await?
+                }
+                _ => {
+                    warn!("Process {} is {}, not signalling it", process.name(), process.state);
+                    continue;
+                }
+            }
+            info!("Sent SIG{signal} to process {}", process.name());
+            signalled = true;
+        }
+        if !signalled {
+            return Err(Error::new(InnerError::ProcessNotRunning(format!(
+                "No running process to send SIG{signal} to: {}",
+                self.args.process
+            ))));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_process_signal() {
+        assert_eq!("HUP".parse::<ProcessSignal>().unwrap(), ProcessSignal::Hup);
+        assert_eq!("SIGUSR1".parse::<ProcessSignal>().unwrap(), ProcessSignal::Usr1);
+        assert_eq!("sigterm".parse::<ProcessSignal>().unwrap(), ProcessSignal::Term);
+        assert_eq!(ProcessSignal::Usr2.to_string(), "USR2");
+        assert!("SIGFOO".parse::<ProcessSignal>().is_err());
+    }
+}
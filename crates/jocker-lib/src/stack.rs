@@ -0,0 +1,179 @@
+use std::{collections::HashSet, sync::Arc};
+
+use tracing::info;
+
+use crate::{
+    common::{Exec, Stack},
+    error::{Error, InnerError, Result},
+    state::State,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct UseStackArgs {
+    /// Stack to select
+    pub stack: Option<String>,
+    /// Revert to the configuration default stack
+    pub clear: bool,
+}
+
+/// Stack used by later commands, as returned by [`UseStack`]
+#[derive(Debug, Eq, PartialEq)]
+pub enum StackSelection {
+    /// Stack selected with [`UseStack`]
+    Selected(String),
+    /// Default stack of the configuration, as none is selected
+    Default(String),
+    /// Every process of the project, as no stack is selected nor configured by default
+    All,
+}
+
+/// Select the stack used by later commands
+///
+/// The selection is stored in the project state, `--stack` and `JOCKER_STACK` still take
+/// precedence over it. Without a stack nor `clear`, the selected stack is only returned.
+pub struct UseStack {
+    args: UseStackArgs,
+    state: Arc<State>,
+}
+
+impl UseStack {
+    pub fn new(args: UseStackArgs, state: Arc<State>) -> Self {
+        UseStack { args, state }
+    }
+
+    async fn default_selection(&self) -> Result<StackSelection> {
+        Ok(match self.state.get_default_stack().await? {
+            Some(stack) => StackSelection::Default(stack),
+            None => StackSelection::All,
+        })
+    }
+}
+
+impl Exec<StackSelection> for UseStack {
+    async fn exec(&self) -> Result<StackSelection> {
+        match (&self.args.stack, self.args.clear) {
+            (Some(stack), false) => {
+                let stack = self.state.get_stack(stack).await?.name;
+                self.state.set_selected_stack(&Some(stack.clone())).await?;
+                Ok(StackSelection::Selected(stack))
+            }
+            (None, true) => {
+                self.state.set_selected_stack(&None).await?;
+                self.default_selection().await
+            }
+            (None, false) => match self.state.get_selected_stack().await? {
+                Some(stack) => Ok(StackSelection::Selected(stack)),
+                None => self.default_selection().await,
+            },
+            (Some(stack), true) => Err(Error::new(InnerError::Parse(format!(
+                "can't both select stack {stack} and clear the selection"
+            )))),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CreateStackArgs {
+    /// Name of the stack to create
+    pub stack: String,
+    /// Processes of the stack
+    pub processes: Vec<String>,
+}
+
+/// Create a stack from the CLI, without editing the configuration
+///
+/// The stack is stored in the project state and kept when the configuration is reloaded, unless
+/// the configuration defines a stack with the same name.
+pub struct CreateStack {
+    args: CreateStackArgs,
+    state: Arc<State>,
+}
+
+impl CreateStack {
+    pub fn new(args: CreateStackArgs, state: Arc<State>) -> Self {
+        CreateStack { args, state }
+    }
+}
+
+impl Exec<()> for CreateStack {
+    async fn exec(&self) -> Result<()> {
+        let name = &self.args.stack;
+        if self.state.get_stack(name).await.is_ok() {
+            return Err(Error::new(InnerError::Parse(format!("stack {name} already exists"))));
+        }
+        if self.args.processes.is_empty() {
+            return Err(Error::new(InnerError::Parse(format!(
+                "stack {name} needs at least one process"
+            ))));
+        }
+        let known_processes: HashSet<String> = self
+            .state
+            .get_processes()
+            .await?
+            .into_iter()
+            .map(|process| process.name)
+            .collect();
+        let missing_processes: Vec<String> = self
+            .args
+            .processes
+            .iter()
+            .filter(|process| !known_processes.contains(*process))
+            .cloned()
+            .collect();
+        if !missing_processes.is_empty() {
+            return Err(Error::new(InnerError::ProcessNotFound(missing_processes)));
+        }
+        self.state
+            .add_stack(&Stack {
+                name: name.to_owned(),
+                processes: self.args.processes.iter().cloned().collect(),
+                inherited_processes: HashSet::new(),
+                max_parallel: None,
+                user_created: true,
+            })
+            .await?;
+        info!("Created stack {name}");
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DeleteStackArgs {
+    /// Name of the stack to delete
+    pub stack: String,
+}
+
+/// Delete a stack created with [`CreateStack`]
+///
+/// Stacks defined by the configuration can only be removed from it. Deleting the selected stack
+/// clears the selection.
+pub struct DeleteStack {
+    args: DeleteStackArgs,
+    state: Arc<State>,
+}
+
+impl DeleteStack {
+    pub fn new(args: DeleteStackArgs, state: Arc<State>) -> Self {
+        DeleteStack { args, state }
+    }
+}
+
+impl Exec<()> for DeleteStack {
+    async fn exec(&self) -> Result<()> {
+        let name = &self.args.stack;
+        let stack = self.state.get_stack(name).await?;
+        if !stack.user_created {
+            return Err(Error::new(InnerError::Parse(format!(
+                "stack {name} is defined by the configuration and can only be removed from it"
+            ))));
+        }
+        if !self.state.delete_stack(name).await? {
+            return Err(Error::new(InnerError::StackNotFound(name.to_owned())));
+        }
+        if self.state.get_selected_stack().await?.as_ref() == Some(name) {
+            self.state.set_selected_stack(&None).await?;
+        }
+        info!("Deleted stack {name}");
+        Ok(())
+    }
+}
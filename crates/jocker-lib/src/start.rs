@@ -1,19 +1,102 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
-use dotenvy::dotenv_iter;
-use once_cell::sync::OnceCell;
-use regex::Regex;
+use chrono::Utc;
+use dotenvy::{dotenv_iter, from_path_iter};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    sync::Semaphore,
+    task::JoinSet,
+    time::{sleep, Instant},
+};
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    command::{cargo::Cargo, util::CommandLogger},
-    common::{Exec, Process, ProcessState},
+    adopt::external_pids,
+    command::{
+        cargo::{Cargo, CargoFeatures, CargoMessage, CargoTarget},
+        util::shell_arg,
+    },
+    common::{
+        BuildRecord, DependencyCondition, EnvSource, EnvVar, Event, EventKind, Exec, JockerEvent,
+        Process, ProcessState, JOCKER_ENV_INSTANCE, JOCKER_ENV_PROCESS_ID,
+    },
+    config::ConfigFile,
     error::{Error, InnerError, Result},
+    health::{is_healthy, wait_healthy, HEALTHCHECK_INTERVAL},
+    interpolate::envsubst,
+    limits,
+    ports::{is_port_available, listening_ports},
+    remote,
+    secrets,
     state::State,
 };
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct StartArgs {
+    pub release: bool,
     pub processes: Vec<String>,
+    /// Wait for started processes to be running and healthy, failing if any of them isn't
+    pub wait: bool,
+    /// Maximum duration of the wait, in seconds
+    pub timeout: Option<u64>,
+    /// Launch already built binaries, without invoking cargo
+    pub no_build: bool,
+    /// Build processes even if their binaries look up to date
+    pub rebuild: bool,
+    /// Only plan what would be built and launched, without doing it
+    pub dry_run: bool,
+    /// Build processes running on another host there, instead of copying binaries built locally
+    pub remote_build: bool,
+    /// Variables overriding the environment of processes, as `(key, value)`
+    pub env: Vec<(String, String)>,
+    /// Files of variables overriding the environment of processes, `env` taking precedence
+    pub env_files: Vec<PathBuf>,
+    /// Arguments appended to the ones of the single process to start, for this run only
+    pub args: Vec<String>,
+    /// Processes not to start, even as dependencies of started ones
+    pub except: Vec<String>,
+    /// Only start processes having all of these labels, as `(key, value)`, and their dependencies
+    pub labels: Vec<(String, String)>,
+}
+
+pub(crate) const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Interval between two checks of whether a task completed
+const TASK_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Maximum number of `cargo build` commands run at once, unless configured otherwise
+pub const DEFAULT_BUILD_PARALLELISM: usize = 2;
+/// Directory of cargo's target directory holding the ones of groups built concurrently
+const GROUPS_TARGET_DIR: &str = "jocker";
+
+/// Builds and launches [`Start`] would go through with `dry_run`, in order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StartPlan {
+    /// Arguments of each `cargo` command building processes, empty with `no_build`
+    pub builds: Vec<Vec<String>>,
+    pub processes: Vec<PlannedStart>,
+}
+
+/// Process of a [`StartPlan`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedStart {
+    pub name: String,
+    /// How it would be launched, `None` if it is already started
+    pub launch: Option<PlannedLaunch>,
+}
+
+/// Launch of a [`PlannedStart`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedLaunch {
+    /// Scheduler command launching the process
+    pub scheduler: String,
+    /// Environment of the process, secrets masked
+    pub env: BTreeMap<String, String>,
 }
 
 pub struct Start {
@@ -21,70 +104,157 @@ pub struct Start {
     state: Arc<State>,
 }
 
+/// Outcome of the launch of a process
+enum Launched {
+    /// Process left running
+    Running,
+    /// Task which ran to completion
+    Completed,
+    /// Process not launched, or which exited unsuccessfully for a task
+    Failed,
+}
+
 impl Start {
     pub fn new(args: StartArgs, state: Arc<State>) -> Self {
         Start { args, state }
     }
 
-    async fn build(&self, processes: &[Process]) -> Result<()> {
-        let binaries: Vec<&str> = processes.iter().map(|p| p.binary()).collect();
-        let cargo_args: Vec<&str> = processes
+    /// Build binaries of given processes, skipping the ones built after the last change of the
+    /// project files, failing if any of them couldn't be built
+    pub(crate) async fn build(&self, processes: &[Process]) -> Result<()> {
+        let _lock = self.state.lock()?;
+        let failed = self.build_processes(processes).await?;
+        if !failed.is_empty() {
+            return Err(Error::new(InnerError::Build(format!(
+                "Processes failed to build: {}",
+                failed.join(", ")
+            ))));
+        }
+        Ok(())
+    }
+
+    /// Same as [`Start::build`], returning sorted names of processes which failed to build
+    ///
+    /// Processes sharing the same build flags are built together, groups are built concurrently.
+    /// Cargo locks its target directory for the whole build, so groups built concurrently each
+    /// get their own one, see [`build_group`].
+    async fn build_processes(&self, processes: &[Process]) -> Result<Vec<String>> {
+        let parallelism = ConfigFile::load(self.state.get_config_file())?
+            .and_then(|config| config.default)
+            .and_then(|default| default.build)
+            .and_then(|build| build.parallelism)
+            .unwrap_or(DEFAULT_BUILD_PARALLELISM)
+            .max(1);
+        let groups = self.build_groups(processes).await?;
+        let concurrent = parallelism > 1 && groups.len() > 1;
+        let semaphore = Arc::new(Semaphore::new(parallelism));
+        let mut builds = JoinSet::new();
+        for (flags, processes) in groups {
+            let state = self.state.clone();
+            let semaphore = semaphore.clone();
+            let processes: Vec<Process> = processes.into_iter().cloned().collect();
+            builds.spawn(async move {
+                let _permit = semaphore
+                    .acquire_owned()
+                    .await
+                    .map_err(|e| Error::new(InnerError::Lock(e.to_string())))?;
+                build_group(&state, &flags, &processes, concurrent).await
+            });
+        }
+        // Let every build finish, the first error is reported
+        let mut failed = vec![];
+        let mut result = Ok(());
+        while let Some(res) = builds.join_next().await {
+            let res = res.map_err(|e| Error::new(InnerError::Build(e.to_string())));
+            match res.and_then(|res| res) {
+                Ok(group_failed) => failed.extend(group_failed),
+                Err(e) if result.is_ok() => result = Err(e),
+                Err(e) => error!("{e}"),
+            }
+        }
+        failed.sort();
+        result.map(|()| failed)
+    }
+
+    /// Processes needing a build, grouped by `cargo build` invocation
+    async fn build_groups<'a>(
+        &self,
+        processes: &'a [Process],
+    ) -> Result<BTreeMap<BuildFlags, Vec<&'a Process>>> {
+        let target_dir = self.state.get_target_dir();
+        let cargo_target_dir = self.state.get_cargo_target_dir().await?;
+        let sources_modified_at = Cargo::sources_modified_at(target_dir, cargo_target_dir).await?;
+        let built_artifacts = self.state.get_built_artifacts().await?;
+        let mut processes_by_build: BTreeMap<BuildFlags, Vec<&Process>> = BTreeMap::new();
+        for process in processes
             .iter()
-            .flat_map(|p| p.cargo_args())
-            .map(String::as_str)
-            .collect();
-        match Cargo::build(
-            self.state.get_target_dir(),
-            binaries.as_slice(),
-            cargo_args.as_slice(),
-        )
-        .await
+            .filter(|p| p.is_cargo() && !self.builds_remotely(p))
         {
-            Ok(mut build_process) => {
-                build_process.log_to_console().await?;
-                let build_exit_status = build_process.wait().await?;
-
-                if !build_exit_status.success() {
-                    return Err(Error::new(InnerError::Start(format!(
-                        "Build produced exit code {}",
-                        build_exit_status
-                    ))));
-                }
-            }
-            Err(e) => {
-                println!("Error while building crates: {e}");
-                for process in processes {
-                    self.state
-                        .set_state(process.name(), ProcessState::Stopped)
-                        .await?;
-                }
+            let flags = BuildFlags::from(process);
+            let artifact_path = process.artifact_path(cargo_target_dir);
+            // A binary built with other features or cargo args than the process ones is outdated
+            let built_with_flags = built_artifacts
+                .get(&artifact_path.display().to_string())
+                .is_some_and(|built_flags| *built_flags == flags.key());
+            if !self.args.rebuild
+                && built_with_flags
+                && Cargo::is_up_to_date(&artifact_path, sources_modified_at)
+            {
+                info!("Process {} is up to date, not building it", process.name());
+                continue;
             }
+            processes_by_build.entry(flags).or_default().push(process);
         }
-        Ok(())
+        Ok(processes_by_build)
     }
 
     pub async fn run(&self, process: Process) -> Result<()> {
-        if process.state != ProcessState::Stopped && process.state != ProcessState::Building {
-            println!("Process is already started: {}", process.name());
+        if !needs_start(&process) {
+            info!("Process is already started: {}", process.name());
             return Ok(());
         }
+        self.check_ports(&process).await?;
+        self.check_binary(&process).await?;
+        self.check_external(&process).await?;
         let process_name = process.name().to_string();
-        println!("Starting process {process_name} ...");
-        let mut env: HashMap<String, String> = HashMap::new();
-        if let Ok(dotenv) = dotenv_iter() {
-            for (key, val) in dotenv.flatten() {
-                env.insert(key, val);
-            }
+        info!("Starting process {process_name} ...");
+        let mut env_sources = launch_env_sources(&self.state, &process);
+        let secret_values = secrets::resolve(&process.secrets, self.state.get_target_dir()).await?;
+        let mut env = env_values(env_sources.clone());
+        env.extend(secret_values.clone());
+        // Only recorded masked, along with where other variables come from
+        for name in secret_values.keys() {
+            env_sources.insert(name.to_owned(), EnvVar::new(SECRET_MASK, EnvSource::Secret));
         }
-        for (key, val) in process.env.iter() {
-            env.insert(key.to_string(), val.to_string());
+        for (key, value) in cli_env(&self.args.env, &self.args.env_files)? {
+            env.insert(key.clone(), value.clone());
+            env_sources.insert(key, EnvVar::new(value, EnvSource::Cli));
         }
-        let env = env;
-
-        let mut command = vec![];
-        command.push(format!("./target/debug/{}", process.binary()));
-        for arg in process.args() {
-            command.push(envsubst(arg, &env));
+        let command = self.launch_command(&process, &env, true).await?;
+        // Secrets may be given as args, or inlined in the SSH command of remote processes
+        let masked_command = secrets::mask(&command, secret_values.values());
+        debug!(process = %process_name, "Launching {masked_command}");
+        // Secrets are kept from the scheduler, which may save its environment: local processes
+        // read them from a file, remote ones get their whole environment from one over SSH.
+        // Windows shells can't source that file, local processes still get them from the scheduler.
+        let mut command = command;
+        if cfg!(unix) || process.host().is_some() {
+            let mut file_values = secret_values;
+            // Unless overridden on the command line
+            file_values.retain(|name, _| {
+                env_sources
+                    .get(name)
+                    .is_some_and(|var| var.source == EnvSource::Secret)
+            });
+            env.retain(|name, _| !file_values.contains_key(name));
+            if process.host().is_none() && !file_values.is_empty() {
+                command = secrets::env_file_command(
+                    self.state.get_project_dir(),
+                    &process_name,
+                    &file_values,
+                    &command,
+                )?;
+            }
         }
 
         let pid = self
@@ -92,7 +262,7 @@ impl Start {
             .scheduler()
             .start(
                 process_name.clone(),
-                command.join(" "),
+                command,
                 self.state.get_target_dir().to_path_buf(),
                 env,
             )
@@ -101,79 +271,1115 @@ impl Start {
             .set_state(process.name(), ProcessState::Running)
             .await?;
         self.state.set_pid(process.name(), Some(pid)).await?;
-        println!("Process {process_name} started");
+        self.state
+            .set_started(process.name(), &masked_command, &env_sources)
+            .await?;
+        self.state
+            .add_event(Event::new(process.name(), EventKind::Started))
+            .await?;
+        self.state.emit(JockerEvent::ProcessStarted {
+            process_name: process_name.clone(),
+            pid,
+        });
+        info!("Process {process_name} started");
         Ok(())
     }
 }
 
-impl Exec<()> for Start {
-    async fn exec(&self) -> Result<()> {
-        let processes = self.state.filter_processes(&self.args.processes).await?;
-        for process in &processes {
+impl Start {
+    fn builds_remotely(&self, process: &Process) -> bool {
+        self.args.remote_build && process.host().is_some()
+    }
+
+    /// Command launching given process, over SSH for processes running on another host
+    ///
+    /// Their locally built binary is copied there first when `upload` is set.
+    async fn launch_command(
+        &self,
+        process: &Process,
+        env: &HashMap<String, String>,
+        upload: bool,
+    ) -> Result<String> {
+        let Some(host) = process.host() else {
+            let command = process_command(&self.state, process, env).await?;
+            return match process.limits.as_ref() {
+                Some(limits) => limits::limit_command(process.name(), limits, &command).await,
+                None => Ok(command),
+            };
+        };
+        if process.limits.is_some() {
+            warn!(
+                "Resource limits of process {} are not enforced on {host}",
+                process.name()
+            );
+        }
+        let command = if !process.is_cargo() {
+            process_command(&self.state, process, env).await?
+        } else if self.builds_remotely(process) {
+            let args = Cargo::run_args(
+                process.cargo_target(),
+                process.cargo_args(),
+                process.profile(),
+                process.package(),
+                &process.cargo_features(),
+                process.args().iter().map(|arg| envsubst(arg, env)),
+            );
+            format!("cargo {}", args.join(" "))
+        } else {
+            let artifact_path = process.artifact_path(self.state.get_cargo_target_dir().await?);
+            let binary = if upload {
+                remote::upload(host, &artifact_path).await?
+            } else {
+                // Its hash isn't known until it is built
+                remote::binary_path(&artifact_path)
+                    .await
+                    .unwrap_or_else(|_| process.binary().to_owned())
+            };
+            let mut command = vec![binary];
+            command.extend(process.args().iter().map(|arg| envsubst(arg, env)));
+            command.join(" ")
+        };
+        let target_dir = self.state.get_target_dir();
+        let remote_env_file =
+            remote::env_file_path(&State::get_project_id(target_dir), process.name());
+        if upload {
+            let env_file = secrets::env_file_path(self.state.get_project_dir(), process.name());
+            secrets::write_env_file(&env_file, env)?;
+            remote::upload_env_file(host, &env_file, &remote_env_file).await?;
+        }
+        Ok(remote::ssh_command(host, target_dir, &remote_env_file, &command))
+    }
+
+    /// Builds and launches `exec` would go through, in order, without changing anything
+    async fn dry_run(&self, processes: &[Process]) -> Result<StartPlan> {
+        let mut plan = StartPlan::default();
+        if !self.args.no_build {
+            for (flags, processes) in self.build_groups(processes).await? {
+                let targets: Vec<CargoTarget> =
+                    processes.iter().map(|p| p.cargo_target()).collect();
+                let args = Cargo::build_args(
+                    &targets,
+                    &flags
+                        .cargo_args
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>(),
+                    flags.profile.as_deref(),
+                    flags.package.as_deref(),
+                    &flags.features,
+                );
+                plan.builds.push(args);
+            }
+        }
+        for process in processes {
+            if !matches!(
+                process.state,
+                ProcessState::Stopped | ProcessState::CrashLooping | ProcessState::Done
+            ) {
+                plan.processes.push(PlannedStart {
+                    name: process.name().to_owned(),
+                    launch: None,
+                });
+                continue;
+            }
+            let mut env = launch_env(&self.state, process);
+            // Secrets are not read, only shown masked
+            env.extend(
+                process
+                    .secrets
+                    .keys()
+                    .map(|name| (name.to_owned(), SECRET_MASK.to_owned())),
+            );
+            env.extend(cli_env(&self.args.env, &self.args.env_files)?);
+            let command = self.launch_command(process, &env, false).await?;
+            let env = env
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = if is_secret(&key) {
+                        SECRET_MASK.to_owned()
+                    } else {
+                        value
+                    };
+                    (key, value)
+                })
+                .collect();
+            plan.processes.push(PlannedStart {
+                name: process.name().to_owned(),
+                launch: Some(PlannedLaunch {
+                    scheduler: self
+                        .state
+                        .scheduler()
+                        .describe_start(process.name(), &command),
+                    env,
+                }),
+            });
+        }
+        Ok(plan)
+    }
+
+    /// Fail if the binary of given process wasn't built, rather than launching a task which exits
+    /// right away
+    async fn check_binary(&self, process: &Process) -> Result<()> {
+        if !process.is_cargo() || process.host().is_some() {
+            return Ok(());
+        }
+        let artifact_path = process.artifact_path(self.state.get_cargo_target_dir().await?);
+        if artifact_path.exists() {
+            return Ok(());
+        }
+        let error = InnerError::Start(format!(
+            "Binary of process {} not found at {}",
+            process.name(),
+            artifact_path.display()
+        ));
+        Err(Error::new(error).with_hint(format!(
+            "Build it with `jocker build {}`, or start it without --no-build",
+            process.name()
+        )))
+    }
+
+    /// Fail if the binary of given process already runs outside of jocker, as starting it again
+    /// would likely conflict with it
+    async fn check_external(&self, process: &Process) -> Result<()> {
+        let external_pids = external_pids(&self.state, std::slice::from_ref(process)).await?;
+        let Some(pid) = external_pids.get(process.name()) else {
+            return Ok(());
+        };
+        let error = InnerError::Start(format!(
+            "Process {} already runs outside of jocker (pid {pid})",
+            process.name()
+        ));
+        Err(Error::new(error).with_hint(format!(
+            "Stop it, or manage it from jocker with `jocker adopt {}`",
+            process.name()
+        )))
+    }
+
+    /// Processes of given wave whose ports are free, the other ones being marked as failed
+    ///
+    /// Processes of a wave are launched concurrently, so each one can't tell whether another one
+    /// is about to bind its ports: the first one by name gets them.
+    async fn check_wave_ports(
+        &self,
+        wave: Vec<Process>,
+        failed: &mut HashSet<String>,
+    ) -> Result<Vec<Process>> {
+        let mut claimed: HashMap<u16, String> = HashMap::new();
+        let mut checked = Vec::with_capacity(wave.len());
+        for process in wave {
+            if !needs_start(&process) || process.host().is_some() {
+                checked.push(process);
+                continue;
+            }
+            let conflict = process
+                .ports
+                .iter()
+                .find_map(|port| claimed.get(port).map(|owner| (port, owner)));
+            let res = match conflict {
+                Some((port, owner)) => Err(Error::new(InnerError::Port(format!(
+                    "Cannot start process {}: port {port} is also used by process {owner}",
+                    process.name()
+                )))),
+                None => self.check_ports(&process).await,
+            };
+            if let Err(e) = res {
+                error!("{e}");
+                self.state
+                    .set_state(process.name(), ProcessState::Stopped)
+                    .await?;
+                failed.insert(process.name().to_string());
+                continue;
+            }
+            for port in &process.ports {
+                claimed.insert(*port, process.name().to_string());
+            }
+            checked.push(process);
+        }
+        Ok(checked)
+    }
+
+    /// Fail if a port declared by given process is already bound, telling by what if possible
+    async fn check_ports(&self, process: &Process) -> Result<()> {
+        // Ports of processes running on another host are bound there
+        if process.host().is_some() {
+            return Ok(());
+        }
+        let busy_ports: Vec<u16> = process
+            .ports
+            .iter()
+            .copied()
+            .filter(|port| !is_port_available(*port))
+            .collect();
+        if busy_ports.is_empty() {
+            return Ok(());
+        }
+        let processes = self.state.get_processes().await?;
+        let owners = listening_ports();
+        let conflicts: Vec<String> = busy_ports
+            .into_iter()
+            .map(|port| {
+                let owner = processes
+                    .iter()
+                    .find(|p| {
+                        p.name() != process.name()
+                            && matches!(p.state, ProcessState::Running | ProcessState::Paused)
+                            && p.ports.contains(&port)
+                    })
+                    .map(|p| format!("process {}", p.name()))
+                    .or_else(|| {
+                        owners
+                            .get(&port)
+                            .map(|owner| format!("{} (pid {})", owner.name, owner.pid))
+                    })
+                    .unwrap_or_else(|| "another process".to_owned());
+                format!("port {port} is already in use by {owner}")
+            })
+            .collect();
+        Err(Error::new(InnerError::Port(format!(
+            "Cannot start process {}: {}",
+            process.name(),
+            conflicts.join(", ")
+        ))))
+    }
+
+    /// Add missing dependencies of given processes, recursively
+    async fn with_dependencies(
+        &self,
+        mut processes: Vec<Process>,
+        excluded: &HashSet<String>,
+    ) -> Result<Vec<Process>> {
+        let all_processes = self.state.get_processes().await?;
+        let mut idx = 0;
+        while idx < processes.len() {
+            let process_name = processes[idx].name().to_string();
+            let dependencies: Vec<String> = processes[idx].depends_on.keys().cloned().collect();
+            for dependency in dependencies {
+                if processes.iter().any(|p| p.base_name() == dependency) {
+                    continue;
+                }
+                let instances: Vec<Process> = all_processes
+                    .iter()
+                    .filter(|p| p.base_name() == dependency)
+                    .cloned()
+                    .collect();
+                if instances.is_empty() {
+                    return Err(Error::new(InnerError::Dependency(format!(
+                        "Process {process_name} depends on unknown process {dependency}"
+                    ))));
+                }
+                processes.extend(
+                    instances
+                        .into_iter()
+                        .filter(|instance| !excluded.contains(instance.name())),
+                );
+            }
+            idx += 1;
+        }
+        Ok(processes)
+    }
+
+    /// Wait for dependencies of given process to meet their condition
+    async fn wait_dependencies(&self, process: &Process, failed: &HashSet<String>) -> Result<()> {
+        for (dependency, condition) in &process.depends_on {
+            let instances = self
+                .state
+                .filter_processes(&[dependency.to_owned()])
+                .await?;
+            if instances.iter().any(|i| failed.contains(i.name())) {
+                return Err(Error::new(InnerError::Dependency(format!(
+                    "Dependency {dependency} failed to start"
+                ))));
+            }
+            match condition {
+                DependencyCondition::Started => {}
+                DependencyCondition::Healthy => {
+                    for instance in instances {
+                        info!("Waiting for process {} to be healthy ...", instance.name());
+                        wait_healthy(&self.state, &instance).await?;
+                    }
+                }
+                DependencyCondition::CompletedSuccessfully => {
+                    if let Some(instance) = instances
+                        .iter()
+                        .find(|i| i.state != ProcessState::Done || i.exit_code != Some(0))
+                    {
+                        return Err(Error::new(InnerError::Dependency(format!(
+                            "Dependency {} did not complete successfully",
+                            instance.name()
+                        ))));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Launch given process once its dependencies meet their condition
+    ///
+    /// Tasks are waited for, as their dependents are launched once they completed.
+    async fn launch(&self, process: Process, failed: &HashSet<String>) -> Result<Launched> {
+        let process_name = process.name().to_string();
+        if let Err(e) = self.wait_dependencies(&process, failed).await {
+            warn!("Not starting process {process_name}: {e}");
             self.state
-                .set_state(process.name(), ProcessState::Building)
+                .set_state(&process_name, ProcessState::Stopped)
                 .await?;
+            return Ok(Launched::Failed);
         }
-        self.build(processes.as_slice()).await?;
-        for process in processes {
-            let process_name = process.name().to_string();
-            if let Err(e) = self.run(process).await {
-                println!("Error while starting process {process_name}: {e}")
+        let is_task = process.is_task();
+        Ok(match self.run(process).await {
+            Ok(()) if is_task => match self.wait_task(&process_name).await {
+                Ok(()) => Launched::Completed,
+                Err(e) => {
+                    error!("{e}");
+                    Launched::Failed
+                }
+            },
+            Ok(()) => Launched::Running,
+            Err(e) => {
+                error!("Error while starting process {process_name}: {e}");
+                Launched::Failed
             }
+        })
+    }
+
+    /// Wait for given task to exit, failing if it didn't succeed or is still running after the
+    /// wait timeout
+    ///
+    /// Only the scheduler is polled meanwhile, the state is refreshed once the task exited.
+    async fn wait_task(&self, process_name: &str) -> Result<()> {
+        info!("Waiting for task {process_name} to complete ...");
+        let timeout = self
+            .args
+            .timeout
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_WAIT_TIMEOUT);
+        let deadline = Instant::now() + timeout;
+        loop {
+            let running = self
+                .state
+                .scheduler()
+                .status()
+                .await?
+                .get(process_name)
+                .is_some_and(|scheduled| scheduled.state.is_started());
+            if !running {
+                self.state.refresh(false).await?;
+                let process = self
+                    .state
+                    .filter_processes(&[process_name.to_owned()])
+                    .await?
+                    .remove(0);
+                return match process.exit_code {
+                    Some(0) => {
+                        info!("Task {process_name} completed");
+                        Ok(())
+                    }
+                    Some(exit_code) => Err(Error::new(InnerError::Start(format!(
+                        "Task {process_name} exited with code {exit_code}"
+                    )))),
+                    None => Err(Error::new(InnerError::Start(format!(
+                        "Task {process_name} was stopped before completing"
+                    )))),
+                };
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::new(InnerError::Timeout(format!(
+                    "Task {process_name} is still running after {}s",
+                    timeout.as_secs()
+                ))));
+            }
+            sleep(TASK_POLL_INTERVAL).await;
         }
+    }
 
+    /// Wait for given processes to be running and pass their healthcheck
+    async fn wait_ready(&self, process_names: &[String]) -> Result<()> {
+        let timeout = self
+            .args
+            .timeout
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_WAIT_TIMEOUT);
+        let deadline = Instant::now() + timeout;
+        let mut pending = process_names.to_vec();
+        let mut exited = Vec::new();
+        loop {
+            self.state.refresh(false).await?;
+            let mut still_pending = Vec::new();
+            for process in self.state.filter_processes(&pending).await? {
+                if process.state != ProcessState::Running {
+                    exited.push(process.name().to_string());
+                } else if !is_healthy(&self.state, &process).await {
+                    still_pending.push(process.name().to_string());
+                }
+            }
+            pending = still_pending;
+            if pending.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            sleep(HEALTHCHECK_INTERVAL).await;
+        }
+        if !exited.is_empty() {
+            return Err(Error::new(InnerError::Start(format!(
+                "Processes exited before being ready: {}",
+                exited.join(", ")
+            ))));
+        }
+        if !pending.is_empty() {
+            return Err(Error::new(InnerError::Timeout(format!(
+                "Processes not ready after {}s: {}",
+                timeout.as_secs(),
+                pending.join(", ")
+            ))));
+        }
+        info!("All processes are ready");
         Ok(())
     }
 }
 
-static ENVSUBST_REGEX: OnceCell<Regex> = OnceCell::new();
+/// Returns the plan of a dry run, `None` when processes were actually started
+impl Exec<Option<StartPlan>> for Start {
+    async fn exec(&self) -> Result<Option<StartPlan>> {
+        let mut processes = self.state.filter_processes(&self.args.processes).await?;
+        let excluded: HashSet<String> = if self.args.except.is_empty() {
+            HashSet::new()
+        } else {
+            self.state
+                .filter_processes(&self.args.except)
+                .await?
+                .into_iter()
+                .map(|p| p.name)
+                .collect()
+        };
+        processes.retain(|p| !excluded.contains(p.name()) && p.has_labels(&self.args.labels));
+        // Extra arguments only go to the selected process, not to its dependencies
+        let extra_args_target = if self.args.args.is_empty() {
+            None
+        } else {
+            let mut base_names: Vec<String> =
+                processes.iter().map(|p| p.base_name().to_owned()).collect();
+            base_names.sort();
+            base_names.dedup();
+            if base_names.len() != 1 {
+                return Err(Error::new(InnerError::Parse(format!(
+                    "Extra arguments can only be given to a single process, got {}",
+                    base_names.join(", ")
+                ))));
+            }
+            base_names.pop()
+        };
+        let mut processes = launch_order(self.with_dependencies(processes, &excluded).await?)?;
+        for process in processes.iter_mut() {
+            if self.args.release {
+                process.profile = Some("release".to_owned());
+            }
+            if extra_args_target.as_deref() == Some(process.base_name()) {
+                process.args.extend(self.args.args.iter().cloned());
+            }
+        }
+        if self.args.dry_run {
+            return self.dry_run(&processes).await.map(Some);
+        }
+        let lock = self.state.lock()?;
+        // Every start adds a task to the scheduler, finished ones mustn't pile up
+        let max_age = self.state.get_tasks_max_age()?;
+        if let Err(e) = self.state.prune_tasks(max_age).await {
+            warn!("Unable to prune finished tasks: {e}");
+        }
+        // Already running dependencies are not restarted, leave their state untouched
+        for process in processes
+            .iter()
+            .filter(|p| p.state.is_stopped() || p.state == ProcessState::CrashLooping)
+        {
+            // Explicitly starting a crash looping process gives it a fresh start
+            if process.state == ProcessState::CrashLooping {
+                self.state.set_failures(process.name(), 0, None).await?;
+            }
+            if !self.args.no_build {
+                self.state
+                    .set_state(process.name(), ProcessState::Building)
+                    .await?;
+            }
+        }
+        let build_failed = if self.args.no_build {
+            vec![]
+        } else {
+            self.build_processes(processes.as_slice()).await?
+        };
+        let mut failed: HashSet<String> = build_failed.iter().cloned().collect();
+        let mut started = Vec::new();
+        // Processes of a wave don't depend on each other, they are launched concurrently
+        let start = Arc::new(Start::new(self.args.clone(), self.state.clone()));
+        for wave in launch_waves(processes)? {
+            let mut launches = JoinSet::new();
+            let wave = wave
+                .into_iter()
+                .filter(|process| !build_failed.iter().any(|name| name == process.name()))
+                .collect();
+            for process in self.check_wave_ports(wave, &mut failed).await? {
+                let (start, failed) = (start.clone(), failed.clone());
+                launches.spawn(async move {
+                    let process_name = process.name().to_string();
+                    (process_name, start.launch(process, &failed).await)
+                });
+            }
+            // Every launch of the wave is waited for before reporting an error, so that none is
+            // left half done
+            let mut error = None;
+            while let Some(res) = launches.join_next().await {
+                let launched = res
+                    .map_err(|e| Error::new(InnerError::Start(e.to_string())))
+                    .and_then(|(process_name, launched)| Ok((process_name, launched?)));
+                match launched {
+                    Ok((process_name, Launched::Running)) => started.push(process_name),
+                    Ok((_, Launched::Completed)) => {}
+                    Ok((process_name, Launched::Failed)) => {
+                        failed.insert(process_name);
+                    }
+                    Err(e) if error.is_none() => error = Some(e),
+                    Err(e) => error!("{e}"),
+                }
+            }
+            if let Some(e) = error {
+                return Err(e);
+            }
+        }
+        // Waiting for processes to be ready doesn't change the project
+        drop(lock);
 
-pub fn envsubst(value: &str, env: &HashMap<String, String>) -> String {
-    let re = ENVSUBST_REGEX.get_or_init(|| Regex::new(r"\$\{([a-zA-Z0-9-_:/.\[\]]*)}").unwrap());
+        if !build_failed.is_empty() {
+            return Err(Error::new(InnerError::Build(format!(
+                "Processes failed to build: {}",
+                build_failed.join(", ")
+            ))));
+        }
+        if !failed.is_empty() {
+            let mut failed: Vec<String> = failed.into_iter().collect();
+            failed.sort();
+            return Err(Error::new(InnerError::Start(format!(
+                "Processes failed to start: {}",
+                failed.join(", ")
+            ))));
+        }
+        if self.args.wait {
+            self.wait_ready(&started).await?;
+        }
+        Ok(None)
+    }
+}
 
-    let mut last_range_end = 0;
-    let mut ret = "".to_string();
-    // We take all captures, replace them by their associated env value, then build a new string
-    // keeping the characters outside of placeholders, using captures' ranges.
-    for capture in re.captures_iter(value) {
-        let (_, [name]) = capture.extract();
-        let range = capture
-            .get(0)
-            .expect("Cannot happen as i == 0 is guaranteed to return Some")
-            .range();
-        if range.start != 0 {
-            ret.push_str(&value[last_range_end..range.start]);
+/// Whether given process is not started yet, or not anymore
+fn needs_start(process: &Process) -> bool {
+    matches!(
+        process.state,
+        ProcessState::Stopped
+            | ProcessState::Building
+            | ProcessState::CrashLooping
+            | ProcessState::Done
+    )
+}
+
+/// Sort processes so that each one comes after its dependencies
+///
+/// Dependencies missing from given processes are ignored. Ties are broken by name.
+pub(crate) fn launch_order(processes: Vec<Process>) -> Result<Vec<Process>> {
+    Ok(launch_waves(processes)?.into_iter().flatten().collect())
+}
+
+/// Group processes in waves, each one only depending on processes of previous waves
+///
+/// Dependencies missing from given processes are ignored. Processes of a wave are sorted by name.
+pub(crate) fn launch_waves(processes: Vec<Process>) -> Result<Vec<Vec<Process>>> {
+    let mut remaining: BTreeMap<String, Process> = processes
+        .into_iter()
+        .map(|p| (p.name().to_string(), p))
+        .collect();
+    let mut waves = vec![];
+    while !remaining.is_empty() {
+        let ready: Vec<String> = remaining
+            .values()
+            .filter(|p| {
+                p.depends_on
+                    .keys()
+                    .all(|d| !remaining.values().any(|r| r.base_name() == d))
+            })
+            .map(|p| p.name().to_string())
+            .collect();
+        if ready.is_empty() {
+            return Err(Error::new(InnerError::Dependency(format!(
+                "Dependency loop between processes: {}",
+                remaining.into_keys().collect::<Vec<_>>().join(", ")
+            ))));
         }
-        last_range_end = range.end;
-        let split: Vec<&str> = name.split(":-").collect();
-        let var_name = split.first().map(|s| s.to_string()).unwrap_or_default();
-        let default = split.get(1).map(|s| s.to_string());
-        let var_value = env
-            .get(&var_name)
-            .map(|s| s.to_string())
-            .or(default)
-            .unwrap_or_default();
-        ret.push_str(&var_value);
+        waves.push(
+            ready
+                .iter()
+                .filter_map(|name| remaining.remove(name))
+                .collect(),
+        );
+    }
+    Ok(waves)
+}
+
+/// Flags of a `cargo build` invocation, processes with different ones can't be built together
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+struct BuildFlags {
+    profile: Option<String>,
+    package: Option<String>,
+    features: CargoFeatures,
+    cargo_args: Vec<String>,
+}
+
+impl BuildFlags {
+    /// Hash of the flags, recorded along with the artifacts they built
+    fn key(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        format!("{:x}", hasher.finish())
     }
-    if last_range_end != value.len() {
-        ret.push_str(&value[last_range_end..value.len()]);
+}
+
+impl From<&Process> for BuildFlags {
+    fn from(process: &Process) -> Self {
+        Self {
+            profile: process.profile().map(str::to_owned),
+            package: process.package().map(str::to_owned),
+            features: process.cargo_features(),
+            cargo_args: process.cargo_args().to_vec(),
+        }
     }
-    ret
+}
+
+/// Build binaries, or examples, of given processes with a single `cargo build`, returning names of
+/// processes whose target couldn't be built
+///
+/// Those processes are set back to stopped, the other ones are left untouched. The build is
+/// recorded along with its duration, and binaries of a successful one along with its flags.
+///
+/// A group built `concurrently` with other ones gets its own target directory, named after its
+/// flags to keep incremental builds across starts. Its binaries are then copied to cargo's target
+/// directory, where processes are launched from.
+async fn build_group(
+    state: &State,
+    flags: &BuildFlags,
+    processes: &[Process],
+    concurrently: bool,
+) -> Result<Vec<String>> {
+    let cargo_target_dir = state.get_cargo_target_dir().await?;
+    let group_target_dir =
+        concurrently.then(|| cargo_target_dir.join(GROUPS_TARGET_DIR).join(flags.key()));
+    let mut cargo_args = flags.cargo_args.clone();
+    if let Some(dir) = &group_target_dir {
+        cargo_args.push(format!("--target-dir={}", dir.display()));
+    }
+    // Tells apart the output of groups built concurrently
+    let group: String = processes
+        .iter()
+        .map(|p| p.name())
+        .collect::<Vec<_>>()
+        .join(",");
+    let targets: Vec<CargoTarget> = processes.iter().map(|p| p.cargo_target()).collect();
+    // Executables of built targets
+    let mut built = HashMap::new();
+    let mut compiled = 0;
+    let started_at = Utc::now();
+    let start = Instant::now();
+    let mut success = match Cargo::build(
+        state.get_target_dir(),
+        targets.as_slice(),
+        cargo_args.as_slice(),
+        flags.profile.as_deref(),
+        flags.package.as_deref(),
+        &flags.features,
+    )
+    .await
+    {
+        Ok(mut build_process) => {
+            if let Some(stderr) = build_process.stderr.take() {
+                let group = group.clone();
+                tokio::spawn(async move {
+                    let mut stderr_reader = BufReader::new(stderr).lines();
+                    while let Ok(Some(line)) = stderr_reader.next_line().await {
+                        info!(build = %group, "{line}");
+                    }
+                });
+            }
+            if let Some(stdout) = build_process.stdout.take() {
+                let mut stdout_reader = BufReader::new(stdout).lines();
+                while let Some(line) = stdout_reader.next_line().await? {
+                    match serde_json::from_str::<CargoMessage>(&line) {
+                        Ok(CargoMessage::CompilerArtifact {
+                            target,
+                            executable,
+                            fresh,
+                        }) => {
+                            if !fresh {
+                                compiled += 1;
+                                info!(build = %group, "[{compiled}] Compiled {}", target.name);
+                            }
+                            if let Some(executable) = executable {
+                                built.extend(
+                                    targets
+                                        .iter()
+                                        .filter(|t| target.is(t))
+                                        .map(|t| (t, executable.clone())),
+                                );
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(_) => info!(build = %group, "{line}"),
+                    }
+                }
+            }
+            build_process.wait().await?.success()
+        }
+        Err(e) => {
+            error!("Unable to build crates: {e}");
+            false
+        }
+    };
+    if group_target_dir.is_some() {
+        for process in processes {
+            let Some(executable) = built.get(&process.cargo_target()) else {
+                continue;
+            };
+            if let Err(e) = copy_binary(executable, &process.artifact_path(cargo_target_dir)) {
+                error!("Unable to copy binary of process {}: {e}", process.name());
+                built.remove(&process.cargo_target());
+                success = false;
+            }
+        }
+    }
+    // Records are only informative, failing to write them mustn't fail the build
+    let record = BuildRecord {
+        id: 0,
+        started_at,
+        duration_ms: start.elapsed().as_millis().try_into().unwrap_or(u64::MAX),
+        processes: processes.iter().map(|p| p.name().to_owned()).collect(),
+        profile: flags.profile.clone(),
+        compiled,
+        success,
+    };
+    if let Err(e) = state.add_build(&record).await {
+        warn!("Unable to record the build: {e}");
+    }
+    if success {
+        let artifacts: Vec<String> = processes
+            .iter()
+            .map(|p| p.artifact_path(cargo_target_dir).display().to_string())
+            .collect();
+        // Binaries left unrecorded are built again, which cargo finds fresh
+        if let Err(e) = state.set_built_artifacts(&artifacts, &flags.key()).await {
+            warn!("Unable to record built binaries: {e}");
+        }
+        for process in processes {
+            state.emit(JockerEvent::BuildFinished {
+                process_name: process.name().to_owned(),
+                success: true,
+            });
+        }
+        return Ok(vec![]);
+    }
+
+    let mut failed: Vec<&Process> = processes
+        .iter()
+        .filter(|p| !built.contains_key(&p.cargo_target()))
+        .collect();
+    // Every target may have been built while cargo still failed, on a build script for instance
+    if failed.is_empty() {
+        failed = processes.iter().collect();
+    }
+    for process in processes {
+        let success = !failed.contains(&process);
+        if !success {
+            error!("Build of process {} failed", process.name());
+            state
+                .set_state(process.name(), ProcessState::Stopped)
+                .await?;
+        }
+        state.emit(JockerEvent::BuildFinished {
+            process_name: process.name().to_owned(),
+            success,
+        });
+    }
+    Ok(failed.into_iter().map(|p| p.name().to_owned()).collect())
+}
+
+/// Copy a binary built in the target directory of a group to where its process is launched from
+///
+/// The copy is renamed over the previous binary, which may still be running.
+fn copy_binary(executable: &Path, destination: &Path) -> Result<()> {
+    if let Some(dir) = destination.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let copy = destination.with_extension("jocker");
+    fs::copy(executable, &copy)?;
+    fs::rename(&copy, destination)?;
+    Ok(())
+}
+
+/// Environment given process is launched with: its own one, and its identifier for the scheduler
+pub(crate) fn launch_env(state: &State, process: &Process) -> HashMap<String, String> {
+    env_values(launch_env_sources(state, process))
+}
+
+/// Same as [`launch_env`], with where each variable comes from
+pub(crate) fn launch_env_sources(state: &State, process: &Process) -> BTreeMap<String, EnvVar> {
+    let mut env = process_env_sources(process);
+    env.insert(
+        JOCKER_ENV_PROCESS_ID.to_string(),
+        EnvVar::new(
+            format!("{}/{}", state.scheduler_group(), process.name()),
+            EnvSource::Jocker,
+        ),
+    );
+    env
+}
+
+/// Environment of given process: its instance index and variables of the `.env` file, overridden
+/// by its own ones
+pub(crate) fn process_env(process: &Process) -> HashMap<String, String> {
+    env_values(process_env_sources(process))
+}
+
+/// Same as [`process_env`], with where each variable comes from
+pub(crate) fn process_env_sources(process: &Process) -> BTreeMap<String, EnvVar> {
+    let mut env = BTreeMap::new();
+    env.insert(
+        JOCKER_ENV_INSTANCE.to_string(),
+        EnvVar::new(process.instance.to_string(), EnvSource::Jocker),
+    );
+    if let Ok(dotenv) = dotenv_iter() {
+        for (key, val) in dotenv.flatten() {
+            env.insert(key, EnvVar::new(val, EnvSource::Dotenv));
+        }
+    }
+    for (key, val) in process.env.iter() {
+        env.insert(key.to_string(), EnvVar::new(val, EnvSource::Config));
+    }
+    env
+}
+
+fn env_values(env: BTreeMap<String, EnvVar>) -> HashMap<String, String> {
+    env.into_iter().map(|(key, var)| (key, var.value)).collect()
+}
+
+/// Variables given on the command line: the ones of `env_files`, in order, overridden by `env`
+pub(crate) fn cli_env(
+    env: &[(String, String)],
+    env_files: &[PathBuf],
+) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for path in env_files {
+        let read_error = |e: dotenvy::Error| {
+            Error::new(InnerError::Env(format!(
+                "Unable to read env file {}: {e}",
+                path.display()
+            )))
+        };
+        for var in from_path_iter(path).map_err(read_error)? {
+            vars.push(var.map_err(read_error)?);
+        }
+    }
+    vars.extend(env.iter().cloned());
+    Ok(vars)
+}
+
+/// Variable given as `KEY=VALUE`, or as `KEY` to pass the value it has in the current environment
+pub fn parse_env_var(var: &str) -> Result<(String, String)> {
+    let (key, value) = match var.split_once('=') {
+        Some((key, value)) => (key, value.to_owned()),
+        None => {
+            let value = std::env::var(var)
+                .map_err(|_| Error::new(InnerError::Env(format!("Variable {var} is not set"))))?;
+            (var, value)
+        }
+    };
+    if key.is_empty() {
+        return Err(Error::new(InnerError::Parse(format!("Missing variable name in {var}"))));
+    }
+    Ok((key.to_owned(), value))
+}
+
+/// Shell command launching given process, from the target directory
+pub(crate) async fn process_command(
+    state: &State,
+    process: &Process,
+    env: &HashMap<String, String>,
+) -> Result<String> {
+    let mut command = vec![];
+    match process.command() {
+        Some(process_command) => command.push(envsubst(process_command, env)),
+        None => {
+            let cargo_target_dir = state.get_cargo_target_dir().await?;
+            command.push(shell_arg(
+                &process
+                    .artifact_path(cargo_target_dir)
+                    .display()
+                    .to_string(),
+            ))
+        }
+    }
+    for arg in process.args() {
+        command.push(envsubst(arg, env));
+    }
+    Ok(command.join(" "))
+}
+
+pub(crate) const SECRET_MASK: &str = "********";
+const SECRET_KEY_PARTS: [&str; 5] = ["SECRET", "PASSWORD", "TOKEN", "CREDENTIAL", "KEY"];
+
+/// Whether given environment variable likely holds a secret, based on its name
+pub(crate) fn is_secret(key: &str) -> bool {
+    let key = key.to_uppercase();
+    SECRET_KEY_PARTS.iter().any(|part| key.contains(part))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use crate::{
+        common::{DependencyCondition, Process},
+        error::InnerError,
+        start::{
+            cli_env, copy_binary, is_secret, launch_order, launch_waves, parse_env_var, BuildFlags,
+        },
+    };
+
+    #[test]
+    fn test_build_flags() {
+        let mut api = Process::new("api", "api");
+        let mut worker = Process::new("worker", "worker");
+        assert_eq!(BuildFlags::from(&api), BuildFlags::from(&worker));
+
+        api.cargo_args = vec!["--locked".to_owned()];
+        assert_ne!(BuildFlags::from(&api), BuildFlags::from(&worker));
+        worker.cargo_args = vec!["--locked".to_owned()];
+        assert_eq!(BuildFlags::from(&api), BuildFlags::from(&worker));
+
+        worker.profile = Some("release".to_owned());
+        assert_ne!(BuildFlags::from(&api), BuildFlags::from(&worker));
+    }
+
+    #[test]
+    fn test_copy_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let executable = dir.path().join("jocker").join("api");
+        let destination = dir.path().join("debug").join("api");
+        std::fs::create_dir_all(executable.parent().unwrap()).unwrap();
+        std::fs::write(&executable, "new").unwrap();
+
+        copy_binary(&executable, &destination).unwrap();
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "new");
+
+        std::fs::write(&executable, "newer").unwrap();
+        copy_binary(&executable, &destination).unwrap();
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "newer");
+        assert!(!destination.with_extension("jocker").exists());
+    }
+
+    #[test]
+    fn test_is_secret() {
+        assert!(is_secret("DATABASE_PASSWORD"));
+        assert!(is_secret("api_key"));
+        assert!(is_secret("GITHUB_TOKEN"));
+        assert!(!is_secret("DATABASE_URL"));
+        assert!(!is_secret("RUST_LOG"));
+    }
+
+    #[test]
+    fn test_parse_env_var() {
+        assert_eq!(
+            parse_env_var("FOO=bar=baz").unwrap(),
+            ("FOO".to_owned(), "bar=baz".to_owned())
+        );
+        assert_eq!(parse_env_var("FOO=").unwrap(), ("FOO".to_owned(), String::new()));
+        assert!(parse_env_var("=bar").is_err());
+        assert!(parse_env_var("JOCKER_TEST_UNSET_VARIABLE").is_err());
+    }
+
+    #[test]
+    fn test_cli_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.env");
+        let second = dir.path().join("second.env");
+        std::fs::write(&first, "# Comment\nFOO=first\nBAR=\"quoted value\"\n").unwrap();
+        std::fs::write(&second, "FOO=second\n").unwrap();
 
-    use crate::start::envsubst;
+        let vars = cli_env(
+            &[("BAR".to_owned(), "cli".to_owned())],
+            &[first, second],
+        )
+        .unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("FOO".to_owned(), "first".to_owned()),
+                ("BAR".to_owned(), "quoted value".to_owned()),
+                ("FOO".to_owned(), "second".to_owned()),
+                ("BAR".to_owned(), "cli".to_owned()),
+            ]
+        );
+
+        let missing = dir.path().join("missing.env");
+        let err = cli_env(&[], &[missing]).unwrap_err();
+        assert!(matches!(err.inner_error, InnerError::Env(_)));
+    }
+
+    #[test]
+    fn test_launch_order() {
+        let mut web = Process::new("web", "web");
+        web.depends_on
+            .insert("api".to_owned(), DependencyCondition::Healthy);
+        let mut api = Process::new("api", "api");
+        api.depends_on
+            .insert("db".to_owned(), DependencyCondition::Started);
+        let db = Process::new("db", "db");
+        let worker = Process::new("worker", "worker");
+
+        let names: Vec<String> = launch_order(vec![web.clone(), worker, api.clone(), db])
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, vec!["db", "worker", "api", "web"]);
+
+        // Dependencies outside of the given processes are ignored
+        assert_eq!(launch_order(vec![web.clone()]).unwrap().len(), 1);
+
+        // Every instance of a dependency comes first
+        let mut replicated_api = api.clone();
+        replicated_api.replicas = 2;
+        let mut processes = replicated_api.instances();
+        processes.insert(0, web.clone());
+        let names: Vec<String> = launch_order(processes)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, vec!["api", "api.1", "web"]);
+
+        let mut db = Process::new("db", "db");
+        db.depends_on
+            .insert("web".to_owned(), DependencyCondition::Started);
+        assert!(launch_order(vec![web, api, db]).is_err());
+    }
 
     #[test]
-    fn test_envsubst() {
-        let mut env = HashMap::new();
-        assert_eq!(&envsubst("${FOO:-baz}", &env), "baz");
-        env.insert("FOO".to_string(), "BAR".to_string());
-        assert_eq!(&envsubst("FOO", &env), "FOO");
-        assert_eq!(&envsubst("${FOO}", &env), "BAR");
-        assert_eq!(&envsubst("${FOO:-baz}", &env), "BAR");
+    fn test_launch_waves() {
+        let mut web = Process::new("web", "web");
+        web.depends_on
+            .insert("api".to_owned(), DependencyCondition::Healthy);
+        let mut api = Process::new("api", "api");
+        api.depends_on
+            .insert("db".to_owned(), DependencyCondition::Started);
+        let db = Process::new("db", "db");
+        let worker = Process::new("worker", "worker");
+
+        let waves: Vec<Vec<String>> = launch_waves(vec![web, worker, api, db])
+            .unwrap()
+            .into_iter()
+            .map(|wave| wave.into_iter().map(|p| p.name).collect())
+            .collect();
+        assert_eq!(waves, vec![vec!["db", "worker"], vec!["api"], vec!["web"]]);
     }
 }
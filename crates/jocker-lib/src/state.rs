@@ -1,26 +1,54 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     env,
     fs::{canonicalize, create_dir_all, File},
     hash::{DefaultHasher, Hash, Hasher},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
 };
 
 use chrono::{DateTime, Utc};
-use tokio::fs::remove_dir_all;
+use tokio::sync::{broadcast, OnceCell};
+use tracing::{debug, info, trace, warn};
 
 use crate::{
+    archive::sync_logs,
     command::{
         cargo::{BinaryPackage, Cargo},
+        native::Native,
         pueue::Pueue,
+        systemd::Systemd,
+        util::is_running,
     },
-    common::{Process, ProcessState, Stack, JOCKER, JOCKER_ENV_STACK, MAX_RECURSION_LEVEL},
-    config::{ConfigFile, ConfigStack},
-    database::Database,
-    error::{lock_error, Error, InnerError, Result},
+    common::{
+        match_filter, suggest_filter, BuildRecord, EnvVar, Event, EventKind, HistoryEntry,
+        HistoryKind, JockerEvent, Process, ProcessState, Secret, Stack, JOCKER, JOCKER_ENV_ENABLE,
+        JOCKER_ENV_SCHEDULER, JOCKER_ENV_STACK, MAX_RECURSION_LEVEL,
+    },
+    config::{find_config_file, ConfigFile, ConfigStack, CONFIG_OVERRIDE_FILE},
+    database::{Database, DatabaseOptions},
+    error::{filesystem_error, lock_error, Error, InnerError, Result},
+    handle::ProcessHandle,
+    interpolate::dotenv_file,
+    lock::ProjectLock,
+    projects::{clean_project, ProjectRegistry},
+    scheduler::{Scheduler, SchedulerKind},
+    Pid,
 };
 
+/// Number of latest history entries kept by default
+pub const DEFAULT_MAX_HISTORY_ENTRIES: u32 = 10_000;
+
+/// Number of latest builds kept
+pub const MAX_BUILD_RECORDS: u32 = 1_000;
+
+/// Age after which finished tasks are pruned by default
+pub const DEFAULT_TASKS_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Number of events kept for subscribers lagging behind
+const EVENTS_CAPACITY: usize = 1024;
+
 #[derive(Debug, PartialEq)]
 pub struct StateArgs {
     pub refresh: bool,
@@ -30,45 +58,246 @@ pub struct StateArgs {
 pub struct State {
     project_dir: String,
     target_dir: PathBuf,
+    /// Target directory of cargo, resolved once as `cargo metadata` is slow
+    cargo_target_dir: OnceCell<PathBuf>,
+    config_file: PathBuf,
     db: Database,
     current_stack: Arc<Mutex<Option<String>>>,
-    scheduler: Pueue,
+    enabled_groups: Arc<Mutex<Vec<String>>>,
+    scheduler: Box<dyn Scheduler>,
+    /// Lock of the project, shared by nested commands of this invocation while held
+    project_lock: Mutex<Weak<ProjectLock>>,
+    events: broadcast::Sender<JockerEvent>,
 }
 
-impl State {
-    pub async fn new(
-        refresh: bool,
-        stack: Option<String>,
-        target_dir: Option<impl Into<PathBuf>>,
-    ) -> Result<Self> {
-        let target_dir = target_dir.map(Into::into).unwrap_or(canonicalize(".")?);
-        let (project_id, project_dir) = Self::get_or_create_state_dir(&target_dir)?;
-        let db = Database::new(&project_dir).await?;
-        let scheduler = Pueue::new(&project_id).await?;
-        let state = Self {
+/// Options of a [`State`], built with [`State::builder`]
+#[derive(Default)]
+pub struct StateBuilder {
+    refresh: bool,
+    stack: Option<String>,
+    target_dir: Option<PathBuf>,
+    config_file: Option<PathBuf>,
+    enabled_groups: Vec<String>,
+    scheduler: Option<Box<dyn Scheduler>>,
+    skip_refresh: bool,
+    database: DatabaseOptions,
+}
+
+impl StateBuilder {
+    /// Reload processes from the configuration, even if it didn't change
+    pub fn refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Stack to use instead of the selected or default one
+    pub fn stack(mut self, stack: impl Into<String>) -> Self {
+        self.stack = Some(stack.into());
+        self
+    }
+
+    /// Directory of the project, the current one by default
+    pub fn target_dir(mut self, target_dir: impl Into<PathBuf>) -> Self {
+        self.target_dir = Some(target_dir.into());
+        self
+    }
+
+    /// Configuration file to read instead of `jocker.yml` of the target directory
+    pub fn config_file(mut self, config_file: impl Into<PathBuf>) -> Self {
+        self.config_file = Some(config_file.into());
+        self
+    }
+
+    /// Groups of optional processes to enable, the ones of `JOCKER_ENABLE` by default
+    pub fn enable(mut self, groups: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.enabled_groups = groups.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Backend running processes, picked from `JOCKER_SCHEDULER` by default
+    pub fn scheduler(mut self, scheduler: Box<dyn Scheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Maximum wait for another command to release the database of the project, before failing
+    /// with `database is locked`, 10 seconds by default
+    pub fn busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.database.busy_timeout = busy_timeout;
+        self
+    }
+
+    /// Write-ahead logging of the database of the project, letting commands read it while another
+    /// one writes, enabled by default. Not supported by network filesystems.
+    pub fn wal(mut self, wal: bool) -> Self {
+        self.database.wal = wal;
+        self
+    }
+
+    /// Leave the stored state as is, neither reconciling it with the scheduler and the
+    /// configuration nor selecting a stack, so that a state which fails to refresh can be repaired
+    pub fn skip_refresh(mut self) -> Self {
+        self.skip_refresh = true;
+        self
+    }
+
+    pub async fn build(self) -> Result<State> {
+        let target_dir = match self.target_dir {
+            Some(target_dir) => target_dir,
+            None => canonicalize(".")?,
+        };
+        let config_file = match self.config_file {
+            Some(config_file) => {
+                if !config_file.exists() {
+                    let error = InnerError::Filesystem(format!(
+                        "Configuration file {} does not exist",
+                        config_file.display()
+                    ));
+                    let hint = "Check the path given to --file, or create it with `jocker init`";
+                    return Err(Error::new(error).with_hint(hint));
+                }
+                canonicalize(config_file)?
+            }
+            None => find_config_file(&target_dir),
+        };
+        let (project_id, project_dir) = State::get_or_create_state_dir(&target_dir)?;
+        let scheduler = match self.scheduler {
+            Some(scheduler) => scheduler,
+            None => {
+                ProjectRegistry::new()?.touch(&project_id, &target_dir)?;
+                State::new_scheduler(&project_id, &project_dir).await?
+            }
+        };
+        let db = Database::new(&project_dir, self.database).await?;
+        let state = State {
             project_dir,
             target_dir,
+            cargo_target_dir: OnceCell::new(),
+            config_file,
             db,
             current_stack: Arc::new(Mutex::new(None)),
+            enabled_groups: Arc::new(Mutex::new(Vec::new())),
             scheduler,
+            project_lock: Mutex::new(Weak::new()),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
         };
-        state.refresh(refresh).await?;
-        state.set_current_stack(&stack).await?;
+        if !self.skip_refresh {
+            // Refreshing writes the state, which mustn't race with a command holding the lock.
+            // That command keeps the state up to date itself.
+            match state.lock() {
+                Ok(_lock) => state.refresh(self.refresh).await?,
+                Err(e) if matches!(e.inner_error, InnerError::ProjectLocked(_)) => {
+                    if self.refresh {
+                        warn!("Not refreshing the state: {e}");
+                    } else {
+                        debug!("Not refreshing the state: {e}");
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+            state.set_current_stack(&self.stack).await?;
+        }
+        state.set_enabled_groups(&self.enabled_groups)?;
         Ok(state)
     }
+}
 
-    pub(crate) fn scheduler(&self) -> &Pueue {
-        &self.scheduler
+impl State {
+    /// Configuration is read from `config_file` if given, from `jocker.yml` of the target
+    /// directory otherwise
+    pub async fn new(
+        refresh: bool,
+        stack: Option<String>,
+        target_dir: Option<impl Into<PathBuf>>,
+        config_file: Option<impl Into<PathBuf>>,
+    ) -> Result<Self> {
+        StateBuilder {
+            refresh,
+            stack,
+            target_dir: target_dir.map(Into::into),
+            config_file: config_file.map(Into::into),
+            ..Default::default()
+        }
+        .build()
+        .await
+    }
+
+    /// Configure a state option by option, any option left out keeping its default
+    pub fn builder() -> StateBuilder {
+        StateBuilder::default()
+    }
+
+    /// Scheduler of given project, whose kind is picked from the environment
+    pub(crate) async fn new_scheduler(
+        project_id: &str,
+        project_dir: impl Into<PathBuf>,
+    ) -> Result<Box<dyn Scheduler>> {
+        let scheduler_kind = match env::var(JOCKER_ENV_SCHEDULER) {
+            Ok(kind) => kind.parse()?,
+            Err(_) => SchedulerKind::default(),
+        };
+        Ok(match scheduler_kind {
+            SchedulerKind::Pueue => Box::new(Pueue::new(project_id)?),
+            SchedulerKind::Native => Box::new(Native::new(project_id, project_dir)?),
+            SchedulerKind::Systemd => Box::new(Systemd::new(project_id, project_dir)?),
+        })
+    }
+
+    /// Same as [`State::new`], running processes through given [`Scheduler`] backend
+    pub async fn with_scheduler(
+        refresh: bool,
+        stack: Option<String>,
+        target_dir: Option<impl Into<PathBuf>>,
+        config_file: Option<impl Into<PathBuf>>,
+        scheduler: Box<dyn Scheduler>,
+    ) -> Result<Self> {
+        StateBuilder {
+            refresh,
+            stack,
+            target_dir: target_dir.map(Into::into),
+            config_file: config_file.map(Into::into),
+            scheduler: Some(scheduler),
+            ..Default::default()
+        }
+        .build()
+        .await
+    }
+
+    pub(crate) fn scheduler(&self) -> &dyn Scheduler {
+        self.scheduler.as_ref()
     }
 
     pub fn scheduler_group(&self) -> &str {
         self.scheduler.group()
     }
 
+    /// Handle controlling the process with given name, which is only looked up when used
+    pub fn process(self: &Arc<Self>, name: impl Into<String>) -> ProcessHandle {
+        ProcessHandle::new(name.into(), self.clone())
+    }
+
+    /// Lock the project against other jocker commands changing it, until the guard is dropped
+    ///
+    /// Taking the lock again while it is held by this state only shares it.
+    pub fn lock(&self) -> Result<Arc<ProjectLock>> {
+        let mut project_lock = self.project_lock.lock().map_err(lock_error)?;
+        if let Some(held) = project_lock.upgrade() {
+            return Ok(held);
+        }
+        let acquired = Arc::new(ProjectLock::acquire(Path::new(&self.project_dir))?);
+        debug!(project_dir = %self.project_dir, "Locked project");
+        *project_lock = Arc::downgrade(&acquired);
+        Ok(acquired)
+    }
+
     pub async fn clean(self) -> Result<()> {
-        remove_dir_all(self.project_dir).await?;
-        self.scheduler.clean().await?;
-        Ok(())
+        let project_id = Self::get_project_id(&self.target_dir);
+        clean_project(
+            &project_id,
+            Path::new(&self.project_dir),
+            self.scheduler.as_ref(),
+        )
+        .await
     }
 
     pub async fn get_elapsed_since_last_binaries_update(&self) -> Result<u64> {
@@ -105,10 +334,27 @@ impl State {
         self.db.set_config_updated_at(date).await
     }
 
+    pub fn get_project_dir(&self) -> &Path {
+        Path::new(&self.project_dir)
+    }
+
     pub fn get_target_dir(&self) -> &Path {
         &self.target_dir
     }
 
+    /// Directory cargo builds artifacts of the project into
+    pub async fn get_cargo_target_dir(&self) -> Result<&Path> {
+        let dir = self
+            .cargo_target_dir
+            .get_or_try_init(|| Cargo::target_directory(&self.target_dir))
+            .await?;
+        Ok(dir)
+    }
+
+    pub fn get_config_file(&self) -> &Path {
+        &self.config_file
+    }
+
     pub async fn get_binaries(&self) -> Result<Vec<BinaryPackage>> {
         self.db.get_binaries().await
     }
@@ -119,12 +365,27 @@ impl State {
 
     /// Filter processes list based on given process names
     ///
-    /// If [`process_names`] is empty, returns all processes
+    /// A configured process name selects all of its instances, while an instance name only selects
+    /// that instance. If [`process_names`] is empty, returns all processes but optional ones whose
+    /// groups aren't enabled
     pub async fn filter_processes(&self, process_names: &[String]) -> Result<Vec<Process>> {
+        let processes = self.select_processes(process_names).await?;
+        if !process_names.is_empty() {
+            return Ok(processes);
+        }
+        let enabled_groups = self.get_enabled_groups()?;
+        Ok(processes
+            .into_iter()
+            .filter(|process| process.is_enabled(&enabled_groups))
+            .collect())
+    }
+
+    async fn select_processes(&self, process_names: &[String]) -> Result<Vec<Process>> {
+        if !process_names.is_empty() {
+            return self.match_processes(process_names).await;
+        }
         let current_stack = self.get_current_stack()?;
-        let expected_processes: Vec<String> = if !process_names.is_empty() {
-            process_names.to_owned()
-        } else if let Some(stack) = current_stack {
+        let expected_processes: Vec<String> = if let Some(stack) = current_stack {
             self.get_stack(&stack)
                 .await?
                 .get_all_processes()
@@ -141,20 +402,47 @@ impl State {
             .get_processes()
             .await?
             .into_iter()
-            .filter(|process| expected_processes.contains(&process.name))
+            .filter(|process| expected_processes.iter().any(|name| process.matches(name)))
             .collect();
-        if expected_processes.len() != processes.len() {
-            let mut process_names: HashSet<String> = process_names.iter().cloned().collect();
-            for process in processes {
-                process_names.remove(&process.name);
-            }
-            return Err(Error::new(InnerError::ProcessNotFound(
-                process_names.into_iter().collect(),
-            )));
+        let missing_processes: Vec<String> = expected_processes
+            .into_iter()
+            .filter(|name| !processes.iter().any(|process| process.matches(name)))
+            .collect();
+        if !missing_processes.is_empty() {
+            return Err(Error::new(InnerError::ProcessNotFound(missing_processes)));
         }
         Ok(processes)
     }
 
+    /// Processes designated by filters given on CLI, see [`match_filter`]
+    async fn match_processes(&self, filters: &[String]) -> Result<Vec<Process>> {
+        let processes = self.get_processes().await?;
+        let mut selected: HashSet<&str> = HashSet::new();
+        let mut missing_processes = vec![];
+        let mut suggestions = vec![];
+        for filter in filters {
+            let matched = match_filter(filter, &processes)?;
+            if matched.is_empty() {
+                missing_processes.push(filter.to_owned());
+                suggestions.extend(suggest_filter(filter, &processes));
+            }
+            selected.extend(matched.into_iter().map(|p| p.name()));
+        }
+        if !missing_processes.is_empty() {
+            let error = Error::new(InnerError::ProcessNotFound(missing_processes));
+            return Err(if suggestions.is_empty() {
+                error
+            } else {
+                error.add_context(format!("Did you mean {}?", suggestions.join(", ")))
+            });
+        }
+        Ok(processes
+            .iter()
+            .filter(|p| selected.contains(p.name()))
+            .cloned()
+            .collect())
+    }
+
     pub async fn get_processes(&self) -> Result<Vec<Process>> {
         self.db.get_processes().await
     }
@@ -163,8 +451,30 @@ impl State {
         self.db.set_processes(&processes).await
     }
 
+    pub async fn add_processes(&self, processes: &[Process]) -> Result<()> {
+        self.db.add_processes(processes).await
+    }
+
+    pub async fn remove_processes(&self, process_names: &[String]) -> Result<()> {
+        self.db.remove_processes(process_names).await
+    }
+
+    pub async fn set_replicas(&self, base_name: &str, replicas: u32) -> Result<()> {
+        self.db.set_process_replicas(base_name, replicas).await
+    }
+
     pub async fn set_state(&self, process_name: &str, state: ProcessState) -> Result<()> {
-        self.db.set_process_state(process_name, state).await
+        if self
+            .db
+            .set_process_state(process_name, state.clone())
+            .await?
+        {
+            self.emit(JockerEvent::StateChanged {
+                process_name: process_name.to_owned(),
+                state,
+            });
+        }
+        Ok(())
     }
 
     pub async fn set_pid(&self, process_name: &str, pid: Option<usize>) -> Result<()> {
@@ -172,10 +482,87 @@ impl State {
         self.db.set_process_pid(process_name, pid).await
     }
 
+    pub async fn set_failures(
+        &self,
+        process_name: &str,
+        failure_count: u32,
+        failures_since: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        self.db
+            .set_process_failures(process_name, failure_count, failures_since)
+            .await
+    }
+
+    pub async fn set_archived_lines(&self, process_name: &str, archived_lines: u32) -> Result<()> {
+        self.db
+            .set_process_archived_lines(process_name, archived_lines)
+            .await
+    }
+
+    /// Record the launch of given process with given command line, in the current stack
+    pub async fn set_started(
+        &self,
+        process_name: &str,
+        command_line: &str,
+        env: &BTreeMap<String, EnvVar>,
+    ) -> Result<()> {
+        let stack = self.get_current_stack()?;
+        self.db
+            .set_process_started(process_name, Utc::now(), command_line, stack.as_deref(), env)
+            .await
+    }
+
+    pub async fn set_exit_code(&self, process_name: &str, exit_code: Option<i32>) -> Result<()> {
+        self.db.set_process_exit_code(process_name, exit_code).await
+    }
+
+    pub async fn add_event(&self, event: Event) -> Result<()> {
+        self.db.add_event(&event).await
+    }
+
+    /// Receive events of the changes made through this state from now on
+    ///
+    /// Events are dropped for a receiver lagging more than 1024 events behind, which then gets a
+    /// [`broadcast::error::RecvError::Lagged`].
+    pub fn subscribe(&self) -> broadcast::Receiver<JockerEvent> {
+        self.events.subscribe()
+    }
+
+    pub(crate) fn emit(&self, event: JockerEvent) {
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+
+    pub async fn get_events(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Event>> {
+        self.db.get_events(since).await
+    }
+
     pub fn get_current_stack(&self) -> Result<Option<String>> {
         Ok(self.current_stack.lock().map_err(lock_error)?.clone())
     }
 
+    pub fn get_enabled_groups(&self) -> Result<Vec<String>> {
+        Ok(self.enabled_groups.lock().map_err(lock_error)?.clone())
+    }
+
+    /// Enable given groups of optional processes, or the ones of `JOCKER_ENABLE` if none is given
+    pub fn set_enabled_groups(&self, groups: &[String]) -> Result<()> {
+        let groups = if !groups.is_empty() {
+            groups.to_vec()
+        } else if let Ok(groups) = env::var(JOCKER_ENV_ENABLE) {
+            groups
+                .split(',')
+                .map(str::trim)
+                .filter(|g| !g.is_empty())
+                .map(str::to_owned)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        *self.enabled_groups.lock().map_err(lock_error)? = groups;
+        Ok(())
+    }
+
     pub async fn set_current_stack(&self, stack: &Option<String>) -> Result<()> {
         if let Some(stack) = stack {
             *self.current_stack.lock().map_err(lock_error)? =
@@ -183,9 +570,16 @@ impl State {
         } else if let Ok(stack) = env::var(JOCKER_ENV_STACK) {
             *self.current_stack.lock().map_err(lock_error)? =
                 Some(self.get_stack(&stack).await?.name);
+        } else if let Some(stack) = self.get_selected_stack().await? {
+            *self.current_stack.lock().map_err(lock_error)? =
+                Some(self.get_stack(&stack).await?.name);
         } else {
             *self.current_stack.lock().map_err(lock_error)? = self.get_default_stack().await?;
         };
+        if let Some(stack) = self.get_current_stack()? {
+            let max_parallel = self.get_stack(&stack).await?.max_parallel;
+            self.scheduler.use_stack(&stack, max_parallel).await?;
+        }
 
         Ok(())
     }
@@ -198,66 +592,225 @@ impl State {
         self.db.set_default_stack(stack).await
     }
 
+    pub async fn get_selected_stack(&self) -> Result<Option<String>> {
+        self.db.get_selected_stack().await
+    }
+
+    /// Persist the stack used when neither `--stack` nor `JOCKER_STACK` are given, `None`
+    /// reverts to the configuration default
+    pub async fn set_selected_stack(&self, stack: &Option<String>) -> Result<()> {
+        let previous_stack = self.db.get_selected_stack().await?;
+        if previous_stack == *stack {
+            return Ok(());
+        }
+        self.db.set_selected_stack(stack).await?;
+        let change = format!(
+            "{} -> {}",
+            previous_stack.as_deref().unwrap_or("-"),
+            stack.as_deref().unwrap_or("-")
+        );
+        self.db.add_history(HistoryKind::Stack, None, &change).await
+    }
+
+    /// History of the project, or of given process only, oldest change first
+    pub async fn get_history(&self, process_name: Option<&str>) -> Result<Vec<HistoryEntry>> {
+        self.db.get_history(process_name).await
+    }
+
+    /// Record a `cargo build`, only the last [`MAX_BUILD_RECORDS`] ones being kept
+    pub(crate) async fn add_build(&self, build: &BuildRecord) -> Result<()> {
+        self.db.add_build(build, MAX_BUILD_RECORDS).await
+    }
+
+    /// Record given artifacts as built with given flags, see [`State::get_built_artifacts`]
+    pub(crate) async fn set_built_artifacts(&self, paths: &[String], flags: &str) -> Result<()> {
+        self.db.set_built_artifacts(paths, flags).await
+    }
+
+    /// Flags artifacts were last built with, by path
+    ///
+    /// An artifact built with other flags than the ones of a process is outdated for it, even if
+    /// it is more recent than every source.
+    pub(crate) async fn get_built_artifacts(&self) -> Result<HashMap<String, String>> {
+        self.db.get_built_artifacts().await
+    }
+
+    /// Last `limit` builds, or the ones covering given process only, most recent first
+    pub async fn get_builds(
+        &self,
+        process_name: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<BuildRecord>> {
+        self.db.get_builds(process_name, limit).await
+    }
+
     pub async fn get_stack(&self, stack: &str) -> Result<Stack> {
         self.db.get_stack(stack).await
     }
 
+    pub async fn get_stacks(&self) -> Result<Vec<Stack>> {
+        self.db.get_stacks().await
+    }
+
     pub async fn set_stacks(&self, stacks: &[Stack]) -> Result<()> {
         self.db.set_stacks(stacks).await
     }
 
+    pub async fn add_stack(&self, stack: &Stack) -> Result<()> {
+        self.db.add_stack(stack).await
+    }
+
+    /// Delete given user-created stack, returning whether there was one
+    pub async fn delete_stack(&self, stack: &str) -> Result<bool> {
+        self.db.delete_stack(stack).await
+    }
+
     // Refresh
 
     pub async fn refresh(&self, hard: bool) -> Result<()> {
-        let mut scheduled_process = self.scheduler().processes().await?;
+        trace!(hard, "Refreshing process states");
+        let mut scheduled_process = self.scheduler().status().await?;
         for process in self.get_processes().await? {
+            // Adopted processes aren't scheduled, they are followed through their OS pid
+            if process.state == ProcessState::External {
+                if !process.pid.is_some_and(is_running) {
+                    self.add_event(Event::new(process.name(), EventKind::Exited))
+                        .await?;
+                    self.set_pid(process.name(), None).await?;
+                    self.set_state(process.name(), ProcessState::Stopped)
+                        .await?;
+                }
+                continue;
+            }
             if let Some(sp) = scheduled_process.remove(process.name()) {
-                self.set_pid(process.name(), Some(sp.0)).await?;
-                self.set_state(process.name(), sp.1.into()).await?;
-            } else {
-                self.set_pid(process.name(), None).await?;
-                self.set_state(process.name(), ProcessState::Stopped)
+                let mut state = sp.state;
+                // Jocker may have been interrupted before recording the task it started
+                if !process.state.is_started() && state.is_started() && process.pid != Some(sp.pid)
+                {
+                    info!("Process {} runs as task {}, tracking it again", process.name(), sp.pid);
+                }
+                if process.state.is_started() && !state.is_started() {
+                    self.add_event(Event {
+                        exit_code: sp.exit_code,
+                        ..Event::new(process.name(), EventKind::Exited)
+                    })
                     .await?;
+                    if process.is_task() {
+                        state = ProcessState::Done;
+                    } else if self.record_exit(process.clone(), sp.exit_code).await? {
+                        state = ProcessState::CrashLooping;
+                    }
+                    sync_logs(self, &process).await;
+                } else if process.state == ProcessState::Done && !state.is_started() {
+                    state = ProcessState::Done;
+                } else if process.state == ProcessState::CrashLooping
+                    && state != ProcessState::Running
+                {
+                    state = ProcessState::CrashLooping;
+                }
+                // Only changes are written, refreshing mustn't wait for concurrent commands
+                if sp.exit_code.is_some() && sp.exit_code != process.exit_code {
+                    self.set_exit_code(process.name(), sp.exit_code).await?;
+                }
+                if process.pid != Some(sp.pid) {
+                    self.set_pid(process.name(), Some(sp.pid)).await?;
+                }
+                self.set_state(process.name(), state).await?;
+            } else {
+                if process.state.is_started() {
+                    self.add_event(Event::new(process.name(), EventKind::Exited))
+                        .await?;
+                }
+                if process.pid.is_some() {
+                    self.set_pid(process.name(), None).await?;
+                }
+                if process.is_task() && process.state.is_started() {
+                    self.set_state(process.name(), ProcessState::Done).await?;
+                } else if !matches!(
+                    process.state,
+                    ProcessState::CrashLooping | ProcessState::Done
+                ) {
+                    self.set_state(process.name(), ProcessState::Stopped)
+                        .await?;
+                }
             }
         }
 
-        if hard || self.needs_to_refresh_binaries().await? {
-            self.refresh_binaries(hard).await?;
-            self.set_binaries_updated_at(Utc::now()).await?;
-        }
+        self.refresh_binaries(hard).await?;
         if hard || self.needs_to_refresh_config().await? {
             self.refresh_processes().await?;
             self.refresh_stacks().await?;
             self.set_config_updated_at(Utc::now()).await?;
+            self.db.set_config_file(self.get_config_file()).await?;
         }
 
         Ok(())
     }
 
-    async fn needs_to_refresh_binaries(&self) -> Result<bool> {
-        let elapsed_since_last_update = self.get_elapsed_since_last_binaries_update().await?;
-        let files = ["./Cargo.toml", "./Cargo.lock"];
-        for file in files {
-            if Path::new(file).exists()
-                && File::open(file)?
-                    .metadata()?
-                    .modified()?
-                    .elapsed()?
-                    .as_secs()
-                    < elapsed_since_last_update
-            {
-                return Ok(true);
+    /// Remove finished tasks of the scheduler older than `older_than`, returning their IDs
+    ///
+    /// The tasks processes point at are kept, as logs of their last run are read from them.
+    pub async fn prune_tasks(&self, older_than: Duration) -> Result<Vec<Pid>> {
+        let kept: HashSet<Pid> = self
+            .get_processes()
+            .await?
+            .into_iter()
+            .filter_map(|process| process.pid)
+            .collect();
+        self.scheduler().prune(&kept, older_than).await
+    }
+
+    /// Age after which finished tasks are pruned when processes are started
+    pub fn get_tasks_max_age(&self) -> Result<Duration> {
+        Ok(ConfigFile::load(self.get_config_file())?
+            .and_then(|config| config.default)
+            .and_then(|default| default.tasks)
+            .and_then(|tasks| tasks.max_age)
+            .map_or(DEFAULT_TASKS_MAX_AGE, Duration::from_secs))
+    }
+
+    /// Track failed exits of given process, returning whether it is now crash looping
+    ///
+    /// A successful exit resets the count of consecutive failures.
+    async fn record_exit(&self, mut process: Process, exit_code: Option<i32>) -> Result<bool> {
+        let crash_looping = match exit_code {
+            Some(0) => {
+                process.failure_count = 0;
+                process.failures_since = None;
+                false
             }
+            Some(_) => process.record_failure(Utc::now()),
+            None => return Ok(false),
+        };
+        self.set_failures(
+            process.name(),
+            process.failure_count,
+            process.failures_since,
+        )
+        .await?;
+        if crash_looping {
+            warn!(
+                "Process {} failed {} times in a row, not restarting it",
+                process.name(),
+                process.failure_count
+            );
         }
-        Ok(false)
+        Ok(crash_looping)
     }
 
     async fn needs_to_refresh_config(&self) -> Result<bool> {
+        // Configuration previously loaded from another file is outdated whatever its age
+        if self.db.get_config_file().await?.as_deref() != Some(self.get_config_file()) {
+            return Ok(true);
+        }
         let elapsed_since_last_update = self.get_elapsed_since_last_config_update().await?;
-        let files = ["./jocker.yml", "./jocker.override.yml"];
+        let mut files = ConfigFile::files(self.get_config_file())?;
+        files.push(self.get_target_dir().join(CONFIG_OVERRIDE_FILE));
+        // Configuration values may be interpolated from it
+        files.extend(dotenv_file());
         for file in files {
-            if Path::new(file).exists()
-                && File::open(file)?
+            if file.exists()
+                && File::open(&file)?
                     .metadata()?
                     .modified()?
                     .elapsed()?
@@ -278,28 +831,69 @@ impl State {
             .collect())
     }
 
+    /// List binaries of the workspace again, unless its manifests didn't change since last time
+    ///
+    /// `cargo metadata` takes up to seconds on large workspaces, its result is kept in the database
+    /// along with the hash of the manifests it was read from. A hard refresh runs it whatever the
+    /// hash, as manifests of workspace members aren't part of it.
     async fn refresh_binaries(&self, hard: bool) -> Result<()> {
-        if !hard {
+        let hash = Cargo::manifests_hash(self.get_target_dir())?;
+        if !hard && hash == self.db.get_cargo_manifests_hash().await? {
             return Ok(());
         }
-        let binaries: Vec<BinaryPackage> = Self::fetch_bins(self.get_target_dir()).await?;
+        let binaries: Vec<BinaryPackage> = match hash {
+            None => vec![],
+            Some(_) => match Self::fetch_bins(self.get_target_dir()).await {
+                Ok(binaries) => binaries,
+                // Manifests being edited mustn't break every command, listing is retried next time
+                Err(e) if !hard => {
+                    warn!("Unable to list binaries of the workspace: {e}");
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            },
+        };
         self.set_binaries(&binaries).await?;
+        self.db.set_cargo_manifests_hash(&hash).await?;
+        self.set_binaries_updated_at(Utc::now()).await?;
         Ok(())
     }
 
     async fn refresh_processes(&self) -> Result<()> {
-        let previous_processes: HashMap<String, Process> = self
-            .get_processes()
-            .await?
-            .into_iter()
-            .map(|p| (p.name().to_string(), p))
-            .collect();
+        let previous_processes = self.get_processes().await?;
+        let previous_names: HashSet<String> =
+            previous_processes.iter().map(|p| p.name.clone()).collect();
+        let mut max_history_entries = DEFAULT_MAX_HISTORY_ENTRIES;
         let processes: Vec<Process> =
-            if let Some(jocker_config) = ConfigFile::load(self.get_target_dir())? {
+            if let Some(jocker_config) = ConfigFile::load(self.get_config_file())? {
                 let mut processes = vec![];
-                let process_defaults = jocker_config.default.and_then(|d| d.process);
+                let (process_defaults, history) = jocker_config
+                    .default
+                    .map(|d| (d.process, d.history))
+                    .unwrap_or_default();
+                if let Some(max_entries) = history.and_then(|h| h.max_entries) {
+                    max_history_entries = max_entries;
+                }
+                let secrets = jocker_config.secrets;
                 for config_process in jocker_config.processes {
+                    let secret_names = config_process.1.secrets.clone();
                     let mut process: Process = config_process.into();
+                    // Only where secrets are read from is stored, never their values
+                    for secret_name in secret_names {
+                        match secrets.get(&secret_name).cloned().map(Secret::try_from) {
+                            Some(Ok(secret)) => {
+                                process.secrets.insert(secret_name, secret);
+                            }
+                            Some(Err(_)) => warn!(
+                                "Secret {secret_name} of process {} is invalid, ignoring it",
+                                process.name()
+                            ),
+                            None => warn!(
+                                "Process {} uses unknown secret {secret_name}, ignoring it",
+                                process.name()
+                            ),
+                        }
+                    }
 
                     if let Some(ref process_defaults) = process_defaults {
                         process
@@ -316,24 +910,74 @@ impl State {
                     .map(|b| Process::new(b.name(), b.name()))
                     .collect()
             };
-        let processes: Vec<Process> = processes
+        // Processes scaled with `jocker scale` keep their number of instances
+        let scaled_replicas = self.db.get_scaled_replicas().await?;
+        let mut processes = processes;
+        for process in processes.iter_mut() {
+            if let Some(replicas) = scaled_replicas.get(process.base_name()) {
+                process.replicas = *replicas;
+            }
+        }
+        // Runtime state of processes already known is kept by the database
+        let mut processes: Vec<Process> = processes.iter().flat_map(Process::instances).collect();
+        let names: HashSet<String> = processes.iter().map(|p| p.name.clone()).collect();
+        // Processes removed from the configuration are stopped before being forgotten, the ones
+        // failing to stop are kept until the next refresh
+        for process in previous_processes
             .into_iter()
-            .map(|mut p| {
-                if let Some(previous_process) = previous_processes.get(p.name()) {
-                    p.pid = previous_process.pid;
-                    p.state = previous_process.state.clone();
-                };
-                p
-            })
-            .collect();
+            .filter(|p| !names.contains(&p.name) && p.state.is_started())
+        {
+            if let Err(e) = self.stop_removed(&process).await {
+                warn!("Unable to stop process {}, keeping it: {e}", process.name());
+                processes.push(process);
+            }
+        }
         self.set_processes(processes).await?;
 
+        let mut change = "reloaded".to_owned();
+        for (label, mut diff) in [
+            (
+                "added",
+                names.difference(&previous_names).collect::<Vec<_>>(),
+            ),
+            ("removed", previous_names.difference(&names).collect()),
+        ] {
+            if !diff.is_empty() {
+                diff.sort();
+                let diff: Vec<&str> = diff.into_iter().map(String::as_str).collect();
+                change.push_str(&format!(", {label} {}", diff.join(", ")));
+            }
+        }
+        self.db
+            .add_history(HistoryKind::Config, None, &change)
+            .await?;
+        self.db.prune_history(max_history_entries).await?;
+
         Ok(())
     }
 
+    /// Stop given process, removed from the configuration
+    async fn stop_removed(&self, process: &Process) -> Result<()> {
+        let Some(pid) = process.pid else {
+            return Ok(());
+        };
+        warn!(
+            "Process {} was removed from the configuration, stopping it",
+            process.name()
+        );
+        // A paused process wouldn't handle SIGTERM until it is resumed
+        if process.state == ProcessState::Paused {
+            self.scheduler().resume(pid).await?;
+        }
+        self.scheduler().stop(pid, false, None).await?;
+        sync_logs(self, process).await;
+        self.add_event(Event::new(process.name(), EventKind::Stopped))
+            .await
+    }
+
     async fn refresh_stacks(&self) -> Result<()> {
         let mut default_stack = None;
-        let stacks = if let Some(jocker_config) = ConfigFile::load(self.get_target_dir())? {
+        let stacks = if let Some(jocker_config) = ConfigFile::load(self.get_config_file())? {
             if let Some(config_default_stack) = jocker_config.default.and_then(|d| d.stack) {
                 default_stack = Some(config_default_stack);
             }
@@ -345,8 +989,14 @@ impl State {
                     stack_name.clone(),
                     Stack {
                         name: stack_name.clone(),
-                        processes: config_stack.processes,
+                        processes: config_stack
+                            .processes
+                            .difference(&config_stack.exclude)
+                            .cloned()
+                            .collect(),
                         inherited_processes: Default::default(),
+                        max_parallel: config_stack.max_parallel,
+                        user_created: false,
                     },
                 );
                 let inherited_processes = Self::recurse_inherited_processes(
@@ -355,7 +1005,10 @@ impl State {
                     &config_stacks,
                     &mut HashSet::new(),
                     HashSet::new(),
-                )?;
+                )?
+                .difference(&config_stack.exclude)
+                .cloned()
+                .collect();
                 stacks
                     .get_mut(&stack_name)
                     .ok_or_else(|| Error::new(InnerError::StackNotFound(stack_name.to_owned())))
@@ -375,6 +1028,12 @@ impl State {
         self.set_stacks(stacks.values().cloned().collect::<Vec<Stack>>().as_slice())
             .await?;
         self.set_default_stack(&default_stack).await?;
+        // A stack removed from the configuration can't stay selected
+        if let Some(selected_stack) = self.get_selected_stack().await? {
+            if self.get_stack(&selected_stack).await.is_err() {
+                self.set_selected_stack(&None).await?;
+            }
+        }
 
         Ok(())
     }
@@ -396,44 +1055,54 @@ impl State {
             let stack = stacks
                 .get(stack_name)
                 .ok_or_else(|| Error::new(InnerError::StackNotFound(stack_name.to_owned())))?;
-            inherited_processes.extend(stack.processes.clone().into_iter());
-            inherited_processes = Self::recurse_inherited_processes(
+            let processes = Self::recurse_inherited_processes(
                 recursion_level + 1,
                 &stack.inherits,
                 stacks,
                 browsed_stacks,
-                inherited_processes,
+                stack.processes.clone(),
             )?;
+            // Exclusions of an inherited stack hold for stacks inheriting it
+            inherited_processes.extend(processes.difference(&stack.exclude).cloned());
         }
         Ok(inherited_processes)
     }
 
-    fn get_project_id(target_dir: &PathBuf) -> String {
+    pub(crate) fn get_project_id(target_dir: &Path) -> String {
         let mut hasher = DefaultHasher::new();
         target_dir.hash(&mut hasher);
         format!("{:x}", hasher.finish())
     }
 
-    fn get_or_create_state_dir(target_dir: &PathBuf) -> Result<(String, String)> {
+    fn get_or_create_state_dir(target_dir: &Path) -> Result<(String, String)> {
         let (project_id, project_dir) = Self::get_or_create_project_dir(target_dir)?;
 
         Ok((project_id, project_dir.clone()))
     }
 
-    fn get_or_create_project_dir(target_dir: &PathBuf) -> Result<(String, String)> {
+    fn get_or_create_project_dir(target_dir: &Path) -> Result<(String, String)> {
         let project_id = Self::get_project_id(target_dir);
 
-        let home =
-            env::var("HOME").map_err(|e| Error::with_context(InnerError::Env(e.to_string()))(e))?;
-        let state_dir =
-            env::var("XDG_STATE_HOME").unwrap_or_else(|_| format!("{home}/.local/state"));
-
-        let project_dir = format!("{state_dir}/{JOCKER}/{project_id}");
-        let project_dir_path = Path::new(&project_dir);
+        let project_dir = Self::state_home()?.join(JOCKER).join(&project_id);
+        let project_dir_path = project_dir.as_path();
         if !project_dir_path.exists() {
-            create_dir_all(project_dir_path)
-                .map_err(Error::with_context(InnerError::Filesystem))?;
+            create_dir_all(project_dir_path).map_err(filesystem_error(project_dir_path))?;
         }
-        Ok((project_id, project_dir))
+        Ok((project_id, project_dir.to_string_lossy().into_owned()))
+    }
+
+    /// Directory holding the state of every project: `$XDG_STATE_HOME`, defaulting to
+    /// `~/.local/state`, and the local app data directory on Windows
+    pub(crate) fn state_home() -> Result<PathBuf> {
+        let state_home = if cfg!(windows) {
+            dirs::data_local_dir()
+        } else {
+            dirs::state_dir().or_else(|| dirs::home_dir().map(|home| home.join(".local/state")))
+        };
+        state_home.ok_or_else(|| {
+            Error::new(InnerError::Env(
+                "Unable to find the home directory".to_owned(),
+            ))
+        })
     }
 }
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::{
+    common::{BuildRecord, Exec},
+    error::Result,
+    state::State,
+};
+
+/// Number of builds listed by default
+pub const DEFAULT_STATS_BUILDS: u32 = 20;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct StatsBuildsArgs {
+    /// Only list builds covering given process
+    pub process: Option<String>,
+    /// Number of builds to list, the latest ones
+    pub limit: Option<u32>,
+}
+
+/// Aggregates of a list of builds
+#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+pub struct BuildsSummary {
+    pub count: usize,
+    pub failed: usize,
+    /// Mean duration of successful builds, in milliseconds
+    pub mean_duration_ms: Option<u64>,
+    /// Duration of the longest successful build, in milliseconds
+    pub max_duration_ms: Option<u64>,
+}
+
+impl BuildsSummary {
+    pub fn new(builds: &[BuildRecord]) -> Self {
+        let durations: Vec<u64> = builds
+            .iter()
+            .filter(|build| build.success)
+            .map(|build| build.duration_ms)
+            .collect();
+        Self {
+            count: builds.len(),
+            failed: builds.len() - durations.len(),
+            mean_duration_ms: (!durations.is_empty())
+                .then(|| durations.iter().sum::<u64>() / durations.len() as u64),
+            max_duration_ms: durations.iter().max().copied(),
+        }
+    }
+}
+
+/// Recent `cargo build` runs of the project, most recent first
+///
+/// Every build of processes sharing the same flags is recorded with its duration, the processes it
+/// covered and whether it succeeded, to follow how compile times evolve.
+pub struct StatsBuilds {
+    args: StatsBuildsArgs,
+    state: Arc<State>,
+}
+
+impl StatsBuilds {
+    pub fn new(args: StatsBuildsArgs, state: Arc<State>) -> Self {
+        StatsBuilds { args, state }
+    }
+
+    pub async fn run(&self) -> Result<Vec<BuildRecord>> {
+        self.state
+            .get_builds(
+                self.args.process.as_deref(),
+                self.args.limit.unwrap_or(DEFAULT_STATS_BUILDS),
+            )
+            .await
+    }
+}
+
+impl Exec<Vec<BuildRecord>> for StatsBuilds {
+    async fn exec(&self) -> Result<Vec<BuildRecord>> {
+        self.run().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn builds_summary() {
+        let build = |duration_ms, success| BuildRecord {
+            id: 0,
+            started_at: Utc::now(),
+            duration_ms,
+            processes: vec!["api".to_owned()],
+            profile: None,
+            compiled: 1,
+            success,
+        };
+        assert_eq!(
+            BuildsSummary::new(&[build(1000, true), build(200, false), build(3000, true)]),
+            BuildsSummary {
+                count: 3,
+                failed: 1,
+                mean_duration_ms: Some(2000),
+                max_duration_ms: Some(3000),
+            }
+        );
+        assert_eq!(BuildsSummary::new(&[]), BuildsSummary::default());
+    }
+}
@@ -0,0 +1,170 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    archive::LogArchive,
+    common::{Event, Exec, ProcessState},
+    error::{Error, InnerError, Result},
+    health::is_healthy,
+    resources::ResourceMonitor,
+    show::ShowFormat,
+    start::{is_secret, launch_env, SECRET_MASK},
+    state::State,
+    Pid,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct StatusArgs {
+    pub process: String,
+    pub format: ShowFormat,
+}
+
+/// Everything known about a process, gathered from the state, its scheduler and the OS
+#[derive(Debug, Serialize)]
+pub struct StatusOutput {
+    pub name: String,
+    pub kind: String,
+    pub state: String,
+    /// Configuration file the process is defined in
+    pub config_file: PathBuf,
+    /// Stack in use when the process was last started
+    pub stack: Option<String>,
+    pub binary: String,
+    pub package: Option<String>,
+    pub profile: Option<String>,
+    pub args: Vec<String>,
+    pub cargo_args: Vec<String>,
+    pub features: Vec<String>,
+    /// Command line of the current or last run, as launched
+    pub command: Option<String>,
+    /// Environment the process is launched with, secrets masked and not read from their sources
+    pub env: BTreeMap<String, String>,
+    /// Identifier of the process for its scheduler, such as a pueue task ID
+    pub scheduler_id: Option<Pid>,
+    /// PIDs of the OS processes of the current run, including children
+    pub pids: Vec<u32>,
+    pub started_at: Option<DateTime<Utc>>,
+    /// End of the last run, once the process stopped or exited
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub restart_count: u32,
+    pub exit_code: Option<i32>,
+    /// Whether the process passes its healthcheck, only known for running processes with one
+    pub healthy: Option<bool>,
+    /// Files logs of the process are written to
+    pub log_files: Vec<PathBuf>,
+    /// Lifecycle events of the process, oldest first
+    pub events: Vec<Event>,
+}
+
+/// Inspect a single process
+pub struct Status {
+    args: StatusArgs,
+    state: Arc<State>,
+}
+
+impl Status {
+    pub fn new(args: StatusArgs, state: Arc<State>) -> Self {
+        Status { args, state }
+    }
+
+    pub async fn run(&self) -> Result<StatusOutput> {
+        let mut processes = self
+            .state
+            .filter_processes(&[self.args.process.clone()])
+            .await?;
+        let process = match processes.iter().position(|p| p.name() == self.args.process) {
+            Some(idx) => processes.remove(idx),
+            None if processes.len() == 1 => processes.remove(0),
+            None => {
+                let names: Vec<&str> = processes.iter().map(|p| p.name()).collect();
+                return Err(Error::new(InnerError::Parse(format!(
+                    "{} matches several processes: {}",
+                    self.args.process,
+                    names.join(", ")
+                ))));
+            }
+        };
+
+        let env = launch_env(&self.state, &process)
+            .into_iter()
+            .map(|(key, value)| {
+                let value = if is_secret(&key) {
+                    SECRET_MASK.to_owned()
+                } else {
+                    value
+                };
+                (key, value)
+            })
+            .chain(
+                process
+                    .secrets
+                    .keys()
+                    .map(|name| (name.to_owned(), SECRET_MASK.to_owned())),
+            )
+            .collect();
+        let running = process.state == ProcessState::Running;
+        let pids = if running {
+            ResourceMonitor::new()
+                .pids(self.state.scheduler_group())
+                .remove(process.name())
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        let healthy = match process.healthcheck {
+            Some(_) if running => Some(is_healthy(&self.state, &process).await),
+            _ => None,
+        };
+        let mut log_files: Vec<PathBuf> = self
+            .state
+            .scheduler()
+            .log_file(process.name())
+            .into_iter()
+            .collect();
+        log_files.push(LogArchive::new(&self.state)?.current_path(process.name()));
+        let events = self
+            .state
+            .get_events(None)
+            .await?
+            .into_iter()
+            .filter(|event| event.process_name == process.name())
+            .collect();
+
+        Ok(StatusOutput {
+            name: process.name.clone(),
+            kind: process.kind.to_string(),
+            state: process.state.to_string(),
+            config_file: self.state.get_config_file().to_path_buf(),
+            stack: process.stack,
+            binary: process.binary,
+            package: process.package,
+            profile: process.profile,
+            args: process.args,
+            cargo_args: process.cargo_args,
+            features: process.features,
+            command: process.command_line,
+            env,
+            scheduler_id: process.pid,
+            pids,
+            started_at: process.started_at,
+            stopped_at: process.stopped_at,
+            restart_count: process.restart_count,
+            exit_code: process.exit_code,
+            healthy,
+            log_files,
+            events,
+        })
+    }
+}
+
+impl Exec<String> for Status {
+    async fn exec(&self) -> Result<String> {
+        let status = self.run().await?;
+        Ok(match self.args.format {
+            ShowFormat::Yaml => serde_yml::to_string(&status)?,
+            ShowFormat::Json => serde_json::to_string_pretty(&status)?,
+        })
+    }
+}
@@ -1,19 +1,64 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
 use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    common::{Exec, Process, ProcessState},
-    error::Result,
+    archive::sync_logs,
+    command::util::terminate,
+    common::{Event, EventKind, Exec, JockerEvent, Process, ProcessState},
+    error::{Error, InnerError, Result},
+    scheduler::ScheduledProcess,
+    start::launch_waves,
     state::State,
+    Pid,
 };
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct StopArgs {
     pub kill: bool,
+    pub timeout: Option<u64>,
+    /// Seconds to wait between stopping a wave of dependents and their dependencies
+    pub delay: Option<u64>,
+    /// Stop every process of the project, whatever the stack, including those missing from the
+    /// configuration but still scheduled
+    pub all_stacks: bool,
     pub processes: Vec<String>,
+    /// Only keep processes having all of these labels, as `(key, value)`
+    pub labels: Vec<(String, String)>,
+    /// Only plan what would be stopped, without doing it
+    pub dry_run: bool,
 }
 
+/// Processes [`Stop`] would stop with `dry_run`, in order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StopPlan {
+    pub kill: bool,
+    pub timeout: Option<u64>,
+    /// Seconds waited between two waves
+    pub delay: Option<u64>,
+    /// Waves of processes, dependents before their dependencies
+    pub waves: Vec<Vec<PlannedStop>>,
+    /// Scheduled processes missing from the configuration, as `(name, pid)`
+    pub unknown: Vec<(String, Pid)>,
+}
+
+/// Process of a [`StopPlan`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlannedStop {
+    AlreadyStopped(String),
+    Signal(String, Pid),
+    /// Process with nothing left to stop, only its state would change
+    MarkStopped(String),
+}
+
+/// Longest wait for an external process to exit after SIGTERM, before sending it SIGKILL
+const EXTERNAL_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stop processes, dependents before their dependencies
+///
+/// A process failing to stop doesn't prevent the other ones from being stopped, the first failure
+/// is returned once they all were, so that its exit status tells what went wrong.
 pub struct Stop {
     args: StopArgs,
     state: Arc<State>,
@@ -23,46 +68,204 @@ impl Stop {
     pub fn new(args: StopArgs, state: Arc<State>) -> Self {
         Stop { args, state }
     }
+
+    /// Running scheduled processes of the project not known from the current configuration
+    async fn unknown_processes(
+        &self,
+        known_processes: &HashSet<String>,
+    ) -> Result<Vec<(String, ScheduledProcess)>> {
+        let mut scheduled: Vec<(String, ScheduledProcess)> = self
+            .state
+            .scheduler()
+            .status()
+            .await?
+            .into_iter()
+            .filter(|(name, sp)| sp.state.is_started() && !known_processes.contains(name))
+            .collect();
+        scheduled.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(scheduled)
+    }
+
+    /// Stop scheduled processes of the project not known from the current configuration
+    async fn stop_unknown(&self, known_processes: &HashSet<String>) -> Result<()> {
+        for (process_name, sp) in self.unknown_processes(known_processes).await? {
+            info!("Stopping unknown process {process_name} ...");
+            if sp.state == ProcessState::Paused {
+                self.state.scheduler().resume(sp.pid).await?;
+            }
+            self.state
+                .scheduler()
+                .stop(
+                    sp.pid,
+                    self.args.kill,
+                    self.args.timeout.map(Duration::from_secs),
+                )
+                .await?;
+            self.state.emit(JockerEvent::ProcessStopped {
+                process_name: process_name.clone(),
+            });
+            info!("Process {process_name} stopped");
+        }
+        Ok(())
+    }
 }
 
-impl Exec<()> for Stop {
-    async fn exec(&self) -> Result<()> {
-        let processes = self.state.filter_processes(&self.args.processes).await?;
-        let mut handles = JoinSet::new();
-        for process in processes {
-            let state = self.state.clone();
-            handles.spawn(run(state, process, self.args.clone()));
+impl Stop {
+    /// How given waves would be stopped, without changing anything
+    async fn dry_run(
+        &self,
+        waves: Vec<Vec<Process>>,
+        known_processes: &HashSet<String>,
+    ) -> Result<StopPlan> {
+        let waves = waves
+            .into_iter()
+            .rev()
+            .map(|wave| {
+                wave.into_iter()
+                    .map(|process| match process.pid {
+                        _ if process.state.is_stopped() => {
+                            PlannedStop::AlreadyStopped(process.name)
+                        }
+                        Some(pid) if process.state != ProcessState::CrashLooping => {
+                            PlannedStop::Signal(process.name, pid)
+                        }
+                        _ => PlannedStop::MarkStopped(process.name),
+                    })
+                    .collect()
+            })
+            .collect();
+        let unknown = if self.args.all_stacks {
+            self.unknown_processes(known_processes)
+                .await?
+                .into_iter()
+                .map(|(process_name, sp)| (process_name, sp.pid))
+                .collect()
+        } else {
+            vec![]
+        };
+        Ok(StopPlan {
+            kill: self.args.kill,
+            timeout: self.args.timeout,
+            delay: self.args.delay,
+            waves,
+            unknown,
+        })
+    }
+}
+
+/// Returns the plan of a dry run, `None` when processes were actually stopped
+impl Exec<Option<StopPlan>> for Stop {
+    async fn exec(&self) -> Result<Option<StopPlan>> {
+        let mut processes = if self.args.all_stacks {
+            self.state.get_processes().await?
+        } else {
+            self.state.filter_processes(&self.args.processes).await?
+        };
+        processes.retain(|p| p.has_labels(&self.args.labels));
+        let known_processes: HashSet<String> =
+            processes.iter().map(|p| p.name().to_string()).collect();
+        // Processes must be stoppable whatever their configuration, a dependency loop included
+        let waves = match launch_waves(processes.clone()) {
+            Ok(waves) => waves,
+            Err(e) => {
+                warn!("{}, stopping processes regardless of their dependencies", e.message());
+                vec![processes]
+            }
+        };
+        if self.args.dry_run {
+            return self.dry_run(waves, &known_processes).await.map(Some);
         }
+        let _lock = self.state.lock()?;
+        // Every process is stopped, the first error is reported along with the failed processes
+        let mut failed = vec![];
+        let mut first_error = None;
+        for (i, wave) in waves.into_iter().rev().enumerate() {
+            if i > 0 {
+                if let Some(delay) = self.args.delay {
+                    tokio::time::sleep(Duration::from_secs(delay)).await;
+                }
+            }
+            let mut handles = JoinSet::new();
+            for process in wave {
+                let state = self.state.clone();
+                let args = self.args.clone();
+                handles.spawn(async move {
+                    let process_name = process.name().to_owned();
+                    (process_name, run(state, process, args).await)
+                });
+            }
 
-        while let Some(res) = handles.join_next().await {
-            match res {
-                Err(e) => println!("Error while stopping process: {e}"),
-                Ok(ok) => {
-                    if let Err(ee) = ok {
-                        println!("Error while stopping process inner: {ee}")
+            while let Some(res) = handles.join_next().await {
+                let e = match res {
+                    Ok((_, Ok(()))) => continue,
+                    Ok((process_name, Err(e))) => {
+                        error!("Unable to stop process {process_name}: {e}");
+                        failed.push(process_name);
+                        e
                     }
-                }
+                    Err(e) => {
+                        error!("Error while stopping process: {e}");
+                        Error::new(InnerError::Scheduler(e.to_string()))
+                    }
+                };
+                first_error.get_or_insert(e);
             }
         }
 
-        Ok(())
+        if self.args.all_stacks {
+            self.stop_unknown(&known_processes).await?;
+        }
+        if let Some(mut e) = first_error {
+            if !failed.is_empty() {
+                failed.sort();
+                e = e.add_context(format!("Processes failed to stop: {}", failed.join(", ")));
+            }
+            return Err(e);
+        }
+        Ok(None)
     }
 }
 
-async fn run(state: Arc<State>, process: Process, args: StopArgs) -> Result<()> {
+pub(crate) async fn run(state: Arc<State>, process: Process, args: StopArgs) -> Result<()> {
     let process_name = process.name().to_string();
-    if process.state == ProcessState::Stopped {
-        println!("Process is already stopped: {process_name}");
+    if process.state.is_stopped() {
+        info!("Process is already stopped: {process_name}");
         return Ok(());
     }
-    if let Some(pid) = process.pid {
-        println!("Stopping process {process_name} ...");
-        state.scheduler().stop(pid, args.kill).await?;
+    match process.pid {
+        // Adopted processes aren't scheduled, they are signalled directly
+        Some(pid) if process.state == ProcessState::External => {
+            info!("Stopping external process {process_name} (pid {pid}) ...");
+            let timeout = args.timeout.map_or(EXTERNAL_STOP_TIMEOUT, Duration::from_secs);
+            terminate(pid, args.kill, timeout).await?;
+        }
+        // A crash looping process already exited, there is nothing left to stop
+        Some(pid) if process.state != ProcessState::CrashLooping => {
+            info!("Stopping process {process_name} ...");
+            // A paused process wouldn't handle SIGTERM until it is resumed
+            if process.state == ProcessState::Paused {
+                state.scheduler().resume(pid).await?;
+            }
+            debug!(process = %process_name, pid, kill = args.kill, "Sending stop signal");
+            state
+                .scheduler()
+                .stop(pid, args.kill, args.timeout.map(Duration::from_secs))
+                .await?;
+            sync_logs(&state, &process).await;
+        }
+        _ => {}
     }
     state
         .set_state(&process_name, ProcessState::Stopped)
         .await?;
     state.set_pid(&process_name, None).await?;
-    println!("Process {process_name} stopped");
+    state.set_failures(&process_name, 0, None).await?;
+    state
+        .add_event(Event::new(&process_name, EventKind::Stopped))
+        .await?;
+    state.emit(JockerEvent::ProcessStopped {
+        process_name: process_name.clone(),
+    });
+    info!("Process {process_name} stopped");
     Ok(())
 }
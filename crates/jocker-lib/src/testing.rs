@@ -0,0 +1,451 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::Sender;
+use tracing::error;
+
+use crate::{
+    command::util::send_line,
+    common::{Exec, Process, ProcessState},
+    error::{lock_error, Error, InnerError, Result},
+    handle::ProcessHandle,
+    scheduler::{ScheduledProcess, Scheduler},
+    signal::ProcessSignal,
+    start::{Start, StartArgs},
+    state::{State, StateBuilder},
+    stop::{Stop, StopArgs},
+    Pid,
+};
+
+/// Options of a [`TestStack`], built with [`TestStack::builder`]
+pub struct TestStackBuilder {
+    state: StateBuilder,
+    processes: Vec<String>,
+    timeout: Option<Duration>,
+}
+
+impl TestStackBuilder {
+    /// Processes to start along with their dependencies, every process of the stack by default
+    pub fn processes(mut self, processes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.processes = processes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Maximum duration of the wait for processes to be running and healthy, 60 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Start processes and wait for them to be healthy
+    ///
+    /// Processes started before a failure are stopped before returning it.
+    pub async fn start(self) -> Result<TestStack> {
+        let state = Arc::new(self.state.build().await?);
+        let already_started = started_processes(&state).await?;
+        let result = Start::new(
+            StartArgs {
+                processes: self.processes,
+                wait: true,
+                timeout: self.timeout.map(|timeout| timeout.as_secs().max(1)),
+                ..Default::default()
+            },
+            state.clone(),
+        )
+        .exec()
+        .await;
+        state.refresh(false).await?;
+        let mut started: Vec<String> = started_processes(&state)
+            .await?
+            .difference(&already_started)
+            .cloned()
+            .collect();
+        started.sort();
+        let stack = TestStack {
+            state,
+            started,
+            stopped: false,
+        };
+        result.map(|_| stack)
+    }
+}
+
+/// Processes started for integration tests, stopped once the guard is dropped
+///
+/// Meant for `#[tokio::test]` tests of services of the same workspace: build the state of the
+/// project as usual, for instance targeting the workspace root and a dedicated stack, start it,
+/// then reach services through their [`ports`](Self::ports) and [`env`](Self::env). Processes
+/// which were already running are left as is.
+///
+/// Dropping the guard stops processes from a runtime of its own, as the one of the test may be
+/// single-threaded, through the default scheduler. Call [`stop`](Self::stop) to go through the
+/// scheduler of the state instead, and to get errors back.
+pub struct TestStack {
+    state: Arc<State>,
+    /// Processes started by this stack, which are stopped along with it
+    started: Vec<String>,
+    stopped: bool,
+}
+
+impl TestStack {
+    /// Stack whose state is configured by given builder
+    pub fn builder(state: StateBuilder) -> TestStackBuilder {
+        TestStackBuilder {
+            state,
+            processes: Vec::new(),
+            timeout: None,
+        }
+    }
+
+    pub fn state(&self) -> &Arc<State> {
+        &self.state
+    }
+
+    /// Names of the processes started by this stack, dependencies included
+    pub fn started(&self) -> &[String] {
+        &self.started
+    }
+
+    /// Handle controlling the process with given name
+    pub fn process(&self, name: impl Into<String>) -> ProcessHandle {
+        self.state.process(name)
+    }
+
+    /// TCP ports given process listens on, as configured
+    pub async fn ports(&self, name: &str) -> Result<Vec<u16>> {
+        Ok(self.get_process(name).await?.ports)
+    }
+
+    /// Environment given process was launched with, secrets masked
+    pub async fn env(&self, name: &str) -> Result<HashMap<String, String>> {
+        Ok(self
+            .get_process(name)
+            .await?
+            .launched_env
+            .into_iter()
+            .map(|(key, var)| (key, var.value))
+            .collect())
+    }
+
+    /// Stop processes started by this stack
+    pub async fn stop(mut self) -> Result<()> {
+        self.stopped = true;
+        stop_processes(&self.state, std::mem::take(&mut self.started)).await
+    }
+
+    async fn get_process(&self, name: &str) -> Result<Process> {
+        self.state
+            .get_processes()
+            .await?
+            .into_iter()
+            .find(|process| process.name() == name)
+            .ok_or_else(|| Error::new(InnerError::ProcessNotFound(vec![name.to_owned()])))
+    }
+}
+
+impl Drop for TestStack {
+    fn drop(&mut self) {
+        if self.stopped || self.started.is_empty() {
+            return;
+        }
+        let target_dir = self.state.get_target_dir().to_path_buf();
+        let config_file = self.state.get_config_file().to_path_buf();
+        let started = std::mem::take(&mut self.started);
+        let teardown = std::thread::spawn(move || -> Result<()> {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(async {
+                let mut builder = State::builder().target_dir(target_dir);
+                if config_file.exists() {
+                    builder = builder.config_file(config_file);
+                }
+                let state = Arc::new(builder.build().await?);
+                stop_processes(&state, started).await
+            })
+        });
+        match teardown.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Unable to stop processes of the test stack: {e}"),
+            Err(_) => error!("Unable to stop processes of the test stack: teardown panicked"),
+        }
+    }
+}
+
+/// Names of the processes of the project which are started
+async fn started_processes(state: &State) -> Result<HashSet<String>> {
+    Ok(state
+        .get_processes()
+        .await?
+        .into_iter()
+        .filter(|process| process.state.is_started())
+        .map(|process| process.name)
+        .collect())
+}
+
+async fn stop_processes(state: &Arc<State>, processes: Vec<String>) -> Result<()> {
+    if processes.is_empty() {
+        return Ok(());
+    }
+    Stop::new(
+        StopArgs {
+            processes,
+            ..Default::default()
+        },
+        state.clone(),
+    )
+    .exec()
+    .await?;
+    Ok(())
+}
+
+/// In-memory [`Scheduler`] spawning nothing, to test commands without running processes
+///
+/// Inject a clone of it with [`StateBuilder::scheduler`], clones sharing the same processes. A
+/// started process runs until it is stopped or made to [`exit`](Self::exit), its only log line
+/// being the command it was started with.
+#[derive(Clone, Debug, Default)]
+pub struct MockScheduler {
+    processes: Arc<Mutex<MockProcesses>>,
+}
+
+#[derive(Debug, Default)]
+struct MockProcesses {
+    last_pid: Pid,
+    by_name: HashMap<String, MockProcess>,
+}
+
+#[derive(Debug)]
+struct MockProcess {
+    scheduled: ScheduledProcess,
+    command: String,
+    signals: Vec<ProcessSignal>,
+}
+
+impl MockScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make given process exit by itself with given code
+    pub fn exit(&self, process_name: &str, exit_code: i32) -> Result<()> {
+        self.update(process_name, |process| {
+            process.scheduled.state = ProcessState::Stopped;
+            process.scheduled.exit_code = Some(exit_code);
+        })
+    }
+
+    /// Command given process was last started with
+    pub fn command(&self, process_name: &str) -> Result<Option<String>> {
+        let processes = self.processes.lock().map_err(lock_error)?;
+        Ok(processes
+            .by_name
+            .get(process_name)
+            .map(|process| process.command.clone()))
+    }
+
+    /// Signals sent to given process since it was last started, stopping ones included
+    pub fn signals(&self, process_name: &str) -> Result<Vec<ProcessSignal>> {
+        let processes = self.processes.lock().map_err(lock_error)?;
+        Ok(processes
+            .by_name
+            .get(process_name)
+            .map(|process| process.signals.clone())
+            .unwrap_or_default())
+    }
+
+    fn update(&self, process_name: &str, change: impl FnOnce(&mut MockProcess)) -> Result<()> {
+        let mut processes = self.processes.lock().map_err(lock_error)?;
+        let process = processes.by_name.get_mut(process_name).ok_or_else(|| {
+            Error::new(InnerError::ProcessNotFound(vec![process_name.to_owned()]))
+        })?;
+        change(process);
+        Ok(())
+    }
+
+    /// Apply `change` to the process with given pid, failing if it isn't running
+    fn update_running(
+        &self,
+        pid: Pid,
+        signal: ProcessSignal,
+        change: impl FnOnce(&mut ScheduledProcess),
+    ) -> Result<()> {
+        let mut processes = self.processes.lock().map_err(lock_error)?;
+        let process = processes
+            .by_name
+            .values_mut()
+            .find(|process| process.scheduled.pid == pid)
+            .ok_or_else(|| Error::new(InnerError::ProcessNotFound(vec![pid.to_string()])))?;
+        if !process.scheduled.state.is_started() {
+            return Err(Error::new(InnerError::ProcessNotRunning(format!(
+                "Process {pid} is not running, not sending SIG{signal} to it"
+            ))));
+        }
+        process.signals.push(signal);
+        change(&mut process.scheduled);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Scheduler for MockScheduler {
+    fn group(&self) -> &str {
+        "jocker-mock"
+    }
+
+    fn describe_start(&self, _process_name: &str, command: &str) -> String {
+        command.to_owned()
+    }
+
+    async fn start(
+        &self,
+        process_name: String,
+        command: String,
+        _path: PathBuf,
+        _envs: HashMap<String, String>,
+    ) -> Result<Pid> {
+        let mut processes = self.processes.lock().map_err(lock_error)?;
+        processes.last_pid += 1;
+        let pid = processes.last_pid;
+        processes.by_name.insert(
+            process_name,
+            MockProcess {
+                scheduled: ScheduledProcess {
+                    pid,
+                    state: ProcessState::Running,
+                    exit_code: None,
+                },
+                command,
+                signals: Vec::new(),
+            },
+        );
+        Ok(pid)
+    }
+
+    async fn stop(&self, pid: Pid, kill: bool, _timeout: Option<Duration>) -> Result<()> {
+        let signal = if kill {
+            ProcessSignal::Kill
+        } else {
+            ProcessSignal::Term
+        };
+        match self.update_running(pid, signal, |process| {
+            process.state = ProcessState::Stopped;
+        }) {
+            Err(e) if matches!(e.inner_error, InnerError::ProcessNotRunning(_)) => Ok(()),
+            res => res,
+        }
+    }
+
+    async fn signal(&self, pid: Pid, signal: ProcessSignal) -> Result<()> {
+        self.update_running(pid, signal, |_| {})
+    }
+
+    async fn pause(&self, pid: Pid) -> Result<()> {
+        self.update_running(pid, ProcessSignal::Stop, |process| {
+            process.state = ProcessState::Paused;
+        })
+    }
+
+    async fn resume(&self, pid: Pid) -> Result<()> {
+        self.update_running(pid, ProcessSignal::Cont, |process| {
+            process.state = ProcessState::Running;
+        })
+    }
+
+    async fn status(&self) -> Result<HashMap<String, ScheduledProcess>> {
+        let processes = self.processes.lock().map_err(lock_error)?;
+        Ok(processes
+            .by_name
+            .iter()
+            .map(|(name, process)| (name.to_owned(), process.scheduled.clone()))
+            .collect())
+    }
+
+    async fn logs(
+        &self,
+        log_tx: Sender<String>,
+        pid: Pid,
+        _lines: Option<usize>,
+        _follow: bool,
+    ) -> Result<()> {
+        let command = {
+            let processes = self.processes.lock().map_err(lock_error)?;
+            processes
+                .by_name
+                .values()
+                .find(|process| process.scheduled.pid == pid)
+                .map(|process| process.command.clone())
+                .ok_or_else(|| Error::new(InnerError::ProcessNotFound(vec![pid.to_string()])))?
+        };
+        send_line(&log_tx, &command).await
+    }
+
+    async fn clean(&self) -> Result<()> {
+        self.processes.lock().map_err(lock_error)?.by_name.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn mock_scheduler() {
+        let scheduler = MockScheduler::new();
+        let injected: Box<dyn Scheduler> = Box::new(scheduler.clone());
+        let api = scheduler
+            .start(
+                "api".to_owned(),
+                "./api --port 80".to_owned(),
+                PathBuf::from("."),
+                HashMap::new(),
+            )
+            .await
+            .unwrap();
+        // Processes started through the injected clone are seen by the original one
+        let worker = injected
+            .start(
+                "worker".to_owned(),
+                "./worker --port 80".to_owned(),
+                PathBuf::from("."),
+                HashMap::new(),
+            )
+            .await
+            .unwrap();
+        assert_ne!(api, worker);
+        assert_eq!(scheduler.command("api").unwrap().as_deref(), Some("./api --port 80"));
+
+        scheduler.pause(api).await.unwrap();
+        scheduler.signal(worker, ProcessSignal::Hup).await.unwrap();
+        scheduler.exit("worker", 1).unwrap();
+        let status = scheduler.status().await.unwrap();
+        assert_eq!(status["api"].state, ProcessState::Paused);
+        assert_eq!(status["worker"].state, ProcessState::Stopped);
+        assert_eq!(status["worker"].exit_code, Some(1));
+        assert!(scheduler.signal(worker, ProcessSignal::Hup).await.is_err());
+
+        // Stopping a process which already exited is fine
+        scheduler.stop(worker, false, None).await.unwrap();
+        scheduler.stop(api, false, None).await.unwrap();
+        assert_eq!(
+            scheduler.signals("api").unwrap(),
+            vec![ProcessSignal::Stop, ProcessSignal::Term]
+        );
+        assert_eq!(scheduler.signals("worker").unwrap(), vec![ProcessSignal::Hup]);
+
+        let (tx, mut rx) = mpsc::channel(8);
+        scheduler.logs(tx, api, None, false).await.unwrap();
+        assert_eq!(rx.recv().await.as_deref(), Some("./api --port 80"));
+
+        scheduler.clean().await.unwrap();
+        assert!(scheduler.status().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use tokio::process::Command;
+use tracing::info;
+
+use crate::{
+    command::util::shell_quote,
+    common::Exec,
+    error::{Error, InnerError, Result},
+    state::State,
+    JOCKER,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct TmuxArgs {
+    pub processes: Vec<String>,
+}
+
+/// Follow logs of processes in a tmux session, one pane per process
+///
+/// The session is named after the current stack, and is only attached to again if it already
+/// exists. Panes run `jocker logs`, so closing them leaves processes running.
+pub struct Tmux {
+    args: TmuxArgs,
+    state: Arc<State>,
+}
+
+impl Tmux {
+    pub fn new(args: TmuxArgs, state: Arc<State>) -> Self {
+        Tmux { args, state }
+    }
+
+    /// Command following logs of given process, whatever the directory it is run from
+    fn logs_command(&self, process_name: &str) -> Result<String> {
+        let jocker = std::env::current_exe()?;
+        let mut command = vec![
+            shell_quote(&jocker.to_string_lossy()),
+            "--target-directory".to_owned(),
+            shell_quote(&self.state.get_target_dir().to_string_lossy()),
+        ];
+        let config_file = self.state.get_config_file();
+        if config_file.exists() {
+            command.push("--file".to_owned());
+            command.push(shell_quote(&config_file.to_string_lossy()));
+        }
+        command.extend(["logs", "-f", process_name].map(str::to_owned));
+        Ok(command.join(" "))
+    }
+}
+
+impl Exec<()> for Tmux {
+    async fn exec(&self) -> Result<()> {
+        let session = session_name(self.state.get_current_stack()?.as_deref());
+        if tmux(&["has-session", "-t", &format!("={session}")])
+            .await
+            .is_err()
+        {
+            let processes = self.state.filter_processes(&self.args.processes).await?;
+            if processes.is_empty() {
+                return Err(Error::new(InnerError::Tmux("No process to follow".to_owned())));
+            }
+            let directory = self.state.get_target_dir().to_string_lossy().to_string();
+            for (idx, process) in processes.iter().enumerate() {
+                let command = self.logs_command(process.name())?;
+                if idx == 0 {
+                    tmux(&["new-session", "-d", "-s", &session, "-c", &directory, &command]).await?;
+                } else {
+                    tmux(&["split-window", "-t", &session, "-c", &directory, &command]).await?;
+                }
+                tmux(&["select-pane", "-t", &session, "-T", process.name()]).await?;
+                // Panes are retiled after each split, so that there is room for the next one
+                tmux(&["select-layout", "-t", &session, "tiled"]).await?;
+            }
+            tmux(&["set-option", "-t", &session, "pane-border-status", "top"]).await?;
+            info!("Created tmux session {session}");
+        }
+        // Inside tmux, attaching would nest sessions
+        let attach = if std::env::var_os("TMUX").is_some() {
+            "switch-client"
+        } else {
+            "attach-session"
+        };
+        let status = Command::new("tmux")
+            .args([attach, "-t", &session])
+            .status()
+            .await
+            .map_err(tmux_error)?;
+        if !status.success() {
+            return Err(Error::new(InnerError::Tmux(format!(
+                "Unable to attach to session {session}"
+            ))));
+        }
+        Ok(())
+    }
+}
+
+/// Name of the session of given stack, without the characters tmux doesn't allow
+fn session_name(stack: Option<&str>) -> String {
+    let name = match stack {
+        Some(stack) => format!("{JOCKER}-{stack}"),
+        None => JOCKER.to_owned(),
+    };
+    name.replace(['.', ':'], "_")
+}
+
+/// Run tmux with given arguments, failing with its error output
+async fn tmux(args: &[&str]) -> Result<()> {
+    let output = Command::new("tmux")
+        .args(args)
+        .output()
+        .await
+        .map_err(tmux_error)?;
+    if !output.status.success() {
+        return Err(Error::new(InnerError::Tmux(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        )));
+    }
+    Ok(())
+}
+
+fn tmux_error(e: std::io::Error) -> Error {
+    Error::new(InnerError::Tmux(format!("Unable to run tmux: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_names() {
+        assert_eq!(session_name(None), "jocker");
+        assert_eq!(session_name(Some("full")), "jocker-full");
+        assert_eq!(session_name(Some("v1.2:api")), "jocker-v1_2_api");
+    }
+}
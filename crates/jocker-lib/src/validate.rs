@@ -0,0 +1,863 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    common::Exec,
+    config::{
+        find_config_file, ConfigDependencyCondition, ConfigFile, ConfigFormat, ConfigProcessKind,
+        CONFIG_OVERRIDE_FILE,
+    },
+    error::Result,
+    limits::parse_memory,
+};
+
+/// Issue found in a configuration file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    /// Line and column of the issue, both starting at 1, if it could be located
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some((line, column)) = self.location {
+            write!(f, ":{line}:{column}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ValidateArgs {
+    pub target_dir: PathBuf,
+    /// Configuration file to check instead of `jocker.yml` of the target directory
+    pub config_file: Option<PathBuf>,
+}
+
+/// Check configuration files, without loading them into the state
+///
+/// Works on files directly, as an invalid configuration prevents building a [`State`].
+///
+/// [`State`]: crate::state::State
+pub struct Validate {
+    args: ValidateArgs,
+}
+
+impl Validate {
+    pub fn new(args: ValidateArgs) -> Self {
+        Validate { args }
+    }
+}
+
+impl Exec<Vec<Diagnostic>> for Validate {
+    async fn exec(&self) -> Result<Vec<Diagnostic>> {
+        let mut diagnostics = Vec::new();
+        if let Some(config_file) = self.args.config_file.as_ref().filter(|f| !f.exists()) {
+            diagnostics.push(Diagnostic {
+                file: config_file.clone(),
+                location: None,
+                message: "Configuration file does not exist".to_owned(),
+            });
+        }
+        let config_file = self
+            .args
+            .config_file
+            .clone()
+            .unwrap_or_else(|| find_config_file(&self.args.target_dir));
+        let mut files = Vec::new();
+        for path in [config_file, self.args.target_dir.join(CONFIG_OVERRIDE_FILE)] {
+            if path.exists() {
+                let content = std::fs::read_to_string(&path)?;
+                files.push((path, content));
+            }
+        }
+        let files: Vec<(&Path, &str)> = files
+            .iter()
+            .map(|(path, content)| (path.as_path(), content.as_str()))
+            .collect();
+        diagnostics.extend(validate_files(&files));
+        Ok(diagnostics)
+    }
+}
+
+/// Check syntax, schema and references of given configuration
+pub fn validate(path: &Path, content: &str) -> Vec<Diagnostic> {
+    validate_files(&[(path, content)])
+}
+
+/// Check given configuration files as a whole, stacks, processes and secrets of a file overriding
+/// the ones of the files before it
+///
+/// References may target entries of any file, an issue is reported in the file defining the entry
+/// it is about.
+pub fn validate_files(files: &[(&Path, &str)]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut sources = Vec::new();
+    let mut configs = Vec::new();
+    for &(path, content) in files {
+        let (value, mut config) = match parse(path, content) {
+            Ok(parsed) => parsed,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+        let source = Source {
+            path,
+            content,
+            value,
+            default_stack: false,
+            stacks: HashSet::new(),
+            processes: HashSet::new(),
+            secrets: HashSet::new(),
+        };
+        // References may target entries of included files
+        if let Err(e) = config.merge_included_files(path) {
+            diagnostics.push(source.diagnostic(
+                &[],
+                "include",
+                format!("Unable to include configuration files: {e}"),
+            ));
+        }
+        sources.push(Source {
+            default_stack: config.default.as_ref().is_some_and(|d| d.stack.is_some()),
+            stacks: config.stacks.keys().cloned().collect(),
+            processes: config.processes.keys().cloned().collect(),
+            secrets: config.secrets.keys().cloned().collect(),
+            ..source
+        });
+        configs.push(config);
+    }
+    let mut config = ConfigFile::default();
+    for file_config in configs.into_iter().rev() {
+        config.merge(file_config);
+    }
+    let Some(last) = sources.last() else {
+        return diagnostics;
+    };
+    // File an entry of the merged configuration comes from
+    let owner = |defines: &dyn Fn(&Source) -> bool| {
+        sources.iter().rev().find(|s| defines(s)).unwrap_or(last)
+    };
+
+    if let Some(stack) = config.default.as_ref().and_then(|d| d.stack.as_ref()) {
+        if !config.stacks.contains_key(stack) {
+            let source = owner(&|s| s.default_stack);
+            diagnostics.push(source.diagnostic(
+                &["default"],
+                stack,
+                format!("Default stack {stack} is not defined"),
+            ));
+        }
+    }
+    let process_names: HashSet<&String> = config.processes.keys().collect();
+    let stack_names: HashSet<&String> = config.stacks.keys().collect();
+    let mut sorted_stack_names: Vec<&String> = config.stacks.keys().collect();
+    sorted_stack_names.sort();
+    for stack_name in sorted_stack_names {
+        let stack = &config.stacks[stack_name];
+        let source = owner(&|s| s.stacks.contains(stack_name));
+        for (list, kind, references, known) in [
+            ("processes", "process", &stack.processes, &process_names),
+            ("inherits", "stack", &stack.inherits, &stack_names),
+            ("exclude", "process", &stack.exclude, &process_names),
+        ] {
+            let path = ["stacks", stack_name.as_str(), list];
+            let mut unknown: Vec<&String> = references
+                .iter()
+                .filter(|reference| !known.contains(reference))
+                .collect();
+            unknown.sort();
+            for reference in unknown {
+                diagnostics.push(source.diagnostic(
+                    &path,
+                    reference,
+                    format!("Stack {stack_name} references unknown {kind} {reference}"),
+                ));
+            }
+            for duplicate in duplicates(&source.value, &path) {
+                diagnostics.push(source.diagnostic(
+                    &path,
+                    &duplicate,
+                    format!("Stack {stack_name} lists {duplicate} more than once in {list}"),
+                ));
+            }
+        }
+        if stack.max_parallel == Some(0) {
+            diagnostics.push(source.diagnostic(
+                &["stacks", stack_name],
+                "max_parallel",
+                format!("Stack {stack_name} must run at least 1 process at once"),
+            ));
+        }
+        let inherits = |name: &String| {
+            config
+                .stacks
+                .get(name)
+                .map(|stack| stack.inherits.iter().collect())
+                .unwrap_or_default()
+        };
+        if let Some(cycle) = find_cycle(stack_name, &inherits) {
+            diagnostics.push(source.diagnostic(
+                &["stacks"],
+                stack_name,
+                format!("Stack inheritance loop: {}", cycle.join(" -> ")),
+            ));
+        }
+    }
+
+    let mut sorted_secret_names: Vec<&String> = config.secrets.keys().collect();
+    sorted_secret_names.sort();
+    for secret_name in sorted_secret_names {
+        let secret = &config.secrets[secret_name];
+        let source = owner(&|s| s.secrets.contains(secret_name));
+        let given = [
+            secret.file.is_some(),
+            secret.pass.is_some(),
+            secret.command.is_some(),
+        ];
+        if given.into_iter().filter(|given| *given).count() != 1 {
+            diagnostics.push(source.diagnostic(
+                &["secrets"],
+                secret_name,
+                format!("Secret {secret_name} needs exactly one of file, pass or command"),
+            ));
+        }
+    }
+
+    let mut sorted_process_names: Vec<&String> = config.processes.keys().collect();
+    sorted_process_names.sort();
+    for process_name in sorted_process_names {
+        let process = &config.processes[process_name];
+        let source = owner(&|s| s.processes.contains(process_name));
+        if process.replicas == Some(0) {
+            diagnostics.push(source.diagnostic(
+                &["processes", process_name],
+                "replicas",
+                format!("Process {process_name} must have at least 1 replica"),
+            ));
+        }
+        if process.binary.is_some() && process.example.is_some() {
+            diagnostics.push(source.diagnostic(
+                &["processes", process_name],
+                "example",
+                format!("Process {process_name} can't run both a binary and an example"),
+            ));
+        }
+        if let Some(limits) = process.limits.as_ref() {
+            let path = ["processes", process_name.as_str(), "limits"];
+            if let Some(memory) = limits.memory.as_ref() {
+                if parse_memory(memory).is_none() {
+                    diagnostics.push(source.diagnostic(
+                        &path,
+                        "memory",
+                        format!("Process {process_name} has invalid memory limit {memory}"),
+                    ));
+                }
+            }
+            if limits.cpus.is_some_and(|cpus| cpus.is_nan() || cpus <= 0.0) {
+                diagnostics.push(source.diagnostic(
+                    &path,
+                    "cpus",
+                    format!("Process {process_name} must be allowed a positive number of CPUs"),
+                ));
+            }
+        }
+        if process.crash_loop.as_ref().and_then(|c| c.window) == Some(0) {
+            diagnostics.push(source.diagnostic(
+                &["processes", process_name.as_str(), "crash_loop"],
+                "window",
+                format!("Process {process_name} must count failures over at least 1 second"),
+            ));
+        }
+        let path = ["processes", process_name.as_str(), "secrets"];
+        for secret in &process.secrets {
+            if !config.secrets.contains_key(secret) {
+                diagnostics.push(source.diagnostic(
+                    &path,
+                    secret,
+                    format!("Process {process_name} uses unknown secret {secret}"),
+                ));
+            }
+        }
+        let path = ["processes", process_name.as_str(), "depends_on"];
+        let mut unknown: Vec<&String> = process
+            .depends_on
+            .keys()
+            .filter(|dependency| !process_names.contains(dependency))
+            .collect();
+        unknown.sort();
+        for dependency in unknown {
+            diagnostics.push(source.diagnostic(
+                &path,
+                dependency,
+                format!("Process {process_name} depends on unknown process {dependency}"),
+            ));
+        }
+        let mut not_tasks: Vec<&String> = process
+            .depends_on
+            .iter()
+            .filter(|(dependency, condition)| {
+                matches!(
+                    condition.condition,
+                    ConfigDependencyCondition::CompletedSuccessfully
+                ) && config
+                    .processes
+                    .get(*dependency)
+                    .is_some_and(|dependency| dependency.kind != ConfigProcessKind::Task)
+            })
+            .map(|(dependency, _)| dependency)
+            .collect();
+        not_tasks.sort();
+        for dependency in not_tasks {
+            diagnostics.push(source.diagnostic(
+                &path,
+                dependency,
+                format!("Process {process_name} requires completion of service {dependency}"),
+            ));
+        }
+        if let Some(parent) = process.extends.as_ref() {
+            if !process_names.contains(parent) {
+                diagnostics.push(source.diagnostic(
+                    &["processes", process_name],
+                    "extends",
+                    format!("Process {process_name} extends unknown process {parent}"),
+                ));
+            }
+        }
+        let extends = |name: &String| {
+            config
+                .processes
+                .get(name)
+                .and_then(|process| process.extends.as_ref())
+                .into_iter()
+                .collect()
+        };
+        if let Some(cycle) = find_cycle(process_name, &extends) {
+            diagnostics.push(source.diagnostic(
+                &["processes"],
+                process_name,
+                format!("Process extension loop: {}", cycle.join(" -> ")),
+            ));
+        }
+        let depends_on = |name: &String| {
+            config
+                .processes
+                .get(name)
+                .map(|process| process.depends_on.keys().collect())
+                .unwrap_or_default()
+        };
+        if let Some(cycle) = find_cycle(process_name, &depends_on) {
+            diagnostics.push(source.diagnostic(
+                &["processes"],
+                process_name,
+                format!("Process dependency loop: {}", cycle.join(" -> ")),
+            ));
+        }
+    }
+    diagnostics
+}
+
+/// Configuration file being validated, with the entries it defines
+struct Source<'a> {
+    path: &'a Path,
+    content: &'a str,
+    /// Raw content, keeping duplicate entries of sequences
+    value: serde_yml::Value,
+    default_stack: bool,
+    stacks: HashSet<String>,
+    processes: HashSet<String>,
+    secrets: HashSet<String>,
+}
+
+impl Source<'_> {
+    /// Issue about `needle`, located under the nested keys of `path` in YAML files only
+    fn diagnostic(&self, path: &[&str], needle: &str, message: String) -> Diagnostic {
+        let location = match ConfigFormat::from_path(self.path) {
+            ConfigFormat::Yaml => locate(self.content, path, needle),
+            ConfigFormat::Toml | ConfigFormat::Json => None,
+        };
+        Diagnostic {
+            file: self.path.to_path_buf(),
+            location,
+            message,
+        }
+    }
+}
+
+/// Raw value and configuration of given file, or the syntax or schema issue preventing to read it
+fn parse(
+    path: &Path,
+    content: &str,
+) -> std::result::Result<(serde_yml::Value, ConfigFile), Diagnostic> {
+    let diagnostic = |location: Option<(usize, usize)>, message: String| Diagnostic {
+        file: path.to_path_buf(),
+        location,
+        message,
+    };
+    let yaml_error = |e: serde_yml::Error| {
+        let location = e.location().map(|l| (l.line(), l.column()));
+        let mut message = e.to_string();
+        if let Some((line, column)) = location {
+            // Location is already part of the diagnostic
+            let suffix = format!(" at line {line} column {column}");
+            if let Some(stripped) = message.strip_suffix(&suffix) {
+                message = stripped.to_owned();
+            }
+        }
+        diagnostic(location, message)
+    };
+
+    // Deserialize as a raw value first, as it rejects duplicate keys while typed maps don't
+    match ConfigFormat::from_path(path) {
+        ConfigFormat::Yaml => serde_yml::from_str::<serde_yml::Value>(content)
+            .and_then(|value| Ok((value, serde_yml::from_str::<ConfigFile>(content)?)))
+            .map_err(yaml_error),
+        ConfigFormat::Toml => toml::from_str::<serde_yml::Value>(content)
+            .and_then(|value| Ok((value, toml::from_str::<ConfigFile>(content)?)))
+            .map_err(|e| {
+                let location = e.span().map(|span| line_column(content, span.start));
+                diagnostic(location, e.message().to_owned())
+            }),
+        ConfigFormat::Json => serde_json::from_str::<serde_yml::Value>(content)
+            .and_then(|value| Ok((value, serde_json::from_str::<ConfigFile>(content)?)))
+            .map_err(|e| {
+                let location = Some((e.line(), e.column()));
+                let message = e.to_string();
+                let suffix = format!(" at line {} column {}", e.line(), e.column());
+                let message = message.strip_suffix(&suffix).unwrap_or(&message).to_owned();
+                diagnostic(location, message)
+            }),
+    }
+}
+
+/// Line and column, both starting at 1, of given byte offset
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+/// Entries appearing several times in the sequence at given path
+fn duplicates(value: &serde_yml::Value, path: &[&str]) -> Vec<String> {
+    let mut value = Some(value);
+    for key in path {
+        value = value.and_then(|v| v.get(key));
+    }
+    let Some(sequence) = value.and_then(|v| v.as_sequence()) else {
+        return Vec::new();
+    };
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    for entry in sequence.iter().filter_map(|e| e.as_str()) {
+        if !seen.insert(entry) && !duplicates.contains(&entry.to_owned()) {
+            duplicates.push(entry.to_owned());
+        }
+    }
+    duplicates
+}
+
+/// Loop of references going back to given node, if any.
+///
+/// Only reported from its lexicographically smallest node, to report each loop once.
+fn find_cycle<'a>(
+    origin: &'a String,
+    references: &impl Fn(&String) -> Vec<&'a String>,
+) -> Option<Vec<String>> {
+    fn visit<'a>(
+        origin: &'a String,
+        current: &String,
+        references: &impl Fn(&String) -> Vec<&'a String>,
+        path: &mut Vec<String>,
+    ) -> bool {
+        let mut next = references(current);
+        next.sort();
+        for reference in next {
+            if reference == origin {
+                path.push(reference.to_owned());
+                return true;
+            }
+            if reference < origin || path.contains(reference) {
+                continue;
+            }
+            path.push(reference.to_owned());
+            if visit(origin, reference, references, path) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    let mut path = vec![origin.to_owned()];
+    visit(origin, origin, references, &mut path).then_some(path)
+}
+
+/// Best effort location of `needle`, searched under the nested keys of `path`
+fn locate(content: &str, path: &[&str], needle: &str) -> Option<(usize, usize)> {
+    let lines: Vec<&str> = content.lines().collect();
+    let (mut start, mut parent_indent) = (0, None);
+    for key in path {
+        let idx = find_child(&lines, start, parent_indent, |entry| {
+            entry.starts_with(&format!("{key}:"))
+        })?;
+        (start, parent_indent) = (idx + 1, Some(indent(lines[idx])));
+    }
+    let idx = find_child(&lines, start, parent_indent, |entry| {
+        let value = entry.trim_matches(['"', '\'']);
+        value == needle
+            || entry.starts_with(&format!("{needle}:"))
+            || entry.ends_with(&format!(": {needle}"))
+    })?;
+    Some((idx + 1, lines[idx].rfind(needle).unwrap_or(0) + 1))
+}
+
+/// Index of the first direct child line of a block matching `predicate`
+///
+/// Children are the lines following `start` up to the end of the block, at the indentation of the
+/// first of them. Sequence markers are stripped before calling `predicate`.
+fn find_child(
+    lines: &[&str],
+    start: usize,
+    parent_indent: Option<usize>,
+    predicate: impl Fn(&str) -> bool,
+) -> Option<usize> {
+    let mut child_indent = None;
+    for (idx, line) in lines.iter().enumerate().skip(start) {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let line_indent = indent(line);
+        if parent_indent.is_some_and(|parent| line_indent <= parent) && !trimmed.starts_with("- ") {
+            return None;
+        }
+        if *child_indent.get_or_insert(line_indent) == line_indent
+            && predicate(trimmed.trim_start_matches("- "))
+        {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(content: &str) -> Vec<String> {
+        validate(Path::new("jocker.yml"), content)
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn valid_config() {
+        let config = include_str!("../../../examples/jocker.yml");
+        assert_eq!(messages(config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn toml_and_json_configs() {
+        let diagnostics: Vec<String> = validate(
+            Path::new("jocker.toml"),
+            "[processes.foo]\ndepends_on = { bar = {} }\n",
+        )
+        .into_iter()
+        .map(|d| d.to_string())
+        .collect();
+        assert_eq!(
+            diagnostics,
+            vec!["jocker.toml: Process foo depends on unknown process bar"]
+        );
+
+        let diagnostics = validate(
+            Path::new("jocker.json"),
+            "{\n  \"processes\": {\n    \"foo\": { \"bianry\": \"bar\" }\n  }\n}\n",
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].location.map(|l| l.0), Some(3));
+        assert!(diagnostics[0].message.starts_with("unknown field `bianry`"));
+
+        let diagnostics = validate(Path::new("jocker.toml"), "[processes.foo]\nbinary = \n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].location.map(|l| l.0), Some(2));
+    }
+
+    #[test]
+    fn unknown_key() {
+        let diagnostics = validate(
+            Path::new("jocker.yml"),
+            "processes:\n  foo:\n    bianry: bar\n",
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].location.map(|l| l.0), Some(3));
+        assert!(diagnostics[0]
+            .message
+            .starts_with("processes.foo: unknown field `bianry`"));
+        assert!(!diagnostics[0].message.contains("line"));
+    }
+
+    #[test]
+    fn duplicate_process() {
+        let diagnostics = validate(
+            Path::new("jocker.yml"),
+            "processes:\n  foo: {}\n  foo: {}\n",
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("duplicate"));
+    }
+
+    #[test]
+    fn invalid_references() {
+        let config = "\
+default:
+  stack: missing
+stacks:
+  a:
+    inherits:
+      - b
+    processes:
+      - foo
+      - bar
+      - foo
+  b:
+    inherits:
+      - a
+processes:
+  foo: {}
+";
+        assert_eq!(
+            messages(config),
+            vec![
+                "jocker.yml:2:10: Default stack missing is not defined",
+                "jocker.yml:9:9: Stack a references unknown process bar",
+                "jocker.yml:8:9: Stack a lists foo more than once in processes",
+                "jocker.yml:4:3: Stack inheritance loop: a -> b -> a",
+            ]
+        );
+    }
+
+    #[test]
+    fn override_file() {
+        let config = "\
+stacks:
+  default:
+    processes:
+      - api
+      - worker
+processes:
+  api:
+    depends_on:
+      db: {}
+";
+        let override_config = "\
+processes:
+  worker:
+    depends_on:
+      queue: {}
+  db: {}
+";
+        let diagnostics: Vec<String> = validate_files(&[
+            (Path::new("jocker.yml"), config),
+            (Path::new("jocker.override.yml"), override_config),
+        ])
+        .into_iter()
+        .map(|d| d.to_string())
+        .collect();
+        assert_eq!(
+            diagnostics,
+            vec!["jocker.override.yml:4:7: Process worker depends on unknown process queue"]
+        );
+    }
+
+    #[test]
+    fn unknown_exclusion() {
+        let config = "\
+stacks:
+  light:
+    inherits:
+      - full
+    exclude:
+      - analytics
+      - mailer
+  full:
+    processes:
+      - api
+      - analytics
+processes:
+  api: {}
+  analytics: {}
+";
+        assert_eq!(
+            messages(config),
+            vec!["jocker.yml:7:9: Stack light references unknown process mailer"]
+        );
+    }
+
+    #[test]
+    fn invalid_max_parallel() {
+        let config = "\
+stacks:
+  heavy:
+    processes:
+      - foo
+    max_parallel: 0
+processes:
+  foo: {}
+";
+        assert_eq!(
+            messages(config),
+            vec!["jocker.yml:5:5: Stack heavy must run at least 1 process at once"]
+        );
+    }
+
+    #[test]
+    fn invalid_dependencies() {
+        let config = "\
+processes:
+  foo:
+    depends_on:
+      bar:
+        condition: healthy
+  bar:
+    depends_on:
+      foo: {}
+      baz: {}
+    replicas: 0
+";
+        assert_eq!(
+            messages(config),
+            vec![
+                "jocker.yml:10:5: Process bar must have at least 1 replica",
+                "jocker.yml:9:7: Process bar depends on unknown process baz",
+                "jocker.yml:6:3: Process dependency loop: bar -> foo -> bar",
+            ]
+        );
+    }
+
+    #[test]
+    fn completion_of_service() {
+        let config = "\
+processes:
+  migrate:
+    kind: task
+  db: {}
+  api:
+    depends_on:
+      migrate:
+        condition: completed_successfully
+      db:
+        condition: completed_successfully
+";
+        assert_eq!(
+            messages(config),
+            vec!["jocker.yml:9:7: Process api requires completion of service db"]
+        );
+    }
+
+    #[test]
+    fn binary_and_example() {
+        let config = "\
+processes:
+  foo:
+    binary: foo
+    example: foo-demo
+    package: foo
+";
+        assert_eq!(
+            messages(config),
+            vec!["jocker.yml:4:5: Process foo can't run both a binary and an example"]
+        );
+    }
+
+    #[test]
+    fn invalid_limits() {
+        let config = "\
+processes:
+  foo:
+    limits:
+      memory: 512Q
+      cpus: 0
+";
+        assert_eq!(
+            messages(config),
+            vec![
+                "jocker.yml:4:7: Process foo has invalid memory limit 512Q",
+                "jocker.yml:5:7: Process foo must be allowed a positive number of CPUs",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_crash_loop() {
+        let config = "\
+processes:
+  foo:
+    crash_loop:
+      max_failures: 5
+      window: 0
+";
+        assert_eq!(
+            messages(config),
+            vec!["jocker.yml:5:7: Process foo must count failures over at least 1 second"]
+        );
+    }
+
+    #[test]
+    fn invalid_secrets() {
+        let config = "\
+secrets:
+  DB_PASSWORD:
+    file: secrets/db
+    pass: dev/db
+  API_TOKEN:
+    command: vault read -field=token secret/api
+processes:
+  api:
+    secrets:
+      - API_TOKEN
+      - DB_PASSWORD
+      - SMTP_PASSWORD
+";
+        assert_eq!(
+            messages(config),
+            vec![
+                "jocker.yml:2:3: Secret DB_PASSWORD needs exactly one of file, pass or command",
+                "jocker.yml:12:9: Process api uses unknown secret SMTP_PASSWORD",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_extends() {
+        let config = "\
+processes:
+  foo:
+    extends: bar
+  bar:
+    extends: foo
+  baz:
+    extends: qux
+";
+        assert_eq!(
+            messages(config),
+            vec![
+                "jocker.yml:4:3: Process extension loop: bar -> foo -> bar",
+                "jocker.yml:7:5: Process baz extends unknown process qux",
+            ]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{fmt::Display, str::FromStr, sync::Arc, time::Duration};
+
+use tokio::time::{sleep, Instant};
+use tracing::info;
+
+use crate::{
+    common::{Exec, Process, ProcessState},
+    error::{Error, InnerError, Result},
+    health::{is_healthy, HEALTHCHECK_INTERVAL},
+    start::DEFAULT_WAIT_TIMEOUT,
+    state::State,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct WaitArgs {
+    pub processes: Vec<String>,
+    pub condition: WaitCondition,
+    /// Maximum duration of the wait, in seconds
+    pub timeout: Option<u64>,
+}
+
+/// State [`Wait`] waits for every selected process to be in
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum WaitCondition {
+    #[default]
+    Running,
+    /// Running and passing its healthcheck, if any
+    Healthy,
+    /// Not running, whether it was stopped, exited or is crashlooping
+    Stopped,
+}
+
+impl FromStr for WaitCondition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "running" => Ok(Self::Running),
+            "healthy" => Ok(Self::Healthy),
+            "stopped" => Ok(Self::Stopped),
+            _ => Err(Error::new(InnerError::Parse(s.to_owned()))),
+        }
+    }
+}
+
+impl Display for WaitCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Self::Running => "running",
+            Self::Healthy => "healthy",
+            Self::Stopped => "stopped",
+        };
+        write!(f, "{str}")
+    }
+}
+
+/// Block until every selected process meets a condition
+///
+/// Waiting for processes to run fails right away once one of them is crashlooping, as it won't be
+/// restarted on its own.
+pub struct Wait {
+    args: WaitArgs,
+    state: Arc<State>,
+}
+
+impl Wait {
+    pub fn new(args: WaitArgs, state: Arc<State>) -> Self {
+        Wait { args, state }
+    }
+
+    /// Whether given process meets the awaited condition
+    async fn is_met(&self, process: &Process) -> bool {
+        match self.args.condition {
+            WaitCondition::Running => process.state == ProcessState::Running,
+            WaitCondition::Healthy => {
+                process.state == ProcessState::Running && is_healthy(&self.state, process).await
+            }
+            WaitCondition::Stopped => {
+                process.state.is_stopped() || process.state == ProcessState::CrashLooping
+            }
+        }
+    }
+}
+
+impl Exec<()> for Wait {
+    async fn exec(&self) -> Result<()> {
+        let condition = self.args.condition;
+        let timeout = self
+            .args
+            .timeout
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_WAIT_TIMEOUT);
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.state.refresh(false).await?;
+            let mut pending = Vec::new();
+            let mut crashlooping = Vec::new();
+            for process in self.state.filter_processes(&self.args.processes).await? {
+                if self.is_met(&process).await {
+                    continue;
+                }
+                if process.state == ProcessState::CrashLooping {
+                    crashlooping.push(process.name().to_owned());
+                } else {
+                    pending.push(process.name().to_owned());
+                }
+            }
+            if !crashlooping.is_empty() {
+                return Err(Error::new(InnerError::Start(format!(
+                    "Processes crashlooping, they won't be {condition}: {}",
+                    crashlooping.join(", ")
+                ))));
+            }
+            if pending.is_empty() {
+                info!("All processes are {condition}");
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::new(InnerError::Timeout(format!(
+                    "Processes not {condition} after {}s: {}",
+                    timeout.as_secs(),
+                    pending.join(", ")
+                ))));
+            }
+            sleep(HEALTHCHECK_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_wait_condition() {
+        assert_eq!("healthy".parse::<WaitCondition>().unwrap(), WaitCondition::Healthy);
+        assert_eq!("stopped".parse::<WaitCondition>().unwrap(), WaitCondition::Stopped);
+        assert!("ready".parse::<WaitCondition>().is_err());
+    }
+}
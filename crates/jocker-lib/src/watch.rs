@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use notify::{recommended_watcher, Event, EventKind, RecursiveMode, Watcher as _};
+use tokio::{sync::mpsc, time::sleep};
+use tracing::{error, info, warn};
+
+use crate::{
+    command::cargo::Cargo,
+    common::{Exec, Process, ProcessState},
+    error::Result,
+    start::{Start, StartArgs},
+    state::State,
+    stop::{self, StopArgs},
+};
+
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Default, PartialEq)]
+pub struct WatchArgs {
+    pub processes: Vec<String>,
+}
+
+pub struct Watch {
+    args: WatchArgs,
+    state: Arc<State>,
+}
+
+impl Watch {
+    pub fn new(args: WatchArgs, state: Arc<State>) -> Self {
+        Watch { args, state }
+    }
+
+    /// Map each watched process name to the source directory of the crate owning its binary
+    async fn process_dirs(&self, processes: &[Process]) -> Result<HashMap<String, PathBuf>> {
+        let packages = Cargo::metadata(self.state.get_target_dir()).await?;
+        let mut process_dirs = HashMap::new();
+        for process in processes.iter().filter(|p| p.is_cargo()) {
+            let cargo_target = process.cargo_target();
+            let package = packages.iter().find(|package| match process.package() {
+                Some(name) => package.name == name,
+                None => {
+                    package.name == process.binary()
+                        || package
+                            .targets
+                            .iter()
+                            .any(|target| target.is(&cargo_target))
+                }
+            });
+            match package {
+                Some(package) => {
+                    process_dirs
+                        .insert(process.name().to_string(), PathBuf::from(package.id.path()));
+                }
+                None => warn!(
+                    "Unable to find crate of process {}, it will not be watched",
+                    process.name()
+                ),
+            }
+        }
+        Ok(process_dirs)
+    }
+
+    /// Rebuild and restart given processes, only logging errors of a process so that the others
+    /// are still restarted
+    async fn restart(&self, process_names: Vec<String>) -> Result<()> {
+        // Held from the build to the last start, so that no other command changes processes in
+        // between
+        let _lock = self.state.lock()?;
+        // Catch up with exits since the last restart, so crash loops are detected
+        self.state.refresh(false).await?;
+        let processes = self.state.filter_processes(&process_names).await?;
+        let start = Start::new(StartArgs::default(), self.state.clone());
+        if let Err(e) = start.build(&processes).await {
+            error!("Error while rebuilding processes: {e}");
+            return Ok(());
+        }
+        for process in processes {
+            let process_name = process.name().to_string();
+            if process.state == ProcessState::CrashLooping {
+                warn!("Not restarting crash looping process {process_name}, start it explicitly");
+                continue;
+            }
+            if let Err(e) = stop::run(self.state.clone(), process, StopArgs::default()).await {
+                error!("Error while stopping process {process_name}: {e}");
+                continue;
+            }
+            let process = match self.state.filter_processes(&[process_name.clone()]).await {
+                Ok(mut processes) => processes.remove(0),
+                Err(e) => {
+                    error!("Error while refreshing process {process_name}: {e}");
+                    continue;
+                }
+            };
+            if let Err(e) = start.run(process).await {
+                error!("Error while starting process {process_name}: {e}")
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Exec<()> for Watch {
+    async fn exec(&self) -> Result<()> {
+        Start::new(
+            StartArgs {
+                processes: self.args.processes.clone(),
+                ..Default::default()
+            },
+            self.state.clone(),
+        )
+        .exec()
+        .await?;
+
+        let processes = self.state.filter_processes(&self.args.processes).await?;
+        let process_dirs = self.process_dirs(&processes).await?;
+        let ignored_dir = self.state.get_target_dir().join("target");
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = recommended_watcher(move |res: notify::Result<Event>| {
+            let _ = tx.send(res);
+        })?;
+        for dir in process_dirs.values() {
+            watcher.watch(dir, RecursiveMode::Recursive)?;
+        }
+        info!("Watching {} processes for changes ...", process_dirs.len());
+
+        while let Some(affected_processes) =
+            next_changes(&mut rx, &ignored_dir, &process_dirs).await
+        {
+            info!(
+                "Changes detected, restarting processes: {}",
+                affected_processes.join(", ")
+            );
+            // Keep watching whatever happened, the next change may fix it
+            if let Err(e) = self.restart(affected_processes).await {
+                error!("Error while restarting processes: {e}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Wait for changes to the directory of at least one process, returning the names of affected
+/// processes, sorted
+///
+/// Events of a burst (e.g. an editor saving several files) are merged together. Returns `None`
+/// once the watcher is gone.
+async fn next_changes(
+    rx: &mut mpsc::UnboundedReceiver<notify::Result<Event>>,
+    ignored_dir: &Path,
+    process_dirs: &HashMap<String, PathBuf>,
+) -> Option<Vec<String>> {
+    while let Some(res) = rx.recv().await {
+        let mut changed_paths = Vec::new();
+        collect_changed_paths(res, ignored_dir, &mut changed_paths);
+        if changed_paths.is_empty() {
+            continue;
+        }
+        // Let the burst settle
+        sleep(DEBOUNCE_DELAY).await;
+        while let Ok(res) = rx.try_recv() {
+            collect_changed_paths(res, ignored_dir, &mut changed_paths);
+        }
+        let mut affected_processes: Vec<String> = process_dirs
+            .iter()
+            .filter(|(_, dir)| changed_paths.iter().any(|path| path.starts_with(dir)))
+            .map(|(name, _)| name.to_owned())
+            .collect();
+        affected_processes.sort();
+        if !affected_processes.is_empty() {
+            return Some(affected_processes);
+        }
+    }
+    None
+}
+
+fn collect_changed_paths(
+    res: notify::Result<Event>,
+    ignored_dir: &Path,
+    changed_paths: &mut Vec<PathBuf>,
+) {
+    match res {
+        Ok(event) => {
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+            changed_paths.extend(
+                event
+                    .paths
+                    .into_iter()
+                    .filter(|path| !path.starts_with(ignored_dir)),
+            );
+        }
+        Err(e) => warn!("Error while watching files: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{AccessKind, ModifyKind};
+
+    use super::*;
+
+    fn event(kind: EventKind, path: &str) -> notify::Result<Event> {
+        Ok(Event::new(kind).add_path(PathBuf::from(path)))
+    }
+
+    #[tokio::test]
+    async fn debounce_changes() {
+        let process_dirs = HashMap::from([
+            ("api".to_owned(), PathBuf::from("/ws/api")),
+            ("web".to_owned(), PathBuf::from("/ws/web")),
+            ("worker".to_owned(), PathBuf::from("/ws/worker")),
+        ]);
+        let ignored_dir = PathBuf::from("/ws/target");
+        let modify = EventKind::Modify(ModifyKind::Any);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        // Only reads and build outputs, then a burst touching two crates
+        tx.send(event(EventKind::Access(AccessKind::Any), "/ws/api/src/main.rs"))
+            .unwrap();
+        tx.send(event(modify, "/ws/target/debug/api")).unwrap();
+        tx.send(event(modify, "/ws/web/src/main.rs")).unwrap();
+        let sender = tx.clone();
+        tokio::spawn(async move {
+            sleep(DEBOUNCE_DELAY / 3).await;
+            sender.send(event(modify, "/ws/api/src/lib.rs")).unwrap();
+        });
+        assert_eq!(
+            next_changes(&mut rx, &ignored_dir, &process_dirs).await,
+            Some(vec!["api".to_owned(), "web".to_owned()])
+        );
+
+        // Changes outside of process crates don't wake watchers up
+        tx.send(event(modify, "/ws/README.md")).unwrap();
+        tx.send(Err(notify::Error::generic("watch failed"))).unwrap();
+        drop(tx);
+        assert_eq!(next_changes(&mut rx, &ignored_dir, &process_dirs).await, None);
+    }
+}
@@ -1,15 +1,43 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf};
 
 use argh::FromArgs;
+use chrono::{DateTime, Utc};
 
 use jocker_lib::{
+    adopt::AdoptArgs,
+    build::BuildArgs,
+    common::parse_label,
+    common::Event,
+    common::BuildRecord,
+    common::HistoryEntry,
     common::ProcessState,
+    daemon::DaemonArgs,
+    env::EnvArgs,
+    error::ErrorFormat,
+    events::{parse_since, EventsArgs},
+    exec::ExecArgs,
+    export::{ExportArgs, ExportFormat},
+    history::HistoryArgs,
+    init::InitArgs,
     logs::LogsArgs,
-    ps::{PsArgs, PsOutput},
-    start::StartArgs,
-    stop::StopArgs,
+    pause::{PauseArgs, ResumeArgs},
+    projects::{CleanProjectArgs, Project},
+    ps::{PsArgs, PsOutput, PsSort, PsStateFilter},
+    repair::RepairArgs,
+    run::RunArgs,
+    scale::{parse_replicas, ScaleArgs},
+    show::{ShowArgs, ShowFormat},
+    signal::{ProcessSignal, SignalArgs},
+    stack::{CreateStackArgs, DeleteStackArgs, StackSelection, UseStackArgs},
+    start::{parse_env_var, StartArgs, StartPlan},
+    stats::{BuildsSummary, StatsBuildsArgs},
+    status::StatusArgs,
+    stop::{PlannedStop, StopArgs, StopPlan},
+    tmux::TmuxArgs,
+    wait::{WaitArgs, WaitCondition},
+    watch::WatchArgs,
 };
-use tabled::Tabled;
+use tabled::{builder::Builder, Table, Tabled};
 
 #[derive(FromArgs, PartialEq, Debug)]
 /// Top-level command.
@@ -26,30 +54,432 @@ pub struct Cli {
     #[argh(option)]
     pub target_directory: Option<String>,
 
+    /// configuration file to use, instead of jocker.yml in target directory
+    #[argh(option, short = 'f')]
+    pub file: Option<String>,
+
+    /// group of optional processes to enable, may be repeated
+    #[argh(option)]
+    pub enable: Vec<String>,
+
+    /// show more details, repeat for even more
+    #[argh(switch, short = 'v')]
+    pub verbose: u8,
+
+    /// only show warnings and errors
+    #[argh(switch, short = 'q')]
+    pub quiet: bool,
+
+    /// format of errors, either text (default) or json
+    #[argh(
+        option,
+        default = "ErrorFormat::Text",
+        from_str_fn(parse_error_format_cli)
+    )]
+    pub output: ErrorFormat,
+
     #[argh(subcommand)]
     pub sub_command: CliSubCommand,
 }
 
+fn parse_error_format_cli(value: &str) -> Result<ErrorFormat, String> {
+    value.parse().map_err(|_| format!("unknown format {value}"))
+}
+
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand)]
 pub enum CliSubCommand {
-    Ui(UiArgs),
+    Ui(UiArgsCli),
+    Adopt(AdoptArgsCli),
+    Build(BuildArgsCli),
     Clean(CleanArgsCli),
+    Config(ConfigArgsCli),
+    Daemon(DaemonArgsCli),
+    Env(EnvArgsCli),
+    Events(EventsArgsCli),
+    Exec(ExecArgsCli),
+    Export(ExportArgsCli),
+    History(HistoryArgsCli),
+    Init(InitArgsCli),
     Logs(LogsArgsCli),
+    Pause(PauseArgsCli),
+    Projects(ProjectsArgsCli),
     Ps(PsArgsCli),
+    Resume(ResumeArgsCli),
+    Run(RunArgsCli),
+    Scale(ScaleArgsCli),
+    Signal(SignalArgsCli),
+    Stack(StackArgsCli),
     Start(StartArgsCli),
+    State(StateArgsCli),
+    Stats(StatsArgsCli),
+    Status(StatusArgsCli),
     Stop(StopArgsCli),
+    Top(TopArgsCli),
+    Wait(WaitArgsCli),
+    Watch(WatchArgsCli),
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
-/// First subcommand.
+/// Follow processes from a terminal user interface
 #[argh(subcommand, name = "ui")]
-pub struct UiArgs {}
+pub struct UiArgsCli {
+    #[argh(subcommand)]
+    pub sub_command: UiSubCommandCli,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+pub enum UiSubCommandCli {
+    Tmux(UiTmuxArgsCli),
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Follow logs in a tmux session named after the stack, one pane per process
+#[argh(subcommand, name = "tmux")]
+pub struct UiTmuxArgsCli {
+    #[argh(positional)]
+    /// filter process to act upon
+    pub processes: Vec<String>,
+}
+
+impl From<UiTmuxArgsCli> for TmuxArgs {
+    fn from(value: UiTmuxArgsCli) -> Self {
+        Self {
+            processes: value.processes,
+        }
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Manage processes whose binary was started outside of jocker, such as with `cargo run`
+#[argh(subcommand, name = "adopt")]
+pub struct AdoptArgsCli {
+    #[argh(positional)]
+    /// filter process to act upon
+    pub processes: Vec<String>,
+}
+
+impl From<AdoptArgsCli> for AdoptArgs {
+    fn from(value: AdoptArgsCli) -> Self {
+        Self {
+            processes: value.processes,
+        }
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Build processes binaries, without starting them
+#[argh(subcommand, name = "build")]
+pub struct BuildArgsCli {
+    /// build binaries with the release profile
+    #[argh(switch)]
+    pub release: bool,
+    #[argh(positional)]
+    /// filter process to act upon
+    pub processes: Vec<String>,
+}
+
+impl From<BuildArgsCli> for BuildArgs {
+    fn from(value: BuildArgsCli) -> Self {
+        Self {
+            release: value.release,
+            processes: value.processes,
+        }
+    }
+}
 
 #[derive(Clone, Debug, FromArgs, PartialEq)]
 /// Clean jocker state and resources
 #[argh(subcommand, name = "clean")]
-pub struct CleanArgsCli {}
+pub struct CleanArgsCli {
+    /// clean every project, and remove all jocker pueue groups
+    #[argh(switch)]
+    pub global: bool,
+    /// only remove finished scheduler tasks, keeping the state of the project
+    #[argh(switch)]
+    pub tasks: bool,
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Inspect jocker configuration
+#[argh(subcommand, name = "config")]
+pub struct ConfigArgsCli {
+    #[argh(subcommand)]
+    pub sub_command: ConfigSubCommandCli,
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+#[argh(subcommand)]
+pub enum ConfigSubCommandCli {
+    Show(ConfigShowArgsCli),
+    Validate(ConfigValidateArgsCli),
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Print the effective configuration, once defaults and stack inheritance are applied
+#[argh(subcommand, name = "show")]
+pub struct ConfigShowArgsCli {
+    /// output format, either yaml (default) or json
+    #[argh(
+        option,
+        default = "ShowFormat::Yaml",
+        from_str_fn(parse_show_format_cli)
+    )]
+    pub format: ShowFormat,
+}
+
+impl From<ConfigShowArgsCli> for ShowArgs {
+    fn from(value: ConfigShowArgsCli) -> Self {
+        Self {
+            format: value.format,
+        }
+    }
+}
+
+fn parse_show_format_cli(value: &str) -> Result<ShowFormat, String> {
+    value.parse().map_err(|_| format!("unknown format {value}"))
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Check configuration files and report issues with their location
+#[argh(subcommand, name = "validate")]
+pub struct ConfigValidateArgsCli {}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Serve a local HTTP/JSON API to control processes
+#[argh(subcommand, name = "daemon")]
+pub struct DaemonArgsCli {
+    /// listen on given localhost port instead of the project unix socket
+    #[argh(option)]
+    pub port: Option<u16>,
+}
+
+impl From<DaemonArgsCli> for DaemonArgs {
+    fn from(value: DaemonArgsCli) -> Self {
+        Self { port: value.port }
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Print the environment of a process, with where each variable comes from: jocker, dotenv,
+/// config, secret or cli
+#[argh(subcommand, name = "env")]
+pub struct EnvArgsCli {
+    #[argh(positional)]
+    /// process whose environment is printed
+    pub process: String,
+}
+
+impl From<EnvArgsCli> for EnvArgs {
+    fn from(value: EnvArgsCli) -> Self {
+        Self {
+            process: value.process,
+        }
+    }
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// List processes lifecycle events
+#[argh(subcommand, name = "events")]
+pub struct EventsArgsCli {
+    /// only show events after given date (RFC 3339) or duration ago (e.g. 10m, 2h, 1d)
+    #[argh(option, from_str_fn(parse_since_cli))]
+    pub since: Option<DateTime<Utc>>,
+    #[argh(positional)]
+    /// filter process to act upon
+    pub processes: Vec<String>,
+}
+
+impl From<EventsArgsCli> for EventsArgs {
+    fn from(value: EventsArgsCli) -> Self {
+        Self {
+            since: value.since,
+            processes: value.processes,
+        }
+    }
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Execute a command with the environment of a process
+#[argh(subcommand, name = "exec")]
+pub struct ExecArgsCli {
+    #[argh(positional)]
+    /// process whose environment is used
+    pub process: String,
+    #[argh(positional, greedy)]
+    /// command to execute, after `--`
+    pub command: Vec<String>,
+}
+
+impl From<ExecArgsCli> for ExecArgs {
+    fn from(value: ExecArgsCli) -> Self {
+        Self {
+            process: value.process,
+            command: value.command,
+        }
+    }
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Export processes to be run by another tool
+#[argh(subcommand, name = "export")]
+pub struct ExportArgsCli {
+    #[argh(subcommand)]
+    pub format: ExportFormatCli,
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+#[argh(subcommand)]
+pub enum ExportFormatCli {
+    Compose(ExportComposeArgsCli),
+    Systemd(ExportSystemdArgsCli),
+    Vscode(ExportVscodeArgsCli),
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Generate a docker-compose.yml running processes with cargo in Rust containers
+#[argh(subcommand, name = "compose")]
+pub struct ExportComposeArgsCli {
+    /// export given stack instead of the current one
+    #[argh(option)]
+    pub stack: Option<String>,
+    /// directory to write docker-compose.yml to, defaults to the current one
+    #[argh(option)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Generate systemd user units
+#[argh(subcommand, name = "systemd")]
+pub struct ExportSystemdArgsCli {
+    /// export given stack instead of the current one
+    #[argh(option)]
+    pub stack: Option<String>,
+    /// directory to write units to, defaults to the current one
+    #[argh(option)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Generate VS Code tasks starting, stopping and following logs of processes and stacks
+#[argh(subcommand, name = "vscode")]
+pub struct ExportVscodeArgsCli {
+    /// only export given stack, instead of processes of the current one and every stack
+    #[argh(option)]
+    pub stack: Option<String>,
+    /// directory to write tasks.json to, defaults to .vscode of the current one
+    #[argh(option)]
+    pub output: Option<PathBuf>,
+    /// also write launch.json, attaching CodeLLDB to processes
+    #[argh(switch)]
+    pub launch: bool,
+}
+
+impl From<ExportArgsCli> for ExportArgs {
+    fn from(value: ExportArgsCli) -> Self {
+        match value.format {
+            ExportFormatCli::Compose(args) => Self {
+                format: ExportFormat::Compose,
+                stack: args.stack,
+                output: args.output,
+                launch: false,
+            },
+            ExportFormatCli::Systemd(args) => Self {
+                format: ExportFormat::Systemd,
+                stack: args.stack,
+                output: args.output,
+                launch: false,
+            },
+            ExportFormatCli::Vscode(args) => Self {
+                format: ExportFormat::Vscode,
+                stack: args.stack,
+                output: args.output,
+                launch: args.launch,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// List changes of the project state: configuration reloads, stack selections and process states
+#[argh(subcommand, name = "history")]
+pub struct HistoryArgsCli {
+    #[argh(positional)]
+    /// only list changes of given process
+    pub process: Option<String>,
+}
+
+impl From<HistoryArgsCli> for HistoryArgs {
+    fn from(value: HistoryArgsCli) -> Self {
+        Self {
+            process: value.process,
+        }
+    }
+}
+
+fn parse_since_cli(value: &str) -> Result<DateTime<Utc>, String> {
+    parse_since(value).map_err(|e| e.to_string())
+}
+
+#[derive(Tabled)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct HistoryOutputCli {
+    version: i64,
+    time: String,
+    #[tabled(display_with = "tabled_display_option")]
+    process: Option<String>,
+    kind: String,
+    change: String,
+}
+
+impl From<HistoryEntry> for HistoryOutputCli {
+    fn from(value: HistoryEntry) -> Self {
+        Self {
+            version: value.version,
+            time: value.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            process: value.process_name,
+            kind: value.kind.to_string(),
+            change: value.change,
+        }
+    }
+}
+
+#[derive(Tabled)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct EventOutputCli {
+    time: String,
+    process: String,
+    event: String,
+    #[tabled(rename = "EXIT CODE", display_with = "tabled_display_option")]
+    exit_code: Option<i32>,
+}
+
+impl From<Event> for EventOutputCli {
+    fn from(value: Event) -> Self {
+        Self {
+            time: value.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            process: value.process_name,
+            event: value.kind.to_string(),
+            exit_code: value.exit_code,
+        }
+    }
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Generate a starter jocker.yml from the binaries of the cargo workspace
+#[argh(subcommand, name = "init")]
+pub struct InitArgsCli {
+    /// overwrite an existing jocker.yml
+    #[argh(switch)]
+    pub force: bool,
+}
+
+impl From<InitArgsCli> for InitArgs {
+    fn from(value: InitArgsCli) -> Self {
+        Self { force: value.force }
+    }
+}
 
 #[derive(Clone, Debug, FromArgs, PartialEq)]
 /// Start processes
@@ -64,6 +494,22 @@ pub struct LogsArgsCli {
     /// only show new log entries
     #[argh(switch, short = 't')]
     pub tail: bool,
+    /// only show lines matching this regular expression
+    #[argh(option, short = 'g')]
+    pub grep: Option<String>,
+    /// show lines not matching --grep instead
+    #[argh(switch, short = 'v')]
+    pub invert: bool,
+    /// read logs archived by jocker, kept after pueued is reset
+    #[argh(switch)]
+    pub from_file: bool,
+    /// only show logs of processes with given label, as key=value, may be repeated
+    #[argh(option, from_str_fn(parse_label_cli))]
+    pub label: Vec<(String, String)>,
+    /// number of lines kept while the terminal lags behind, the oldest ones being dropped past it
+    /// when following, 1024 by default
+    #[argh(option)]
+    pub buffer: Option<usize>,
     /// filter process to act upon
     #[argh(positional)]
     pub processes: Vec<String>,
@@ -75,7 +521,94 @@ impl From<LogsArgsCli> for LogsArgs {
             follow: value.follow,
             process_prefix: value.process_prefix,
             tail: value.tail,
+            grep: value.grep,
+            invert: value.invert,
+            from_file: value.from_file,
             processes: value.processes,
+            labels: value.label,
+            buffer: value.buffer,
+        }
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Freeze running processes without stopping them, until they are resumed
+#[argh(subcommand, name = "pause")]
+pub struct PauseArgsCli {
+    #[argh(positional)]
+    /// filter process to act upon
+    pub processes: Vec<String>,
+}
+
+impl From<PauseArgsCli> for PauseArgs {
+    fn from(value: PauseArgsCli) -> Self {
+        Self {
+            processes: value.processes,
+        }
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Manage projects whose state is kept by jocker
+#[argh(subcommand, name = "projects")]
+pub struct ProjectsArgsCli {
+    #[argh(subcommand)]
+    pub sub_command: ProjectsSubCommandCli,
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+#[argh(subcommand)]
+pub enum ProjectsSubCommandCli {
+    Clean(ProjectsCleanArgsCli),
+    Gc(ProjectsGcArgsCli),
+    List(ProjectsListArgsCli),
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Stop processes of a project and remove its state
+#[argh(subcommand, name = "clean")]
+pub struct ProjectsCleanArgsCli {
+    #[argh(positional)]
+    /// directory of the project, which may not exist anymore
+    pub path: PathBuf,
+}
+
+impl From<ProjectsCleanArgsCli> for CleanProjectArgs {
+    fn from(value: ProjectsCleanArgsCli) -> Self {
+        Self { path: value.path }
+    }
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Remove the state of projects whose directory doesn't exist anymore
+#[argh(subcommand, name = "gc")]
+pub struct ProjectsGcArgsCli {}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// List projects whose state is kept by jocker
+#[argh(subcommand, name = "list")]
+pub struct ProjectsListArgsCli {}
+
+#[derive(Tabled)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct ProjectOutputCli {
+    id: String,
+    #[tabled(display_with = "tabled_display_option")]
+    path: Option<String>,
+    #[tabled(rename = "LAST USED", display_with = "tabled_display_option")]
+    last_used: Option<String>,
+    stale: bool,
+}
+
+impl From<Project> for ProjectOutputCli {
+    fn from(value: Project) -> Self {
+        Self {
+            stale: value.is_stale(),
+            id: value.id,
+            path: value.path.map(|path| path.display().to_string()),
+            last_used: value
+                .last_used_at
+                .map(|at| at.format("%Y-%m-%d %H:%M:%S").to_string()),
         }
     }
 }
@@ -87,16 +620,99 @@ pub struct PsArgsCli {
     #[argh(positional)]
     /// filter process to act upon
     pub processes: Vec<String>,
+    /// keep redrawing the list, on every process change and at least every interval
+    #[argh(switch, short = 'w')]
+    pub watch: bool,
+    /// seconds between two redraws with --watch, 2 by default
+    #[argh(option, default = "2")]
+    pub interval: u64,
+    /// comma separated columns to show, in order, e.g. name,state,pid,uptime
+    #[argh(option, default = "Vec::new()", from_str_fn(parse_columns_cli))]
+    pub columns: Vec<String>,
+    /// column to sort processes by, their name by default
+    #[argh(option, default = "PsSort::Name", from_str_fn(parse_sort_cli))]
+    pub sort: PsSort,
+    /// only print names of processes, one per line
+    #[argh(switch)]
+    pub quiet: bool,
+    /// only list processes in given state, such as running, stopped or unhealthy, may be repeated
+    #[argh(option, from_str_fn(parse_state_cli))]
+    pub state: Vec<PsStateFilter>,
+    /// only list processes with given label, as key=value, may be repeated
+    #[argh(option, from_str_fn(parse_label_cli))]
+    pub label: Vec<(String, String)>,
 }
 
 impl From<PsArgsCli> for PsArgs {
     fn from(value: PsArgsCli) -> Self {
         Self {
             processes: value.processes,
+            sort: value.sort,
+            states: value.state,
+            labels: value.label,
         }
     }
 }
 
+/// Columns of the table of `ps`, in order
+pub const PS_COLUMNS: [&str; 13] = [
+    "name",
+    "state",
+    "pid",
+    "uptime",
+    "downtime",
+    "restarts",
+    "exit_code",
+    "ports",
+    "cpu",
+    "mem",
+    "stack",
+    "labels",
+    "command",
+];
+/// Columns shown when none is given, long ones being left out
+const PS_DEFAULT_COLUMNS: [&str; 9] = [
+    "name",
+    "state",
+    "pid",
+    "uptime",
+    "restarts",
+    "exit_code",
+    "ports",
+    "cpu",
+    "mem",
+];
+
+fn parse_columns_cli(value: &str) -> Result<Vec<String>, String> {
+    value
+        .split(',')
+        .map(|column| {
+            let column = column.trim().to_lowercase();
+            if PS_COLUMNS.contains(&column.as_str()) {
+                Ok(column)
+            } else {
+                Err(format!(
+                    "unknown column {column}, expected some of {}",
+                    PS_COLUMNS.join(",")
+                ))
+            }
+        })
+        .collect()
+}
+
+fn parse_state_cli(value: &str) -> Result<PsStateFilter, String> {
+    value.parse().map_err(|_| format!("unknown state {value}"))
+}
+
+fn parse_sort_cli(value: &str) -> Result<PsSort, String> {
+    value.parse().map_err(|_| {
+        format!(
+            "unknown column {value}, expected one of {}",
+            PS_COLUMNS.join(",")
+        )
+    })
+}
+
 #[derive(Tabled)]
 #[tabled(rename_all = "UPPERCASE")]
 pub struct PsOutputCli {
@@ -104,22 +720,537 @@ pub struct PsOutputCli {
     state: ProcessState,
     #[tabled(display_with = "tabled_display_option")]
     pid: Option<usize>,
+    #[tabled(display_with = "tabled_display_option")]
+    uptime: Option<String>,
+    /// Time since the last run ended, for processes which aren't started
+    #[tabled(display_with = "tabled_display_option")]
+    downtime: Option<String>,
+    restarts: u32,
+    #[tabled(rename = "EXIT CODE", display_with = "tabled_display_option")]
+    exit_code: Option<i32>,
+    #[tabled(display_with = "tabled_display_option")]
+    ports: Option<String>,
+    #[tabled(display_with = "tabled_display_option")]
+    cpu: Option<String>,
+    #[tabled(rename = "MEM", display_with = "tabled_display_option")]
+    memory: Option<String>,
+    #[tabled(display_with = "tabled_display_option")]
+    stack: Option<String>,
+    #[tabled(display_with = "tabled_display_option")]
+    labels: Option<String>,
+    #[tabled(display_with = "tabled_display_option")]
+    command: Option<String>,
 }
 
 impl From<PsOutput> for PsOutputCli {
     fn from(value: PsOutput) -> Self {
+        let since = |at: DateTime<Utc>| {
+            format_duration(Utc::now().signed_duration_since(at).num_seconds().max(0))
+        };
+        let uptime = match (&value.state, value.started_at) {
+            (ProcessState::Running, Some(started_at)) => Some(since(started_at)),
+            _ => None,
+        };
+        let downtime = value
+            .stopped_at
+            .filter(|_| !value.state.is_started())
+            .map(since);
+        let ports = format_ports(&value);
+        let labels = (!value.labels.is_empty()).then(|| {
+            value
+                .labels
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect::<Vec<_>>()
+                .join(",")
+        });
         Self {
             name: value.name,
             state: value.state,
             pid: value.pid,
+            uptime,
+            downtime,
+            restarts: value.restart_count,
+            exit_code: value.exit_code,
+            ports,
+            cpu: value.cpu.map(|cpu| format!("{cpu:.1}%")),
+            memory: value.memory.map(format_bytes),
+            stack: value.stack,
+            labels,
+            command: value.command,
         }
     }
 }
 
+impl PsOutputCli {
+    /// Table of given processes, with only given columns of [`PS_COLUMNS`], or
+    /// [`PS_DEFAULT_COLUMNS`] if none
+    pub fn table(processes: Vec<Self>, columns: &[String]) -> Table {
+        let columns: Vec<&str> = if columns.is_empty() {
+            PS_DEFAULT_COLUMNS.to_vec()
+        } else {
+            columns.iter().map(String::as_str).collect()
+        };
+        let indexes: Vec<usize> = columns
+            .iter()
+            .filter_map(|column| PS_COLUMNS.iter().position(|c| c == column))
+            .collect();
+        let mut builder = Builder::default();
+        let headers = Self::headers();
+        builder.push_record(indexes.iter().map(|&i| headers[i].to_string()));
+        for process in processes {
+            let fields = process.fields();
+            builder.push_record(indexes.iter().map(|&i| fields[i].to_string()));
+        }
+        builder.build()
+    }
+}
+
+/// Ports actually listened on by running processes, declared ones otherwise
+fn format_ports(value: &PsOutput) -> Option<String> {
+    let ports = if value.state == ProcessState::Running {
+        &value.listening_ports
+    } else {
+        &value.ports
+    };
+    if ports.is_empty() {
+        return None;
+    }
+    let ports: Vec<String> = ports.iter().map(u16::to_string).collect();
+    Some(ports.join(","))
+}
+
+/// Format a number of bytes with a binary unit, e.g. `12.3 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Format a number of seconds with its two most significant units, e.g. `3h 12m`
+pub fn format_duration(seconds: i64) -> String {
+    let (days, hours, minutes, seconds) = (
+        seconds / 86400,
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+    );
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Let paused processes run again
+#[argh(subcommand, name = "resume")]
+pub struct ResumeArgsCli {
+    #[argh(positional)]
+    /// filter process to act upon
+    pub processes: Vec<String>,
+}
+
+impl From<ResumeArgsCli> for ResumeArgs {
+    fn from(value: ResumeArgsCli) -> Self {
+        Self {
+            processes: value.processes,
+        }
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Build and run a single process in the foreground, exiting with its exit code
+#[argh(subcommand, name = "run")]
+pub struct RunArgsCli {
+    /// variable overriding the environment of the process, as KEY=VALUE or KEY to pass the
+    /// current value, may be repeated
+    #[argh(option, short = 'e', from_str_fn(parse_env_cli))]
+    pub env: Vec<(String, String)>,
+    /// file of variables overriding the environment of the process, may be repeated
+    #[argh(option)]
+    pub env_file: Vec<PathBuf>,
+    #[argh(positional)]
+    /// process to run
+    pub process: String,
+    #[argh(positional, greedy)]
+    /// additional arguments given to the process, after `--`
+    pub args: Vec<String>,
+}
+
+impl From<RunArgsCli> for RunArgs {
+    fn from(value: RunArgsCli) -> Self {
+        Self {
+            process: value.process,
+            args: value.args,
+            env: value.env,
+            env_files: value.env_file,
+        }
+    }
+}
+
+fn parse_env_cli(value: &str) -> Result<(String, String), String> {
+    parse_env_var(value)
+        .map_err(|_| format!("expected KEY=VALUE or a set variable KEY, got {value}"))
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Change the number of instances of processes
+#[argh(subcommand, name = "scale")]
+pub struct ScaleArgsCli {
+    #[argh(positional, from_str_fn(parse_replicas_cli))]
+    /// wanted number of instances, as <process>=<replicas>
+    pub replicas: Vec<(String, u32)>,
+}
+
+impl From<ScaleArgsCli> for ScaleArgs {
+    fn from(value: ScaleArgsCli) -> Self {
+        Self {
+            replicas: value.replicas,
+        }
+    }
+}
+
+fn parse_replicas_cli(value: &str) -> Result<(String, u32), String> {
+    parse_replicas(value).map_err(|_| format!("expected <process>=<replicas>, got {value}"))
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Send a signal to a running process, such as SIGHUP to reload its configuration
+#[argh(subcommand, name = "signal")]
+pub struct SignalArgsCli {
+    #[argh(positional)]
+    /// process to signal, every instance of it if scaled
+    pub process: String,
+    #[argh(positional, from_str_fn(parse_signal_cli))]
+    /// signal to send, such as HUP, SIGUSR1 or usr2
+    pub signal: ProcessSignal,
+}
+
+impl From<SignalArgsCli> for SignalArgs {
+    fn from(value: SignalArgsCli) -> Self {
+        Self {
+            process: value.process,
+            signal: value.signal,
+        }
+    }
+}
+
+fn parse_signal_cli(value: &str) -> Result<ProcessSignal, String> {
+    value.parse().map_err(|_| {
+        format!(
+            "unknown signal {value}, expected one of \
+            HUP, INT, QUIT, KILL, USR1, USR2, TERM, CONT, STOP or WINCH"
+        )
+    })
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Manage stacks
+#[argh(subcommand, name = "stack")]
+pub struct StackArgsCli {
+    #[argh(subcommand)]
+    pub sub_command: StackSubCommandCli,
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+#[argh(subcommand)]
+pub enum StackSubCommandCli {
+    Use(StackUseArgsCli),
+    Create(StackCreateArgsCli),
+    Delete(StackDeleteArgsCli),
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Select the stack used by later commands, print the selected one without arguments
+#[argh(subcommand, name = "use")]
+pub struct StackUseArgsCli {
+    /// revert to the configuration default stack
+    #[argh(switch)]
+    pub clear: bool,
+    #[argh(positional)]
+    /// stack to select
+    pub stack: Option<String>,
+}
+
+impl From<StackUseArgsCli> for UseStackArgs {
+    fn from(value: StackUseArgsCli) -> Self {
+        Self {
+            stack: value.stack,
+            clear: value.clear,
+        }
+    }
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Create a stack of given processes, kept across configuration reloads
+#[argh(subcommand, name = "create")]
+pub struct StackCreateArgsCli {
+    #[argh(positional)]
+    /// name of the stack
+    pub stack: String,
+    #[argh(positional)]
+    /// processes of the stack
+    pub processes: Vec<String>,
+}
+
+impl From<StackCreateArgsCli> for CreateStackArgs {
+    fn from(value: StackCreateArgsCli) -> Self {
+        Self {
+            stack: value.stack,
+            processes: value.processes,
+        }
+    }
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Delete a stack created with `jocker stack create`
+#[argh(subcommand, name = "delete")]
+pub struct StackDeleteArgsCli {
+    #[argh(positional)]
+    /// name of the stack
+    pub stack: String,
+}
+
+impl From<StackDeleteArgsCli> for DeleteStackArgs {
+    fn from(value: StackDeleteArgsCli) -> Self {
+        Self { stack: value.stack }
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Inspect the state jocker keeps of the project
+#[argh(subcommand, name = "state")]
+pub struct StateArgsCli {
+    #[argh(subcommand)]
+    pub sub_command: StateSubCommandCli,
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+#[argh(subcommand)]
+pub enum StateSubCommandCli {
+    Repair(StateRepairArgsCli),
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// Detect processes pointing at dead tasks, orphan tasks and stacks left by interrupted reloads,
+/// then repair them
+#[argh(subcommand, name = "repair")]
+pub struct StateRepairArgsCli {
+    /// only report issues, without repairing them
+    #[argh(switch)]
+    pub dry_run: bool,
+}
+
+impl From<StateRepairArgsCli> for RepairArgs {
+    fn from(value: StateRepairArgsCli) -> Self {
+        Self {
+            dry_run: value.dry_run,
+        }
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Report metrics recorded by jocker
+#[argh(subcommand, name = "stats")]
+pub struct StatsArgsCli {
+    #[argh(subcommand)]
+    pub sub_command: StatsSubCommandCli,
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+#[argh(subcommand)]
+pub enum StatsSubCommandCli {
+    Builds(StatsBuildsArgsCli),
+}
+
+#[derive(Clone, Debug, FromArgs, PartialEq)]
+/// List recent builds with their duration, most recent first
+#[argh(subcommand, name = "builds")]
+pub struct StatsBuildsArgsCli {
+    /// number of builds to list, 20 by default
+    #[argh(option, short = 'n')]
+    pub limit: Option<u32>,
+    #[argh(positional)]
+    /// only list builds of given process
+    pub process: Option<String>,
+}
+
+impl From<StatsBuildsArgsCli> for StatsBuildsArgs {
+    fn from(value: StatsBuildsArgsCli) -> Self {
+        Self {
+            process: value.process,
+            limit: value.limit,
+        }
+    }
+}
+
+#[derive(Tabled)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct BuildOutputCli {
+    time: String,
+    duration: String,
+    result: String,
+    compiled: u32,
+    #[tabled(display_with = "tabled_display_option")]
+    profile: Option<String>,
+    processes: String,
+}
+
+impl From<BuildRecord> for BuildOutputCli {
+    fn from(value: BuildRecord) -> Self {
+        Self {
+            time: value.started_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            duration: format_duration_ms(value.duration_ms),
+            result: if value.success { "ok" } else { "failed" }.to_owned(),
+            compiled: value.compiled,
+            profile: value.profile,
+            processes: value.processes.join(","),
+        }
+    }
+}
+
+/// Line telling which stack later commands use
+pub fn format_stack_selection(selection: &StackSelection) -> String {
+    match selection {
+        StackSelection::Selected(stack) => format!("Using stack {stack}"),
+        StackSelection::Default(stack) => format!("Using default stack {stack}"),
+        StackSelection::All => "No stack selected, using every process of the project".to_owned(),
+    }
+}
+
+/// Builds and launches `start --dry-run` would go through, in order
+pub fn format_start_plan(plan: &StartPlan) -> String {
+    let names: Vec<&str> = plan.processes.iter().map(|p| p.name.as_str()).collect();
+    let mut lines = vec![format!("Processes to start, in order: {}", names.join(", "))];
+    for args in &plan.builds {
+        lines.push(format!("Would run: cargo {}", args.join(" ")));
+    }
+    for process in &plan.processes {
+        let Some(launch) = &process.launch else {
+            lines.push(format!("Process {} is already started", process.name));
+            continue;
+        };
+        lines.push(format!("Would start process {}:", process.name));
+        lines.push(format!("  scheduler: {}", launch.scheduler));
+        lines.push("  env:".to_owned());
+        for (key, value) in &launch.env {
+            lines.push(format!("    {key}={value}"));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Processes `stop --dry-run` would stop, in order
+pub fn format_stop_plan(plan: &StopPlan) -> String {
+    let signal = match (plan.kill, plan.timeout) {
+        (true, _) => "SIGKILL".to_owned(),
+        (false, Some(timeout)) => format!("SIGTERM, then SIGKILL after {timeout}s"),
+        (false, None) => "SIGTERM".to_owned(),
+    };
+    let mut lines = vec![];
+    for (i, wave) in plan.waves.iter().enumerate() {
+        if i > 0 {
+            if let Some(delay) = plan.delay {
+                lines.push(format!("Would wait {delay}s"));
+            }
+        }
+        for process in wave {
+            lines.push(match process {
+                PlannedStop::AlreadyStopped(name) => format!("Process is already stopped: {name}"),
+                PlannedStop::Signal(name, pid) => {
+                    format!("Would stop process {name} (pid {pid}) with {signal}")
+                }
+                PlannedStop::MarkStopped(name) => format!("Would mark process {name} as stopped"),
+            });
+        }
+    }
+    for (name, pid) in &plan.unknown {
+        lines.push(format!("Would stop unknown process {name} (pid {pid}) with {signal}"));
+    }
+    lines.join("\n")
+}
+
+/// One line summary of given builds, such as `12 builds, 1 failed, 8.2s on average, 31.0s at most`
+pub fn format_builds_summary(summary: &BuildsSummary) -> String {
+    let mut line = format!("{} builds, {} failed", summary.count, summary.failed);
+    if let Some(mean) = summary.mean_duration_ms {
+        line.push_str(&format!(", {} on average", format_duration_ms(mean)));
+    }
+    if let Some(max) = summary.max_duration_ms {
+        line.push_str(&format!(", {} at most", format_duration_ms(max)));
+    }
+    line
+}
+
+/// Format milliseconds as seconds with a decimal under a minute, as [`format_duration`] past it
+fn format_duration_ms(milliseconds: u64) -> String {
+    if milliseconds < 60_000 {
+        format!("{:.1}s", milliseconds as f64 / 1000.0)
+    } else {
+        format_duration((milliseconds / 1000) as i64)
+    }
+}
+
 #[derive(Debug, FromArgs, PartialEq)]
 /// Start processes
 #[argh(subcommand, name = "start")]
 pub struct StartArgsCli {
+    /// stream processes logs and stop them on Ctrl-C
+    #[argh(switch)]
+    pub attach: bool,
+    /// build and run processes with the release profile
+    #[argh(switch)]
+    pub release: bool,
+    /// wait for processes to be running and healthy, exiting with an error if they aren't
+    #[argh(switch)]
+    pub wait: bool,
+    /// maximum duration to wait for processes, or for each task to complete, in seconds
+    /// (default: 60)
+    #[argh(option)]
+    pub timeout: Option<u64>,
+    /// launch already built binaries, without running cargo
+    #[argh(switch)]
+    pub no_build: bool,
+    /// build processes even if their binaries look up to date
+    #[argh(switch)]
+    pub rebuild: bool,
+    /// print what would be built and started, without doing it
+    #[argh(switch)]
+    pub dry_run: bool,
+    /// build processes running on another host there, instead of copying local binaries
+    #[argh(switch)]
+    pub remote_build: bool,
+    /// variable overriding the environment of processes, as KEY=VALUE or KEY to pass the
+    /// current value, may be repeated
+    #[argh(option, short = 'e', from_str_fn(parse_env_cli))]
+    pub env: Vec<(String, String)>,
+    /// file of variables overriding the environment of processes, may be repeated
+    #[argh(option)]
+    pub env_file: Vec<PathBuf>,
+    /// argument appended to the ones of the single process to start, may be repeated. Arguments
+    /// given after `--` are appended too.
+    #[argh(option)]
+    pub arg: Vec<String>,
+    /// comma separated processes not to start, even as dependencies, e.g. analytics,mailer
+    #[argh(option, default = "Vec::new()", from_str_fn(parse_process_list_cli))]
+    pub except: Vec<String>,
+    /// only start processes with given label, as key=value, may be repeated
+    #[argh(option, from_str_fn(parse_label_cli))]
+    pub label: Vec<(String, String)>,
     #[argh(positional)]
     /// filter process to act upon
     pub processes: Vec<String>,
@@ -128,7 +1259,76 @@ pub struct StartArgsCli {
 impl From<StartArgsCli> for StartArgs {
     fn from(value: StartArgsCli) -> Self {
         Self {
+            release: value.release,
             processes: value.processes,
+            wait: value.wait,
+            timeout: value.timeout,
+            no_build: value.no_build,
+            rebuild: value.rebuild,
+            dry_run: value.dry_run,
+            remote_build: value.remote_build,
+            env: value.env,
+            env_files: value.env_file,
+            args: value.arg,
+            except: value.except,
+            labels: value.label,
+        }
+    }
+}
+
+fn parse_label_cli(value: &str) -> Result<(String, String), String> {
+    parse_label(value).map_err(|_| format!("expected key=value, got {value}"))
+}
+
+fn parse_process_list_cli(value: &str) -> Result<Vec<String>, String> {
+    Ok(value
+        .split(',')
+        .map(str::trim)
+        .filter(|process| !process.is_empty())
+        .map(str::to_owned)
+        .collect())
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Display processes and their resource usage, refreshed every second
+#[argh(subcommand, name = "top")]
+pub struct TopArgsCli {
+    #[argh(positional)]
+    /// filter process to act upon
+    pub processes: Vec<String>,
+}
+
+impl From<TopArgsCli> for PsArgs {
+    fn from(value: TopArgsCli) -> Self {
+        Self {
+            processes: value.processes,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Print everything known about a process: configuration, environment, PIDs, health, logs and
+/// lifecycle events
+#[argh(subcommand, name = "status")]
+pub struct StatusArgsCli {
+    #[argh(positional)]
+    /// process to inspect
+    pub process: String,
+    /// output format, either yaml (default) or json
+    #[argh(
+        option,
+        default = "ShowFormat::Yaml",
+        from_str_fn(parse_show_format_cli)
+    )]
+    pub output: ShowFormat,
+}
+
+impl From<StatusArgsCli> for StatusArgs {
+    fn from(value: StatusArgsCli) -> Self {
+        Self {
+            process: value.process,
+            format: value.output,
         }
     }
 }
@@ -140,6 +1340,21 @@ pub struct StopArgsCli {
     /// send SIGKILL instead of SIGTERM
     #[argh(switch)]
     pub kill: bool,
+    /// seconds to wait after SIGTERM before sending SIGKILL
+    #[argh(option)]
+    pub timeout: Option<u64>,
+    /// seconds to wait between stopping dependents and their dependencies
+    #[argh(option)]
+    pub delay: Option<u64>,
+    /// stop every process of the project, ignoring stack and process filters
+    #[argh(switch)]
+    pub all_stacks: bool,
+    /// print what would be stopped, without doing it
+    #[argh(switch)]
+    pub dry_run: bool,
+    /// only stop processes with given label, as key=value, may be repeated
+    #[argh(option, from_str_fn(parse_label_cli))]
+    pub label: Vec<(String, String)>,
     #[argh(positional)]
     /// filter process to act upon
     pub processes: Vec<String>,
@@ -149,6 +1364,64 @@ impl From<StopArgsCli> for StopArgs {
     fn from(value: StopArgsCli) -> Self {
         Self {
             kill: value.kill,
+            timeout: value.timeout,
+            delay: value.delay,
+            all_stacks: value.all_stacks,
+            processes: value.processes,
+            labels: value.label,
+            dry_run: value.dry_run,
+        }
+    }
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Wait for processes to be running, healthy or stopped, to sequence jocker with other tools
+#[argh(subcommand, name = "wait")]
+pub struct WaitArgsCli {
+    /// condition to wait for, either running (default), healthy or stopped
+    #[argh(
+        option,
+        long = "for",
+        default = "WaitCondition::Running",
+        from_str_fn(parse_wait_condition_cli)
+    )]
+    pub condition: WaitCondition,
+    /// maximum number of seconds to wait, 60 by default
+    #[argh(option)]
+    pub timeout: Option<u64>,
+    #[argh(positional)]
+    /// filter process to act upon
+    pub processes: Vec<String>,
+}
+
+impl From<WaitArgsCli> for WaitArgs {
+    fn from(value: WaitArgsCli) -> Self {
+        Self {
+            processes: value.processes,
+            condition: value.condition,
+            timeout: value.timeout,
+        }
+    }
+}
+
+fn parse_wait_condition_cli(value: &str) -> Result<WaitCondition, String> {
+    value
+        .parse()
+        .map_err(|_| format!("unknown condition {value}, expected running, healthy or stopped"))
+}
+
+#[derive(Debug, FromArgs, PartialEq)]
+/// Start processes, then rebuild and restart them when their sources change
+#[argh(subcommand, name = "watch")]
+pub struct WatchArgsCli {
+    #[argh(positional)]
+    /// filter process to act upon
+    pub processes: Vec<String>,
+}
+
+impl From<WatchArgsCli> for WatchArgs {
+    fn from(value: WatchArgsCli) -> Self {
+        Self {
             processes: value.processes,
         }
     }
@@ -1,25 +1,250 @@
 mod cli;
+mod picker;
 
 use core::panic;
-use std::sync::Arc;
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use cli::{Cli, CliSubCommand, PsOutputCli};
+use cli::{
+    format_builds_summary, format_stack_selection, format_start_plan, format_stop_plan,
+    BuildOutputCli, CleanArgsCli, Cli, CliSubCommand, ConfigArgsCli, ConfigSubCommandCli,
+    EventOutputCli, HistoryOutputCli, ProjectOutputCli, ProjectsArgsCli, ProjectsSubCommandCli,
+    PsOutputCli, StackArgsCli, StackSubCommandCli, StateArgsCli, StateSubCommandCli, StatsArgsCli,
+    StatsSubCommandCli, UiArgsCli, UiSubCommandCli,
+};
+use jocker_lib::adopt::Adopt;
+use jocker_lib::attach::Attach;
+use jocker_lib::build::Build;
 use jocker_lib::common::Exec;
+use jocker_lib::daemon::Daemon;
+use jocker_lib::env::Env;
+use jocker_lib::events::Events;
+use jocker_lib::exec::ExecCommand;
+use jocker_lib::export::Export;
+use jocker_lib::history::History;
+use jocker_lib::init::Init;
 use jocker_lib::logs::Logs;
+use jocker_lib::pause::{Pause, Resume};
+use jocker_lib::projects::{CleanAllProjects, CleanProject, GcProjects, ListProjects};
 use jocker_lib::ps::Ps;
+use jocker_lib::repair::Repair;
+use jocker_lib::run::Run;
+use jocker_lib::scale::Scale;
+use jocker_lib::show::Show;
+use jocker_lib::signal::Signal;
+use jocker_lib::stack::{CreateStack, DeleteStack, UseStack};
 use jocker_lib::start::Start;
 use jocker_lib::state::State;
-use jocker_lib::stop::Stop;
+use jocker_lib::stats::{BuildsSummary, StatsBuilds};
+use jocker_lib::status::Status;
+use jocker_lib::stop::{Stop, StopArgs};
+use jocker_lib::tmux::Tmux;
+use jocker_lib::validate::{Validate, ValidateArgs};
+use jocker_lib::wait::Wait;
+use jocker_lib::watch::Watch;
 
-use jocker_lib::error::{Error, InnerError, Result};
+use argh::FromArgs;
+use chrono::{DateTime, Local, Utc};
+use jocker_lib::error::{Error, ErrorFormat, InnerError, Result, EXIT_CONFIG, EXIT_USAGE};
 use tabled::settings::Style;
 use tabled::Table;
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt,
+    prelude::*,
+};
+
+/// Interval between two checks of the events recorded while `ps --watch` waits to redraw
+const PS_WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Interval between two draws of `top`
+const TOP_INTERVAL: Duration = Duration::from_secs(1);
 
 #[tokio::main]
-pub async fn main() -> Result<()> {
-    let cli: Cli = argh::from_env();
-    let state = Arc::new(State::new(cli.refresh, cli.stack, cli.target_directory).await?);
+pub async fn main() {
+    let cli = parse_cli();
+    init_logging(cli.verbose, cli.quiet);
+    let error_format = cli.output;
+    if let Err(e) = run(cli).await {
+        report_error(&e, error_format);
+        std::process::exit(e.exit_code());
+    }
+}
+
+/// Print given error on stderr, as a single JSON object per line for tools wrapping jocker
+fn report_error(e: &Error, format: ErrorFormat) {
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {e}"),
+        ErrorFormat::Json => match serde_json::to_string(&e.output()) {
+            Ok(output) => eprintln!("{output}"),
+            Err(_) => eprintln!("Error: {e}"),
+        },
+    }
+}
+
+/// Same as `argh::from_env`, exiting with `EXIT_USAGE` on invalid arguments
+fn parse_cli() -> Cli {
+    let strings: Vec<String> = std::env::args_os()
+        .map(|s| s.into_string())
+        .collect::<std::result::Result<_, _>>()
+        .unwrap_or_else(|arg| {
+            eprintln!("Invalid utf8: {}", arg.to_string_lossy());
+            std::process::exit(EXIT_USAGE)
+        });
+    let Some((program, args)) = strings.split_first() else {
+        eprintln!("No program name, argv is empty");
+        std::process::exit(EXIT_USAGE)
+    };
+    let cmd = Path::new(program)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or(program);
+    let args = expand_verbose(args);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let mut cli = Cli::from_args(&[cmd], &args).unwrap_or_else(|early_exit| {
+        match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                std::process::exit(0)
+            }
+            Err(()) => {
+                eprintln!(
+                    "{}\nRun {cmd} --help for more information.",
+                    early_exit.output
+                );
+                std::process::exit(EXIT_USAGE)
+            }
+        }
+    });
+    // argh gives arguments following `--` as positional ones, they come last among process names
+    if let CliSubCommand::Start(start) = &mut cli.sub_command {
+        if let Some(idx) = args.iter().position(|arg| *arg == "--") {
+            let extra = args.len() - idx - 1;
+            let first = start.processes.len().saturating_sub(extra);
+            start.arg.extend(start.processes.split_off(first));
+        }
+    }
+    cli
+}
+
+/// Split `-vv` into `-v -v`, as argh doesn't group short switches
+///
+/// Arguments after `--` belong to the process command and are left untouched.
+fn expand_verbose(args: &[String]) -> Vec<String> {
+    let mut expanded = Vec::with_capacity(args.len());
+    let mut args = args.iter();
+    for arg in args.by_ref() {
+        if arg == "--" {
+            expanded.push(arg.clone());
+            break;
+        }
+        match arg.strip_prefix('-') {
+            Some(flags) if flags.len() > 1 && flags.chars().all(|c| c == 'v') => {
+                expanded.extend(flags.chars().map(|_| "-v".to_owned()))
+            }
+            _ => expanded.push(arg.clone()),
+        }
+    }
+    expanded.extend(args.cloned());
+    expanded
+}
+
+/// Print messages of jocker on stderr, at the level asked by `-v` and `-q`
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::WARN,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    // Dependencies only get to report their own problems
+    let targets = Targets::new()
+        .with_default(LevelFilter::WARN)
+        .with_target("jocker", level)
+        .with_target("jocker_lib", level);
+    let layer = fmt::layer()
+        .with_writer(std::io::stderr)
+        .without_time()
+        .with_level(verbose > 0)
+        .with_target(verbose > 0);
+    tracing_subscriber::registry()
+        .with(layer)
+        .with(targets)
+        .init();
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    // Validation must not depend on a state, which can't be built from an invalid configuration
+    if let CliSubCommand::Config(ConfigArgsCli {
+        sub_command: ConfigSubCommandCli::Validate(_),
+    }) = cli.sub_command
+    {
+        let target_dir = match cli.target_directory {
+            Some(target_directory) => PathBuf::from(target_directory),
+            None => std::env::current_dir()?,
+        };
+        let diagnostics = Validate::new(ValidateArgs {
+            target_dir,
+            config_file: cli.file.map(PathBuf::from),
+        })
+        .exec()
+        .await?;
+        if diagnostics.is_empty() {
+            println!("Configuration is valid");
+            return Ok(());
+        }
+        for diagnostic in diagnostics {
+            println!("{diagnostic}");
+        }
+        std::process::exit(EXIT_CONFIG);
+    }
+    // Projects are managed globally, the current directory must not be registered as one
+    if let CliSubCommand::Projects(ProjectsArgsCli { sub_command }) = cli.sub_command {
+        return run_projects(sub_command).await;
+    }
+    if let CliSubCommand::Clean(CleanArgsCli { global: true, .. }) = cli.sub_command {
+        return CleanAllProjects::new().exec().await;
+    }
+    // Repairs must not depend on a refresh of the state, which may be what fails
+    if let CliSubCommand::State(StateArgsCli {
+        sub_command: StateSubCommandCli::Repair(args),
+    }) = cli.sub_command
+    {
+        let mut builder = State::builder().skip_refresh();
+        if let Some(target_directory) = cli.target_directory {
+            builder = builder.target_dir(target_directory);
+        }
+        if let Some(file) = cli.file {
+            builder = builder.config_file(file);
+        }
+        let state = Arc::new(builder.build().await?);
+        let dry_run = args.dry_run;
+        let issues = Repair::new(args.into(), state).exec().await?;
+        if issues.is_empty() {
+            println!("No issue found");
+        }
+        for issue in issues {
+            if dry_run {
+                println!("Found: {issue}");
+            } else {
+                println!("Repaired: {issue}");
+            }
+        }
+        return Ok(());
+    }
+    let explicit_stack = cli.stack.is_some();
+    let state = Arc::new(State::new(cli.refresh, cli.stack, cli.target_directory, cli.file).await?);
+    state.set_enabled_groups(&cli.enable)?;
     match cli.sub_command {
+        CliSubCommand::Adopt(args) => Adopt::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Build(args) => Build::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Clean(CleanArgsCli { tasks: true, .. }) => {
+            let pruned = state.prune_tasks(Duration::ZERO).await?;
+            println!("Removed {} finished tasks", pruned.len());
+        }
         CliSubCommand::Clean(_) => {
             Arc::try_unwrap(state)
                 .map_err(|_| {
@@ -30,21 +255,206 @@ pub async fn main() -> Result<()> {
                 .clean()
                 .await?
         }
-        CliSubCommand::Logs(args) => Logs::new(args.into(), state.clone()).exec().await?,
-        CliSubCommand::Ps(args) => {
-            let ps: Vec<PsOutputCli> = Ps::new(args.into(), state.clone())
+        CliSubCommand::Config(ConfigArgsCli {
+            sub_command: ConfigSubCommandCli::Show(args),
+        }) => print!("{}", Show::new(args.into(), state.clone()).exec().await?),
+        CliSubCommand::Daemon(args) => Daemon::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Env(args) => {
+            print!("{}", Env::new(args.into(), state.clone()).exec().await?)
+        }
+        CliSubCommand::Events(args) => {
+            let events: Vec<EventOutputCli> = Events::new(args.into(), state.clone())
+                .run()
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect();
+            let mut table = Table::new(events);
+            table.with(Style::blank());
+            println!("{table}");
+        }
+        CliSubCommand::Exec(args) => {
+            let exit_code = ExecCommand::new(args.into(), state.clone()).exec().await?;
+            std::process::exit(exit_code);
+        }
+        CliSubCommand::Export(args) => Export::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::History(args) => {
+            let history: Vec<HistoryOutputCli> = History::new(args.into(), state.clone())
                 .run()
                 .await?
                 .into_iter()
                 .map(Into::into)
                 .collect();
-            let mut table = Table::new(ps);
+            let mut table = Table::new(history);
             table.with(Style::blank());
             println!("{table}");
         }
-        CliSubCommand::Start(args) => Start::new(args.into(), state.clone()).exec().await?,
-        CliSubCommand::Stop(args) => Stop::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Init(args) => Init::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Logs(args) => Logs::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Pause(args) => Pause::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Ps(args) if args.quiet => {
+            for process in Ps::new(args.into(), state.clone()).run().await? {
+                println!("{}", process.name);
+            }
+        }
+        CliSubCommand::Ps(args) if args.watch => {
+            let interval = Duration::from_secs(args.interval.max(1));
+            let columns = args.columns.clone();
+            let ps = Ps::new(args.into(), state.clone());
+            watch_ps(&state, &ps, &columns, interval).await?
+        }
+        CliSubCommand::Ps(args) => {
+            let columns = args.columns.clone();
+            let ps = Ps::new(args.into(), state.clone());
+            println!("{}", ps_table(&ps, &columns).await?);
+        }
+        CliSubCommand::Resume(args) => Resume::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Run(args) => {
+            let exit_code = Run::new(args.into(), state.clone()).exec().await?;
+            std::process::exit(exit_code);
+        }
+        CliSubCommand::Scale(args) => Scale::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Signal(args) => Signal::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Stack(StackArgsCli {
+            sub_command: StackSubCommandCli::Use(args),
+        }) => {
+            let selection = UseStack::new(args.into(), state.clone()).exec().await?;
+            println!("{}", format_stack_selection(&selection));
+        }
+        CliSubCommand::Stack(StackArgsCli {
+            sub_command: StackSubCommandCli::Create(args),
+        }) => CreateStack::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Stack(StackArgsCli {
+            sub_command: StackSubCommandCli::Delete(args),
+        }) => DeleteStack::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Start(args) if args.attach => {
+            Attach::new(args.into(), state.clone())
+                .run(async {
+                    if let Err(e) = tokio::signal::ctrl_c().await {
+                        println!("Unable to listen for Ctrl-C: {e}");
+                        std::future::pending::<()>().await;
+                    }
+                })
+                .await?
+        }
+        CliSubCommand::Start(args) => {
+            if let Some(plan) = Start::new(args.into(), state.clone()).exec().await? {
+                println!("{}", format_start_plan(&plan));
+            }
+        }
+        CliSubCommand::Stats(StatsArgsCli {
+            sub_command: StatsSubCommandCli::Builds(args),
+        }) => {
+            let builds = StatsBuilds::new(args.into(), state.clone()).run().await?;
+            let summary = BuildsSummary::new(&builds);
+            let builds: Vec<BuildOutputCli> = builds.into_iter().map(Into::into).collect();
+            let mut table = Table::new(builds);
+            table.with(Style::blank());
+            println!("{table}");
+            println!("\n{}", format_builds_summary(&summary));
+        }
+        CliSubCommand::Status(args) => {
+            print!("{}", Status::new(args.into(), state.clone()).exec().await?)
+        }
+        CliSubCommand::Stop(args) => {
+            let mut args: StopArgs = args.into();
+            // Confirm which processes to act upon, when nothing was explicitly given
+            if args.processes.is_empty() && !args.all_stacks && !explicit_stack && is_interactive()
+            {
+                let processes = state.filter_processes(&[]).await?;
+                match picker::pick_processes("stop", &processes)? {
+                    Some(process_names) if !process_names.is_empty() => {
+                        args.processes = process_names
+                    }
+                    _ => {
+                        println!("No process selected");
+                        return Ok(());
+                    }
+                }
+            }
+            if let Some(plan) = Stop::new(args, state.clone()).exec().await? {
+                println!("{}", format_stop_plan(&plan));
+            }
+        }
+        CliSubCommand::Top(args) => {
+            let ps = Ps::new(args.into(), state.clone());
+            watch_ps(&state, &ps, &[], TOP_INTERVAL).await?
+        }
+        CliSubCommand::Ui(UiArgsCli {
+            sub_command: UiSubCommandCli::Tmux(args),
+        }) => Tmux::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Wait(args) => Wait::new(args.into(), state.clone()).exec().await?,
+        CliSubCommand::Watch(args) => Watch::new(args.into(), state.clone()).exec().await?,
         _ => panic!(),
     };
     Ok(())
 }
+
+/// Table of processes listed by `ps`, with given columns only if any
+async fn ps_table(ps: &Ps, columns: &[String]) -> Result<Table> {
+    let processes: Vec<PsOutputCli> = ps.run().await?.into_iter().map(Into::into).collect();
+    let mut table = PsOutputCli::table(processes, columns);
+    table.with(Style::blank());
+    Ok(table)
+}
+
+/// Draw the table of processes listed by `ps` again every `interval`, or sooner once a process
+/// was started, stopped or exited
+///
+/// The screen is only cleared on a terminal, tables are printed one after the other otherwise.
+async fn watch_ps(state: &State, ps: &Ps, columns: &[String], interval: Duration) -> Result<()> {
+    let clear = std::io::stdout().is_terminal();
+    loop {
+        state.refresh(false).await?;
+        let drawn_at = Utc::now();
+        let table = ps_table(ps, columns).await?;
+        if clear {
+            // Clear screen and move cursor back to top-left corner
+            print!("\x1b[2J\x1b[H");
+        }
+        println!(
+            "Every {}s, last at {}\n",
+            interval.as_secs(),
+            drawn_at.with_timezone(&Local).format("%H:%M:%S")
+        );
+        println!("{table}");
+        wait_ps_change(state, drawn_at, interval).await?;
+    }
+}
+
+/// Wait for `interval`, or less if a process was started, stopped or exited since `since`
+async fn wait_ps_change(state: &State, since: DateTime<Utc>, interval: Duration) -> Result<()> {
+    let deadline = Instant::now() + interval;
+    while Instant::now() < deadline {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        tokio::time::sleep(PS_WATCH_POLL_INTERVAL.min(remaining)).await;
+        if !state.get_events(Some(since)).await?.is_empty() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Whether a user is there to answer prompts
+fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stdout().is_terminal()
+}
+
+async fn run_projects(sub_command: ProjectsSubCommandCli) -> Result<()> {
+    match sub_command {
+        ProjectsSubCommandCli::Clean(args) => CleanProject::new(args.into()).exec().await,
+        ProjectsSubCommandCli::Gc(_) => GcProjects::new().exec().await,
+        ProjectsSubCommandCli::List(_) => {
+            let projects: Vec<ProjectOutputCli> = ListProjects::new()
+                .run()
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect();
+            let mut table = Table::new(projects);
+            table.with(Style::blank());
+            println!("{table}");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::{stdout, Stdout, Write};
+
+use jocker_lib::{common::Process, error::Result};
+use ratatui::crossterm::{
+    cursor::{MoveToColumn, MoveUp},
+    event::{read, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::Print,
+    terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
+};
+
+/// Let the user pick processes to `action` in the terminal, every process being selected at first
+///
+/// Returns names of selected processes, or `None` if the prompt was cancelled.
+pub fn pick_processes(action: &str, processes: &[Process]) -> Result<Option<Vec<String>>> {
+    if processes.is_empty() {
+        return Ok(Some(vec![]));
+    }
+    enable_raw_mode()?;
+    let picked = prompt(action, processes);
+    disable_raw_mode()?;
+    picked
+}
+
+fn prompt(action: &str, processes: &[Process]) -> Result<Option<Vec<String>>> {
+    let mut out = stdout();
+    let mut selected = vec![true; processes.len()];
+    let mut cursor = 0;
+    render(&mut out, action, processes, &selected, cursor)?;
+    loop {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = read()?
+        else {
+            continue;
+        };
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                cursor = cursor.checked_sub(1).unwrap_or(processes.len() - 1)
+            }
+            KeyCode::Down | KeyCode::Char('j') => cursor = (cursor + 1) % processes.len(),
+            KeyCode::Char(' ') => selected[cursor] = !selected[cursor],
+            KeyCode::Char('a') => {
+                let all = selected.iter().all(|s| *s);
+                selected.fill(!all);
+            }
+            KeyCode::Enter => {
+                return Ok(Some(
+                    processes
+                        .iter()
+                        .zip(selected)
+                        .filter(|(_, selected)| *selected)
+                        .map(|(process, _)| process.name().to_owned())
+                        .collect(),
+                ))
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(None),
+            _ => continue,
+        }
+        // Header and one line per process were printed, draw over them
+        let printed_lines = u16::try_from(processes.len() + 1)?;
+        queue!(
+            out,
+            MoveUp(printed_lines),
+            MoveToColumn(0),
+            Clear(ClearType::FromCursorDown)
+        )?;
+        render(&mut out, action, processes, &selected, cursor)?;
+    }
+}
+
+fn render(
+    out: &mut Stdout,
+    action: &str,
+    processes: &[Process],
+    selected: &[bool],
+    cursor: usize,
+) -> Result<()> {
+    // Raw mode doesn't return to the first column on new lines
+    queue!(
+        out,
+        Print(format!(
+            "Processes to {action} (space: toggle, a: all, enter: confirm, esc: cancel)\r\n"
+        ))
+    )?;
+    for (i, process) in processes.iter().enumerate() {
+        queue!(
+            out,
+            Print(format!(
+                "{} [{}] {} ({})\r\n",
+                if i == cursor { ">" } else { " " },
+                if selected[i] { "x" } else { " " },
+                process.name(),
+                process.state
+            ))
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
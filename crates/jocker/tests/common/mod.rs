@@ -16,7 +16,11 @@ pub async fn setup() -> (Arc<State>, TempDir) {
     let dir = tempdir().unwrap();
     copy_dir_all(&project_path, &dir).unwrap();
     (
-        Arc::new(State::new(true, None, Some(dir.path())).await.unwrap()),
+        Arc::new(
+            State::new(true, None, Some(dir.path()), None::<&Path>)
+                .await
+                .unwrap(),
+        ),
         dir,
     )
 }
@@ -1,12 +1,22 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
 use common::{clean, setup};
 use jocker_lib::{
-    common::{Exec as _, ProcessState},
-    logs::{Logs, LogsArgs},
+    attach::Attach,
+    build::{Build, BuildArgs},
+    common::{Exec as _, JockerEvent, ProcessState},
+    error::InnerError,
+    logs::{LogEntry, Logs, LogsArgs},
     ps::{Ps, PsArgs},
+    scale::{Scale, ScaleArgs},
     start::{Start, StartArgs},
-    stop::{Stop, StopArgs},
+    state::State,
+    stop::{PlannedStop, Stop, StopArgs},
+    testing::TestStack,
 };
 use pueue_lib::{Client, Request, Response, Settings};
+use tokio::time::sleep;
+use tokio_stream::StreamExt;
 
 mod common;
 
@@ -46,19 +56,438 @@ async fn start_log_stop_default() {
     assert_eq!(&ps_stopped_output[1].state, &ProcessState::Stopped);
     assert_eq!(ps_stopped_output.len(), 2);
 
-    let (mut handles, mut rx) = logs.unwrap();
-    let mut logs = Vec::new();
+    let logs: Vec<LogEntry> = logs.unwrap().filter_map(Result::ok).collect().await;
 
-    while (handles.join_next().await).is_some() {}
-    while let Some(message) = rx.recv().await {
-        logs.push(message);
-    }
+    assert!(logs.len() >= 2);
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn logs_after_stop() {
+    let (state, tempdir) = setup().await;
+
+    Start::new(StartArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+    sleep(std::time::Duration::from_secs(1)).await;
+    Stop::new(StopArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+
+    let logs: Vec<LogEntry> = Logs::new(
+        LogsArgs {
+            follow: true,
+            ..Default::default()
+        },
+        state.clone(),
+    )
+    .run()
+    .await
+    .unwrap()
+    .map(Result::unwrap)
+    .collect()
+    .await;
 
     assert!(logs.len() >= 2);
+    assert!(logs
+        .iter()
+        .all(|entry| ["eris", "harmonia"].contains(&entry.process_name.as_str())));
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn attach_stops_started_processes() {
+    let (state, tempdir) = setup().await;
+
+    Attach::new(StartArgs::default(), state.clone())
+        .run(sleep(std::time::Duration::from_secs(2)))
+        .await
+        .unwrap();
+
+    let ps_output = Ps::new(PsArgs::default(), state.clone())
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(ps_output.len(), 2);
+    assert!(ps_output
+        .iter()
+        .all(|process| process.state == ProcessState::Stopped));
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn start_wait_until_ready() {
+    let (state, tempdir) = setup().await;
+
+    Start::new(
+        StartArgs {
+            wait: true,
+            timeout: Some(30),
+            ..Default::default()
+        },
+        state.clone(),
+    )
+    .exec()
+    .await
+    .unwrap();
+
+    let ps_output = Ps::new(PsArgs::default(), state.clone())
+        .run()
+        .await
+        .unwrap();
+
+    Stop::new(StopArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+
+    assert_eq!(ps_output.len(), 2);
+    assert!(ps_output
+        .iter()
+        .all(|process| process.state == ProcessState::Running));
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn build_then_start_without_build() {
+    let (state, tempdir) = setup().await;
+
+    Build::new(BuildArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+    let ps_built_output = Ps::new(PsArgs::default(), state.clone())
+        .run()
+        .await
+        .unwrap();
+
+    Start::new(
+        StartArgs {
+            no_build: true,
+            ..Default::default()
+        },
+        state.clone(),
+    )
+    .exec()
+    .await
+    .unwrap();
+    let ps_running_output = Ps::new(PsArgs::default(), state.clone())
+        .run()
+        .await
+        .unwrap();
+
+    Stop::new(StopArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+
+    assert!(ps_built_output
+        .iter()
+        .all(|process| process.state == ProcessState::Stopped));
+    assert!(ps_running_output
+        .iter()
+        .all(|process| process.state == ProcessState::Running));
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn subscribe_to_events() {
+    let (state, tempdir) = setup().await;
+    Build::new(BuildArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+
+    let mut events = state.subscribe();
+    Start::new(
+        StartArgs {
+            processes: vec!["eris".to_owned()],
+            no_build: true,
+            ..Default::default()
+        },
+        state.clone(),
+    )
+    .exec()
+    .await
+    .unwrap();
+    Stop::new(StopArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+
+    let mut received = vec![];
+    while let Ok(event) = events.try_recv() {
+        received.push(event);
+    }
+    assert!(matches!(
+        received.as_slice(),
+        [
+            JockerEvent::StateChanged {
+                state: ProcessState::Running,
+                ..
+            },
+            JockerEvent::ProcessStarted { .. },
+            JockerEvent::StateChanged {
+                state: ProcessState::Stopped,
+                ..
+            },
+            JockerEvent::ProcessStopped { .. },
+        ]
+    ));
+    assert!(received.iter().all(|event| match event {
+        JockerEvent::ProcessStarted { process_name, .. }
+        | JockerEvent::ProcessStopped { process_name }
+        | JockerEvent::StateChanged { process_name, .. } => process_name == "eris",
+        JockerEvent::BuildFinished { .. } => false,
+    }));
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn process_handle() {
+    let (state, tempdir) = setup().await;
+    let eris = state.process("eris");
+
+    eris.start().await.unwrap();
+    let started = eris.status().await.unwrap();
+    eris.restart().await.unwrap();
+    eris.wait_until(ProcessState::Running, Duration::from_secs(5))
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(1)).await;
+    let logs: Vec<LogEntry> = eris
+        .logs(false)
+        .await
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    eris.stop().await.unwrap();
+    let stopped = eris.status().await.unwrap();
+    let missing = state.process("nope").status().await;
+
+    assert_eq!(started, ProcessState::Running);
+    assert!(!logs.is_empty());
+    assert!(logs.iter().all(|entry| entry.process_name == "eris"));
+    assert_eq!(stopped, ProcessState::Stopped);
+    assert!(matches!(
+        missing.unwrap_err().inner_error,
+        InnerError::ProcessNotFound(_)
+    ));
 
     clean(state, tempdir).await.unwrap();
 }
 
+#[tokio::test]
+async fn test_stack_guard() {
+    let (state, tempdir) = setup().await;
+
+    let stack = TestStack::builder(State::builder().target_dir(tempdir.path()))
+        .processes(["eris"])
+        .start()
+        .await
+        .unwrap();
+    let started = stack.started().to_vec();
+    let running = stack.process("eris").status().await.unwrap();
+    let env = stack.env("eris").await.unwrap();
+    drop(stack);
+    let stopped = state.process("eris").status().await.unwrap();
+
+    assert_eq!(started, vec!["eris".to_owned()]);
+    assert_eq!(running, ProcessState::Running);
+    assert_eq!(env.get("JOCKER_INSTANCE").map(String::as_str), Some("0"));
+    assert_eq!(stopped, ProcessState::Stopped);
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn scale_running_process() {
+    let (state, tempdir) = setup().await;
+
+    Start::new(StartArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+    let scale = |replicas| {
+        Scale::new(
+            ScaleArgs {
+                replicas: vec![("harmonia".to_owned(), replicas)],
+            },
+            state.clone(),
+        )
+    };
+
+    scale(3).exec().await.unwrap();
+    let scaled_up_output = Ps::new(PsArgs::default(), state.clone())
+        .run()
+        .await
+        .unwrap();
+
+    scale(1).exec().await.unwrap();
+    let scaled_down_output = Ps::new(PsArgs::default(), state.clone())
+        .run()
+        .await
+        .unwrap();
+
+    Stop::new(StopArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+
+    let names: Vec<&str> = scaled_up_output.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["eris", "harmonia", "harmonia.1", "harmonia.2"]);
+    assert!(scaled_up_output
+        .iter()
+        .all(|process| process.state == ProcessState::Running));
+    let names: Vec<&str> = scaled_down_output.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["eris", "harmonia"]);
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn stop_all_stacks() {
+    let (state, tempdir) = setup().await;
+    state
+        .set_current_stack(&Some("full".to_string()))
+        .await
+        .unwrap();
+    Start::new(StartArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+    state
+        .set_current_stack(&Some("minimal".to_string()))
+        .await
+        .unwrap();
+
+    Stop::new(
+        StopArgs {
+            all_stacks: true,
+            ..Default::default()
+        },
+        state.clone(),
+    )
+    .exec()
+    .await
+    .unwrap();
+    state
+        .set_current_stack(&Some("full".to_string()))
+        .await
+        .unwrap();
+    let ps_output = Ps::new(PsArgs::default(), state.clone())
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(ps_output.len(), 4);
+    assert!(ps_output
+        .iter()
+        .all(|process| process.state == ProcessState::Stopped));
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn dry_run_changes_nothing() {
+    let (state, tempdir) = setup().await;
+
+    let plan = Start::new(
+        StartArgs {
+            dry_run: true,
+            ..Default::default()
+        },
+        state.clone(),
+    )
+    .exec()
+    .await
+    .unwrap()
+    .unwrap();
+    let ps_output = Ps::new(PsArgs::default(), state.clone())
+        .run()
+        .await
+        .unwrap();
+    assert!(ps_output
+        .iter()
+        .all(|process| process.state == ProcessState::Stopped));
+    assert_eq!(plan.processes.len(), ps_output.len());
+    assert!(plan.processes.iter().all(|process| process.launch.is_some()));
+
+    Start::new(StartArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+    let plan = Stop::new(
+        StopArgs {
+            dry_run: true,
+            ..Default::default()
+        },
+        state.clone(),
+    )
+    .exec()
+    .await
+    .unwrap()
+    .unwrap();
+    assert!(plan
+        .waves
+        .iter()
+        .flatten()
+        .all(|process| matches!(process, PlannedStop::Signal(..))));
+    let ps_output = Ps::new(PsArgs::default(), state.clone())
+        .run()
+        .await
+        .unwrap();
+    assert!(ps_output
+        .iter()
+        .all(|process| process.state == ProcessState::Running));
+
+    Stop::new(StopArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn concurrent_commands_are_locked() {
+    let (state, tempdir) = setup().await;
+    let other_state = Arc::new(
+        State::new(false, None, Some(tempdir.path()), None::<&Path>)
+            .await
+            .unwrap(),
+    );
+
+    let lock = state.lock().unwrap();
+    let err = Start::new(StartArgs::default(), other_state.clone())
+        .exec()
+        .await
+        .unwrap_err();
+    assert!(matches!(err.inner_error, InnerError::ProjectLocked(_)));
+    // Nested commands of the lock owner share it
+    Start::new(StartArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+    drop(lock);
+
+    // Only the state which spawned processes can reap them once stopped
+    Stop::new(StopArgs::default(), state.clone())
+        .exec()
+        .await
+        .unwrap();
+    drop(other_state);
+    clean(state, tempdir).await.unwrap();
+}
+
 #[tokio::test]
 async fn start_log_stop_process_stack() {
     let (state, tempdir) = setup().await;
@@ -107,13 +536,7 @@ async fn start_log_stop_process_stack() {
     assert_eq!(&ps_stopped_output[3].state, &ProcessState::Stopped);
     assert_eq!(ps_stopped_output.len(), 4);
 
-    let (mut handles, mut rx) = logs.unwrap();
-    let mut logs = Vec::new();
-
-    while (handles.join_next().await).is_some() {}
-    while let Some(message) = rx.recv().await {
-        logs.push(message);
-    }
+    let logs: Vec<LogEntry> = logs.unwrap().filter_map(Result::ok).collect().await;
 
     assert!(logs.len() >= 4);
 
@@ -132,6 +555,7 @@ async fn start_log_stop_process_stack_filter() {
     Start::new(
         StartArgs {
             processes: processes.clone(),
+            ..Default::default()
         },
         state.clone(),
     )
@@ -142,6 +566,7 @@ async fn start_log_stop_process_stack_filter() {
     let ps_running_output = Ps::new(
         PsArgs {
             processes: processes.clone(),
+            ..Default::default()
         },
         state.clone(),
     )
@@ -165,6 +590,7 @@ async fn start_log_stop_process_stack_filter() {
     let ps_stopped_output = Ps::new(
         PsArgs {
             processes: processes.clone(),
+            ..Default::default()
         },
         state.clone(),
     )
@@ -180,13 +606,7 @@ async fn start_log_stop_process_stack_filter() {
     assert_eq!(&ps_stopped_output[0].state, &ProcessState::Stopped);
     assert_eq!(ps_stopped_output.len(), 1);
 
-    let (mut handles, mut rx) = logs.unwrap();
-    let mut logs = Vec::new();
-
-    while (handles.join_next().await).is_some() {}
-    while let Some(message) = rx.recv().await {
-        logs.push(message);
-    }
+    let logs: Vec<LogEntry> = logs.unwrap().filter_map(Result::ok).collect().await;
 
     assert!(!logs.is_empty());
 
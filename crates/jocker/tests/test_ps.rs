@@ -1,5 +1,13 @@
+use std::{path::Path, sync::Arc};
+
 use common::{clean, setup};
-use jocker_lib::ps::{Ps, PsArgs};
+use jocker_lib::{
+    common::{Exec, ProcessState},
+    error::InnerError,
+    ps::{Ps, PsArgs, PsStateFilter},
+    stack::{StackSelection, UseStack, UseStackArgs},
+    state::State,
+};
 
 mod common;
 
@@ -47,6 +55,137 @@ async fn ps_default_with_stack() {
     clean(state, tempdir).await.unwrap();
 }
 
+#[tokio::test]
+async fn ps_selected_stack() {
+    let (state, tempdir) = setup().await;
+    let ps_len = |stack: Option<&str>| {
+        let stack = stack.map(ToOwned::to_owned);
+        let target_dir = tempdir.path().to_path_buf();
+        async move {
+            let state = Arc::new(
+                State::new(false, stack, Some(target_dir), None::<&Path>)
+                    .await
+                    .unwrap(),
+            );
+            Ps::new(PsArgs::default(), state).run().await.unwrap().len()
+        }
+    };
+
+    let selection = UseStack::new(
+        UseStackArgs {
+            stack: Some("full".to_owned()),
+            ..Default::default()
+        },
+        state.clone(),
+    )
+    .exec()
+    .await
+    .unwrap();
+    assert_eq!(selection, StackSelection::Selected("full".to_owned()));
+    assert_eq!(ps_len(None).await, 4);
+    // An explicit stack still wins over the selected one
+    assert_eq!(ps_len(Some("minimal")).await, 2);
+
+    UseStack::new(
+        UseStackArgs {
+            clear: true,
+            ..Default::default()
+        },
+        state.clone(),
+    )
+    .exec()
+    .await
+    .unwrap();
+    assert_eq!(ps_len(None).await, 2);
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn ps_config_file() {
+    let (state, tempdir) = setup().await;
+    let config_file = tempdir.path().join("deploy/jocker.staging.yml");
+    std::fs::create_dir_all(config_file.parent().unwrap()).unwrap();
+    std::fs::write(&config_file, "processes:\n  eris: {}\n").unwrap();
+
+    // Switching configuration file refreshes processes, whatever the file modification time
+    let staging_state = Arc::new(
+        State::new(false, None, Some(tempdir.path()), Some(&config_file))
+            .await
+            .unwrap(),
+    );
+    let ps_output = Ps::new(PsArgs::default(), staging_state.clone())
+        .run()
+        .await
+        .unwrap();
+    drop(staging_state);
+
+    assert_eq!(ps_output.len(), 1);
+    assert_eq!(&ps_output[0].name, "eris");
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn ps_groups() {
+    let (state, tempdir) = setup().await;
+    let config_file = tempdir.path().join("jocker.groups.yml");
+    std::fs::write(
+        &config_file,
+        "processes:\n  eris: {}\n  harmonia:\n    groups:\n      - debug\n",
+    )
+    .unwrap();
+    let groups_state = Arc::new(
+        State::new(false, None, Some(tempdir.path()), Some(&config_file))
+            .await
+            .unwrap(),
+    );
+
+    let ps = Ps::new(PsArgs::default(), groups_state.clone());
+    let default_output = ps.run().await.unwrap();
+    groups_state.set_enabled_groups(&["debug".to_owned()]).unwrap();
+    let debug_output = ps.run().await.unwrap();
+    drop(ps);
+    drop(groups_state);
+
+    assert_eq!(default_output.len(), 1);
+    assert_eq!(&default_output[0].name, "eris");
+    assert_eq!(debug_output.len(), 2);
+
+    clean(state, tempdir).await.unwrap();
+}
+
+#[tokio::test]
+async fn ps_state_builder() {
+    let (state, tempdir) = setup().await;
+    let config_file = tempdir.path().join("jocker.groups.yml");
+    std::fs::write(
+        &config_file,
+        "processes:\n  eris: {}\n  harmonia:\n    groups:\n      - debug\n",
+    )
+    .unwrap();
+    let built_state = Arc::new(
+        State::builder()
+            .target_dir(tempdir.path())
+            .config_file(&config_file)
+            .enable(["debug"])
+            .build()
+            .await
+            .unwrap(),
+    );
+
+    let ps_output = Ps::new(PsArgs::default(), built_state.clone())
+        .run()
+        .await
+        .unwrap();
+    drop(built_state);
+
+    let names: Vec<&str> = ps_output.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["eris", "harmonia"]);
+
+    clean(state, tempdir).await.unwrap();
+}
+
 #[tokio::test]
 async fn ps_filter() {
     let (state, tempdir) = setup().await;
@@ -54,6 +193,7 @@ async fn ps_filter() {
     let ps_output = Ps::new(
         PsArgs {
             processes: vec!["eris".to_owned()],
+            ..Default::default()
         },
         state.clone(),
     )
@@ -67,6 +207,38 @@ async fn ps_filter() {
     clean(state, tempdir).await.unwrap();
 }
 
+#[tokio::test]
+async fn ps_filter_patterns() {
+    let (state, tempdir) = setup().await;
+    let ps_names = |processes: &[&str]| {
+        let state = state.clone();
+        let processes = processes.iter().map(|p| p.to_string()).collect();
+        async move {
+            Ps::new(
+                PsArgs {
+                    processes,
+                    ..Default::default()
+                },
+                state,
+            )
+                .run()
+                .await
+                .map(|output| output.into_iter().map(|p| p.name).collect::<Vec<_>>())
+        }
+    };
+
+    assert_eq!(ps_names(&["a*"]).await.unwrap(), vec!["ares", "athena"]);
+    assert_eq!(ps_names(&["harm"]).await.unwrap(), vec!["harmonia"]);
+    let error = ps_names(&["atena"]).await.unwrap_err();
+    assert!(matches!(
+        error.inner_error,
+        InnerError::ProcessNotFound(ref missing) if missing == &["atena"]
+    ));
+    assert_eq!(error.debug_context, vec!["Did you mean athena?"]);
+
+    clean(state, tempdir).await.unwrap();
+}
+
 #[tokio::test]
 async fn ps_filter_with_stack() {
     let (state, tempdir) = setup().await;
@@ -78,6 +250,7 @@ async fn ps_filter_with_stack() {
     let ps_output = Ps::new(
         PsArgs {
             processes: vec!["eris".to_owned(), "athena".to_owned()],
+            ..Default::default()
         },
         state.clone(),
     )
@@ -91,3 +264,41 @@ async fn ps_filter_with_stack() {
 
     clean(state, tempdir).await.unwrap();
 }
+
+#[tokio::test]
+async fn ps_filter_state() {
+    let (state, tempdir) = setup().await;
+    let ps_count = |states: Vec<PsStateFilter>| {
+        let state = state.clone();
+        async move {
+            Ps::new(
+                PsArgs {
+                    states,
+                    ..Default::default()
+                },
+                state,
+            )
+            .run()
+            .await
+            .unwrap()
+            .len()
+        }
+    };
+
+    assert_eq!(
+        ps_count(vec![PsStateFilter::State(ProcessState::Stopped)]).await,
+        2
+    );
+    assert_eq!(
+        ps_count(vec![PsStateFilter::State(ProcessState::Running)]).await,
+        0
+    );
+    assert_eq!(ps_count(vec![PsStateFilter::Unhealthy]).await, 0);
+    assert_eq!(
+        "crashlooping".parse::<PsStateFilter>().unwrap(),
+        PsStateFilter::State(ProcessState::CrashLooping)
+    );
+    assert!("sleepy".parse::<PsStateFilter>().is_err());
+
+    clean(state, tempdir).await.unwrap();
+}